@@ -1,4 +1,5 @@
 use divan::Bencher;
+use rodio::conversions::{ResampleQuality, Resampler, SampleRateConverter};
 use rodio::source::UniformSourceIterator;
 
 mod shared;
@@ -41,3 +42,49 @@ fn resample_to(bencher: Bencher, target_sample_rate: u32) {
                 .for_each(divan::black_box_drop)
         })
 }
+
+#[divan::bench(args = COMMON_SAMPLE_RATES)]
+fn resample_to_legacy_linear(bencher: Bencher, target_sample_rate: u32) {
+    bencher
+        .with_inputs(|| {
+            let source = music_wav();
+            (source.channels(), source.sample_rate(), source)
+        })
+        .bench_values(|(channels, sample_rate, source)| {
+            SampleRateConverter::new(source, sample_rate, target_sample_rate, channels)
+                .for_each(divan::black_box_drop)
+        })
+}
+
+const QUALITIES: [ResampleQuality; 4] = [
+    ResampleQuality::Linear,
+    ResampleQuality::Low,
+    ResampleQuality::Medium,
+    ResampleQuality::High,
+];
+
+#[divan::bench(args = QUALITIES)]
+fn resample_44100_to_48000(bencher: Bencher, quality: ResampleQuality) {
+    bencher
+        .with_inputs(|| {
+            let source = music_wav();
+            (source.channels(), source.sample_rate(), source)
+        })
+        .bench_values(|(channels, sample_rate, source)| {
+            Resampler::with_quality(source, sample_rate, 48_000, channels, quality)
+                .for_each(divan::black_box_drop)
+        })
+}
+
+#[divan::bench]
+fn resample_44100_to_48000_legacy_linear(bencher: Bencher) {
+    bencher
+        .with_inputs(|| {
+            let source = music_wav();
+            (source.channels(), source.sample_rate(), source)
+        })
+        .bench_values(|(channels, sample_rate, source)| {
+            SampleRateConverter::new(source, sample_rate, 48_000, channels)
+                .for_each(divan::black_box_drop)
+        })
+}
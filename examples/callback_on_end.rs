@@ -5,7 +5,7 @@ use std::sync::Arc;
 
 fn main() -> Result<(), Box<dyn Error>> {
     let stream_handle = rodio::OutputStreamBuilder::open_default_stream()?;
-    let sink = rodio::Sink::connect_new(&stream_handle.mixer());
+    let sink = rodio::Sink::connect_new(stream_handle.mixer());
 
     let file = std::fs::File::open("assets/music.wav")?;
     sink.append(rodio::Decoder::new(BufReader::new(file))?);
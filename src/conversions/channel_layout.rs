@@ -0,0 +1,541 @@
+use crate::common::ChannelCount;
+
+/// Position of a loudspeaker.
+///
+/// The speakers are declared in the order in which their channels appear in a stream, which
+/// is the order used by WAV files.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Speaker {
+    /// Front left.
+    FrontLeft,
+    /// Front right.
+    FrontRight,
+    /// Front center.
+    FrontCenter,
+    /// Low frequency effects, also known as subwoofer.
+    LowFrequency,
+    /// Back (rear) left.
+    BackLeft,
+    /// Back (rear) right.
+    BackRight,
+    /// Front left of center.
+    FrontLeftOfCenter,
+    /// Front right of center.
+    FrontRightOfCenter,
+    /// Back (rear) center.
+    BackCenter,
+    /// Side left.
+    SideLeft,
+    /// Side right.
+    SideRight,
+}
+
+impl Speaker {
+    /// All speakers, in channel order.
+    pub const ALL: [Speaker; 11] = [
+        Speaker::FrontLeft,
+        Speaker::FrontRight,
+        Speaker::FrontCenter,
+        Speaker::LowFrequency,
+        Speaker::BackLeft,
+        Speaker::BackRight,
+        Speaker::FrontLeftOfCenter,
+        Speaker::FrontRightOfCenter,
+        Speaker::BackCenter,
+        Speaker::SideLeft,
+        Speaker::SideRight,
+    ];
+
+    #[inline]
+    fn bit(self) -> u16 {
+        1 << self as u16
+    }
+}
+
+/// A set of speakers. A stream with these speakers has one channel per speaker, in the order
+/// of [`Speaker::ALL`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct SpeakerSet {
+    bits: u16,
+}
+
+impl SpeakerSet {
+    /// Set with the bits of a WAV (`WAVEFORMATEXTENSIBLE`) channel mask. Returns `None` if
+    /// the mask contains speakers not known to rodio.
+    pub fn from_wav_mask(mask: u32) -> Option<SpeakerSet> {
+        let known = (1 << Speaker::ALL.len()) - 1;
+        (mask & !known == 0).then_some(SpeakerSet { bits: mask as u16 })
+    }
+
+    /// The set as a WAV (`WAVEFORMATEXTENSIBLE`) channel mask.
+    #[inline]
+    pub fn wav_mask(self) -> u32 {
+        self.bits as u32
+    }
+
+    /// `true` if the set contains the speaker.
+    #[inline]
+    pub fn contains(self, speaker: Speaker) -> bool {
+        self.bits & speaker.bit() != 0
+    }
+
+    /// Number of speakers in the set.
+    #[inline]
+    pub fn len(self) -> usize {
+        self.bits.count_ones() as usize
+    }
+
+    /// `true` if the set contains no speakers.
+    #[inline]
+    pub fn is_empty(self) -> bool {
+        self.bits == 0
+    }
+
+    /// The speakers in channel order.
+    pub fn iter(self) -> impl Iterator<Item = Speaker> {
+        Speaker::ALL
+            .into_iter()
+            .filter(move |speaker| self.contains(*speaker))
+    }
+
+    /// Index of the channel of `speaker`, if the set contains it.
+    #[inline]
+    pub fn position(self, speaker: Speaker) -> Option<usize> {
+        self.contains(speaker)
+            .then(|| (self.bits & (speaker.bit() - 1)).count_ones() as usize)
+    }
+}
+
+impl FromIterator<Speaker> for SpeakerSet {
+    fn from_iter<T: IntoIterator<Item = Speaker>>(iter: T) -> Self {
+        SpeakerSet {
+            bits: iter
+                .into_iter()
+                .fold(0, |bits, speaker| bits | speaker.bit()),
+        }
+    }
+}
+
+/// Arrangement of the channels of a stream.
+///
+/// Common speaker arrangements have their own variant, [`ChannelLayout::new`] picks it when
+/// given their speakers.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ChannelLayout {
+    /// A single channel.
+    Mono,
+    /// Front left and right.
+    Stereo,
+    /// Stereo with a subwoofer: front left, front right and low frequency.
+    TwoPointOne,
+    /// Front left, front right, back left and back right.
+    Quad,
+    /// Front left, front right, center, low frequency, side left and side right.
+    FivePointOne,
+    /// Front left, front right, center, low frequency, back left, back right, side left and
+    /// side right.
+    SevenPointOne,
+    /// Any other set of speakers.
+    Speakers(SpeakerSet),
+    /// The given number of channels that are not meant for particular speakers, for example
+    /// ambisonics or independent tracks.
+    Discrete(ChannelCount),
+}
+
+impl ChannelLayout {
+    const NAMED: [ChannelLayout; 6] = [
+        ChannelLayout::Mono,
+        ChannelLayout::Stereo,
+        ChannelLayout::TwoPointOne,
+        ChannelLayout::Quad,
+        ChannelLayout::FivePointOne,
+        ChannelLayout::SevenPointOne,
+    ];
+
+    /// Layout with the given speakers.
+    pub fn new(speakers: SpeakerSet) -> ChannelLayout {
+        ChannelLayout::NAMED
+            .into_iter()
+            .find(|layout| layout.speakers() == Some(speakers))
+            .unwrap_or(ChannelLayout::Speakers(speakers))
+    }
+
+    /// The layout that is assumed for a stream with this many channels, if any.
+    pub fn from_channel_count(channels: ChannelCount) -> Option<ChannelLayout> {
+        match channels {
+            1 => Some(ChannelLayout::Mono),
+            2 => Some(ChannelLayout::Stereo),
+            3 => Some(ChannelLayout::TwoPointOne),
+            4 => Some(ChannelLayout::Quad),
+            6 => Some(ChannelLayout::FivePointOne),
+            8 => Some(ChannelLayout::SevenPointOne),
+            _ => None,
+        }
+    }
+
+    /// The layout of a stream that reports `layout` and has `channels` channels.
+    ///
+    /// Falls back to the [assumed layout](ChannelLayout::from_channel_count) or to discrete
+    /// channels if the stream does not report a layout or if it does not match the channel
+    /// count.
+    pub fn resolve(layout: Option<ChannelLayout>, channels: ChannelCount) -> ChannelLayout {
+        layout
+            .filter(|layout| layout.channel_count() == channels)
+            .or_else(|| ChannelLayout::from_channel_count(channels))
+            .unwrap_or(ChannelLayout::Discrete(channels))
+    }
+
+    /// The speakers of the channels, `None` for discrete channels.
+    pub fn speakers(self) -> Option<SpeakerSet> {
+        use Speaker::*;
+        let speakers: &[Speaker] = match self {
+            ChannelLayout::Mono => &[FrontCenter],
+            ChannelLayout::Stereo => &[FrontLeft, FrontRight],
+            ChannelLayout::TwoPointOne => &[FrontLeft, FrontRight, LowFrequency],
+            ChannelLayout::Quad => &[FrontLeft, FrontRight, BackLeft, BackRight],
+            ChannelLayout::FivePointOne => &[
+                FrontLeft,
+                FrontRight,
+                FrontCenter,
+                LowFrequency,
+                SideLeft,
+                SideRight,
+            ],
+            ChannelLayout::SevenPointOne => &[
+                FrontLeft,
+                FrontRight,
+                FrontCenter,
+                LowFrequency,
+                BackLeft,
+                BackRight,
+                SideLeft,
+                SideRight,
+            ],
+            ChannelLayout::Speakers(speakers) => return Some(speakers),
+            ChannelLayout::Discrete(_) => return None,
+        };
+        Some(speakers.iter().copied().collect())
+    }
+
+    /// Number of channels of this layout.
+    #[inline]
+    pub fn channel_count(self) -> ChannelCount {
+        match self.speakers() {
+            Some(speakers) => speakers.len() as ChannelCount,
+            None => match self {
+                ChannelLayout::Discrete(channels) => channels,
+                _ => unreachable!(),
+            },
+        }
+    }
+}
+
+/// -3 dB, the level at which a channel is folded into two neighbouring speakers.
+const HALF_POWER: f32 = std::f32::consts::FRAC_1_SQRT_2;
+
+/// Describes how each output channel is computed from the input channels.
+///
+/// Every output sample is the sum of the input samples of the same frame, each multiplied by
+/// its gain.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChannelMatrix {
+    from: ChannelCount,
+    to: ChannelCount,
+    /// One row of `from` gains for each output channel.
+    gains: Vec<f32>,
+}
+
+impl ChannelMatrix {
+    /// Creates a matrix from its gains, given as one row of `from` gains for each of the `to`
+    /// output channels.
+    ///
+    /// # Panic
+    /// Panics if `from` or `to` are 0, or if there are not exactly `from * to` gains.
+    pub fn new(from: ChannelCount, to: ChannelCount, gains: Vec<f32>) -> ChannelMatrix {
+        assert!(from >= 1);
+        assert!(to >= 1);
+        assert_eq!(gains.len(), from as usize * to as usize);
+        ChannelMatrix { from, to, gains }
+    }
+
+    /// Matrix that keeps every channel as it is.
+    pub fn identity(channels: ChannelCount) -> ChannelMatrix {
+        let mut matrix = ChannelMatrix::silent(channels, channels);
+        for channel in 0..channels {
+            matrix.set_gain(channel, channel, 1.0);
+        }
+        matrix
+    }
+
+    fn silent(from: ChannelCount, to: ChannelCount) -> ChannelMatrix {
+        ChannelMatrix::new(from, to, vec![0.0; from as usize * to as usize])
+    }
+
+    /// Standard down- or upmix between two layouts, following ITU-R BS.775.
+    ///
+    /// Channels missing from the output are folded into the nearest speakers, the center at
+    /// -3 dB into front left and right and the surrounds at -3 dB into their front side.
+    /// The low frequency channel is dropped when the output has none. Mono is played at full
+    /// level on both front speakers if the output has no center, and a downmix to mono
+    /// averages left and right of the stereo downmix. Upmixing does not synthesize content,
+    /// additional speakers stay silent.
+    ///
+    /// Discrete channels are kept in order, see
+    /// [`for_channel_counts`](ChannelMatrix::for_channel_counts).
+    ///
+    /// Downmixes are not normalized, so loud multichannel material can exceed full scale.
+    pub fn between_layouts(from: ChannelLayout, to: ChannelLayout) -> ChannelMatrix {
+        let (Some(inputs), Some(outputs)) = (from.speakers(), to.speakers()) else {
+            return ChannelMatrix::in_order(from.channel_count(), to.channel_count());
+        };
+        let mono = ChannelLayout::Mono.speakers();
+        if Some(outputs) == mono && Some(inputs) != mono {
+            let stereo = ChannelMatrix::between_layouts(from, ChannelLayout::Stereo);
+            let mut matrix = ChannelMatrix::silent(from.channel_count(), 1);
+            for input in 0..from.channel_count() {
+                let gain = 0.5 * (stereo.gain(input, 0) + stereo.gain(input, 1));
+                matrix.set_gain(input, 0, gain);
+            }
+            return matrix;
+        }
+
+        let mut matrix = ChannelMatrix::silent(from.channel_count(), to.channel_count());
+        for (input, speaker) in inputs.iter().enumerate() {
+            let input = input as ChannelCount;
+            let full_level = Some(inputs) == mono;
+            for (output, gain) in fold(speaker, outputs, full_level) {
+                let output = output as ChannelCount;
+                matrix.set_gain(input, output, matrix.gain(input, output) + gain);
+            }
+        }
+        matrix
+    }
+
+    /// Matrix used when converting between channel counts without further information.
+    ///
+    /// Uses [`between_layouts`](ChannelMatrix::between_layouts) with the
+    /// [assumed layouts](ChannelLayout::from_channel_count) of both counts.
+    /// Otherwise channels are kept in order, surplus input channels are dropped and surplus
+    /// output channels are silent, except that mono is copied to the first two outputs.
+    pub fn for_channel_counts(from: ChannelCount, to: ChannelCount) -> ChannelMatrix {
+        ChannelMatrix::between_layouts(
+            ChannelLayout::resolve(None, from),
+            ChannelLayout::resolve(None, to),
+        )
+    }
+
+    fn in_order(from: ChannelCount, to: ChannelCount) -> ChannelMatrix {
+        let mut matrix = ChannelMatrix::silent(from, to);
+        for channel in 0..from.min(to) {
+            matrix.set_gain(channel, channel, 1.0);
+        }
+        if from == 1 && to >= 2 {
+            matrix.set_gain(0, 1, 1.0);
+        }
+        matrix
+    }
+
+    /// Number of input channels.
+    #[inline]
+    pub fn from_channels(&self) -> ChannelCount {
+        self.from
+    }
+
+    /// Number of output channels.
+    #[inline]
+    pub fn to_channels(&self) -> ChannelCount {
+        self.to
+    }
+
+    /// Gain with which the `input` channel contributes to the `output` channel.
+    ///
+    /// # Panic
+    /// Panics if either channel is out of range.
+    #[inline]
+    pub fn gain(&self, input: ChannelCount, output: ChannelCount) -> f32 {
+        self.gains[self.index(input, output)]
+    }
+
+    /// Changes the gain with which the `input` channel contributes to the `output` channel.
+    ///
+    /// # Panic
+    /// Panics if either channel is out of range.
+    #[inline]
+    pub fn set_gain(&mut self, input: ChannelCount, output: ChannelCount, gain: f32) {
+        let index = self.index(input, output);
+        self.gains[index] = gain;
+    }
+
+    #[inline]
+    fn index(&self, input: ChannelCount, output: ChannelCount) -> usize {
+        assert!(input < self.from && output < self.to);
+        output as usize * self.from as usize + input as usize
+    }
+
+    /// `true` if the output is the same as the input.
+    pub(crate) fn is_identity(&self) -> bool {
+        *self == ChannelMatrix::identity(self.from)
+    }
+
+    /// Computes one output frame from one input frame.
+    #[inline]
+    pub(crate) fn apply(&self, input: &[f32], output: &mut Vec<f32>) {
+        output.clear();
+        output.extend(self.gains.chunks_exact(self.from as usize).map(|row| {
+            row.iter()
+                .zip(input)
+                .map(|(gain, sample)| gain * sample)
+                .sum::<f32>()
+        }));
+    }
+}
+
+/// Output channels and gains that play `speaker` on `outputs`. With `full_level` a center
+/// speaker missing from the output is played at full level on the front speakers.
+fn fold(speaker: Speaker, outputs: SpeakerSet, full_level: bool) -> Vec<(usize, f32)> {
+    use Speaker::*;
+    if let Some(output) = outputs.position(speaker) {
+        return vec![(output, 1.0)];
+    }
+    let pair = |left: Speaker, right: Speaker, gain: f32| match (
+        outputs.position(left),
+        outputs.position(right),
+    ) {
+        (Some(left), Some(right)) => Some(vec![(left, gain), (right, gain)]),
+        _ => None,
+    };
+    let single = |alternative: Speaker, gain: f32| {
+        outputs
+            .position(alternative)
+            .map(|output| vec![(output, gain)])
+    };
+    let folded = match speaker {
+        FrontCenter => pair(
+            FrontLeft,
+            FrontRight,
+            if full_level { 1.0 } else { HALF_POWER },
+        ),
+        FrontLeftOfCenter => single(FrontLeft, 1.0),
+        FrontRightOfCenter => single(FrontRight, 1.0),
+        SideLeft => single(BackLeft, 1.0).or_else(|| single(FrontLeft, HALF_POWER)),
+        SideRight => single(BackRight, 1.0).or_else(|| single(FrontRight, HALF_POWER)),
+        BackLeft => single(SideLeft, 1.0).or_else(|| single(FrontLeft, HALF_POWER)),
+        BackRight => single(SideRight, 1.0).or_else(|| single(FrontRight, HALF_POWER)),
+        BackCenter => pair(BackLeft, BackRight, HALF_POWER)
+            .or_else(|| pair(SideLeft, SideRight, HALF_POWER))
+            .or_else(|| pair(FrontLeft, FrontRight, 0.5)),
+        // Also the low frequency channel, it is dropped.
+        _ => None,
+    };
+    folded.unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ChannelLayout, ChannelMatrix, Speaker, SpeakerSet, HALF_POWER};
+
+    const LAYOUTS: [ChannelLayout; 6] = [
+        ChannelLayout::Mono,
+        ChannelLayout::Stereo,
+        ChannelLayout::TwoPointOne,
+        ChannelLayout::Quad,
+        ChannelLayout::FivePointOne,
+        ChannelLayout::SevenPointOne,
+    ];
+
+    fn rows(matrix: &ChannelMatrix) -> Vec<Vec<f32>> {
+        (0..matrix.to_channels())
+            .map(|output| {
+                (0..matrix.from_channels())
+                    .map(|input| matrix.gain(input, output))
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn same_layout_is_identity() {
+        for layout in LAYOUTS {
+            assert!(ChannelMatrix::between_layouts(layout, layout).is_identity());
+        }
+    }
+
+    #[test]
+    fn every_channel_but_lfe_is_heard() {
+        for from in LAYOUTS {
+            for to in LAYOUTS {
+                let matrix = ChannelMatrix::between_layouts(from, to);
+                for (input, speaker) in from.speakers().unwrap().iter().enumerate() {
+                    let heard = (0..to.channel_count())
+                        .any(|output| matrix.gain(input as u16, output) > 0.0);
+                    let lfe = speaker == Speaker::LowFrequency;
+                    assert!(heard || lfe, "{from:?} -> {to:?}: {speaker:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn five_point_one_to_stereo() {
+        let matrix =
+            ChannelMatrix::between_layouts(ChannelLayout::FivePointOne, ChannelLayout::Stereo);
+        let h = HALF_POWER;
+        assert_eq!(
+            rows(&matrix),
+            [
+                vec![1.0, 0.0, h, 0.0, h, 0.0],
+                vec![0.0, 1.0, h, 0.0, 0.0, h]
+            ]
+        );
+    }
+
+    #[test]
+    fn mono_upmix() {
+        let matrix = ChannelMatrix::between_layouts(ChannelLayout::Mono, ChannelLayout::Quad);
+        assert_eq!(rows(&matrix), [[1.0], [1.0], [0.0], [0.0]]);
+        let matrix =
+            ChannelMatrix::between_layouts(ChannelLayout::Mono, ChannelLayout::FivePointOne);
+        assert_eq!(rows(&matrix), [[0.0], [0.0], [1.0], [0.0], [0.0], [0.0]]);
+    }
+
+    #[test]
+    fn stereo_to_mono() {
+        let matrix = ChannelMatrix::between_layouts(ChannelLayout::Stereo, ChannelLayout::Mono);
+        assert_eq!(rows(&matrix), [[0.5, 0.5]]);
+    }
+
+    #[test]
+    fn unknown_counts_keep_channel_order() {
+        let matrix = ChannelMatrix::for_channel_counts(5, 2);
+        assert_eq!(
+            rows(&matrix),
+            [[1.0, 0.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0, 0.0]]
+        );
+        let matrix = ChannelMatrix::for_channel_counts(1, 5);
+        assert_eq!(rows(&matrix), [[1.0], [1.0], [0.0], [0.0], [0.0]]);
+    }
+
+    #[test]
+    fn named_layouts_from_speakers() {
+        for layout in LAYOUTS {
+            assert_eq!(ChannelLayout::new(layout.speakers().unwrap()), layout);
+        }
+        let speakers: SpeakerSet = [Speaker::FrontLeft, Speaker::FrontCenter]
+            .into_iter()
+            .collect();
+        assert_eq!(speakers.position(Speaker::FrontCenter), Some(1));
+        assert_eq!(
+            ChannelLayout::new(speakers),
+            ChannelLayout::Speakers(speakers)
+        );
+    }
+
+    #[test]
+    fn discrete_channels_stay_in_order() {
+        let matrix =
+            ChannelMatrix::between_layouts(ChannelLayout::Discrete(4), ChannelLayout::Stereo);
+        assert_eq!(rows(&matrix), [[1.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0]]);
+    }
+}
@@ -1,16 +1,22 @@
+use super::{ChannelLayout, ChannelMatrix};
 use crate::common::ChannelCount;
 use crate::Sample;
 
 /// Iterator that converts from a certain channel count to another.
+///
+/// The channels are mixed with a [`ChannelMatrix`], by default the standard matrix for the
+/// two channel counts. An incomplete last frame of the input is dropped.
 #[derive(Clone, Debug)]
 pub struct ChannelCountConverter<I>
 where
     I: Iterator<Item = Sample>,
 {
     input: I,
-    from: ChannelCount,
-    to: ChannelCount,
-    sample_repeat: Option<Sample>,
+    matrix: ChannelMatrix,
+    /// `true` if the matrix does not change anything, samples are then passed through.
+    passthrough: bool,
+    input_frame: Vec<Sample>,
+    output_frame: Vec<Sample>,
     next_output_sample_pos: ChannelCount,
 }
 
@@ -18,7 +24,7 @@ impl<I> ChannelCountConverter<I>
 where
     I: Iterator<Item = Sample>,
 {
-    /// Initializes the iterator.
+    /// Initializes the iterator, using [`ChannelMatrix::for_channel_counts`].
     ///
     /// # Panic
     ///
@@ -29,11 +35,28 @@ where
         assert!(from >= 1);
         assert!(to >= 1);
 
+        Self::with_matrix(input, ChannelMatrix::for_channel_counts(from, to))
+    }
+
+    /// Initializes the iterator, using [`ChannelMatrix::between_layouts`].
+    #[inline]
+    pub fn with_layouts(
+        input: I,
+        from: ChannelLayout,
+        to: ChannelLayout,
+    ) -> ChannelCountConverter<I> {
+        Self::with_matrix(input, ChannelMatrix::between_layouts(from, to))
+    }
+
+    /// Initializes the iterator with a custom mixing matrix.
+    #[inline]
+    pub fn with_matrix(input: I, matrix: ChannelMatrix) -> ChannelCountConverter<I> {
         ChannelCountConverter {
             input,
-            from,
-            to,
-            sample_repeat: None,
+            passthrough: matrix.is_identity(),
+            input_frame: Vec::with_capacity(matrix.from_channels() as usize),
+            output_frame: Vec::with_capacity(matrix.to_channels() as usize),
+            matrix,
             next_output_sample_pos: 0,
         }
     }
@@ -44,6 +67,12 @@ where
         self.input
     }
 
+    /// Get immutable access to the iterator
+    #[inline]
+    pub fn inner(&self) -> &I {
+        &self.input
+    }
+
     /// Get mutable access to the iterator
     #[inline]
     pub fn inner_mut(&mut self) -> &mut I {
@@ -58,50 +87,46 @@ where
     type Item = I::Item;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let result = match self.next_output_sample_pos {
-            0 => {
-                // save first sample for mono -> stereo conversion
-                let value = self.input.next();
-                self.sample_repeat = value;
-                value
-            }
-            x if x < self.from => self.input.next(),
-            1 => self.sample_repeat,
-            _ => Some(0.0),
-        };
-
-        if result.is_some() {
-            self.next_output_sample_pos += 1;
+        if self.passthrough {
+            return self.input.next();
         }
 
-        if self.next_output_sample_pos == self.to {
-            self.next_output_sample_pos = 0;
-
-            if self.from > self.to {
-                for _ in self.to..self.from {
-                    self.input.next(); // discarding extra input
-                }
+        if self.next_output_sample_pos == 0 {
+            self.input_frame.clear();
+            self.input_frame.extend(
+                self.input
+                    .by_ref()
+                    .take(self.matrix.from_channels() as usize),
+            );
+            if self.input_frame.len() < self.matrix.from_channels() as usize {
+                return None;
             }
+            self.matrix.apply(&self.input_frame, &mut self.output_frame);
         }
 
-        result
+        let sample = self.output_frame[self.next_output_sample_pos as usize];
+        self.next_output_sample_pos += 1;
+        if self.next_output_sample_pos == self.matrix.to_channels() {
+            self.next_output_sample_pos = 0;
+        }
+        Some(sample)
     }
 
     #[inline]
     fn size_hint(&self) -> (usize, Option<usize>) {
         let (min, max) = self.input.size_hint();
+        if self.passthrough {
+            return (min, max);
+        }
 
-        let consumed = std::cmp::min(self.from, self.next_output_sample_pos) as usize;
-
-        let min = ((min + consumed) / self.from as usize * self.to as usize)
-            .saturating_sub(self.next_output_sample_pos as usize);
-
-        let max = max.map(|max| {
-            ((max + consumed) / self.from as usize * self.to as usize)
-                .saturating_sub(self.next_output_sample_pos as usize)
-        });
-
-        (min, max)
+        let from = self.matrix.from_channels() as usize;
+        let to = self.matrix.to_channels() as usize;
+        let pending = match self.next_output_sample_pos {
+            0 => 0,
+            pos => to - pos as usize,
+        };
+        let convert = |samples: usize| samples / from * to + pending;
+        (convert(min), max.map(convert))
     }
 }
 
@@ -111,6 +136,7 @@ impl<I> ExactSizeIterator for ChannelCountConverter<I> where I: ExactSizeIterato
 mod test {
     use super::ChannelCountConverter;
     use crate::common::ChannelCount;
+    use crate::conversions::{ChannelLayout, ChannelMatrix, Speaker};
     use crate::Sample;
 
     #[test]
@@ -121,7 +147,19 @@ mod test {
 
         let input = vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0];
         let output = ChannelCountConverter::new(input.into_iter(), 4, 1).collect::<Vec<_>>();
-        assert_eq!(output, [1.0, 5.0]);
+        // Quad to mono: left and right are averaged, the back channels count at half power.
+        let back = std::f32::consts::FRAC_1_SQRT_2 / 2.0;
+        assert_eq!(
+            output,
+            [
+                1.0 * 0.5 + 2.0 * 0.5 + 3.0 * back + 4.0 * back,
+                5.0 * 0.5 + 6.0 * 0.5 + 7.0 * back + 8.0 * back
+            ]
+        );
+
+        let input = vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0];
+        let output = ChannelCountConverter::new(input.into_iter(), 5, 2).collect::<Vec<_>>();
+        assert_eq!(output, [1.0, 2.0]);
     }
 
     #[test]
@@ -139,6 +177,41 @@ mod test {
         assert_eq!(output, [1.0, 2.0, 0.0, 0.0, 3.0, 4.0, 0.0, 0.0]);
     }
 
+    #[test]
+    fn downmix_surround() {
+        // Front left, front right, center, low frequency, side left, side right.
+        let input = vec![1.0, 2.0, 4.0, 8.0, 16.0, 32.0];
+        let output = ChannelCountConverter::new(input.into_iter(), 6, 2).collect::<Vec<_>>();
+        let h = std::f32::consts::FRAC_1_SQRT_2;
+        assert_eq!(output, [1.0 + 4.0 * h + 16.0 * h, 2.0 + 4.0 * h + 32.0 * h]);
+
+        let input = vec![1.0, 3.0];
+        let output = ChannelCountConverter::new(input.into_iter(), 2, 1).collect::<Vec<_>>();
+        assert_eq!(output, [2.0]);
+    }
+
+    #[test]
+    fn custom_matrix() {
+        // Swap left and right and send their sum to a third channel.
+        let matrix = ChannelMatrix::new(2, 3, vec![0.0, 1.0, 1.0, 0.0, 1.0, 1.0]);
+        let input = vec![1.0, 2.0, 3.0, 4.0];
+        let output =
+            ChannelCountConverter::with_matrix(input.into_iter(), matrix).collect::<Vec<_>>();
+        assert_eq!(output, [2.0, 1.0, 3.0, 4.0, 3.0, 7.0]);
+    }
+
+    #[test]
+    fn mix_by_layout() {
+        let speakers = [Speaker::FrontLeft, Speaker::FrontRight, Speaker::BackCenter];
+        let from = ChannelLayout::new(speakers.into_iter().collect());
+        let input = vec![1.0, 2.0, 4.0];
+        let output =
+            ChannelCountConverter::with_layouts(input.into_iter(), from, ChannelLayout::Quad)
+                .collect::<Vec<_>>();
+        let h = std::f32::consts::FRAC_1_SQRT_2;
+        assert_eq!(output, [1.0, 2.0, 4.0 * h, 4.0 * h]);
+    }
+
     #[test]
     fn size_hint() {
         fn test(input: &[Sample], from: ChannelCount, to: ChannelCount) {
@@ -2,12 +2,17 @@
 This module contains functions that convert from one PCM format to another.
 
 This includes conversion between sample formats, channels or sample rates.
+Channel conversions mix according to the speaker layout, see [`ChannelMatrix`].
 */
 
+pub use self::channel_layout::{ChannelLayout, ChannelMatrix, Speaker, SpeakerSet};
 pub use self::channels::ChannelCountConverter;
+pub use self::resampler::{ResampleQuality, Resampler};
 pub use self::sample::SampleTypeConverter;
 pub use self::sample_rate::SampleRateConverter;
 
+mod channel_layout;
 mod channels;
+mod resampler;
 mod sample;
 mod sample_rate;
@@ -0,0 +1,901 @@
+use crate::common::{ChannelCount, SampleRate};
+use crate::Sample;
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+
+/// Trade-off between resampling quality and CPU cost.
+///
+/// The sinc based presets band-limit the signal before changing its rate, so they do not
+/// introduce the aliasing and imaging distortions of plain interpolation
+/// (see [#584](https://github.com/RustAudio/rodio/issues/584)). Higher presets use longer
+/// filters, which gives a steeper cut-off close to the Nyquist frequency at the cost of
+/// more multiplications per sample.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ResampleQuality {
+    /// Linear interpolation between neighbouring samples. Cheapest, but not band-limited.
+    Linear,
+    /// Windowed-sinc filter with 8 zero crossings on each side.
+    Low,
+    /// Windowed-sinc filter with 16 zero crossings on each side.
+    #[default]
+    Medium,
+    /// Windowed-sinc filter with 32 zero crossings on each side.
+    High,
+}
+
+/// Pre-computed one-sided windowed-sinc kernel.
+#[derive(Debug)]
+struct SincTable {
+    /// Number of zero crossings on each side of the kernel center.
+    zero_crossings: usize,
+    /// Kernel values sampled `OVERSAMPLING` times per zero crossing, from 0 to `zero_crossings`.
+    values: Vec<f32>,
+}
+
+/// Resolution of the kernel table. Values in between are linearly interpolated.
+const OVERSAMPLING: usize = 256;
+
+impl SincTable {
+    fn new(zero_crossings: usize) -> SincTable {
+        let len = zero_crossings * OVERSAMPLING;
+        let values = (0..=len + 1)
+            .map(|i| {
+                let x = i as f64 / OVERSAMPLING as f64;
+                if x >= zero_crossings as f64 {
+                    return 0.0;
+                }
+                let sinc = if i == 0 {
+                    1.0
+                } else {
+                    (PI * x).sin() / (PI * x)
+                };
+                // Blackman window, centered at 0.
+                let t = x / zero_crossings as f64;
+                let window = 0.42 + 0.5 * (PI * t).cos() + 0.08 * (2.0 * PI * t).cos();
+                (sinc * window) as f32
+            })
+            .collect();
+        SincTable {
+            zero_crossings,
+            values,
+        }
+    }
+
+    /// Kernel value at `pos / OVERSAMPLING` zero crossings from the center.
+    #[inline]
+    fn value(&self, pos: f32) -> f32 {
+        let pos = pos.abs();
+        let index = pos as usize;
+        match self.values.get(index..index + 2) {
+            Some(&[a, b]) => a + (b - a) * (pos - index as f32),
+            _ => 0.0,
+        }
+    }
+}
+
+/// Number of fractional positions for which the filter weights are pre-computed. Weights for
+/// positions in between are linearly interpolated.
+const PHASES: usize = 256;
+
+/// Upper limit for the number of pre-computed filter weights. Longer filters, needed for large
+/// downsampling ratios, look up the kernel table for every weight instead.
+const MAX_PHASE_TABLE_LEN: usize = 1 << 16;
+
+/// Number of polyphase tables kept for reuse, see [`polyphase_table`].
+const KERNEL_CACHE_LEN: usize = 8;
+
+/// How the interpolation weights are obtained.
+#[derive(Clone, Debug)]
+enum Kernel {
+    /// Linear interpolation between two neighbouring frames.
+    Linear,
+    /// Filter weights for `PHASES + 1` evenly spaced fractional positions, each
+    /// `2 * half_width` long.
+    Polyphase(Arc<[f32]>),
+    /// Weights computed from the kernel table on the fly.
+    Direct {
+        table: &'static SincTable,
+        cutoff: f64,
+    },
+}
+
+/// Filter weights for the frames around a position `fraction` frames after the frame at
+/// index `half_width - 1`.
+fn sinc_weights(
+    table: &SincTable,
+    cutoff: f64,
+    half_width: usize,
+    fraction: f64,
+) -> impl Iterator<Item = f32> + '_ {
+    // Table position of the first frame. Each following frame is one input frame closer.
+    let scale = cutoff * OVERSAMPLING as f64;
+    let first = (half_width as f64 - 1.0 + fraction) * scale;
+    let gain = cutoff as f32;
+    (0..2 * half_width).map(move |k| table.value((first - k as f64 * scale) as f32) * gain)
+}
+
+impl ResampleQuality {
+    /// Kernel table shared by all resamplers of this quality, `None` for linear interpolation.
+    fn table(self) -> Option<&'static SincTable> {
+        static LOW: OnceLock<SincTable> = OnceLock::new();
+        static MEDIUM: OnceLock<SincTable> = OnceLock::new();
+        static HIGH: OnceLock<SincTable> = OnceLock::new();
+        match self {
+            ResampleQuality::Linear => None,
+            ResampleQuality::Low => Some(LOW.get_or_init(|| SincTable::new(8))),
+            ResampleQuality::Medium => Some(MEDIUM.get_or_init(|| SincTable::new(16))),
+            ResampleQuality::High => Some(HIGH.get_or_init(|| SincTable::new(32))),
+        }
+    }
+
+    /// Fraction of the Nyquist frequency of the slower of both rates that is kept intact.
+    fn bandwidth(self) -> f64 {
+        match self {
+            ResampleQuality::Linear => 1.0,
+            ResampleQuality::Low => 0.80,
+            ResampleQuality::Medium => 0.90,
+            ResampleQuality::High => 0.95,
+        }
+    }
+}
+
+/// Iterator that converts from a certain sample rate to another using band-limited
+/// (windowed-sinc) interpolation.
+///
+/// Unlike [`SampleRateConverter`](super::SampleRateConverter) this works for any pair of
+/// rates, including mutually prime ones, and preserves the duration of the input: `n` input
+/// frames always produce `ceil(n * to / from)` output frames.
+///
+/// The conversion ratio does not need to be a fraction of whole sample rates and can be
+/// changed while running with [`set_ratio`](Resampler::set_ratio), for example to change
+/// the pitch of a sound or to simulate the Doppler effect.
+#[derive(Clone, Debug)]
+pub struct Resampler<I>
+where
+    I: Iterator,
+{
+    /// The iterator that gives us samples.
+    input: I,
+    /// Number of channels in the stream.
+    channels: ChannelCount,
+    /// Used to rebuild the kernel when the ratio changes.
+    quality: ResampleQuality,
+    /// Source of the interpolation weights.
+    kernel: Kernel,
+    /// Cut-off frequency of `kernel`, relative to the input Nyquist frequency.
+    cutoff: f64,
+    /// Number of input frames used on each side of the interpolated position.
+    half_width: usize,
+    /// Number of frames kept before the current position, enough for the longest filter
+    /// allowed by `RATIO_HEADROOM`.
+    history: usize,
+    /// The kernel built for the initial ratio with its cut-off and half width, used again
+    /// when the ratio comes back to it.
+    initial_kernel: (Kernel, f64, usize),
+    /// Input frames advanced for each output frame (`from / to`).
+    step: f64,
+    /// Value `step` is moving towards after a call to `set_ratio`.
+    target_step: f64,
+    /// Change of `step` for each output frame while moving towards `target_step`.
+    step_delta: f64,
+    /// Number of output frames until `step` reaches `target_step`.
+    ramp_left: usize,
+    /// Index in `buffer` of the input frame from which on a new ratio applies, together
+    /// with that ratio, after a call to `switch_ratio`.
+    switch: Option<(usize, f64)>,
+    /// Input samples, one buffer per channel. The first `history` frames before the actual
+    /// input are silence so that the first output frame lines up with the first input frame.
+    buffer: Vec<Vec<Sample>>,
+    /// Index of the frame in `buffer` at or right before the current position.
+    position: usize,
+    /// Fractional part of the current position, between 0 and 1.
+    fraction: f64,
+    /// Distance in input frames between the previous and the current position.
+    last_advance: f64,
+    /// Index in `buffer` right after the last input frame, once the input has ended.
+    input_end: Option<usize>,
+    /// Number of actual samples in the last input frame if the input ended in the middle
+    /// of a frame, 0 otherwise.
+    partial_frame: usize,
+    /// Interpolation weights for the current output frame.
+    weights: Vec<f32>,
+    /// The frame currently being output.
+    output_frame: Vec<Sample>,
+    /// Next sample of `output_frame` to return.
+    output_pos: usize,
+}
+
+/// Number of output frames over which a new ratio is faded in, so that the pitch glides
+/// instead of jumping.
+const RATIO_RAMP_FRAMES: usize = 64;
+
+/// Relative change of the required cut-off frequency that causes the kernel to be rebuilt.
+const CUTOFF_TOLERANCE: f64 = 0.02;
+
+/// The buffers have room and history for the longer filter needed when the ratio grows by
+/// this factor, so that changing it while playing does not allocate. Past that the filter is
+/// cut short.
+const RATIO_HEADROOM: usize = 4;
+
+/// Minimum number of frames in `buffer` before old frames are discarded.
+const MIN_COMPACT_FRAMES: usize = 1024;
+
+impl<I> Resampler<I>
+where
+    I: Iterator<Item = Sample>,
+{
+    /// Create a new resampler using the default [`ResampleQuality`].
+    ///
+    /// # Panic
+    /// Panics if `from`, `to` or `num_channels` are 0.
+    #[inline]
+    pub fn new(
+        input: I,
+        from: SampleRate,
+        to: SampleRate,
+        num_channels: ChannelCount,
+    ) -> Resampler<I> {
+        Self::with_quality(input, from, to, num_channels, ResampleQuality::default())
+    }
+
+    /// Create a new resampler with the given quality.
+    ///
+    /// # Panic
+    /// Panics if `from`, `to` or `num_channels` are 0.
+    pub fn with_quality(
+        input: I,
+        from: SampleRate,
+        to: SampleRate,
+        num_channels: ChannelCount,
+        quality: ResampleQuality,
+    ) -> Resampler<I> {
+        assert!(from >= 1);
+        assert!(to >= 1);
+        Self::with_ratio(input, from as f64 / to as f64, num_channels, quality)
+    }
+
+    /// Create a new resampler that reads `ratio` input frames for every output frame, in
+    /// other words `ratio` is the input sample rate divided by the output sample rate.
+    ///
+    /// # Panic
+    /// Panics if `num_channels` is 0 or if `ratio` is not a positive finite number.
+    pub fn with_ratio(
+        input: I,
+        ratio: f64,
+        num_channels: ChannelCount,
+        quality: ResampleQuality,
+    ) -> Resampler<I> {
+        assert!(num_channels >= 1);
+        assert!(ratio > 0.0 && ratio.is_finite());
+
+        let cutoff = required_cutoff(quality, ratio);
+        let (kernel, half_width) = build_kernel(quality, cutoff);
+        let channels = num_channels as usize;
+        let history = RATIO_HEADROOM * half_width;
+        let capacity = MIN_COMPACT_FRAMES.max(4 * history) + 4 * history;
+        // Built one by one, cloning a `Vec` does not keep its capacity.
+        let buffer = (0..channels)
+            .map(|_| {
+                let mut channel = Vec::with_capacity(capacity);
+                channel.resize(history, 0.0);
+                channel
+            })
+            .collect();
+        Resampler {
+            input,
+            channels: num_channels,
+            quality,
+            initial_kernel: (kernel.clone(), cutoff, half_width),
+            kernel,
+            cutoff,
+            half_width,
+            history,
+            step: ratio,
+            target_step: ratio,
+            step_delta: 0.0,
+            ramp_left: 0,
+            switch: None,
+            buffer,
+            position: history,
+            fraction: 0.0,
+            last_advance: 0.0,
+            input_end: None,
+            partial_frame: 0,
+            weights: Vec::with_capacity(2 * history),
+            output_frame: Vec::with_capacity(channels),
+            output_pos: 0,
+        }
+    }
+
+    /// Returns the number of input frames read for every output frame that the resampler
+    /// is converging to.
+    #[inline]
+    pub fn ratio(&self) -> f64 {
+        self.target_step
+    }
+
+    /// Changes the number of input frames read for every output frame, that is the input
+    /// sample rate divided by the output sample rate.
+    ///
+    /// The new ratio is reached gradually over a few milliseconds of output, so changing it
+    /// while playing does not cause clicks.
+    ///
+    /// # Panic
+    /// Panics if `ratio` is not a positive finite number.
+    pub fn set_ratio(&mut self, ratio: f64) {
+        assert!(ratio > 0.0 && ratio.is_finite());
+        if ratio == self.target_step {
+            return;
+        }
+        self.target_step = ratio;
+        self.step_delta = (ratio - self.step) / RATIO_RAMP_FRAMES as f64;
+        self.ramp_left = RATIO_RAMP_FRAMES;
+        // The filter must be good enough for every ratio on the way to the new one. A lower
+        // cut-off than needed only takes away some treble until the ramp is done.
+        let cutoff = f64::min(
+            required_cutoff(self.quality, self.step),
+            required_cutoff(self.quality, ratio),
+        );
+        self.update_kernel(cutoff);
+    }
+
+    /// Changes the ratio at once, starting with the first of the `recent_frames` input frames
+    /// read last. The frames read before keep the previous ratio.
+    ///
+    /// Use this when the sample rate of the input changes, for example at a span boundary,
+    /// and [`set_ratio`](Resampler::set_ratio) for a gradual change. A switch that is still
+    /// waiting for its input frame is applied right away.
+    ///
+    /// # Panic
+    /// Panics if `ratio` is not a positive finite number.
+    pub fn switch_ratio(&mut self, ratio: f64, recent_frames: usize) {
+        assert!(ratio > 0.0 && ratio.is_finite());
+        if let Some((_, pending)) = self.switch.take() {
+            self.apply_ratio(pending);
+        }
+        let read = self.input_end.unwrap_or_else(|| self.buffered_frames());
+        let frame = read.saturating_sub(recent_frames) as f64;
+        self.switch = Some((frame as usize, ratio));
+
+        let position = self.position as f64 + self.fraction;
+        let previous = position - self.last_advance;
+        if previous <= frame && frame < position {
+            // The position has just moved past the switch at the previous ratio, which
+            // happens when copying frames as they are.
+            self.position = previous.floor() as usize;
+            self.fraction = previous - previous.floor();
+            self.advance();
+        }
+    }
+
+    /// Destroys this iterator and returns the underlying iterator.
+    #[inline]
+    pub fn into_inner(self) -> I {
+        self.input
+    }
+
+    /// Get immutable access to the iterator.
+    #[inline]
+    pub fn inner(&self) -> &I {
+        &self.input
+    }
+
+    /// Get mutable access to the iterator.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut I {
+        &mut self.input
+    }
+
+    /// Number of frames currently held in `buffer`.
+    #[inline]
+    fn buffered_frames(&self) -> usize {
+        self.buffer[0].len()
+    }
+
+    /// Changes the kernel if `cutoff` is too far away from the one it was built for.
+    ///
+    /// Only the initial kernel is pre-computed, the weights for other cut-offs are computed
+    /// on the fly so that changing the ratio while playing does not allocate.
+    fn update_kernel(&mut self, cutoff: f64) {
+        let close = |other: f64| (cutoff - other).abs() <= other * CUTOFF_TOLERANCE;
+        let Some(table) = self.quality.table() else {
+            return;
+        };
+        if close(self.cutoff) {
+            return;
+        }
+        let (initial_kernel, initial_cutoff, initial_half_width) = &self.initial_kernel;
+        let (kernel, cutoff, half_width) = if close(*initial_cutoff) {
+            (initial_kernel.clone(), *initial_cutoff, *initial_half_width)
+        } else {
+            let half_width = (table.zero_crossings as f64 / cutoff).ceil() as usize;
+            (
+                Kernel::Direct { table, cutoff },
+                cutoff,
+                half_width.min(self.history),
+            )
+        };
+        self.kernel = kernel;
+        self.cutoff = cutoff;
+        self.half_width = half_width;
+    }
+
+    /// Reads input until `buffer` holds the frame at index `last`. Frames past the end of the
+    /// input are filled with silence.
+    fn fill_buffer(&mut self, last: usize) {
+        while self.buffered_frames() <= last {
+            if self.input_end.is_some() {
+                self.buffer.iter_mut().for_each(|channel| channel.push(0.0));
+                continue;
+            }
+
+            let mut read = 0;
+            for channel in &mut self.buffer {
+                let Some(sample) = self.input.next() else {
+                    break;
+                };
+                channel.push(sample);
+                read += 1;
+            }
+            if read < self.buffer.len() {
+                // The input has ended, an incomplete last frame is padded with silence.
+                if read > 0 {
+                    self.buffer[read..]
+                        .iter_mut()
+                        .for_each(|channel| channel.push(0.0));
+                    self.partial_frame = read;
+                }
+                self.input_end = Some(self.buffered_frames());
+            }
+        }
+    }
+
+    /// Drops the frames that are no longer needed by the interpolation, keeping enough
+    /// history for a longer filter after a change of the ratio.
+    fn discard_old_frames(&mut self) {
+        let keep_from = self.position - self.history;
+        // Only compact once in a while, draining is linear in the buffer size.
+        if keep_from >= MIN_COMPACT_FRAMES.max(self.history * 4) {
+            for channel in &mut self.buffer {
+                channel.drain(..keep_from);
+            }
+            self.position -= keep_from;
+            if let Some(end) = self.input_end.as_mut() {
+                *end -= keep_from;
+            }
+            if let Some((frame, _)) = self.switch.as_mut() {
+                *frame = frame.saturating_sub(keep_from);
+            }
+        }
+    }
+
+    fn compute_weights(&mut self) {
+        self.weights.clear();
+        match &self.kernel {
+            Kernel::Linear => {
+                self.weights
+                    .extend([1.0 - self.fraction as f32, self.fraction as f32]);
+            }
+            Kernel::Polyphase(phases) => {
+                let len = 2 * self.half_width;
+                let pos = self.fraction * PHASES as f64;
+                let phase = (pos as usize).min(PHASES - 1);
+                let t = (pos - phase as f64) as f32;
+                let before = &phases[phase * len..(phase + 1) * len];
+                let after = &phases[(phase + 1) * len..(phase + 2) * len];
+                self.weights
+                    .extend(before.iter().zip(after).map(|(a, b)| a + (b - a) * t));
+            }
+            Kernel::Direct { table, cutoff } => {
+                self.weights
+                    .extend(sinc_weights(table, *cutoff, self.half_width, self.fraction));
+            }
+        }
+    }
+
+    /// Computes the next output frame into `output_frame`. Returns `false` once the input has
+    /// been fully consumed.
+    fn next_frame(&mut self) -> bool {
+        // At a 1:1 ratio the input frames are copied as they are. They still go through
+        // `buffer` so that the filter has the history it needs once the ratio changes.
+        let copy = self.step == 1.0 && self.fraction == 0.0;
+        let last_needed = if copy {
+            self.position
+        } else {
+            self.position + self.half_width
+        };
+        self.fill_buffer(last_needed);
+        if self.input_end.is_some_and(|end| self.position >= end) {
+            return false;
+        }
+
+        self.output_frame.clear();
+        if copy {
+            let position = self.position;
+            self.output_frame
+                .extend(self.buffer.iter().map(|channel| channel[position]));
+            if self.partial_frame > 0 && self.input_end == Some(position + 1) {
+                self.output_frame.truncate(self.partial_frame);
+            }
+        } else {
+            self.compute_weights();
+            let first = self.position + 1 - self.half_width;
+            let frames = first..first + self.weights.len();
+            self.output_frame.extend(self.buffer.iter().map(|channel| {
+                channel[frames.clone()]
+                    .iter()
+                    .zip(&self.weights)
+                    .map(|(sample, weight)| sample * weight)
+                    .sum::<Sample>()
+            }));
+        }
+        self.output_pos = 0;
+
+        self.advance();
+        self.advance_ramp();
+        self.discard_old_frames();
+        true
+    }
+
+    /// Moves to the position of the next output frame.
+    fn advance(&mut self) {
+        let mut next = self.fraction + self.step;
+        if let Some((frame, ratio)) = self.switch {
+            // Input frames between the current position and the switch.
+            let before = frame.saturating_sub(self.position) as f64 - self.fraction;
+            if self.step >= before {
+                // The part of this output frame after the switch reads at the new ratio.
+                let before = before.max(0.0);
+                next = self.fraction + before + (1.0 - before / self.step) * ratio;
+                self.switch = None;
+                self.apply_ratio(ratio);
+            }
+        }
+        self.last_advance = next - self.fraction;
+        let whole = next.floor();
+        self.position += whole as usize;
+        self.fraction = next - whole;
+    }
+
+    /// Sets the ratio without a transition.
+    fn apply_ratio(&mut self, ratio: f64) {
+        self.step = ratio;
+        self.target_step = ratio;
+        self.ramp_left = 0;
+        self.update_kernel(required_cutoff(self.quality, ratio));
+    }
+
+    /// Moves `step` one output frame closer to `target_step`.
+    #[inline]
+    fn advance_ramp(&mut self) {
+        if self.ramp_left == 0 {
+            return;
+        }
+        self.ramp_left -= 1;
+        if self.ramp_left > 0 {
+            self.step += self.step_delta;
+            return;
+        }
+        self.step = self.target_step;
+        self.update_kernel(required_cutoff(self.quality, self.step));
+    }
+}
+
+/// Cut-off frequency, relative to the input Nyquist frequency, needed when reading `step`
+/// input frames per output frame.
+fn required_cutoff(quality: ResampleQuality, step: f64) -> f64 {
+    // When downsampling the filter must also remove everything above the new Nyquist frequency.
+    quality.bandwidth() * f64::min(1.0, 1.0 / step)
+}
+
+/// Builds the kernel for the given quality and cut-off, returns it together with its
+/// half width.
+fn build_kernel(quality: ResampleQuality, cutoff: f64) -> (Kernel, usize) {
+    let Some(table) = quality.table() else {
+        return (Kernel::Linear, 1);
+    };
+    let half_width = (table.zero_crossings as f64 / cutoff).ceil() as usize;
+    let kernel = if 2 * half_width * (PHASES + 1) > MAX_PHASE_TABLE_LEN {
+        Kernel::Direct { table, cutoff }
+    } else {
+        Kernel::Polyphase(polyphase_table(quality, table, cutoff, half_width))
+    };
+    (kernel, half_width)
+}
+
+/// Returns the filter weights of all phases for the given quality and cut-off.
+///
+/// The tables of the last few ratios are shared like the kernel tables, so a converter that
+/// is built again for a new span or sound does not compute its table again.
+fn polyphase_table(
+    quality: ResampleQuality,
+    table: &SincTable,
+    cutoff: f64,
+    half_width: usize,
+) -> Arc<[f32]> {
+    type Cache = VecDeque<((ResampleQuality, u64), Arc<[f32]>)>;
+    static CACHE: Mutex<Cache> = Mutex::new(VecDeque::new());
+
+    let key = (quality, cutoff.to_bits());
+    let cached = CACHE
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .iter()
+        .find(|(other, _)| *other == key)
+        .map(|(_, phases)| phases.clone());
+    if let Some(phases) = cached {
+        return phases;
+    }
+
+    let phases: Arc<[f32]> = (0..=PHASES)
+        .flat_map(|phase| {
+            let fraction = phase as f64 / PHASES as f64;
+            sinc_weights(table, cutoff, half_width, fraction)
+        })
+        .collect();
+    let mut cache = CACHE.lock().unwrap_or_else(PoisonError::into_inner);
+    if cache.len() == KERNEL_CACHE_LEN {
+        cache.pop_front();
+    }
+    cache.push_back((key, phases.clone()));
+    phases
+}
+
+impl<I> Iterator for Resampler<I>
+where
+    I: Iterator<Item = Sample>,
+{
+    type Item = Sample;
+
+    #[inline]
+    fn next(&mut self) -> Option<Sample> {
+        if self.output_pos == self.output_frame.len() && !self.next_frame() {
+            return None;
+        }
+        let sample = self.output_frame[self.output_pos];
+        self.output_pos += 1;
+        Some(sample)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let channels = self.channels as usize;
+        let pending = self.output_frame.len() - self.output_pos;
+        let buffered = self.buffered_frames();
+        let apply = |remaining: usize| {
+            // Index in `buffer` right after the last input frame once everything is read.
+            let end = match self.input_end {
+                Some(end) => end,
+                None => buffered + remaining.div_ceil(channels),
+            };
+            let left = end as f64 - self.position as f64 - self.fraction;
+            let output_frames = (left.max(0.0) / self.target_step).ceil() as usize;
+            output_frames * channels + pending
+        };
+        let (min, max) = self.input.size_hint();
+        (
+            apply(min).saturating_sub(channels),
+            max.map(|max| apply(max) + channels),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Kernel, ResampleQuality, Resampler};
+    use crate::common::{ChannelCount, SampleRate};
+    use crate::source::{SineWave, Source};
+    use crate::Sample;
+    use quickcheck::{quickcheck, TestResult};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    const QUALITIES: [ResampleQuality; 4] = [
+        ResampleQuality::Linear,
+        ResampleQuality::Low,
+        ResampleQuality::Medium,
+        ResampleQuality::High,
+    ];
+
+    quickcheck! {
+        /// Check that resampling an empty input produces no output.
+        fn empty(from: u16, to: u16, channels: u8) -> TestResult {
+            if channels == 0 || channels > 128 || from == 0 || to == 0 {
+                return TestResult::discard();
+            }
+            if from as u32 > to as u32 * 64 {
+                return TestResult::discard();
+            }
+            let input: Vec<Sample> = Vec::new();
+            let output = Resampler::new(
+                input.into_iter(),
+                from as SampleRate,
+                to as SampleRate,
+                channels as ChannelCount,
+            )
+            .collect::<Vec<_>>();
+            TestResult::from_bool(output.is_empty())
+        }
+
+        /// Check that resampling to the same rate does not change the signal.
+        fn identity(from: u16, channels: u8, input: Vec<i16>) -> TestResult {
+            if channels == 0 || channels > 128 || from == 0 {
+                return TestResult::discard();
+            }
+            let input = Vec::from_iter(input.iter().map(|x| *x as Sample));
+            let output = Resampler::new(
+                input.clone().into_iter(),
+                from as SampleRate,
+                from as SampleRate,
+                channels as ChannelCount,
+            )
+            .collect::<Vec<_>>();
+            TestResult::from_bool(input == output)
+        }
+
+        /// Check that the number of output frames matches the duration of the input,
+        /// also for rates that are mutual primes.
+        fn preserve_durations(from: u16, to: u16, frames: u16, channels: u8) -> TestResult {
+            if channels == 0 || channels > 8 || from == 0 || to == 0 {
+                return TestResult::discard();
+            }
+            let (from, to) = (from as u64, to as u64);
+            if from > to * 64 || to > from * 64 {
+                return TestResult::discard();
+            }
+            let frames = frames as u64 % 2048;
+            let channels = channels as u64;
+            let input = vec![0.5; (frames * channels) as usize];
+            let output = Resampler::with_quality(
+                input.into_iter(),
+                from as SampleRate,
+                to as SampleRate,
+                channels as ChannelCount,
+                ResampleQuality::Low,
+            );
+            let count = output.count() as u64;
+            let expected = (frames * to).div_ceil(from) * channels;
+            TestResult::from_bool(count.abs_diff(expected) <= channels)
+        }
+    }
+
+    #[test]
+    fn linear_upsample() {
+        let input = vec![2.0, 16.0, 4.0, 18.0, 6.0, 20.0, 8.0, 22.0];
+        let output =
+            Resampler::with_quality(input.into_iter(), 2000, 4000, 2, ResampleQuality::Linear)
+                .collect::<Vec<_>>();
+        assert_eq!(
+            output,
+            [
+                2.0, 16.0, 3.0, 17.0, 4.0, 18.0, 5.0, 19.0, 6.0, 20.0, 7.0, 21.0, 8.0, 22.0, 4.0,
+                11.0
+            ]
+        );
+    }
+
+    #[test]
+    fn size_hint_is_close() {
+        for quality in QUALITIES {
+            let source = SineWave::new(440.0).take_duration(Duration::from_millis(100));
+            let from = source.sample_rate();
+            let samples = source.collect::<Vec<_>>();
+            let mut output = Resampler::with_quality(samples.into_iter(), from, 44100, 1, quality);
+            let mut remaining = output.clone().count();
+            loop {
+                let (min, max) = output.size_hint();
+                assert!(min <= remaining && remaining <= max.unwrap(), "{quality:?}");
+                if output.next().is_none() {
+                    break;
+                }
+                remaining -= 1;
+            }
+        }
+    }
+
+    /// A tone below the cut-off frequency keeps its amplitude.
+    #[test]
+    fn passes_tone() {
+        for quality in QUALITIES {
+            let source = SineWave::new(1000.0).take_duration(Duration::from_millis(200));
+            let from = source.sample_rate();
+            let output = Resampler::with_quality(source, from, 44100, 1, quality)
+                .skip(1000)
+                .take(5000)
+                .collect::<Vec<_>>();
+            let peak = output.iter().fold(0.0f32, |acc, x| acc.max(x.abs()));
+            assert!((peak - 1.0).abs() < 0.02, "{quality:?}: {peak}");
+        }
+    }
+
+    /// A tone above the Nyquist frequency of the target rate is removed when downsampling
+    /// instead of aliasing into the audible range.
+    #[test]
+    fn suppresses_aliasing() {
+        let source = SineWave::new(15000.0).take_duration(Duration::from_millis(200));
+        let from = source.sample_rate();
+        let output = Resampler::with_quality(source, from, 16000, 1, ResampleQuality::Medium)
+            .skip(500)
+            .take(2000)
+            .collect::<Vec<_>>();
+        let peak = output.iter().fold(0.0f32, |acc, x| acc.max(x.abs()));
+        assert!(peak < 0.01, "{peak}");
+    }
+
+    /// Large ratios use longer filters, they must keep the level of the signal as well.
+    #[test]
+    fn large_downsampling_ratio() {
+        let input = vec![0.5; 384_000];
+        let output =
+            Resampler::with_quality(input.into_iter(), 384_000, 8_000, 1, ResampleQuality::High)
+                .collect::<Vec<_>>();
+        assert_eq!(output.len(), 8_000);
+        for sample in &output[100..7_900] {
+            assert!((sample - 0.5).abs() < 0.001, "{sample}");
+        }
+    }
+
+    /// Changing the ratio while running glides to the new pitch without discontinuities.
+    #[test]
+    fn ratio_change_is_smooth() {
+        for quality in QUALITIES {
+            let source = SineWave::new(440.0).take_duration(Duration::from_millis(500));
+            let from = source.sample_rate();
+            let mut resampler = Resampler::with_quality(source, from, from, 1, quality);
+            let mut output = resampler.by_ref().take(10_000).collect::<Vec<_>>();
+            resampler.set_ratio(1.5);
+            assert_eq!(resampler.ratio(), 1.5);
+            output.extend(resampler.by_ref().take(4_000));
+            resampler.set_ratio(0.75);
+            output.extend(resampler);
+
+            // A 660 Hz sine at 48 kHz changes by at most about 0.086 between samples.
+            for (n, pair) in output.windows(2).enumerate() {
+                let diff = (pair[1] - pair[0]).abs();
+                assert!(diff < 0.1, "{quality:?}: jump of {diff} at {n}");
+            }
+        }
+    }
+
+    /// Resamplers for the same ratio share their filter weights.
+    #[test]
+    fn kernels_are_shared() {
+        let build =
+            |from| Resampler::with_quality([].into_iter(), from, 48_000, 1, ResampleQuality::High);
+        let (first, second) = (build(44_100), build(44_100));
+        match (&first.kernel, &second.kernel) {
+            (Kernel::Polyphase(a), Kernel::Polyphase(b)) => assert!(Arc::ptr_eq(a, b)),
+            kernels => panic!("{kernels:?}"),
+        }
+    }
+
+    /// Raising the ratio late in the input uses real history for the longer filter, so the
+    /// output matches a resampler that had the final ratio from the start.
+    #[test]
+    fn ratio_raise_keeps_history() {
+        const FRAMES: usize = 6_000;
+        let input: Vec<Sample> = (0..FRAMES + 6_000)
+            .map(|n| {
+                let t = n as f32 / 48_000.0;
+                0.4 * (t * 440.0 * std::f32::consts::TAU).sin()
+                    + 0.2 * (t * 5_000.0 * std::f32::consts::TAU).sin()
+            })
+            .collect();
+        for quality in QUALITIES {
+            let mut raised = Resampler::with_ratio(input.clone().into_iter(), 1.0, 1, quality);
+            let before = raised.by_ref().take(FRAMES).count();
+            assert_eq!(before, FRAMES);
+            raised.switch_ratio(3.0, 0);
+            // The frame at the switch is still copied, the ones after it read at the new ratio.
+            let raised: Vec<Sample> = raised.skip(1).take(1_000).collect();
+
+            let direct = Resampler::with_ratio(input.clone().into_iter(), 3.0, 1, quality);
+            let direct: Vec<Sample> = direct.skip(FRAMES / 3 + 1).take(1_000).collect();
+
+            for (n, (a, b)) in raised.iter().zip(&direct).enumerate() {
+                assert!((a - b).abs() < 1e-4, "{quality:?}: {a} != {b} at {n}");
+            }
+        }
+    }
+}
@@ -39,6 +39,7 @@ where
     /// The converter uses simple linear interpolation for up-sampling
     /// and discards samples for down-sampling. This may introduce audible
     /// distortions in some cases (see [#584](https://github.com/RustAudio/rodio/issues/584)).
+    /// Use [`Resampler`](super::Resampler) for band-limited conversion.
     ///
     /// # Limitations
     /// Some rate conversions where target rate is high and rates are mutual primes the sample
@@ -352,7 +353,7 @@ mod test {
             let duration =
                 Duration::from_secs_f32(resampled.count() as f32 / to as f32);
 
-            let delta = if d < duration { duration - d } else { d - duration };
+            let delta = duration.abs_diff(d);
             TestResult::from_bool(delta < Duration::from_millis(1))
         }
     }
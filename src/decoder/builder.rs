@@ -0,0 +1,337 @@
+use std::io::{Read, Seek, SeekFrom};
+
+#[cfg(all(feature = "flac", not(feature = "symphonia-flac")))]
+use super::flac;
+#[cfg(all(feature = "minimp3", not(feature = "symphonia-mp3")))]
+use super::mp3;
+#[cfg(all(feature = "vorbis", not(feature = "symphonia-vorbis")))]
+use super::vorbis;
+#[cfg(all(feature = "wav", not(feature = "symphonia-wav")))]
+use super::wav;
+#[cfg(feature = "symphonia")]
+use super::ReadSeekSource;
+#[allow(unused_imports)]
+use super::{Decoder, DecoderError, DecoderImpl, ReadOnlySource};
+
+/// Library that decodes the data, see [`DecoderBuilder::with_backend`].
+///
+/// Each one is only available with its feature. The native backends are left out when
+/// Symphonia is enabled for the same format.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DecoderBackend {
+    /// WAV with `hound`, feature "wav".
+    Hound,
+    /// FLAC with `claxon`, feature "flac".
+    Claxon,
+    /// Ogg/Vorbis with `lewton`, feature "vorbis".
+    Lewton,
+    /// MP3 with `minimp3`, feature "minimp3".
+    Minimp3,
+    /// All formats of the enabled "symphonia-*" features.
+    Symphonia,
+}
+
+/// How [`Source::try_seek`](crate::Source::try_seek) moves to the requested position.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum SeekMode {
+    /// Moves exactly to the position, by decoding from the seek point before it.
+    #[default]
+    Accurate,
+    /// Moves to the seek point of the format that is nearest to the position, which is
+    /// faster but can be somewhat before or after it.
+    Coarse,
+}
+
+/// Options that are passed on to the backends.
+#[derive(Clone, Debug)]
+pub(crate) struct DecoderSettings {
+    pub(crate) hint: Option<String>,
+    pub(crate) mime_type: Option<String>,
+    pub(crate) byte_len: Option<u64>,
+    pub(crate) gapless: bool,
+    pub(crate) seek_mode: SeekMode,
+    pub(crate) track: Option<usize>,
+}
+
+impl Default for DecoderSettings {
+    fn default() -> Self {
+        Self {
+            hint: None,
+            mime_type: None,
+            byte_len: None,
+            gapless: true,
+            seek_mode: SeekMode::default(),
+            track: None,
+        }
+    }
+}
+
+impl DecoderSettings {
+    /// The file extension of the hint, or else of the MIME type.
+    pub(crate) fn extension(&self) -> Option<&str> {
+        self.hint
+            .as_deref()
+            .or_else(|| self.mime_type.as_deref().and_then(extension_for_mime_type))
+    }
+
+    /// The backend for the format of the hint, if it is a native one.
+    fn hinted_backend(&self) -> Option<DecoderBackend> {
+        match self.extension()? {
+            #[cfg(all(feature = "wav", not(feature = "symphonia-wav")))]
+            "wav" | "wave" => Some(DecoderBackend::Hound),
+            #[cfg(all(feature = "flac", not(feature = "symphonia-flac")))]
+            "flac" => Some(DecoderBackend::Claxon),
+            #[cfg(all(feature = "vorbis", not(feature = "symphonia-vorbis")))]
+            "ogg" | "oga" => Some(DecoderBackend::Lewton),
+            #[cfg(all(feature = "minimp3", not(feature = "symphonia-mp3")))]
+            "mp3" => Some(DecoderBackend::Minimp3),
+            _ => None,
+        }
+    }
+}
+
+/// File extension for a MIME type like `audio/mpeg`.
+fn extension_for_mime_type(mime_type: &str) -> Option<&'static str> {
+    let extension = match mime_type {
+        "audio/mpeg" | "audio/mp3" | "audio/mpeg3" => "mp3",
+        "audio/ogg" | "application/ogg" | "audio/vorbis" => "ogg",
+        "audio/aac" | "audio/aacp" | "audio/x-aac" => "aac",
+        "audio/flac" | "audio/x-flac" => "flac",
+        "audio/wav" | "audio/wave" | "audio/x-wav" | "audio/vnd.wave" => "wav",
+        "audio/mp4" | "audio/m4a" | "audio/x-m4a" => "m4a",
+        "audio/aiff" | "audio/x-aiff" => "aiff",
+        _ => return None,
+    };
+    Some(extension)
+}
+
+/// Builds a [`Decoder`] with information about the data that can not be detected, or only
+/// by reading it twice, and with decoding options.
+///
+/// # Example
+///
+/// ```no_run
+/// use rodio::decoder::{DecoderBuilder, SeekMode};
+///
+/// // Anything that implements `Read`, like a pipe or the body of an HTTP response.
+/// let stream = std::io::stdin();
+/// let decoder = DecoderBuilder::new()
+///     .with_mime_type("audio/mpeg")
+///     .with_seek_mode(SeekMode::Coarse)
+///     .build_unseekable(stream)
+///     .unwrap();
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct DecoderBuilder {
+    settings: DecoderSettings,
+    backend: Option<DecoderBackend>,
+}
+
+impl DecoderBuilder {
+    /// Creates a builder that detects the format of the data.
+    pub fn new() -> DecoderBuilder {
+        DecoderBuilder::default()
+    }
+
+    /// Sets the format of the data by its usual file extension, like `"wav"`, `"flac"`,
+    /// `"ogg"` or `"mp3"`.
+    ///
+    /// The backend for that format is tried first. Data that can not seek is only given to
+    /// that backend.
+    pub fn with_hint(mut self, extension: &str) -> DecoderBuilder {
+        self.settings.hint = Some(extension.to_lowercase());
+        self
+    }
+
+    /// Sets the format of the data by its MIME type, like the `Content-Type` of an HTTP
+    /// response. Parameters like `; charset=...` are ignored.
+    ///
+    /// Works like [`with_hint`](DecoderBuilder::with_hint), which takes precedence.
+    pub fn with_mime_type(mut self, mime_type: &str) -> DecoderBuilder {
+        let essence = mime_type.split(';').next().unwrap_or_default().trim();
+        self.settings.mime_type = Some(essence.to_lowercase());
+        self
+    }
+
+    /// Sets the length of the data in bytes, like the `Content-Length` of an HTTP response.
+    ///
+    /// Some Symphonia backends use it to find the total duration and for
+    /// [`SeekMode::Coarse`]. [`build`](DecoderBuilder::build) finds it by seeking to the end
+    /// of the data if it is not set.
+    pub fn with_byte_len(mut self, byte_len: u64) -> DecoderBuilder {
+        self.settings.byte_len = Some(byte_len);
+        self
+    }
+
+    /// Sets whether the encoder delay and padding at the start and end of MP3, AAC and Ogg
+    /// Vorbis files are removed, so that consecutive tracks play without a gap. The default is
+    /// `true`.
+    ///
+    /// The lewton Vorbis backend always removes the padding at the end, which its stream
+    /// reader does itself, so `false` only keeps the delay at the start.
+    pub fn with_gapless(mut self, gapless: bool) -> DecoderBuilder {
+        self.settings.gapless = gapless;
+        self
+    }
+
+    /// Sets how exactly seeking moves to the requested position. The default is
+    /// [`SeekMode::Accurate`].
+    ///
+    /// Backends that can seek exactly without extra work, like WAV and FLAC, ignore it.
+    pub fn with_seek_mode(mut self, seek_mode: SeekMode) -> DecoderBuilder {
+        self.settings.seek_mode = seek_mode;
+        self
+    }
+
+    /// Decodes the track at `index` in the container, counted from 0, instead of the first
+    /// track that can be decoded.
+    ///
+    /// Only Symphonia reads containers with several tracks, the other backends are skipped
+    /// for an index other than 0. Building fails if there is no such track.
+    pub fn with_track(mut self, index: usize) -> DecoderBuilder {
+        self.settings.track = Some(index);
+        self
+    }
+
+    /// Tries `backend` first, before the backend of the hint and format detection.
+    ///
+    /// Symphonia can not hand the data back if it fails, the other backends are not tried
+    /// after it. A backend that is not enabled is ignored.
+    pub fn with_backend(mut self, backend: DecoderBackend) -> DecoderBuilder {
+        self.backend = Some(backend);
+        self
+    }
+
+    /// Builds a decoder for data that can seek, like a file.
+    pub fn build<R>(mut self, mut data: R) -> Result<Decoder<R>, DecoderError>
+    where
+        R: Read + Seek + Send + Sync + 'static,
+    {
+        if self.settings.byte_len.is_none() {
+            self.settings.byte_len = byte_len(&mut data);
+        }
+        if self.settings.track.is_some_and(|track| track > 0) {
+            return self.build_symphonia(data);
+        }
+        if cfg!(feature = "symphonia") && self.backend == Some(DecoderBackend::Symphonia) {
+            return self.build_symphonia(data);
+        }
+        let hinted = self.settings.hinted_backend();
+        for backend in [self.backend, hinted].into_iter().flatten() {
+            match self.probe(backend, data) {
+                Ok(decoder) => return Ok(decoder),
+                Err(returned) => data = returned,
+            }
+        }
+        // The hint may have been wrong.
+        Decoder::detect(data, &self.settings)
+    }
+
+    /// Builds a decoder for data that can only be read once from front to back, like a
+    /// pipe, a socket or the body of an HTTP response.
+    ///
+    /// Without a hint or backend only Symphonia can detect the format, the other backends
+    /// need to seek back for that. The data is only given to one backend: the one set with
+    /// [`with_backend`](DecoderBuilder::with_backend), else the one of the hint, else
+    /// Symphonia. Seeking the decoder fails with
+    /// [`SeekError::NotSupported`](crate::source::SeekError::NotSupported). The MP3 backend
+    /// without Symphonia does not remove the encoder delay and padding of such data.
+    #[allow(unused_variables)]
+    pub fn build_unseekable<R>(self, data: R) -> Result<Decoder<ReadOnlySource<R>>, DecoderError>
+    where
+        R: Read + Send + Sync + 'static,
+    {
+        let data = ReadOnlySource::new(data, self.settings.byte_len);
+        let backend = match self.settings.track {
+            Some(track) if track > 0 => Some(DecoderBackend::Symphonia),
+            _ => self.backend.or_else(|| self.settings.hinted_backend()),
+        };
+        let decoder = match backend {
+            #[cfg(all(feature = "wav", not(feature = "symphonia-wav")))]
+            Some(DecoderBackend::Hound) => wav::WavDecoder::new_unprobed(data)
+                .map(|decoder| Decoder::from_impl(DecoderImpl::Wav(decoder)))
+                .ok_or(DecoderError::UnrecognizedFormat),
+            #[cfg(all(feature = "flac", not(feature = "symphonia-flac")))]
+            Some(DecoderBackend::Claxon) => flac::FlacDecoder::new_unprobed(data)
+                .map(|decoder| Decoder::from_impl(DecoderImpl::Flac(decoder)))
+                .ok_or(DecoderError::UnrecognizedFormat),
+            #[cfg(all(feature = "vorbis", not(feature = "symphonia-vorbis")))]
+            Some(DecoderBackend::Lewton) => {
+                vorbis::VorbisDecoder::new_unprobed(data, &self.settings)
+                    .map(|decoder| Decoder::from_impl(DecoderImpl::Vorbis(decoder)))
+                    .ok_or(DecoderError::UnrecognizedFormat)
+            }
+            #[cfg(all(feature = "minimp3", not(feature = "symphonia-mp3")))]
+            Some(DecoderBackend::Minimp3) => mp3::Mp3Decoder::new_unprobed(data)
+                .map(|decoder| Decoder::from_impl(DecoderImpl::Mp3(decoder)))
+                .ok_or(DecoderError::UnrecognizedFormat),
+            #[cfg(feature = "symphonia")]
+            _ => Decoder::from_media_source(Box::new(data), &self.settings),
+            #[cfg(not(feature = "symphonia"))]
+            _ => Err(DecoderError::UnrecognizedFormat),
+        };
+        let mut decoder: Decoder<ReadOnlySource<R>> = decoder?;
+        decoder.seekable = false;
+        Ok(decoder)
+    }
+
+    /// Tries a native backend, returns the data if it can not decode it.
+    fn probe<R>(&self, backend: DecoderBackend, data: R) -> Result<Decoder<R>, R>
+    where
+        R: Read + Seek + Send + Sync + 'static,
+    {
+        match backend {
+            #[cfg(all(feature = "wav", not(feature = "symphonia-wav")))]
+            DecoderBackend::Hound => wav::WavDecoder::new(data)
+                .map(|decoder| Decoder::from_impl(DecoderImpl::Wav(decoder))),
+            #[cfg(all(feature = "flac", not(feature = "symphonia-flac")))]
+            DecoderBackend::Claxon => flac::FlacDecoder::new(data)
+                .map(|decoder| Decoder::from_impl(DecoderImpl::Flac(decoder))),
+            #[cfg(all(feature = "vorbis", not(feature = "symphonia-vorbis")))]
+            DecoderBackend::Lewton => vorbis::VorbisDecoder::new(data, &self.settings)
+                .map(|decoder| Decoder::from_impl(DecoderImpl::Vorbis(decoder))),
+            #[cfg(all(feature = "minimp3", not(feature = "symphonia-mp3")))]
+            DecoderBackend::Minimp3 => mp3::Mp3Decoder::new(data, &self.settings)
+                .map(|decoder| Decoder::from_impl(DecoderImpl::Mp3(decoder))),
+            _ => Err(data),
+        }
+    }
+
+    #[allow(unused_variables)]
+    fn build_symphonia<R>(self, data: R) -> Result<Decoder<R>, DecoderError>
+    where
+        R: Read + Seek + Send + Sync + 'static,
+    {
+        #[cfg(feature = "symphonia")]
+        {
+            let source = ReadSeekSource::new(data, self.settings.byte_len);
+            Decoder::from_media_source(Box::new(source), &self.settings)
+        }
+        #[cfg(not(feature = "symphonia"))]
+        Err(DecoderError::UnrecognizedFormat)
+    }
+}
+
+/// Length of the data, found by seeking to the end and back.
+fn byte_len<R: Seek>(data: &mut R) -> Option<u64> {
+    let position = data.stream_position().ok()?;
+    let end = data.seek(SeekFrom::End(0)).ok()?;
+    data.seek(SeekFrom::Start(position)).ok()?;
+    Some(end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{extension_for_mime_type, DecoderBuilder};
+
+    #[test]
+    fn extension_from_mime_type() {
+        assert_eq!(extension_for_mime_type("audio/mpeg"), Some("mp3"));
+        assert_eq!(extension_for_mime_type("text/html"), None);
+
+        let builder = DecoderBuilder::new().with_mime_type("Application/Ogg; codecs=vorbis");
+        assert_eq!(builder.settings.extension(), Some("ogg"));
+        let builder = builder.with_hint("OGA");
+        assert_eq!(builder.settings.extension(), Some("oga"));
+    }
+}
@@ -6,6 +6,8 @@ use crate::source::SeekError;
 use crate::Source;
 
 use crate::common::{ChannelCount, Sample, SampleRate};
+use crate::conversions::ChannelLayout;
+use crate::decoder::metadata::Metadata;
 
 use claxon::FlacReader;
 use dasp_sample::Sample as _;
@@ -24,6 +26,7 @@ where
     sample_rate: SampleRate,
     channels: ChannelCount,
     total_duration: Option<Duration>,
+    metadata: Metadata,
 }
 
 impl<R> FlacDecoder<R>
@@ -36,7 +39,13 @@ where
             return Err(data);
         }
 
-        let reader = FlacReader::new(data).expect("should still be flac");
+        Ok(Self::new_unprobed(data).expect("should still be flac"))
+    }
+
+    /// Decodes the data as FLAC without checking the format first, which would need to seek
+    /// back.
+    pub fn new_unprobed(data: R) -> Option<FlacDecoder<R>> {
+        let reader = FlacReader::new(data).ok()?;
 
         let spec = reader.streaminfo();
         let sample_rate = spec.sample_rate;
@@ -52,7 +61,9 @@ where
             Duration::new(secs, nanos as u32)
         });
 
-        Ok(FlacDecoder {
+        let metadata = Metadata::from_vorbis_comments(reader.tags());
+
+        Some(FlacDecoder {
             reader,
             current_block: Vec::with_capacity(
                 spec.max_block_size as usize * spec.channels as usize,
@@ -63,9 +74,15 @@ where
             sample_rate,
             channels: spec.channels as ChannelCount,
             total_duration,
+            metadata,
         })
     }
 
+    #[inline]
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
     #[inline]
     pub fn into_inner(self) -> R {
         self.reader.into_inner()
@@ -96,6 +113,11 @@ where
         self.total_duration
     }
 
+    #[inline]
+    fn channel_layout(&self) -> Option<ChannelLayout> {
+        super::xiph_channel_layout(self.channels)
+    }
+
     #[inline]
     fn try_seek(&mut self, _: Duration) -> Result<(), SeekError> {
         Err(SeekError::NotSupported {
@@ -0,0 +1,318 @@
+//! Tags and pictures embedded in audio files.
+
+use std::fmt;
+
+use super::ReplayGain;
+
+/// Metadata of a decoded stream: tags like the title and artist, and attached pictures such
+/// as cover art.
+///
+/// Obtained with [`Decoder::metadata`](super::Decoder::metadata).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Metadata {
+    tags: Vec<Tag>,
+    pictures: Vec<Picture>,
+}
+
+/// A key-value pair of metadata.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Tag {
+    /// The key as it is stored in the file, for example `TITLE` in a Vorbis comment or
+    /// `TIT2` in an ID3v2 tag.
+    pub key: String,
+    /// The value, converted to text.
+    pub value: String,
+    /// The meaning of the key, if it is one of the common ones.
+    pub standard_key: Option<StandardTagKey>,
+}
+
+/// Commonly used tag keys, independent of the tag format.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum StandardTagKey {
+    /// Title of the track.
+    TrackTitle,
+    /// Artist of the track.
+    Artist,
+    /// Title of the album.
+    Album,
+    /// Artist of the album, if it differs from the track artists.
+    AlbumArtist,
+    /// Position of the track on its disc, possibly followed by `/` and the total.
+    TrackNumber,
+    /// Number of tracks on the disc.
+    TrackTotal,
+    /// Number of the disc in a set, possibly followed by `/` and the total.
+    DiscNumber,
+    /// Number of discs in the set.
+    DiscTotal,
+    /// Date of the recording or release.
+    Date,
+    /// Genre.
+    Genre,
+    /// Composer.
+    Composer,
+    /// Free-form comment.
+    Comment,
+    /// ReplayGain adjustment of the track, like `-6.5 dB`.
+    ReplayGainTrackGain,
+    /// ReplayGain peak of the track, relative to full scale.
+    ReplayGainTrackPeak,
+    /// ReplayGain adjustment of the album, like `-6.5 dB`.
+    ReplayGainAlbumGain,
+    /// ReplayGain peak of the album, relative to full scale.
+    ReplayGainAlbumPeak,
+    /// EBU R128 adjustment of the track in Q7.8 fixed point dB, as used by Opus.
+    R128TrackGain,
+    /// EBU R128 adjustment of the album in Q7.8 fixed point dB, as used by Opus.
+    R128AlbumGain,
+}
+
+/// An attached picture.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Picture {
+    /// Media type (MIME type) of the data, for example `image/jpeg`.
+    pub media_type: String,
+    /// What the picture shows, if known.
+    pub kind: Option<PictureKind>,
+    /// The encoded picture.
+    pub data: Box<[u8]>,
+}
+
+/// What a [`Picture`] shows.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum PictureKind {
+    /// Front cover of the album.
+    FrontCover,
+    /// Back cover of the album.
+    BackCover,
+    /// The artist or performer.
+    Artist,
+    /// Anything else.
+    Other,
+}
+
+impl Metadata {
+    /// Metadata without tags or pictures.
+    pub const fn new() -> Metadata {
+        Metadata {
+            tags: Vec::new(),
+            pictures: Vec::new(),
+        }
+    }
+
+    /// Metadata with the given tags and pictures.
+    pub fn with_tags(tags: Vec<Tag>, pictures: Vec<Picture>) -> Metadata {
+        Metadata { tags, pictures }
+    }
+
+    /// Metadata from Vorbis comments, the tag format of Ogg and FLAC files.
+    pub fn from_vorbis_comments<K, V>(comments: impl IntoIterator<Item = (K, V)>) -> Metadata
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        let tags = comments
+            .into_iter()
+            .map(|(key, value)| Tag::new(key.into(), value.into()))
+            .collect();
+        Metadata::with_tags(tags, Vec::new())
+    }
+
+    /// All tags, in the order in which they appear in the stream.
+    #[inline]
+    pub fn tags(&self) -> &[Tag] {
+        &self.tags
+    }
+
+    /// All attached pictures.
+    #[inline]
+    pub fn pictures(&self) -> &[Picture] {
+        &self.pictures
+    }
+
+    /// `true` if there are neither tags nor pictures.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty() && self.pictures.is_empty()
+    }
+
+    /// Value of the first tag with the given meaning.
+    pub fn get(&self, key: StandardTagKey) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|tag| tag.standard_key == Some(key))
+            .map(|tag| tag.value.as_str())
+    }
+
+    /// Title of the track.
+    #[inline]
+    pub fn title(&self) -> Option<&str> {
+        self.get(StandardTagKey::TrackTitle)
+    }
+
+    /// Artist of the track.
+    #[inline]
+    pub fn artist(&self) -> Option<&str> {
+        self.get(StandardTagKey::Artist)
+    }
+
+    /// Title of the album.
+    #[inline]
+    pub fn album(&self) -> Option<&str> {
+        self.get(StandardTagKey::Album)
+    }
+
+    /// Position of the track on its disc. Accepts the `3/12` notation.
+    pub fn track_number(&self) -> Option<u32> {
+        let value = self.get(StandardTagKey::TrackNumber)?;
+        let number = value.split('/').next().unwrap_or(value);
+        number.trim().parse().ok()
+    }
+
+    /// The ReplayGain or R128 loudness information in the tags.
+    #[inline]
+    pub fn replay_gain(&self) -> ReplayGain {
+        ReplayGain::from_metadata(self)
+    }
+
+    /// The front cover, or if there is none, the first picture of unknown kind.
+    pub fn cover(&self) -> Option<&Picture> {
+        self.pictures
+            .iter()
+            .find(|picture| picture.kind == Some(PictureKind::FrontCover))
+            .or_else(|| self.pictures.iter().find(|picture| picture.kind.is_none()))
+    }
+}
+
+impl Tag {
+    /// Tag with a standard key recognized from its name, see
+    /// [`StandardTagKey::from_name`].
+    pub fn new(key: String, value: String) -> Tag {
+        Tag {
+            standard_key: StandardTagKey::from_name(&key),
+            key,
+            value,
+        }
+    }
+}
+
+impl StandardTagKey {
+    /// Recognizes a key by its Vorbis comment name, ignoring case. Also accepts common
+    /// alternative spellings.
+    pub fn from_name(name: &str) -> Option<StandardTagKey> {
+        use StandardTagKey::*;
+        let key = match name.to_ascii_uppercase().as_str() {
+            "TITLE" => TrackTitle,
+            "ARTIST" => Artist,
+            "ALBUM" => Album,
+            "ALBUMARTIST" | "ALBUM ARTIST" | "ALBUM_ARTIST" => AlbumArtist,
+            "TRACKNUMBER" => TrackNumber,
+            "TRACKTOTAL" | "TOTALTRACKS" => TrackTotal,
+            "DISCNUMBER" => DiscNumber,
+            "DISCTOTAL" | "TOTALDISCS" => DiscTotal,
+            "DATE" | "YEAR" => Date,
+            "GENRE" => Genre,
+            "COMPOSER" => Composer,
+            "COMMENT" | "DESCRIPTION" => Comment,
+            "REPLAYGAIN_TRACK_GAIN" => ReplayGainTrackGain,
+            "REPLAYGAIN_TRACK_PEAK" => ReplayGainTrackPeak,
+            "REPLAYGAIN_ALBUM_GAIN" => ReplayGainAlbumGain,
+            "REPLAYGAIN_ALBUM_PEAK" => ReplayGainAlbumPeak,
+            "R128_TRACK_GAIN" => R128TrackGain,
+            "R128_ALBUM_GAIN" => R128AlbumGain,
+            _ => return None,
+        };
+        Some(key)
+    }
+}
+
+impl fmt::Debug for Picture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Picture")
+            .field("media_type", &self.media_type)
+            .field("kind", &self.kind)
+            .field("data", &format_args!("<{} bytes>", self.data.len()))
+            .finish()
+    }
+}
+
+/// Called with the new metadata when it changes while decoding.
+pub(crate) type MetadataCallback = Box<dyn FnMut(&Metadata) + Send + Sync>;
+
+/// Metadata of a decoder and whoever wants to know when it changes.
+#[cfg(any(
+    feature = "symphonia",
+    all(feature = "vorbis", not(feature = "symphonia-vorbis"))
+))]
+#[derive(Default)]
+pub(crate) struct MetadataState {
+    current: Metadata,
+    /// Incremented on every change.
+    revision: u64,
+    callback: Option<MetadataCallback>,
+}
+
+#[cfg(any(
+    feature = "symphonia",
+    all(feature = "vorbis", not(feature = "symphonia-vorbis"))
+))]
+impl MetadataState {
+    pub(crate) fn new(metadata: Metadata) -> MetadataState {
+        MetadataState {
+            current: metadata,
+            revision: 0,
+            callback: None,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn current(&self) -> &Metadata {
+        &self.current
+    }
+
+    #[inline]
+    pub(crate) fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    pub(crate) fn set_callback(&mut self, callback: MetadataCallback) {
+        self.callback = Some(callback);
+    }
+
+    /// Replaces the metadata and reports the new one.
+    pub(crate) fn update(&mut self, metadata: Metadata) {
+        self.current = metadata;
+        self.revision += 1;
+        if let Some(callback) = &mut self.callback {
+            callback(&self.current);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Metadata, StandardTagKey};
+
+    #[test]
+    fn vorbis_comments() {
+        let metadata = Metadata::from_vorbis_comments([
+            ("title", "Song"),
+            ("ARTIST", "Band"),
+            ("TRACKNUMBER", "3/12"),
+            ("REPLAYGAIN_TRACK_GAIN", "-6.50 dB"),
+            ("MOOD", "happy"),
+        ]);
+        assert_eq!(metadata.title(), Some("Song"));
+        assert_eq!(metadata.artist(), Some("Band"));
+        assert_eq!(metadata.album(), None);
+        assert_eq!(metadata.track_number(), Some(3));
+        assert_eq!(
+            metadata.get(StandardTagKey::ReplayGainTrackGain),
+            Some("-6.50 dB")
+        );
+        assert_eq!(metadata.tags()[4].standard_key, None);
+        assert_eq!(metadata.tags()[4].key, "MOOD");
+    }
+}
@@ -11,18 +11,28 @@ use std::time::Duration;
 use crate::source::SeekError;
 use crate::{Sample, Source};
 
+use self::builder::DecoderSettings;
+pub use self::builder::{DecoderBackend, DecoderBuilder, SeekMode};
+pub use self::metadata::{Metadata, Picture, PictureKind, StandardTagKey, Tag};
+pub use self::read_only_source::ReadOnlySource;
 #[cfg(feature = "symphonia")]
 use self::read_seek_source::ReadSeekSource;
+pub use self::replay_gain::{ReplayGain, ReplayGainMode, ReplayGainSettings};
 use crate::common::{ChannelCount, SampleRate};
+use crate::conversions::ChannelLayout;
 #[cfg(feature = "symphonia")]
 use ::symphonia::core::io::{MediaSource, MediaSourceStream};
 
+mod builder;
 #[cfg(all(feature = "flac", not(feature = "symphonia-flac")))]
 mod flac;
+mod metadata;
 #[cfg(all(feature = "minimp3", not(feature = "symphonia-mp3")))]
 mod mp3;
+mod read_only_source;
 #[cfg(feature = "symphonia")]
 mod read_seek_source;
+mod replay_gain;
 #[cfg(feature = "symphonia")]
 /// Symphonia decoders types
 pub mod symphonia;
@@ -34,9 +44,27 @@ mod wav;
 /// Source of audio samples from decoding a file.
 ///
 /// Supports MP3, WAV, Vorbis and Flac.
-pub struct Decoder<R>(DecoderImpl<R>)
+pub struct Decoder<R>
 where
-    R: Read + Seek;
+    R: Read + Seek,
+{
+    inner: DecoderImpl<R>,
+    replay_gain: Option<ReplayGainStage>,
+    /// `false` if the data can not seek, see [`DecoderBuilder::build_unseekable`].
+    seekable: bool,
+}
+
+/// Gain applied by [`Decoder::with_replay_gain`].
+struct ReplayGainStage {
+    settings: ReplayGainSettings,
+    factor: f32,
+    /// Revision of the metadata the factor was computed for.
+    metadata_revision: u64,
+    samples_until_check: usize,
+}
+
+/// Number of samples between two checks for new metadata.
+const METADATA_CHECK_INTERVAL: usize = 1024;
 
 /// Source of audio samples from decoding a file that never ends. When the
 /// end of the file is reached the decoder starts again from the beginning.
@@ -66,7 +94,47 @@ where
     None(::std::marker::PhantomData<R>),
 }
 
+/// Metadata of formats that have none.
+static NO_METADATA: Metadata = Metadata::new();
+
 impl<R: Read + Seek> DecoderImpl<R> {
+    #[inline]
+    fn metadata(&self) -> &Metadata {
+        match self {
+            #[cfg(all(feature = "vorbis", not(feature = "symphonia-vorbis")))]
+            DecoderImpl::Vorbis(source) => source.metadata(),
+            #[cfg(all(feature = "flac", not(feature = "symphonia-flac")))]
+            DecoderImpl::Flac(source) => source.metadata(),
+            #[cfg(feature = "symphonia")]
+            DecoderImpl::Symphonia(source) => source.metadata(),
+            _ => &NO_METADATA,
+        }
+    }
+
+    /// Changes whenever the metadata changes.
+    #[inline]
+    fn metadata_revision(&self) -> u64 {
+        match self {
+            #[cfg(all(feature = "vorbis", not(feature = "symphonia-vorbis")))]
+            DecoderImpl::Vorbis(source) => source.metadata_revision(),
+            #[cfg(feature = "symphonia")]
+            DecoderImpl::Symphonia(source) => source.metadata_revision(),
+            _ => 0,
+        }
+    }
+
+    #[allow(unused_variables)]
+    fn set_metadata_callback(&mut self, callback: metadata::MetadataCallback) {
+        match self {
+            #[cfg(all(feature = "vorbis", not(feature = "symphonia-vorbis")))]
+            DecoderImpl::Vorbis(source) => source.metadata_state().set_callback(callback),
+            #[cfg(feature = "symphonia")]
+            DecoderImpl::Symphonia(source) => source.metadata_state().set_callback(callback),
+            // The metadata of the other formats can not change.
+            _ => (),
+        }
+    }
+
     #[inline]
     fn next(&mut self) -> Option<Sample> {
         match self {
@@ -169,6 +237,23 @@ impl<R: Read + Seek> DecoderImpl<R> {
         }
     }
 
+    #[inline]
+    fn channel_layout(&self) -> Option<ChannelLayout> {
+        match self {
+            #[cfg(all(feature = "wav", not(feature = "symphonia-wav")))]
+            DecoderImpl::Wav(source) => source.channel_layout(),
+            #[cfg(all(feature = "vorbis", not(feature = "symphonia-vorbis")))]
+            DecoderImpl::Vorbis(source) => source.channel_layout(),
+            #[cfg(all(feature = "flac", not(feature = "symphonia-flac")))]
+            DecoderImpl::Flac(source) => source.channel_layout(),
+            #[cfg(all(feature = "minimp3", not(feature = "symphonia-mp3")))]
+            DecoderImpl::Mp3(source) => source.channel_layout(),
+            #[cfg(feature = "symphonia")]
+            DecoderImpl::Symphonia(source) => source.channel_layout(),
+            DecoderImpl::None(_) => None,
+        }
+    }
+
     #[inline]
     fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
         match self {
@@ -195,14 +280,20 @@ where
 {
     /// Builds a new decoder.
     ///
-    /// Attempts to automatically detect the format of the source of data.
-    #[allow(unused_variables)]
+    /// Attempts to automatically detect the format of the source of data. Use a
+    /// [`DecoderBuilder`] to pass a hint about the format.
     pub fn new(data: R) -> Result<Decoder<R>, DecoderError> {
+        Decoder::detect(data, &DecoderSettings::default())
+    }
+
+    /// Tries the backends one after the other, the settings are passed on to them.
+    #[allow(unused_variables)]
+    fn detect(data: R, settings: &DecoderSettings) -> Result<Decoder<R>, DecoderError> {
         #[cfg(all(feature = "wav", not(feature = "symphonia-wav")))]
         let data = match wav::WavDecoder::new(data) {
             Err(data) => data,
             Ok(decoder) => {
-                return Ok(Decoder(DecoderImpl::Wav(decoder)));
+                return Ok(Decoder::from_impl(DecoderImpl::Wav(decoder)));
             }
         };
 
@@ -210,37 +301,30 @@ where
         let data = match flac::FlacDecoder::new(data) {
             Err(data) => data,
             Ok(decoder) => {
-                return Ok(Decoder(DecoderImpl::Flac(decoder)));
+                return Ok(Decoder::from_impl(DecoderImpl::Flac(decoder)));
             }
         };
 
         #[cfg(all(feature = "vorbis", not(feature = "symphonia-vorbis")))]
-        let data = match vorbis::VorbisDecoder::new(data) {
+        let data = match vorbis::VorbisDecoder::new(data, settings) {
             Err(data) => data,
             Ok(decoder) => {
-                return Ok(Decoder(DecoderImpl::Vorbis(decoder)));
+                return Ok(Decoder::from_impl(DecoderImpl::Vorbis(decoder)));
             }
         };
 
         #[cfg(all(feature = "minimp3", not(feature = "symphonia-mp3")))]
-        let data = match mp3::Mp3Decoder::new(data) {
+        let data = match mp3::Mp3Decoder::new(data, settings) {
             Err(data) => data,
             Ok(decoder) => {
-                return Ok(Decoder(DecoderImpl::Mp3(decoder)));
+                return Ok(Decoder::from_impl(DecoderImpl::Mp3(decoder)));
             }
         };
 
         #[cfg(feature = "symphonia")]
         {
-            let mss = MediaSourceStream::new(
-                Box::new(ReadSeekSource::new(data)) as Box<dyn MediaSource>,
-                Default::default(),
-            );
-
-            match symphonia::SymphoniaDecoder::new(mss, None) {
-                Err(e) => Err(e),
-                Ok(decoder) => Ok(Decoder(DecoderImpl::Symphonia(decoder))),
-            }
+            let source = ReadSeekSource::new(data, settings.byte_len);
+            Decoder::from_media_source(Box::new(source), settings)
         }
         #[cfg(not(feature = "symphonia"))]
         Err(DecoderError::UnrecognizedFormat)
@@ -258,7 +342,7 @@ where
     pub fn new_wav(data: R) -> Result<Decoder<R>, DecoderError> {
         match wav::WavDecoder::new(data) {
             Err(_) => Err(DecoderError::UnrecognizedFormat),
-            Ok(decoder) => Ok(Decoder(DecoderImpl::Wav(decoder))),
+            Ok(decoder) => Ok(Decoder::from_impl(DecoderImpl::Wav(decoder))),
         }
     }
 
@@ -273,7 +357,7 @@ where
     pub fn new_flac(data: R) -> Result<Decoder<R>, DecoderError> {
         match flac::FlacDecoder::new(data) {
             Err(_) => Err(DecoderError::UnrecognizedFormat),
-            Ok(decoder) => Ok(Decoder(DecoderImpl::Flac(decoder))),
+            Ok(decoder) => Ok(Decoder::from_impl(DecoderImpl::Flac(decoder))),
         }
     }
 
@@ -286,9 +370,9 @@ where
     /// Builds a new decoder from vorbis data.
     #[cfg(all(feature = "vorbis", not(feature = "symphonia-vorbis")))]
     pub fn new_vorbis(data: R) -> Result<Decoder<R>, DecoderError> {
-        match vorbis::VorbisDecoder::new(data) {
+        match vorbis::VorbisDecoder::new(data, &DecoderSettings::default()) {
             Err(_) => Err(DecoderError::UnrecognizedFormat),
-            Ok(decoder) => Ok(Decoder(DecoderImpl::Vorbis(decoder))),
+            Ok(decoder) => Ok(Decoder::from_impl(DecoderImpl::Vorbis(decoder))),
         }
     }
 
@@ -301,9 +385,9 @@ where
     /// Builds a new decoder from mp3 data.
     #[cfg(all(feature = "minimp3", not(feature = "symphonia-mp3")))]
     pub fn new_mp3(data: R) -> Result<Decoder<R>, DecoderError> {
-        match mp3::Mp3Decoder::new(data) {
+        match mp3::Mp3Decoder::new(data, &DecoderSettings::default()) {
             Err(_) => Err(DecoderError::UnrecognizedFormat),
-            Ok(decoder) => Ok(Decoder(DecoderImpl::Mp3(decoder))),
+            Ok(decoder) => Ok(Decoder::from_impl(DecoderImpl::Mp3(decoder))),
         }
     }
 
@@ -327,15 +411,11 @@ where
 
     #[cfg(feature = "symphonia")]
     fn new_symphonia(data: R, hint: &str) -> Result<Decoder<R>, DecoderError> {
-        let mss = MediaSourceStream::new(
-            Box::new(ReadSeekSource::new(data)) as Box<dyn MediaSource>,
-            Default::default(),
-        );
-
-        match symphonia::SymphoniaDecoder::new(mss, Some(hint)) {
-            Err(e) => Err(e),
-            Ok(decoder) => Ok(Decoder(DecoderImpl::Symphonia(decoder))),
-        }
+        let settings = DecoderSettings {
+            hint: Some(hint.to_owned()),
+            ..DecoderSettings::default()
+        };
+        Decoder::from_media_source(Box::new(ReadSeekSource::new(data, None)), &settings)
     }
 }
 
@@ -383,12 +463,88 @@ impl fmt::Display for Mp4Type {
     }
 }
 
+impl<R> Decoder<R>
+where
+    R: Read + Seek,
+{
+    #[allow(dead_code)] // Unused when no decoder is enabled.
+    fn from_impl(inner: DecoderImpl<R>) -> Decoder<R> {
+        Decoder {
+            inner,
+            replay_gain: None,
+            seekable: true,
+        }
+    }
+
+    #[cfg(feature = "symphonia")]
+    fn from_media_source(
+        source: Box<dyn MediaSource>,
+        settings: &DecoderSettings,
+    ) -> Result<Decoder<R>, DecoderError> {
+        let mss = MediaSourceStream::new(source, Default::default());
+        match symphonia::SymphoniaDecoder::new(mss, settings) {
+            Err(e) => Err(e),
+            Ok(decoder) => Ok(Decoder::from_impl(DecoderImpl::Symphonia(decoder))),
+        }
+    }
+
+    /// Normalizes the loudness with the ReplayGain or R128 gain in the tags of the file.
+    ///
+    /// The gain is static for each track and lowered if the peak in the tags shows that the
+    /// track would clip. When the metadata changes while decoding, like between the parts of
+    /// a chained Ogg file, the gain is adjusted within a few milliseconds.
+    pub fn with_replay_gain(mut self, settings: ReplayGainSettings) -> Decoder<R> {
+        self.replay_gain = Some(ReplayGainStage {
+            settings,
+            factor: ReplayGain::from_metadata(self.metadata()).factor(&settings),
+            metadata_revision: self.inner.metadata_revision(),
+            samples_until_check: METADATA_CHECK_INTERVAL,
+        });
+        self
+    }
+
+    /// The factor the samples are multiplied with by
+    /// [`with_replay_gain`](Decoder::with_replay_gain), 1.0 if it is not used.
+    #[inline]
+    pub fn replay_gain_factor(&self) -> f32 {
+        self.replay_gain.as_ref().map_or(1.0, |stage| stage.factor)
+    }
+
+    /// Returns the tags and pictures of the stream, like title, artist and cover art.
+    ///
+    /// Formats without metadata support (WAV, and MP3 without the `symphonia-mp3` feature)
+    /// return empty metadata. Metadata that is embedded in the stream, like the comments of
+    /// the next part of a chained Ogg file, is picked up while decoding, see
+    /// [`on_metadata_change`](Decoder::on_metadata_change).
+    #[inline]
+    pub fn metadata(&self) -> &Metadata {
+        self.inner.metadata()
+    }
+
+    /// Calls `callback` with the new metadata whenever it changes while decoding.
+    ///
+    /// The callback runs on the thread that plays the decoder, so it should return quickly.
+    /// Replaces a previously set callback.
+    pub fn on_metadata_change<F>(&mut self, callback: F)
+    where
+        F: FnMut(&Metadata) + Send + Sync + 'static,
+    {
+        self.inner.set_metadata_callback(Box::new(callback));
+    }
+}
+
 impl<R> LoopedDecoder<R>
 where
     R: Read + Seek,
 {
     fn new(decoder: Decoder<R>) -> LoopedDecoder<R> {
-        Self(decoder.0)
+        Self(decoder.inner)
+    }
+
+    /// Returns the tags and pictures of the stream, see [`Decoder::metadata`].
+    #[inline]
+    pub fn metadata(&self) -> &Metadata {
+        self.0.metadata()
     }
 }
 
@@ -400,12 +556,26 @@ where
 
     #[inline]
     fn next(&mut self) -> Option<Self::Item> {
-        self.0.next()
+        let sample = self.inner.next()?;
+        let Some(stage) = &mut self.replay_gain else {
+            return Some(sample);
+        };
+        stage.samples_until_check -= 1;
+        if stage.samples_until_check == 0 {
+            stage.samples_until_check = METADATA_CHECK_INTERVAL;
+            let revision = self.inner.metadata_revision();
+            if revision != stage.metadata_revision {
+                stage.metadata_revision = revision;
+                stage.factor =
+                    ReplayGain::from_metadata(self.inner.metadata()).factor(&stage.settings);
+            }
+        }
+        Some(sample * stage.factor)
     }
 
     #[inline]
     fn size_hint(&self) -> (usize, Option<usize>) {
-        self.0.size_hint()
+        self.inner.size_hint()
     }
 }
 
@@ -415,26 +585,36 @@ where
 {
     #[inline]
     fn current_span_len(&self) -> Option<usize> {
-        self.0.current_span_len()
+        self.inner.current_span_len()
     }
 
     #[inline]
     fn channels(&self) -> ChannelCount {
-        self.0.channels()
+        self.inner.channels()
     }
 
     fn sample_rate(&self) -> SampleRate {
-        self.0.sample_rate()
+        self.inner.sample_rate()
     }
 
     #[inline]
     fn total_duration(&self) -> Option<Duration> {
-        self.0.total_duration()
+        self.inner.total_duration()
+    }
+
+    #[inline]
+    fn channel_layout(&self) -> Option<ChannelLayout> {
+        self.inner.channel_layout()
     }
 
     #[inline]
     fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
-        self.0.try_seek(pos)
+        if !self.seekable {
+            return Err(SeekError::NotSupported {
+                underlying_source: "ReadOnlySource",
+            });
+        }
+        self.inner.try_seek(pos)
     }
 }
 
@@ -482,7 +662,8 @@ where
                 DecoderImpl::Mp3(source) => {
                     let mut reader = source.into_inner();
                     reader.seek(SeekFrom::Start(0)).ok()?;
-                    let mut source = mp3::Mp3Decoder::new(reader).ok()?;
+                    let mut source =
+                        mp3::Mp3Decoder::new(reader, &DecoderSettings::default()).ok()?;
                     let sample = source.next();
                     (DecoderImpl::Mp3(source), sample)
                 }
@@ -490,7 +671,8 @@ where
                 DecoderImpl::Symphonia(source) => {
                     let mut reader = source.into_inner();
                     reader.seek(SeekFrom::Start(0)).ok()?;
-                    let mut source = symphonia::SymphoniaDecoder::new(reader, None).ok()?;
+                    let settings = DecoderSettings::default();
+                    let mut source = symphonia::SymphoniaDecoder::new(reader, &settings).ok()?;
                     let sample = source.next();
                     (DecoderImpl::Symphonia(source), sample)
                 }
@@ -531,11 +713,53 @@ where
         None
     }
 
+    #[inline]
+    fn channel_layout(&self) -> Option<ChannelLayout> {
+        self.0.channel_layout()
+    }
+
     fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
         self.0.try_seek(pos)
     }
 }
 
+/// Layout of FLAC and Vorbis streams, which define the speakers by the channel count. Vorbis
+/// streams are reordered to match.
+#[cfg(any(
+    all(feature = "flac", not(feature = "symphonia-flac")),
+    all(feature = "vorbis", not(feature = "symphonia-vorbis"))
+))]
+fn xiph_channel_layout(channels: ChannelCount) -> Option<ChannelLayout> {
+    use crate::conversions::Speaker::*;
+    let speakers: &[_] = match channels {
+        1 => return Some(ChannelLayout::Mono),
+        2 => return Some(ChannelLayout::Stereo),
+        3 => &[FrontLeft, FrontRight, FrontCenter],
+        4 => return Some(ChannelLayout::Quad),
+        5 => &[FrontLeft, FrontRight, FrontCenter, BackLeft, BackRight],
+        6 => &[
+            FrontLeft,
+            FrontRight,
+            FrontCenter,
+            LowFrequency,
+            BackLeft,
+            BackRight,
+        ],
+        7 => &[
+            FrontLeft,
+            FrontRight,
+            FrontCenter,
+            LowFrequency,
+            BackCenter,
+            SideLeft,
+            SideRight,
+        ],
+        8 => return Some(ChannelLayout::SevenPointOne),
+        _ => return None,
+    };
+    Some(ChannelLayout::new(speakers.iter().copied().collect()))
+}
+
 /// Error that can happen when creating a decoder.
 #[derive(Debug, Clone)]
 pub enum DecoderError {
@@ -1,11 +1,12 @@
-use std::io::{Read, Seek, SeekFrom};
+use std::io::{self, Read, Seek, SeekFrom};
 use std::time::Duration;
 
-use super::DecoderSample;
-use crate::common::{ChannelCount, SampleRate};
+use super::{DecoderSettings, SeekMode};
+use crate::common::{ChannelCount, Sample, SampleRate};
 use crate::source::SeekError;
 use crate::Source;
 
+use dasp_sample::Sample as _;
 use minimp3::Decoder;
 use minimp3::Frame;
 use minimp3_fixed as minimp3;
@@ -15,38 +16,154 @@ where
     R: Read + Seek,
 {
     // decoder: SeekDecoder<R>,
-    decoder: Decoder<R>,
+    /// Only `None` while seeking, which replaces it.
+    decoder: Option<Decoder<R>>,
     // what minimp3 calls frames rodio calls spans
     current_span: Frame,
     current_span_offset: usize,
+    /// Samples still to be dropped at the start: the LAME tag frame, encoder and decoder delay.
+    skip: usize,
+    /// Samples left before the encoder padding at the end, if the file tells.
+    remaining: Option<u64>,
+    /// Samples per channel in one frame.
+    frame_samples: usize,
+    /// Samples per channel before the track starts: the LAME tag frame and the delay.
+    start_delay: usize,
+    /// Samples per channel of the track without delay and padding, if the file tells.
+    valid_samples: Option<u64>,
+    /// Positions of the frames, found while seeking.
+    index: FrameIndex,
+    /// Table of contents of the Xing or VBRI tag, used for coarse seeking.
+    toc: Option<Toc>,
+    seek_mode: SeekMode,
 }
 
 impl<R> Mp3Decoder<R>
 where
     R: Read + Seek,
 {
-    pub fn new(mut data: R) -> Result<Self, R> {
+    /// With `settings.gapless` the encoder delay and padding are removed.
+    pub(crate) fn new(mut data: R, settings: &DecoderSettings) -> Result<Self, R> {
         if !is_mp3(data.by_ref()) {
             return Err(data);
         }
+        let first_frame = read_first_frame(data.by_ref());
+        let first_frame = first_frame.as_deref();
+        let gapless = first_frame
+            .and_then(GaplessInfo::parse)
+            .filter(|_| settings.gapless);
+        let mut decoder = Self::with_gapless_info(data, gapless).expect("should still be mp3");
+        decoder.toc = first_frame.and_then(Toc::parse);
+        decoder.seek_mode = settings.seek_mode;
+        Ok(decoder)
+    }
+
+    /// Decodes the data as MP3 without checking the format first, which would need to seek
+    /// back. The encoder delay and padding are not removed, reading them needs seeking too.
+    pub fn new_unprobed(data: R) -> Option<Self> {
+        Self::with_gapless_info(data, None)
+    }
+
+    fn with_gapless_info(mut data: R, gapless: Option<GaplessInfo>) -> Option<Self> {
+        let data_start = data.stream_position().unwrap_or_default();
         // let mut decoder = SeekDecoder::new(data)
         let mut decoder = Decoder::new(data);
-        // parameters are correct and minimp3 is used correctly
-        // thus if we crash here one of these invariants is broken:
-        // .expect("should be able to allocate memory, perform IO");
         // let current_span = decoder.decode_frame()
-        let current_span = decoder.next_frame().expect("should still be mp3");
+        let current_span = decoder.next_frame().ok()?;
 
-        Ok(Mp3Decoder {
-            decoder,
+        let channels = current_span.channels;
+        let (start_delay, valid_samples) = match gapless {
+            Some(gapless) => (
+                gapless.frame_samples + gapless.delay,
+                gapless.valid_samples(),
+            ),
+            None => (0, None),
+        };
+        let mut decoder = Mp3Decoder {
+            decoder: Some(decoder),
+            frame_samples: current_span.data.len() / channels.max(1),
             current_span,
             current_span_offset: 0,
-        })
+            skip: start_delay * channels,
+            remaining: valid_samples.map(|samples| samples * channels as u64),
+            start_delay,
+            valid_samples,
+            index: FrameIndex::new(data_start),
+            toc: None,
+            seek_mode: SeekMode::default(),
+        };
+        decoder.skip_delay();
+        Some(decoder)
+    }
+
+    fn decoder(&mut self) -> &mut Decoder<R> {
+        self.decoder.as_mut().expect("only taken while seeking")
+    }
+
+    /// Drops the samples of the LAME tag frame and the delay, they are silence that is not
+    /// part of the track. Also drops the samples before the position after seeking.
+    fn skip_delay(&mut self) {
+        while self.skip > 0 {
+            let left = self.current_span.data.len() - self.current_span_offset;
+            if self.skip < left {
+                self.current_span_offset += self.skip;
+                self.skip = 0;
+            } else {
+                self.skip -= left;
+                match self.decoder().next_frame() {
+                    Ok(span) => {
+                        self.current_span = span;
+                        self.current_span_offset = 0;
+                    }
+                    Err(_) => {
+                        self.current_span_offset = self.current_span.data.len();
+                        self.skip = 0;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Moves the reader to where decoding has to start for `frame`, returns the number of
+    /// decoded samples per channel to drop before the frame. Returns `None` if the reader is
+    /// only near the frame, or at the end of the data.
+    fn seek_reader(&mut self, reader: &mut R, frame: usize) -> io::Result<Option<usize>> {
+        if self.seek_mode == SeekMode::Coarse && self.index.positions.len() <= frame {
+            if let Some(toc) = &self.toc {
+                // Jumps to the estimated position instead of reading all headers before it.
+                self.index.scan(reader, 0)?;
+                if let Some(&first) = self.index.positions.first() {
+                    reader.seek(SeekFrom::Start(first + toc.offset(frame)))?;
+                    return Ok(None);
+                }
+            }
+        }
+
+        self.index.scan(reader, frame)?;
+        if frame >= self.index.positions.len() {
+            let end = self.index.end.unwrap_or(self.index.data_start);
+            reader.seek(SeekFrom::Start(end))?;
+            return Ok(None);
+        }
+        // Layer III frames can use data of the frames before them, the bit reservoir. The
+        // decoder skips frames without output until it has the data they refer to. Decoding
+        // starts early enough that the frame before the target has output, which also fills
+        // the overlap of the synthesis filter bank.
+        let mut start = frame.saturating_sub(1);
+        let undecodable = loop {
+            let undecodable = self.index.undecodable_frames(reader, start, frame)?;
+            if start == 0 || undecodable < frame - start {
+                break undecodable;
+            }
+            start -= 1;
+        };
+        reader.seek(SeekFrom::Start(self.index.positions[start]))?;
+        Ok(Some((frame - start - undecodable) * self.frame_samples))
     }
 
     #[inline]
     pub fn into_inner(self) -> R {
-        self.decoder.into_inner()
+        self.decoder.expect("only taken while seeking").into_inner()
     }
 }
 
@@ -56,7 +173,14 @@ where
 {
     #[inline]
     fn current_span_len(&self) -> Option<usize> {
-        Some(self.current_span.data.len())
+        let len = self.current_span.data.len();
+        match self.remaining {
+            Some(remaining) => {
+                let left = (remaining as usize).min(len - self.current_span_offset);
+                Some(self.current_span_offset + left)
+            }
+            None => Some(len),
+        }
     }
 
     #[inline]
@@ -74,18 +198,36 @@ where
         None
     }
 
-    fn try_seek(&mut self, _pos: Duration) -> Result<(), SeekError> {
-        // TODO waiting for PR in minimp3_fixed or minimp3
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        let channels = self.channels() as usize;
+        let target = (pos.as_secs_f64() * self.sample_rate() as f64) as u64;
+        let target = match self.valid_samples {
+            Some(valid_samples) => target.min(valid_samples),
+            None => target,
+        };
+        let position = target + self.start_delay as u64;
+        let frame = (position / self.frame_samples as u64) as usize;
+        let within_frame = (position % self.frame_samples as u64) as usize;
+        // make sure the next sample is for the right channel
+        let to_skip = self.current_span_offset % channels;
 
-        // let pos = (pos.as_secs_f32() * self.sample_rate() as f32) as u64;
-        // // do not trigger a sample_rate, channels and frame/span len update
-        // // as the seek only takes effect after the current frame/span is done
-        // self.decoder.seek_samples(pos)?;
-        // Ok(())
+        let mut reader = self.decoder.take().expect("not seeking").into_inner();
+        let skip = self.seek_reader(&mut reader, frame);
+        // The old decoder has data of the previous position buffered.
+        self.decoder = Some(Decoder::new(reader));
+        let skip = skip.map_err(SeekError::Minimp3Decoder)?;
 
-        Err(SeekError::NotSupported {
-            underlying_source: std::any::type_name::<Self>(),
-        })
+        match self.decoder().next_frame() {
+            Ok(span) => self.current_span = span,
+            Err(_) => self.current_span.data.clear(),
+        }
+        self.current_span_offset = 0;
+        self.skip = skip.map_or(0, |skip| (skip + within_frame) * channels) + to_skip;
+        self.remaining = self
+            .valid_samples
+            .map(|samples| ((samples - target) * channels as u64).saturating_sub(to_skip as u64));
+        self.skip_delay();
+        Ok(())
     }
 }
 
@@ -93,12 +235,14 @@ impl<R> Iterator for Mp3Decoder<R>
 where
     R: Read + Seek,
 {
-    type Item = DecoderSample;
+    type Item = Sample;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let current_span_len = self.current_span_len()?;
-        if self.current_span_offset == current_span_len {
-            if let Ok(span) = self.decoder.next_frame() {
+        if self.remaining == Some(0) {
+            return None;
+        }
+        if self.current_span_offset == self.current_span.data.len() {
+            if let Ok(span) = self.decoder().next_frame() {
                 // if let Ok(span) = self.decoder.decode_frame() {
                 self.current_span = span;
                 self.current_span_offset = 0;
@@ -109,6 +253,9 @@ where
 
         let v = self.current_span.data[self.current_span_offset];
         self.current_span_offset += 1;
+        if let Some(remaining) = &mut self.remaining {
+            *remaining -= 1;
+        }
 
         Some(v.to_sample())
     }
@@ -125,3 +272,547 @@ where
     let _ = data.seek(SeekFrom::Start(stream_pos));
     result
 }
+
+/// Encoder delay and padding from the Xing/Info tag that LAME and FFmpeg write into the
+/// first frame of a file.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct GaplessInfo {
+    /// Samples per channel in one frame.
+    frame_samples: usize,
+    /// Number of audio frames, not counting the tag frame.
+    frames: Option<u32>,
+    /// Samples per channel before the track starts, including the delay of the decoder.
+    delay: usize,
+    /// Samples per channel after the track ends.
+    padding: usize,
+}
+
+/// Delay of the MP3 synthesis filter bank, which the encoder delay in the tag leaves out.
+const DECODER_DELAY: usize = 529;
+
+impl GaplessInfo {
+    /// Parses the tag from a frame that starts with an MPEG layer III header.
+    fn parse(frame: &[u8]) -> Option<GaplessInfo> {
+        let mpeg1 = frame.get(1)? & 0x08 != 0;
+        let tag = xing_tag(frame)?;
+        let flags = *tag.get(7)?;
+        let mut offset = 8;
+        let mut frames = None;
+        if flags & 0x01 != 0 {
+            frames = Some(u32::from_be_bytes(
+                tag.get(offset..offset + 4)?.try_into().ok()?,
+            ));
+            offset += 4;
+        }
+        // Byte count, table of contents and quality.
+        for (flag, size) in [(0x02, 4), (0x04, 100), (0x08, 4)] {
+            if flags & flag != 0 {
+                offset += size;
+            }
+        }
+
+        let frame_samples = if mpeg1 { 1152 } else { 576 };
+        // The encoder delay and padding follow the 9 byte encoder version, the revision, the
+        // lowpass frequency, the ReplayGain fields, the flags and the bitrate.
+        let (delay, padding) = match tag.get(offset + 21..offset + 24) {
+            Some(&[a, b, c]) if tag[offset] != 0 => (
+                ((a as usize) << 4) | (b as usize >> 4),
+                (((b & 0x0f) as usize) << 8) | c as usize,
+            ),
+            _ => (0, 0),
+        };
+        Some(GaplessInfo {
+            frame_samples,
+            frames,
+            delay: delay + DECODER_DELAY,
+            padding: padding.saturating_sub(DECODER_DELAY),
+        })
+    }
+
+    /// Samples per channel of the track without delay and padding.
+    fn valid_samples(&self) -> Option<u64> {
+        let total = self.frames? as u64 * self.frame_samples as u64;
+        Some(total.saturating_sub((self.delay + self.padding) as u64))
+    }
+}
+
+/// Size of the ID3v2 tag at the start of `header`, which holds at least 10 bytes.
+fn id3v2_len(header: &[u8]) -> Option<u64> {
+    if &header[..3] != b"ID3" {
+        return None;
+    }
+    let size = header[6..10]
+        .iter()
+        .fold(0, |size, &byte| (size << 7) | (byte & 0x7f) as u64);
+    let footer = if header[5] & 0x10 != 0 { 10 } else { 0 };
+    Some(10 + size + footer)
+}
+
+/// Reads the start of the first frame, which can hold a Xing/Info or VBRI tag, then resets
+/// the stream to where it was.
+fn read_first_frame<R>(mut data: R) -> Option<Vec<u8>>
+where
+    R: Read + Seek,
+{
+    let stream_pos = data.stream_position().ok()?;
+    let frame = read_first_frame_inner(data.by_ref());
+    data.seek(SeekFrom::Start(stream_pos)).ok()?;
+    frame
+}
+
+fn read_first_frame_inner<R>(mut data: R) -> Option<Vec<u8>>
+where
+    R: Read + Seek,
+{
+    let mut header = [0; 10];
+    data.read_exact(&mut header).ok()?;
+    match id3v2_len(&header) {
+        Some(len) => data.seek(SeekFrom::Current(len as i64 - 10)).ok()?,
+        None => data.seek(SeekFrom::Current(-10)).ok()?,
+    };
+
+    // Large enough for the tag frame and some junk before it.
+    let mut buffer = Vec::with_capacity(4096);
+    data.take(4096).read_to_end(&mut buffer).ok()?;
+    let start = buffer
+        .windows(4)
+        .position(|bytes| bytes[0] == 0xff && bytes[1] & 0xe6 == 0xe2)?;
+    buffer.drain(..start);
+    Some(buffer)
+}
+
+/// The Xing/Info tag in a frame that starts with an MPEG layer III header, it follows the
+/// side information.
+fn xing_tag(frame: &[u8]) -> Option<&[u8]> {
+    let mpeg1 = frame.get(1)? & 0x08 != 0;
+    let crc = frame[1] & 0x01 == 0;
+    let mono = frame.get(3)? >> 6 == 3;
+    let tag = frame.get(4 + if crc { 2 } else { 0 } + side_info_len(mpeg1, mono)..)?;
+    (tag.starts_with(b"Xing") || tag.starts_with(b"Info")).then_some(tag)
+}
+
+/// Bytes of layer III side information.
+fn side_info_len(mpeg1: bool, mono: bool) -> usize {
+    match (mpeg1, mono) {
+        (true, true) => 17,
+        (true, false) => 32,
+        (false, true) => 9,
+        (false, false) => 17,
+    }
+}
+
+/// Where the frames are, according to the tag in the first frame.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Toc {
+    /// Xing tag: the position at each percent of the duration, in 256ths of the bytes.
+    Xing {
+        frames: u32,
+        bytes: u32,
+        entries: [u8; 100],
+    },
+    /// VBRI tag, written by the Fraunhofer encoder: the bytes of each group of frames.
+    Vbri {
+        frames_per_entry: usize,
+        sizes: Vec<u64>,
+    },
+}
+
+impl Toc {
+    /// Parses the table from a frame that starts with an MPEG layer III header.
+    fn parse(frame: &[u8]) -> Option<Toc> {
+        let u32_at = |tag: &[u8], offset: usize| {
+            Some(u32::from_be_bytes(
+                tag.get(offset..offset + 4)?.try_into().ok()?,
+            ))
+        };
+        if let Some(tag) = xing_tag(frame) {
+            // Frames, bytes and the table.
+            if tag.get(7)? & 0x07 != 0x07 {
+                return None;
+            }
+            let frames = u32_at(tag, 8)?;
+            let bytes = u32_at(tag, 12)?;
+            let entries = tag.get(16..116)?.try_into().ok()?;
+            return (frames > 0).then_some(Toc::Xing {
+                frames,
+                bytes,
+                entries,
+            });
+        }
+
+        // The VBRI tag is always 32 bytes after the header.
+        let tag = frame.get(36..)?;
+        if !tag.starts_with(b"VBRI") {
+            return None;
+        }
+        let u16_at = |offset: usize| {
+            Some(u16::from_be_bytes(tag.get(offset..offset + 2)?.try_into().ok()?) as usize)
+        };
+        let entries = u16_at(18)?;
+        let scale = u16_at(20)? as u64;
+        let entry_size = u16_at(22)?;
+        let frames_per_entry = u16_at(24)?;
+        if frames_per_entry == 0 || !(1..=4).contains(&entry_size) {
+            return None;
+        }
+        let sizes = tag
+            .get(26..26 + entries * entry_size)?
+            .chunks(entry_size)
+            .map(|entry| {
+                let size = entry
+                    .iter()
+                    .fold(0, |size, &byte| (size << 8) | byte as u64);
+                size * scale
+            })
+            .collect();
+        Some(Toc::Vbri {
+            frames_per_entry,
+            sizes,
+        })
+    }
+
+    /// Estimated position of `frame` relative to the first frame.
+    fn offset(&self, frame: usize) -> u64 {
+        match self {
+            Toc::Xing {
+                frames,
+                bytes,
+                entries,
+            } => {
+                let percent = (frame as f64 * 100.0 / *frames as f64).clamp(0.0, 99.99);
+                let index = percent as usize;
+                let before = entries[index] as f64;
+                let after = entries.get(index + 1).map_or(256.0, |&entry| entry as f64);
+                let fraction = before + (after - before) * percent.fract();
+                (fraction / 256.0 * *bytes as f64) as u64
+            }
+            Toc::Vbri {
+                frames_per_entry,
+                sizes,
+            } => sizes.iter().take(frame / frames_per_entry).sum(),
+        }
+    }
+}
+
+/// The parts of an MPEG audio frame header needed to find the frames.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct FrameHeader {
+    /// Bytes of the whole frame.
+    len: usize,
+    /// Bytes of the header and CRC, the side information follows.
+    side_info_offset: usize,
+    /// Layer III only: bytes of the header, CRC and side information, the main data follows.
+    main_data_offset: Option<usize>,
+    mpeg1: bool,
+}
+
+/// Bitrates in kbit/s by bitrate index, starting at index 1.
+const BITRATES: [[u16; 14]; 5] = [
+    // MPEG-1 layer I, II and III.
+    [
+        32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448,
+    ],
+    [
+        32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384,
+    ],
+    [
+        32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+    ],
+    // MPEG-2 and 2.5 layer I, II and III.
+    [
+        32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256,
+    ],
+    [8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+];
+
+/// Most bytes of earlier frames a layer III frame can refer to.
+const MAX_RESERVOIR: usize = 511;
+
+impl FrameHeader {
+    fn parse(bytes: &[u8]) -> Option<FrameHeader> {
+        let &[0xff, b1, b2, b3, ..] = bytes else {
+            return None;
+        };
+        // 0: MPEG-2.5, 1: reserved, 2: MPEG-2, 3: MPEG-1.
+        let version = (b1 >> 3) & 0x03;
+        // 1: layer III, 2: layer II, 3: layer I.
+        let layer = (b1 >> 1) & 0x03;
+        let bitrate_index = (b2 >> 4) as usize;
+        let sample_rate_index = ((b2 >> 2) & 0x03) as usize;
+        if b1 & 0xe0 != 0xe0
+            || version == 1
+            || layer == 0
+            || bitrate_index == 0
+            || bitrate_index == 15
+            || sample_rate_index == 3
+        {
+            return None;
+        }
+
+        let mpeg1 = version == 3;
+        let table = match (mpeg1, layer) {
+            (true, layer) => 3 - layer as usize,
+            (false, 3) => 3,
+            (false, _) => 4,
+        };
+        let bitrate = BITRATES[table][bitrate_index - 1] as usize * 1000;
+        let sample_rate = [44100, 48000, 32000][sample_rate_index]
+            >> match version {
+                3 => 0,
+                2 => 1,
+                _ => 2,
+            };
+        let padding = ((b2 >> 1) & 0x01) as usize;
+        let len = match layer {
+            3 => (12 * bitrate / sample_rate + padding) * 4,
+            1 if !mpeg1 => 72 * bitrate / sample_rate + padding,
+            _ => 144 * bitrate / sample_rate + padding,
+        };
+        let crc = b1 & 0x01 == 0;
+        let mono = b3 >> 6 == 3;
+        let side_info_offset = if crc { 6 } else { 4 };
+        let main_data_offset = (layer == 1).then(|| side_info_offset + side_info_len(mpeg1, mono));
+        Some(FrameHeader {
+            len,
+            side_info_offset,
+            main_data_offset,
+            mpeg1,
+        })
+    }
+
+    /// Whether two headers belong to the same stream: the version, layer and sample rate
+    /// can not change between frames.
+    fn same_stream(a: &[u8], b: &[u8]) -> bool {
+        a[1] & 0xfe == b[1] & 0xfe && a[2] & 0x0c == b[2] & 0x0c
+    }
+}
+
+/// How many bytes are read at once while looking for frames.
+const CHUNK_SIZE: usize = 64 * 1024;
+/// How far junk before the first frame is skipped.
+const MAX_JUNK: u64 = 64 * 1024;
+
+/// Buffers the data while looking for frames, seeking for every header would be slow.
+#[derive(Default)]
+struct Chunk {
+    start: u64,
+    data: Vec<u8>,
+}
+
+impl Chunk {
+    /// Returns `len` bytes at `position`, fewer at the end of the data.
+    fn get<R>(&mut self, reader: &mut R, position: u64, len: usize) -> io::Result<&[u8]>
+    where
+        R: Read + Seek,
+    {
+        let chunk_end = self.start + self.data.len() as u64;
+        if position < self.start || position + len as u64 > chunk_end {
+            self.start = position;
+            self.data.clear();
+            reader.seek(SeekFrom::Start(position))?;
+            reader
+                .by_ref()
+                .take(CHUNK_SIZE.max(len) as u64)
+                .read_to_end(&mut self.data)?;
+        }
+        let offset = (position - self.start) as usize;
+        Ok(&self.data[offset..(offset + len).min(self.data.len())])
+    }
+}
+
+/// Positions of the frames, found by following the frame headers from the start. The index
+/// grows as far as seeking needs it.
+struct FrameIndex {
+    /// Where the data starts, before any ID3v2 tag.
+    data_start: u64,
+    positions: Vec<u64>,
+    /// Header of the first frame.
+    first_header: [u8; 4],
+    /// Where the frames end, once all are found.
+    end: Option<u64>,
+}
+
+impl FrameIndex {
+    fn new(data_start: u64) -> FrameIndex {
+        FrameIndex {
+            data_start,
+            positions: Vec::new(),
+            first_header: [0; 4],
+            end: None,
+        }
+    }
+
+    /// Finds the frames up to `frame`, or all of them if there are fewer.
+    fn scan<R>(&mut self, reader: &mut R, frame: usize) -> io::Result<()>
+    where
+        R: Read + Seek,
+    {
+        if self.positions.len() > frame || self.end.is_some() {
+            return Ok(());
+        }
+        let mut chunk = Chunk::default();
+        if self.positions.is_empty() {
+            self.find_first(reader, &mut chunk)?;
+        }
+        while self.positions.len() <= frame && self.end.is_none() {
+            let position = *self.positions.last().expect("the first frame was found");
+            let header = chunk.get(reader, position, 4)?;
+            let next = position + FrameHeader::parse(header).map_or(0, |header| header.len) as u64;
+            let header = chunk.get(reader, next, 4)?;
+            if header.len() == 4
+                && FrameHeader::parse(header).is_some()
+                && FrameHeader::same_stream(&self.first_header, header)
+            {
+                self.positions.push(next);
+            } else {
+                self.end = Some(next);
+            }
+        }
+        Ok(())
+    }
+
+    /// Skips the ID3v2 tag and junk like the decoder does. The first frame must be followed
+    /// by a frame of the same stream, or the end of the data.
+    fn find_first<R>(&mut self, reader: &mut R, chunk: &mut Chunk) -> io::Result<()>
+    where
+        R: Read + Seek,
+    {
+        let mut position = self.data_start;
+        let header = chunk.get(reader, position, 10)?;
+        if header.len() == 10 {
+            position += id3v2_len(header).unwrap_or(0);
+        }
+        let junk_end = position + MAX_JUNK;
+        while position < junk_end {
+            let bytes = chunk.get(reader, position, 4)?;
+            let Ok(header) = <[u8; 4]>::try_from(bytes) else {
+                break;
+            };
+            if let Some(parsed) = FrameHeader::parse(&header) {
+                let next = chunk.get(reader, position + parsed.len as u64, 4)?;
+                if next.len() < 4
+                    || FrameHeader::parse(next).is_some() && FrameHeader::same_stream(&header, next)
+                {
+                    self.first_header = header;
+                    self.positions.push(position);
+                    return Ok(());
+                }
+            }
+            position += 1;
+        }
+        self.end = Some(position);
+        Ok(())
+    }
+
+    /// Number of frames from `start` on, up to `end`, that the decoder skips when it starts
+    /// at `start`. It skips layer III frames until it has the bytes of the bit reservoir they
+    /// refer to.
+    fn undecodable_frames<R>(&self, reader: &mut R, start: usize, end: usize) -> io::Result<usize>
+    where
+        R: Read + Seek,
+    {
+        let mut chunk = Chunk::default();
+        let mut reservoir = 0;
+        for frame in start..end {
+            let bytes = chunk.get(reader, self.positions[frame], 8)?;
+            let Some(header) = FrameHeader::parse(bytes) else {
+                return Ok(frame - start);
+            };
+            let Some(offset) = header.main_data_offset else {
+                return Ok(frame - start);
+            };
+            let side_info = header.side_info_offset;
+            let Some(&[first, second]) = bytes.get(side_info..side_info + 2) else {
+                return Ok(frame - start);
+            };
+            // The side information starts with the number of bytes the main data begins
+            // before this frame, 9 bits for MPEG-1 and 8 for MPEG-2.
+            let main_data_begin = if header.mpeg1 {
+                ((first as usize) << 1) | (second as usize >> 7)
+            } else {
+                first as usize
+            };
+            if reservoir >= main_data_begin {
+                return Ok(frame - start);
+            }
+            reservoir = (reservoir + header.len - offset).min(MAX_RESERVOIR);
+        }
+        Ok(end - start)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FrameHeader, GaplessInfo, Toc};
+
+    #[test]
+    fn parses_lame_tag() {
+        // MPEG-1 layer III, 128 kbit/s, 44.1 kHz, joint stereo, no CRC.
+        let mut frame = vec![0xff, 0xfb, 0x90, 0x64];
+        frame.extend([0; 32]);
+        frame.extend(b"Info");
+        frame.extend([0, 0, 0, 0x01]);
+        frame.extend(1000u32.to_be_bytes());
+        let mut lame = b"LAME3.100".to_vec();
+        lame.resize(21, 0);
+        // Delay 576, padding 1000.
+        lame.extend([0x24, 0x03, 0xe8]);
+        frame.extend(lame);
+
+        let info = GaplessInfo::parse(&frame).unwrap();
+        assert_eq!(
+            info,
+            GaplessInfo {
+                frame_samples: 1152,
+                frames: Some(1000),
+                delay: 576 + 529,
+                padding: 1000 - 529,
+            }
+        );
+        assert_eq!(info.valid_samples(), Some(1000 * 1152 - 576 - 1000));
+    }
+
+    #[test]
+    fn parses_frame_header() {
+        // MPEG-1 layer III, 128 kbit/s, 44.1 kHz, padding, joint stereo, no CRC.
+        assert_eq!(
+            FrameHeader::parse(&[0xff, 0xfb, 0x92, 0x64]),
+            Some(FrameHeader {
+                len: 418,
+                side_info_offset: 4,
+                main_data_offset: Some(36),
+                mpeg1: true,
+            })
+        );
+        // MPEG-2 layer III, 64 kbit/s, 22.05 kHz, mono, CRC.
+        assert_eq!(
+            FrameHeader::parse(&[0xff, 0xf2, 0x80, 0xc0]),
+            Some(FrameHeader {
+                len: 208,
+                side_info_offset: 6,
+                main_data_offset: Some(15),
+                mpeg1: false,
+            })
+        );
+        // Reserved version and free bitrate.
+        assert_eq!(FrameHeader::parse(&[0xff, 0xeb, 0x90, 0x64]), None);
+        assert_eq!(FrameHeader::parse(&[0xff, 0xfb, 0x00, 0x64]), None);
+    }
+
+    #[test]
+    fn estimates_position_from_toc() {
+        let mut frame = vec![0xff, 0xfb, 0x90, 0x64];
+        frame.extend([0; 32]);
+        frame.extend(b"Xing");
+        frame.extend([0, 0, 0, 0x07]);
+        frame.extend(1000u32.to_be_bytes());
+        frame.extend(256_000u32.to_be_bytes());
+        frame.extend((0..100).map(|percent| (percent * 256 / 100) as u8));
+
+        let toc = Toc::parse(&frame).unwrap();
+        assert_eq!(toc.offset(0), 0);
+        assert_eq!(toc.offset(500), 128_000);
+        // Past the end stays within the data.
+        assert!((255_000..256_000).contains(&toc.offset(2000)));
+    }
+}
@@ -0,0 +1,95 @@
+use std::io::{self, Read, Seek, SeekFrom};
+
+/// Data that can only be read front to back, like a pipe, a socket or the body of an HTTP
+/// response. See [`DecoderBuilder::build_unseekable`](super::DecoderBuilder::build_unseekable).
+///
+/// Seeking forward skips data, seeking backward fails.
+pub struct ReadOnlySource<R> {
+    inner: R,
+    position: u64,
+    byte_len: Option<u64>,
+}
+
+impl<R: Read> ReadOnlySource<R> {
+    #[inline]
+    pub(crate) fn new(inner: R, byte_len: Option<u64>) -> Self {
+        ReadOnlySource {
+            inner,
+            position: 0,
+            byte_len,
+        }
+    }
+
+    /// Returns the wrapped reader.
+    #[inline]
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for ReadOnlySource<R> {
+    #[inline]
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl<R: Read> Seek for ReadOnlySource<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(target) => Some(target),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+            SeekFrom::End(offset) => self
+                .byte_len
+                .and_then(|byte_len| byte_len.checked_add_signed(offset)),
+        };
+        let Some(target) = target.filter(|&target| target >= self.position) else {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "the data can not be read again",
+            ));
+        };
+        let skip = target - self.position;
+        let skipped = io::copy(&mut self.by_ref().take(skip), &mut io::sink())?;
+        if skipped < skip {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(self.position)
+    }
+}
+
+#[cfg(feature = "symphonia")]
+impl<R: Read + Send + Sync> symphonia::core::io::MediaSource for ReadOnlySource<R> {
+    #[inline]
+    fn is_seekable(&self) -> bool {
+        false
+    }
+
+    #[inline]
+    fn byte_len(&self) -> Option<u64> {
+        self.byte_len
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Seek, SeekFrom};
+
+    use super::ReadOnlySource;
+
+    #[test]
+    fn seeks_forward_only() {
+        let mut source = ReadOnlySource::new(&[0u8, 1, 2, 3, 4, 5, 6, 7][..], Some(8));
+        let mut byte = [0];
+        source.read_exact(&mut byte).unwrap();
+        assert_eq!(source.stream_position().unwrap(), 1);
+        assert_eq!(source.seek(SeekFrom::Current(2)).unwrap(), 3);
+        assert_eq!(source.seek(SeekFrom::End(-2)).unwrap(), 6);
+        source.read_exact(&mut byte).unwrap();
+        assert_eq!(byte, [6]);
+        assert!(source.seek(SeekFrom::Start(0)).is_err());
+        assert!(source.seek(SeekFrom::Start(9)).is_err());
+    }
+}
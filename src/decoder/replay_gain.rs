@@ -0,0 +1,181 @@
+//! Loudness normalization with ReplayGain and R128 tags.
+
+use super::metadata::{Metadata, StandardTagKey};
+
+/// R128 gains are relative to -23 LUFS, ReplayGain to about -18 LUFS.
+const R128_TO_REPLAY_GAIN: f32 = 5.0;
+
+/// Which of the gains in the tags to apply.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ReplayGainMode {
+    /// Every track is played at the same loudness.
+    #[default]
+    Track,
+    /// Albums are played at the same loudness, keeping the differences between the tracks of
+    /// an album.
+    Album,
+}
+
+/// How to apply ReplayGain, see [`Decoder::with_replay_gain`](super::Decoder::with_replay_gain).
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ReplayGainSettings {
+    /// Which gain to apply. If the file only has the other one, that one is used.
+    pub mode: ReplayGainMode,
+    /// Gain in dB added to the gain of the tags.
+    pub preamp: f32,
+    /// Gain in dB for files without ReplayGain or R128 tags.
+    pub fallback_gain: f32,
+}
+
+impl Default for ReplayGainSettings {
+    fn default() -> Self {
+        ReplayGainSettings {
+            mode: ReplayGainMode::Track,
+            preamp: 0.0,
+            fallback_gain: 0.0,
+        }
+    }
+}
+
+impl ReplayGainSettings {
+    /// Default settings with the given mode.
+    pub fn new(mode: ReplayGainMode) -> ReplayGainSettings {
+        ReplayGainSettings {
+            mode,
+            ..Default::default()
+        }
+    }
+}
+
+/// Loudness information from the tags of a file.
+///
+/// Gains are in dB. Opus R128 gains are converted to the ReplayGain reference level, so that
+/// both kinds of files play equally loud.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct ReplayGain {
+    /// Gain that brings the track to the reference loudness.
+    pub track_gain: Option<f32>,
+    /// Largest absolute sample value of the track, 1.0 is full scale.
+    pub track_peak: Option<f32>,
+    /// Gain that brings the album to the reference loudness.
+    pub album_gain: Option<f32>,
+    /// Largest absolute sample value of the album, 1.0 is full scale.
+    pub album_peak: Option<f32>,
+}
+
+impl ReplayGain {
+    /// Reads the `REPLAYGAIN_*` tags, or the `R128_*` tags if those are missing.
+    pub fn from_metadata(metadata: &Metadata) -> ReplayGain {
+        let value = |key| metadata.get(key).and_then(parse_number);
+        let r128 = |key| value(key).map(|gain| gain / 256.0 + R128_TO_REPLAY_GAIN);
+        ReplayGain {
+            track_gain: value(StandardTagKey::ReplayGainTrackGain)
+                .or_else(|| r128(StandardTagKey::R128TrackGain)),
+            track_peak: value(StandardTagKey::ReplayGainTrackPeak),
+            album_gain: value(StandardTagKey::ReplayGainAlbumGain)
+                .or_else(|| r128(StandardTagKey::R128AlbumGain)),
+            album_peak: value(StandardTagKey::ReplayGainAlbumPeak),
+        }
+    }
+
+    /// The factor to multiply the samples with.
+    ///
+    /// The factor is lowered if the peak shows that the track would clip otherwise.
+    pub fn factor(&self, settings: &ReplayGainSettings) -> f32 {
+        let track = (self.track_gain, self.track_peak);
+        let album = (self.album_gain, self.album_peak);
+        let (preferred, other) = match settings.mode {
+            ReplayGainMode::Track => (track, album),
+            ReplayGainMode::Album => (album, track),
+        };
+        let (gain, peak) = match (preferred, other) {
+            ((Some(gain), peak), _) | (_, (Some(gain), peak)) => (gain + settings.preamp, peak),
+            _ => (settings.fallback_gain, None),
+        };
+
+        let factor = 10f32.powf(gain / 20.0);
+        match peak {
+            Some(peak) if peak > 0.0 => factor.min(1.0 / peak),
+            _ => factor,
+        }
+    }
+}
+
+/// Parses values like `-6.50 dB`.
+fn parse_number(value: &str) -> Option<f32> {
+    let value = value.trim();
+    let value = value
+        .strip_suffix("dB")
+        .or_else(|| value.strip_suffix("db"))
+        .or_else(|| value.strip_suffix("DB"))
+        .unwrap_or(value);
+    value
+        .trim()
+        .parse()
+        .ok()
+        .filter(|value: &f32| value.is_finite())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ReplayGain, ReplayGainMode, ReplayGainSettings};
+    use crate::decoder::Metadata;
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 1e-4, "{actual} != {expected}");
+    }
+
+    #[test]
+    fn reads_tags() {
+        let metadata = Metadata::from_vorbis_comments([
+            ("REPLAYGAIN_TRACK_GAIN", "-6.02 dB"),
+            ("REPLAYGAIN_TRACK_PEAK", "0.25"),
+            ("R128_ALBUM_GAIN", "-512"),
+        ]);
+        let replay_gain = ReplayGain::from_metadata(&metadata);
+        assert_eq!(
+            replay_gain,
+            ReplayGain {
+                track_gain: Some(-6.02),
+                track_peak: Some(0.25),
+                album_gain: Some(3.0),
+                album_peak: None,
+            }
+        );
+    }
+
+    #[test]
+    fn factor() {
+        let replay_gain = ReplayGain {
+            track_gain: Some(-6.0206),
+            track_peak: None,
+            album_gain: None,
+            album_peak: None,
+        };
+        let album = ReplayGainSettings::new(ReplayGainMode::Album);
+        // Falls back to the track gain.
+        assert_close(replay_gain.factor(&album), 0.5);
+        let preamp = ReplayGainSettings {
+            preamp: 6.0206,
+            ..Default::default()
+        };
+        assert_close(replay_gain.factor(&preamp), 1.0);
+
+        let fallback = ReplayGainSettings {
+            fallback_gain: -6.0206,
+            ..Default::default()
+        };
+        assert_close(ReplayGain::default().factor(&fallback), 0.5);
+    }
+
+    #[test]
+    fn peak_prevents_clipping() {
+        let replay_gain = ReplayGain {
+            track_gain: Some(12.0),
+            track_peak: Some(0.8),
+            album_gain: None,
+            album_peak: None,
+        };
+        assert_close(replay_gain.factor(&ReplayGainSettings::default()), 1.25);
+    }
+}
@@ -5,17 +5,19 @@ use symphonia::{
         audio::{AudioBufferRef, SampleBuffer, SignalSpec},
         codecs::{Decoder, DecoderOptions, CODEC_TYPE_NULL},
         errors::Error,
-        formats::{FormatOptions, FormatReader, SeekedTo},
+        formats::{FormatOptions, FormatReader, Packet, SeekedTo},
         io::MediaSourceStream,
-        meta::MetadataOptions,
+        meta::{self, MetadataOptions, MetadataRevision, StandardVisualKey, Value},
         probe::Hint,
         units::{self, Time},
     },
     default::get_probe,
 };
 
-use super::DecoderError;
+use super::metadata::{Metadata, MetadataState, Picture, PictureKind, StandardTagKey, Tag};
+use super::{DecoderError, DecoderSettings, SeekMode};
 use crate::common::{ChannelCount, Sample, SampleRate};
+use crate::conversions::{ChannelLayout, SpeakerSet};
 use crate::{source, Source};
 
 // Decoder errors are not considered fatal.
@@ -27,17 +29,25 @@ pub(crate) struct SymphoniaDecoder {
     decoder: Box<dyn Decoder>,
     current_span_offset: usize,
     format: Box<dyn FormatReader>,
+    /// Track that is decoded, packets of the other tracks are skipped.
+    track_id: u32,
+    seek_mode: SeekMode,
     total_duration: Option<Time>,
     buffer: SampleBuffer<Sample>,
     spec: SignalSpec,
+    metadata: MetadataState,
+    /// Metadata found in front of the container, like an ID3v2 tag.
+    probed_metadata: Option<MetadataRevision>,
+    /// `true` once the container has reported metadata.
+    container_metadata: bool,
 }
 
 impl SymphoniaDecoder {
     pub(crate) fn new(
         mss: MediaSourceStream,
-        extension: Option<&str>,
+        settings: &DecoderSettings,
     ) -> Result<Self, DecoderError> {
-        match SymphoniaDecoder::init(mss, extension) {
+        match SymphoniaDecoder::init(mss, settings) {
             Err(e) => match e {
                 Error::IoError(e) => Err(DecoderError::IoError(e.to_string())),
                 Error::DecodeError(e) => Err(DecoderError::DecodeError(e)),
@@ -58,53 +68,79 @@ impl SymphoniaDecoder {
         self.format.into_inner()
     }
 
+    #[inline]
+    pub(crate) fn metadata(&self) -> &Metadata {
+        self.metadata.current()
+    }
+
+    #[inline]
+    pub(crate) fn metadata_state(&mut self) -> &mut MetadataState {
+        &mut self.metadata
+    }
+
+    #[inline]
+    pub(crate) fn metadata_revision(&self) -> u64 {
+        self.metadata.revision()
+    }
+
+    /// Picks up metadata the container has read since the last call.
+    fn poll_metadata(&mut self) {
+        let mut log = self.format.metadata();
+        if log.is_latest() && (self.container_metadata || log.current().is_none()) {
+            return;
+        }
+        self.container_metadata = true;
+        let metadata = convert_metadata(self.probed_metadata.as_ref(), log.skip_to_latest());
+        self.metadata.update(metadata);
+    }
+
     fn init(
         mss: MediaSourceStream,
-        extension: Option<&str>,
+        settings: &DecoderSettings,
     ) -> symphonia::core::errors::Result<Option<SymphoniaDecoder>> {
         let mut hint = Hint::new();
-        if let Some(ext) = extension {
+        if let Some(ext) = settings.extension() {
             hint.with_extension(ext);
         }
+        if let Some(mime_type) = &settings.mime_type {
+            hint.mime_type(mime_type);
+        }
         let format_opts: FormatOptions = FormatOptions {
-            enable_gapless: true,
+            enable_gapless: settings.gapless,
             ..Default::default()
         };
         let metadata_opts: MetadataOptions = Default::default();
         let mut probed = get_probe().format(&hint, mss, &format_opts, &metadata_opts)?;
-
-        let stream = match probed.format.default_track() {
-            Some(stream) => stream,
-            None => return Ok(None),
-        };
-
-        // Select the first supported track
-        let track_id = probed
-            .format
-            .tracks()
-            .iter()
-            .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
-            .ok_or(symphonia::core::errors::Error::Unsupported(
-                "No track with supported codec",
-            ))?
-            .id;
-
-        let track = match probed
-            .format
-            .tracks()
-            .iter()
-            .find(|track| track.id == track_id)
-        {
-            Some(track) => track,
-            None => return Ok(None),
+        let probed_metadata = probed
+            .metadata
+            .get()
+            .and_then(|mut log| log.skip_to_latest().cloned());
+
+        let tracks = probed.format.tracks();
+        if tracks.is_empty() {
+            return Ok(None);
+        }
+        let track = match settings.track {
+            Some(index) => match tracks.get(index) {
+                Some(track) => track,
+                None => return Ok(None),
+            },
+            // Select the first supported track
+            None => tracks
+                .iter()
+                .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+                .ok_or(symphonia::core::errors::Error::Unsupported(
+                    "No track with supported codec",
+                ))?,
         };
+        let track_id = track.id;
 
         let mut decoder = symphonia::default::get_codecs()
             .make(&track.codec_params, &DecoderOptions::default())?;
-        let total_duration = stream
+        let total_duration = track
             .codec_params
             .time_base
-            .zip(stream.codec_params.n_frames)
+            .zip(track.codec_params.n_frames)
             .map(|(base, spans)| base.calc_time(spans));
 
         let mut decode_errors: usize = 0;
@@ -137,16 +173,35 @@ impl SymphoniaDecoder {
         };
         let spec = decoded.spec().to_owned();
         let buffer = SymphoniaDecoder::get_buffer(decoded, &spec);
+        let mut format = probed.format;
+        let mut log = format.metadata();
+        let container_metadata = log.skip_to_latest().is_some();
+        let metadata = convert_metadata(probed_metadata.as_ref(), log.current());
         Ok(Some(SymphoniaDecoder {
             decoder,
             current_span_offset: 0,
-            format: probed.format,
+            format,
+            track_id,
+            seek_mode: settings.seek_mode,
             total_duration,
             buffer,
             spec,
+            metadata: MetadataState::new(metadata),
+            probed_metadata,
+            container_metadata,
         }))
     }
 
+    /// Returns the next packet of the decoded track.
+    fn next_packet(&mut self) -> symphonia::core::errors::Result<Packet> {
+        loop {
+            let packet = self.format.next_packet()?;
+            if packet.track_id() == self.track_id {
+                return Ok(packet);
+            }
+        }
+    }
+
     #[inline]
     fn get_buffer(decoded: AudioBufferRef, spec: &SignalSpec) -> SampleBuffer<Sample> {
         let duration = units::Duration::from(decoded.capacity() as u64);
@@ -177,8 +232,21 @@ impl Source for SymphoniaDecoder {
         self.total_duration.map(time_to_duration)
     }
 
+    #[inline]
+    fn channel_layout(&self) -> Option<ChannelLayout> {
+        // Symphonia orders the channels like WAV, so the bits map directly.
+        let channels = self.spec.channels;
+        if channels.count() == 1 {
+            return Some(ChannelLayout::Mono);
+        }
+        let layout = SpeakerSet::from_wav_mask(channels.bits())
+            .map(ChannelLayout::new)
+            .unwrap_or(ChannelLayout::Discrete(channels.count() as ChannelCount));
+        Some(layout)
+    }
+
     fn try_seek(&mut self, pos: Duration) -> Result<(), source::SeekError> {
-        use symphonia::core::formats::{SeekMode, SeekTo};
+        use symphonia::core::formats::{self, SeekTo};
 
         let seek_beyond_end = self
             .total_duration()
@@ -194,16 +262,24 @@ impl Source for SymphoniaDecoder {
         // make sure the next sample is for the right channel
         let to_skip = self.current_span_offset % self.channels() as usize;
 
-        let seek_res = self
+        let mode = match self.seek_mode {
+            SeekMode::Accurate => formats::SeekMode::Accurate,
+            SeekMode::Coarse => formats::SeekMode::Coarse,
+        };
+        let mut seek_res = self
             .format
             .seek(
-                SeekMode::Accurate,
+                mode,
                 SeekTo::Time {
                     time,
-                    track_id: None,
+                    track_id: Some(self.track_id),
                 },
             )
             .map_err(SeekError::BaseSeek)?;
+        if self.seek_mode == SeekMode::Coarse {
+            // Continue where the format reader went, it may even be after `time`.
+            seek_res.required_ts = seek_res.actual_ts;
+        }
 
         self.refine_position(seek_res)?;
         self.current_span_offset += to_skip;
@@ -270,7 +346,7 @@ impl SymphoniaDecoder {
     fn refine_position(&mut self, seek_res: SeekedTo) -> Result<(), source::SeekError> {
         let mut samples_to_pass = seek_res.required_ts - seek_res.actual_ts;
         let packet = loop {
-            let candidate = self.format.next_packet().map_err(SeekError::Refining)?;
+            let candidate = self.next_packet().map_err(SeekError::Refining)?;
             if candidate.dur() > samples_to_pass {
                 break candidate;
             } else {
@@ -281,7 +357,7 @@ impl SymphoniaDecoder {
         let mut decoded = self.decoder.decode(&packet);
         for _ in 0..MAX_DECODE_RETRIES {
             if decoded.is_err() {
-                let packet = self.format.next_packet().map_err(SeekError::Retrying)?;
+                let packet = self.next_packet().map_err(SeekError::Retrying)?;
                 decoded = self.decoder.decode(&packet);
             }
         }
@@ -294,6 +370,65 @@ impl SymphoniaDecoder {
     }
 }
 
+/// Combines the metadata in front of the container with that of the container.
+fn convert_metadata(
+    probed: Option<&MetadataRevision>,
+    container: Option<&MetadataRevision>,
+) -> Metadata {
+    let revisions = || probed.into_iter().chain(container);
+    let tags = revisions()
+        .flat_map(|revision| revision.tags())
+        .filter_map(convert_tag)
+        .collect();
+    let pictures = revisions()
+        .flat_map(|revision| revision.visuals())
+        .map(|visual| Picture {
+            media_type: visual.media_type.clone(),
+            kind: visual.usage.map(|usage| match usage {
+                StandardVisualKey::FrontCover => PictureKind::FrontCover,
+                StandardVisualKey::BackCover => PictureKind::BackCover,
+                StandardVisualKey::LeadArtistPerformerSoloist
+                | StandardVisualKey::ArtistPerformer => PictureKind::Artist,
+                _ => PictureKind::Other,
+            }),
+            data: visual.data.clone(),
+        })
+        .collect();
+    Metadata::with_tags(tags, pictures)
+}
+
+fn convert_tag(tag: &meta::Tag) -> Option<Tag> {
+    use meta::StandardTagKey as Key;
+    if let Value::Binary(_) = tag.value {
+        return None;
+    }
+    let standard_key = match tag.std_key {
+        Some(Key::TrackTitle) => Some(StandardTagKey::TrackTitle),
+        Some(Key::Artist) => Some(StandardTagKey::Artist),
+        Some(Key::Album) => Some(StandardTagKey::Album),
+        Some(Key::AlbumArtist) => Some(StandardTagKey::AlbumArtist),
+        Some(Key::TrackNumber) => Some(StandardTagKey::TrackNumber),
+        Some(Key::TrackTotal) => Some(StandardTagKey::TrackTotal),
+        Some(Key::DiscNumber) => Some(StandardTagKey::DiscNumber),
+        Some(Key::DiscTotal) => Some(StandardTagKey::DiscTotal),
+        Some(Key::Date) => Some(StandardTagKey::Date),
+        Some(Key::Genre) => Some(StandardTagKey::Genre),
+        Some(Key::Composer) => Some(StandardTagKey::Composer),
+        Some(Key::Comment) => Some(StandardTagKey::Comment),
+        Some(Key::ReplayGainTrackGain) => Some(StandardTagKey::ReplayGainTrackGain),
+        Some(Key::ReplayGainTrackPeak) => Some(StandardTagKey::ReplayGainTrackPeak),
+        Some(Key::ReplayGainAlbumGain) => Some(StandardTagKey::ReplayGainAlbumGain),
+        Some(Key::ReplayGainAlbumPeak) => Some(StandardTagKey::ReplayGainAlbumPeak),
+        _ => StandardTagKey::from_name(&tag.key),
+    };
+    Some(Tag {
+        key: tag.key.clone(),
+        // RIFF INFO values can be padded with null characters.
+        value: tag.value.to_string().trim_end_matches('\0').to_owned(),
+        standard_key,
+    })
+}
+
 fn skip_back_a_tiny_bit(
     Time {
         mut seconds,
@@ -325,11 +460,11 @@ impl Iterator for SymphoniaDecoder {
     #[inline]
     fn next(&mut self) -> Option<Self::Item> {
         if self.current_span_offset >= self.buffer.len() {
-            let packet = self.format.next_packet().ok()?;
+            let packet = self.next_packet().ok()?;
             let mut decoded = self.decoder.decode(&packet);
             for _ in 0..MAX_DECODE_RETRIES {
                 if decoded.is_err() {
-                    let packet = self.format.next_packet().ok()?;
+                    let packet = self.next_packet().ok()?;
                     decoded = self.decoder.decode(&packet);
                 }
             }
@@ -337,6 +472,7 @@ impl Iterator for SymphoniaDecoder {
             decoded.spec().clone_into(&mut self.spec);
             self.buffer = SymphoniaDecoder::get_buffer(decoded, &self.spec);
             self.current_span_offset = 0;
+            self.poll_metadata();
         }
 
         let sample = *self.buffer.samples().get(self.current_span_offset)?;
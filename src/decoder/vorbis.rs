@@ -5,8 +5,13 @@ use crate::source::SeekError;
 use crate::Source;
 
 use crate::common::{ChannelCount, Sample, SampleRate};
+use crate::conversions::ChannelLayout;
+use crate::decoder::metadata::{Metadata, MetadataState};
+use crate::decoder::DecoderSettings;
+use lewton::audio::AudioReadError;
 use lewton::inside_ogg::OggStreamReader;
 use lewton::samples::InterleavedSamples;
+use lewton::VorbisError;
 
 /// Decoder for an OGG file that contains Vorbis sound format.
 pub struct VorbisDecoder<R>
@@ -16,42 +21,172 @@ where
     stream_reader: OggStreamReader<R>,
     current_data: Vec<Sample>,
     next: usize,
+    metadata: MetadataState,
+    /// Serial of the logical stream the metadata belongs to, changes in chained files.
+    stream_serial: u32,
+    /// Granule position of the first sample, a stream cut from a longer one can start later.
+    start_granule: u64,
+    /// Granule position of the last sample, if the file is not chained.
+    end_granule: Option<u64>,
 }
 
 impl<R> VorbisDecoder<R>
 where
     R: Read + Seek,
 {
-    /// Attempts to decode the data as ogg/vorbis.
-    pub fn new(mut data: R) -> Result<VorbisDecoder<R>, R> {
+    /// Attempts to decode the data as ogg/vorbis. With `settings.gapless` the encoder delay is
+    /// removed.
+    pub(crate) fn new(mut data: R, settings: &DecoderSettings) -> Result<VorbisDecoder<R>, R> {
         if !is_vorbis(data.by_ref()) {
             return Err(data);
         }
 
-        let stream_reader = OggStreamReader::new(data).expect("should still be vorbis");
-        Ok(Self::from_stream_reader(stream_reader))
+        let last_page = read_last_page(data.by_ref());
+        let mut decoder = Self::new_unprobed(data, settings).expect("should still be vorbis");
+        decoder.end_granule = last_page
+            .filter(|page| page.serial == decoder.stream_serial)
+            .map(|page| page.granule.max(decoder.start_granule));
+        Ok(decoder)
     }
-    pub fn from_stream_reader(mut stream_reader: OggStreamReader<R>) -> Self {
-        let mut data = match stream_reader.read_dec_packet_generic::<InterleavedSamples<Sample>>() {
-            Ok(Some(d)) => d.samples,
-            _ => Vec::new(),
-        };
 
-        // The first packet is always empty, therefore
-        // we need to read the second frame to get some data
-        if let Ok(Some(mut d)) =
-            stream_reader.read_dec_packet_generic::<InterleavedSamples<Sample>>()
-        {
-            data.append(&mut d.samples);
-        }
+    /// Decodes the data as Ogg/Vorbis without checking the format first, which would need
+    /// to seek back.
+    pub(crate) fn new_unprobed(data: R, settings: &DecoderSettings) -> Option<VorbisDecoder<R>> {
+        let stream_reader = OggStreamReader::new(data).ok()?;
+        Some(Self::with_gapless(stream_reader, settings.gapless))
+    }
+
+    pub fn from_stream_reader(stream_reader: OggStreamReader<R>) -> Self {
+        Self::with_gapless(stream_reader, true)
+    }
+
+    fn with_gapless(mut stream_reader: OggStreamReader<R>, gapless: bool) -> Self {
+        let (data, start_granule) = read_first_page(&mut stream_reader, gapless);
 
         VorbisDecoder {
+            metadata: MetadataState::new(comments(&stream_reader)),
+            stream_serial: stream_reader.stream_serial(),
             stream_reader,
             current_data: data,
             next: 0,
+            start_granule,
+            end_granule: None,
         }
     }
 
+    #[inline]
+    pub fn metadata(&self) -> &Metadata {
+        self.metadata.current()
+    }
+
+    #[inline]
+    pub(crate) fn metadata_state(&mut self) -> &mut MetadataState {
+        &mut self.metadata
+    }
+
+    #[inline]
+    pub(crate) fn metadata_revision(&self) -> u64 {
+        self.metadata.revision()
+    }
+
+    /// Decodes the next packet and picks up the comments of a new stream in a chained file.
+    fn next_packet(&mut self) -> Option<Vec<Sample>> {
+        let packet = read_packet(&mut self.stream_reader);
+        self.update_stream();
+        packet
+    }
+
+    fn update_stream(&mut self) {
+        if self.stream_reader.stream_serial() != self.stream_serial {
+            self.stream_serial = self.stream_reader.stream_serial();
+            self.metadata.update(comments(&self.stream_reader));
+        }
+    }
+
+    /// Finds the page before `granule` by bisection, then decodes to the packet with it. The
+    /// granule positions of the pages tell where the decoded samples belong.
+    fn seek_granule(&mut self, granule: u64) -> Result<(), VorbisError> {
+        let mut goal = granule;
+        // The seek can land after the position when a packet continues on the next page.
+        let mut step = self.sample_rate() as u64 / 4;
+        loop {
+            self.stream_reader.seek_absgp_pg(goal)?;
+            if let Some(data) = self.decode_to(granule)? {
+                self.current_data = data;
+                self.next = 0;
+                return Ok(());
+            }
+            if goal == 0 {
+                return self.seek_from_start(granule - self.start_granule);
+            }
+            goal = goal.saturating_sub(step);
+            step *= 2;
+        }
+    }
+
+    /// Decodes from where the stream reader is up to the packet with `granule`, returns the
+    /// samples from there on. Returns `None` if the reader is already past it.
+    fn decode_to(&mut self, granule: u64) -> Result<Option<Vec<Sample>>, VorbisError> {
+        let channels = self.channels() as usize;
+        let mut data = Vec::new();
+        let mut packets = 0;
+        loop {
+            let packet = match decode_packet(&mut self.stream_reader) {
+                Ok(Some(packet)) => packet,
+                Ok(None) => return Ok(Some(Vec::new())),
+                // The first packet can be the end of one that started before the page.
+                Err(VorbisError::BadAudio(_)) => {
+                    data.clear();
+                    packets = 0;
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+            // The first packet only primes the decoder, the output of the second depends on
+            // the first, which might not have been decoded correctly.
+            packets += 1;
+            if packets > 2 {
+                data.extend(packet);
+            }
+            let Some(end) = self.stream_reader.get_last_absgp() else {
+                continue;
+            };
+            let start = end as i64 - (data.len() / channels) as i64;
+            if start > granule as i64 {
+                return Ok(None);
+            }
+            if end > granule {
+                data.drain(..(granule as i64 - start) as usize * channels);
+                return Ok(Some(data));
+            }
+            data.clear();
+        }
+    }
+
+    /// Decodes from the start to `position`, for chained files whose granule positions start
+    /// over in each stream, and for files whose end is not known.
+    fn seek_from_start(&mut self, position: u64) -> Result<(), VorbisError> {
+        self.stream_reader.seek_absgp_pg(0)?;
+        // Seek positions do not include the encoder delay, also without gapless playback.
+        (self.current_data, _) = read_first_page(&mut self.stream_reader, true);
+        self.next = 0;
+        self.update_stream();
+
+        let mut skip = position as usize * self.channels() as usize;
+        while skip >= self.current_data.len() {
+            skip -= self.current_data.len();
+            match self.next_packet() {
+                Some(data) => self.current_data = data,
+                None => {
+                    self.current_data.clear();
+                    return Ok(());
+                }
+            }
+        }
+        self.next = skip;
+        Ok(())
+    }
+
     #[inline]
     pub fn into_inner(self) -> OggStreamReader<R> {
         self.stream_reader
@@ -79,23 +214,35 @@ where
 
     #[inline]
     fn total_duration(&self) -> Option<Duration> {
-        None
-    }
-
-    /// seek is broken, https://github.com/RustAudio/lewton/issues/73.
-    // We could work around it by:
-    //  - using unsafe to create an instance of Self
-    //  - use mem::swap to turn the &mut self into a mut self
-    //  - take out the underlying Read+Seek
-    //  - make a new self and seek
-    //
-    // If this issue is fixed use the implementation in
-    // commit: 3bafe32388b4eb7a48c6701e6c65044dc8c555e6
+        let samples = self.end_granule? - self.start_granule;
+        Some(Duration::from_secs_f64(
+            samples as f64 / self.sample_rate() as f64,
+        ))
+    }
+
     #[inline]
-    fn try_seek(&mut self, _: Duration) -> Result<(), SeekError> {
-        Err(SeekError::NotSupported {
-            underlying_source: std::any::type_name::<Self>(),
-        })
+    fn channel_layout(&self) -> Option<ChannelLayout> {
+        super::xiph_channel_layout(self.channels())
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        let position = (pos.as_secs_f64() * self.sample_rate() as f64) as u64;
+        // make sure the next sample is for the right channel
+        let to_skip = self.next % self.channels() as usize;
+
+        let result = match self.end_granule {
+            // saturate pos at the end of the source
+            Some(end_granule) => {
+                self.seek_granule((self.start_granule + position).min(end_granule))
+            }
+            None => self.seek_from_start(position),
+        };
+        result.map_err(SeekError::LewtonDecoder)?;
+
+        for _ in 0..to_skip {
+            self.next();
+        }
+        Ok(())
     }
 }
 
@@ -107,30 +254,14 @@ where
 
     #[inline]
     fn next(&mut self) -> Option<Self::Item> {
-        if let Some(sample) = self.current_data.get(self.next).copied() {
-            self.next += 1;
-            if self.current_data.is_empty() {
-                if let Ok(Some(data)) = self
-                    .stream_reader
-                    .read_dec_packet_generic::<InterleavedSamples<Sample>>()
-                {
-                    self.current_data = data.samples;
-                    self.next = 0;
-                }
-            }
-            Some(sample)
-        } else {
-            if let Ok(Some(data)) = self
-                .stream_reader
-                .read_dec_packet_generic::<InterleavedSamples<Sample>>()
-            {
-                self.current_data = data.samples;
-                self.next = 0;
-            }
-            let sample = self.current_data.get(self.next).copied();
-            self.next += 1;
-            sample
+        // Packets can be empty, for example at the end of a part of a chained file.
+        while self.next >= self.current_data.len() {
+            self.current_data = self.next_packet()?;
+            self.next = 0;
         }
+        let sample = self.current_data[self.next];
+        self.next += 1;
+        Some(sample)
     }
 
     #[inline]
@@ -139,6 +270,97 @@ where
     }
 }
 
+fn comments<R>(stream_reader: &OggStreamReader<R>) -> Metadata
+where
+    R: Read + Seek,
+{
+    Metadata::from_vorbis_comments(stream_reader.comment_hdr.comment_list.iter().cloned())
+}
+
+/// Decodes the packets of the first page of the stream and removes the encoder delay if
+/// `gapless` is set. Also returns the granule position of the first sample.
+///
+/// The first packet only primes the decoder. If the granule position at the end of the first
+/// page is lower than the number of samples decoded up to there, the difference is delay that
+/// is cut from the start, see section A.2 of the Vorbis I specification. If it is higher the
+/// stream starts at that offset. Lewton already cuts the padding at the end of the stream,
+/// except when the stream is a single page.
+fn read_first_page<R>(stream_reader: &mut OggStreamReader<R>, gapless: bool) -> (Vec<Sample>, u64)
+where
+    R: Read + Seek,
+{
+    let channels = stream_reader.ident_hdr.audio_channels as usize;
+    // A reader that was used before is past the first page.
+    let at_start = stream_reader.get_last_absgp().is_none();
+    let mut data = Vec::new();
+    while let Some(mut packet) = read_packet(stream_reader) {
+        data.append(&mut packet);
+        if stream_reader.get_last_absgp().is_some() {
+            break;
+        }
+    }
+    let Some(granule) = stream_reader.get_last_absgp().filter(|_| at_start) else {
+        return (data, 0);
+    };
+    let start_granule = granule.saturating_sub((data.len() / channels) as u64);
+
+    let next = read_packet(stream_reader);
+    let valid = granule as usize * channels;
+    if gapless && valid < data.len() {
+        if next.is_some() {
+            data.drain(..data.len() - valid);
+        } else {
+            data.truncate(valid);
+        }
+    }
+    data.extend(next.unwrap_or_default());
+    (data, start_granule)
+}
+
+/// Decodes the next packet, ends the stream on errors.
+fn read_packet<R>(stream_reader: &mut OggStreamReader<R>) -> Option<Vec<Sample>>
+where
+    R: Read + Seek,
+{
+    decode_packet(stream_reader).ok().flatten()
+}
+
+/// Decodes the next packet, with the channels in the order of [`super::xiph_channel_layout`].
+/// Skips the header packets, which come again after seeking to the start.
+fn decode_packet<R>(
+    stream_reader: &mut OggStreamReader<R>,
+) -> Result<Option<Vec<Sample>>, VorbisError>
+where
+    R: Read + Seek,
+{
+    let mut samples = loop {
+        match stream_reader.read_dec_packet_generic::<InterleavedSamples<Sample>>() {
+            Ok(Some(data)) => break data.samples,
+            Ok(None) => return Ok(None),
+            Err(VorbisError::BadAudio(AudioReadError::AudioIsHeader)) => continue,
+            Err(err) => return Err(err),
+        }
+    };
+    // Vorbis puts the center next to front left and the low frequency channel last, see
+    // section 4.3.9 of the Vorbis I specification.
+    let order: &[usize] = match stream_reader.ident_hdr.audio_channels {
+        3 => &[0, 2, 1],
+        5 => &[0, 2, 1, 3, 4],
+        6 => &[0, 2, 1, 5, 3, 4],
+        7 => &[0, 2, 1, 6, 5, 3, 4],
+        8 => &[0, 2, 1, 7, 5, 6, 3, 4],
+        _ => return Ok(Some(samples)),
+    };
+    let mut frame = [0.0; 8];
+    for chunk in samples.chunks_exact_mut(order.len()) {
+        frame[..order.len()].copy_from_slice(chunk);
+        for (sample, &channel) in chunk.iter_mut().zip(order) {
+            *sample = frame[channel];
+        }
+    }
+    Ok(Some(samples))
+}
+
 /// Returns true if the stream contains Vorbis data, then resets it to where it was.
 fn is_vorbis<R>(mut data: R) -> bool
 where
@@ -149,3 +371,59 @@ where
     let _ = data.seek(SeekFrom::Start(stream_pos));
     result
 }
+
+/// Granule position and serial of an Ogg page.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct PageInfo {
+    granule: u64,
+    serial: u32,
+}
+
+/// Returns the last page that ends a packet, then resets the stream to where it was. Its
+/// granule position is the number of samples per channel at the end of the stream.
+fn read_last_page<R>(mut data: R) -> Option<PageInfo>
+where
+    R: Read + Seek,
+{
+    let stream_pos = data.stream_position().ok()?;
+    let page = read_last_page_inner(data.by_ref());
+    data.seek(SeekFrom::Start(stream_pos)).ok()?;
+    page
+}
+
+fn read_last_page_inner<R>(mut data: R) -> Option<PageInfo>
+where
+    R: Read + Seek,
+{
+    const CHUNK_SIZE: u64 = 64 * 1024;
+    // The part of a page header up to the serial.
+    const HEADER_LEN: usize = 18;
+
+    let mut chunk_end = data.seek(SeekFrom::End(0)).ok()?;
+    let mut buffer = Vec::new();
+    while chunk_end > 0 {
+        let chunk_start = chunk_end.saturating_sub(CHUNK_SIZE);
+        data.seek(SeekFrom::Start(chunk_start)).ok()?;
+        buffer.clear();
+        // Overlaps the next chunk by a header, one can start at the end of this chunk.
+        data.by_ref()
+            .take(chunk_end - chunk_start + HEADER_LEN as u64)
+            .read_to_end(&mut buffer)
+            .ok()?;
+        let page = buffer
+            .windows(HEADER_LEN)
+            .rev()
+            .filter(|header| header.starts_with(b"OggS") && header[4] == 0)
+            .map(|header| PageInfo {
+                granule: u64::from_le_bytes(header[6..14].try_into().unwrap()),
+                serial: u32::from_le_bytes(header[14..18].try_into().unwrap()),
+            })
+            // Pages without the end of a packet have no granule position.
+            .find(|page| page.granule != u64::MAX);
+        if page.is_some() {
+            return page;
+        }
+        chunk_end = chunk_start;
+    }
+    None
+}
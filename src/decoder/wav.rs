@@ -31,7 +31,13 @@ where
             return Err(data);
         }
 
-        let reader = WavReader::new(data).expect("should still be wav");
+        Ok(Self::new_unprobed(data).expect("should still be wav"))
+    }
+
+    /// Decodes the data as WAV without checking the format first, which would need to seek
+    /// back.
+    pub fn new_unprobed(data: R) -> Option<WavDecoder<R>> {
+        let reader = WavReader::new(data).ok()?;
         let spec = reader.spec();
         let len = reader.len() as u64;
         let reader = SamplesIterator {
@@ -49,7 +55,7 @@ where
             Duration::new(secs, nanos as u32)
         };
 
-        Ok(WavDecoder {
+        Some(WavDecoder {
             reader,
             total_duration,
             sample_rate: sample_rate as SampleRate,
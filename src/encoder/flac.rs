@@ -0,0 +1,562 @@
+use std::io::Write;
+
+use dasp_sample::Sample as _;
+use dasp_sample::I24;
+
+use super::{Encoder, EncoderError, SampleFormat};
+use crate::common::{ChannelCount, SampleRate};
+use crate::Sample;
+
+/// Number of frames in a FLAC block.
+const BLOCK_SIZE: usize = 4096;
+
+/// Highest partition order tried for the residual.
+const MAX_PARTITION_ORDER: u32 = 8;
+
+/// Encoder for the FLAC format.
+///
+/// Blocks are written as soon as they are full, so the output does not need to be seekable.
+/// Each channel is predicted with the best fixed polynomial predictor of the FLAC format. The
+/// total length and checksum in the header are left unset, as allowed by the format.
+pub struct FlacEncoder<W>
+where
+    W: Write,
+{
+    writer: Option<W>,
+    channels: usize,
+    bits_per_sample: u32,
+    sample_rate_code: u64,
+    format: SampleFormat,
+    // Interleaved samples of the block being collected.
+    block: Vec<i32>,
+    frame_number: u64,
+    frame: BitWriter,
+    // Scratch buffers, reused between blocks.
+    channel: Vec<i64>,
+    residual: Vec<u64>,
+}
+
+impl<W> FlacEncoder<W>
+where
+    W: Write,
+{
+    /// Writes the header of a FLAC file with samples in `format`, which must be
+    /// [`SampleFormat::I16`] or [`SampleFormat::I24`]. FLAC stores up to 8 channels.
+    pub fn new(
+        mut writer: W,
+        channels: ChannelCount,
+        sample_rate: SampleRate,
+        format: SampleFormat,
+    ) -> Result<FlacEncoder<W>, EncoderError> {
+        if !matches!(format, SampleFormat::I16 | SampleFormat::I24) {
+            return Err(EncoderError::UnsupportedSampleFormat(format));
+        }
+        if !(1..=8).contains(&channels) {
+            return Err(EncoderError::UnsupportedChannelCount(channels));
+        }
+        if !(1..(1 << 20)).contains(&sample_rate) {
+            return Err(EncoderError::UnsupportedSampleRate(sample_rate));
+        }
+        let bits_per_sample = format.bits_per_sample() as u32;
+
+        let mut header = BitWriter::default();
+        header.write(u32::from_be_bytes(*b"fLaC") as u64, 32);
+        // Last metadata block, STREAMINFO, 34 bytes long.
+        header.write(1, 1);
+        header.write(0, 7);
+        header.write(34, 24);
+        header.write(BLOCK_SIZE as u64, 16);
+        header.write(BLOCK_SIZE as u64, 16);
+        // Unknown frame sizes.
+        header.write(0, 24);
+        header.write(0, 24);
+        header.write(sample_rate as u64, 20);
+        header.write(channels as u64 - 1, 3);
+        header.write(bits_per_sample as u64 - 1, 5);
+        // Unknown total length and checksum.
+        header.write(0, 36);
+        header.write(0, 64);
+        header.write(0, 64);
+        writer.write_all(&header.bytes)?;
+
+        Ok(FlacEncoder {
+            writer: Some(writer),
+            channels: channels as usize,
+            bits_per_sample,
+            sample_rate_code: sample_rate_code(sample_rate),
+            format,
+            block: Vec::with_capacity(BLOCK_SIZE * channels as usize),
+            frame_number: 0,
+            frame: BitWriter::default(),
+            channel: Vec::with_capacity(BLOCK_SIZE),
+            residual: Vec::with_capacity(BLOCK_SIZE),
+        })
+    }
+
+    fn write_frame(&mut self) -> Result<(), EncoderError> {
+        let frames = self.block.len() / self.channels;
+        if frames == 0 {
+            return Ok(());
+        }
+
+        self.frame.clear();
+        // Sync code, fixed block size.
+        self.frame.write(0b1111_1111_1111_1000, 16);
+        // Block size in 16 bits at the end of the header, independent channels.
+        self.frame.write(0b0111, 4);
+        self.frame.write(self.sample_rate_code, 4);
+        self.frame.write(self.channels as u64 - 1, 4);
+        self.frame.write(
+            if self.bits_per_sample == 16 {
+                0b100
+            } else {
+                0b110
+            },
+            3,
+        );
+        self.frame.write(0, 1);
+        write_utf8_number(&mut self.frame, self.frame_number);
+        self.frame.write(frames as u64 - 1, 16);
+        let crc = crc8(&self.frame.bytes);
+        self.frame.write(crc as u64, 8);
+
+        for channel in 0..self.channels {
+            self.channel.clear();
+            self.channel.extend(
+                self.block
+                    .iter()
+                    .skip(channel)
+                    .step_by(self.channels)
+                    .map(|&sample| sample as i64),
+            );
+            write_subframe(
+                &mut self.frame,
+                &self.channel,
+                self.bits_per_sample,
+                &mut self.residual,
+            );
+        }
+
+        self.frame.align();
+        let crc = crc16(&self.frame.bytes);
+        self.frame.write(crc as u64, 16);
+
+        let writer = self.writer.as_mut().ok_or(EncoderError::Finished)?;
+        writer.write_all(&self.frame.bytes)?;
+        self.block.clear();
+        self.frame_number += 1;
+        Ok(())
+    }
+}
+
+impl<W> Encoder for FlacEncoder<W>
+where
+    W: Write,
+{
+    fn write_samples(&mut self, samples: &[Sample]) -> Result<(), EncoderError> {
+        if self.writer.is_none() {
+            return Err(EncoderError::Finished);
+        }
+        for &sample in samples {
+            let value = match self.format {
+                SampleFormat::I16 => sample.to_sample::<i16>() as i32,
+                _ => sample.to_sample::<I24>().inner(),
+            };
+            self.block.push(value);
+            if self.block.len() == BLOCK_SIZE * self.channels {
+                self.write_frame()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), EncoderError> {
+        if self.writer.is_none() {
+            return Ok(());
+        }
+        // Drop an incomplete last frame.
+        let whole_frames = self.block.len() - self.block.len() % self.channels;
+        self.block.truncate(whole_frames);
+        self.write_frame()?;
+        if let Some(mut writer) = self.writer.take() {
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl<W> Drop for FlacEncoder<W>
+where
+    W: Write,
+{
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+/// Code of a sample rate in a frame header, `0` for the one in STREAMINFO.
+fn sample_rate_code(sample_rate: SampleRate) -> u64 {
+    match sample_rate {
+        88200 => 0b0001,
+        176400 => 0b0010,
+        192000 => 0b0011,
+        8000 => 0b0100,
+        16000 => 0b0101,
+        22050 => 0b0110,
+        24000 => 0b0111,
+        32000 => 0b1000,
+        44100 => 0b1001,
+        48000 => 0b1010,
+        96000 => 0b1011,
+        _ => 0b0000,
+    }
+}
+
+const SUBFRAME_CONSTANT: u64 = 0b00_0000;
+const SUBFRAME_VERBATIM: u64 = 0b00_0001;
+const SUBFRAME_FIXED: u64 = 0b00_1000;
+
+/// Writes the subframe type with padding and without wasted bits.
+fn write_subframe_header(out: &mut BitWriter, subframe_type: u64) {
+    out.write(0, 1);
+    out.write(subframe_type, 6);
+    out.write(0, 1);
+}
+
+/// Encodes one channel of a block with the smallest of the constant, fixed and verbatim
+/// subframe types.
+fn write_subframe(out: &mut BitWriter, samples: &[i64], bits: u32, residual: &mut Vec<u64>) {
+    if samples.iter().all(|&sample| sample == samples[0]) {
+        write_subframe_header(out, SUBFRAME_CONSTANT);
+        out.write_signed(samples[0], bits);
+        return;
+    }
+
+    let verbatim_bits = samples.len() as u64 * bits as u64;
+    let mut best: Option<(u32, u64)> = None;
+    for order in 0..=4usize.min(samples.len() - 1) {
+        fixed_residual(samples, order, residual);
+        let (_, residual_bits) = best_partitioning(residual, order);
+        let bits = order as u64 * bits as u64 + residual_bits;
+        if best.is_none_or(|(_, best_bits)| bits < best_bits) {
+            best = Some((order as u32, bits));
+        }
+    }
+
+    match best {
+        Some((order, fixed_bits)) if fixed_bits < verbatim_bits => {
+            let order = order as usize;
+            write_subframe_header(out, SUBFRAME_FIXED | order as u64);
+            for &sample in &samples[..order] {
+                out.write_signed(sample, bits);
+            }
+            fixed_residual(samples, order, residual);
+            let (partition_order, _) = best_partitioning(residual, order);
+            write_residual(out, residual, order, partition_order);
+        }
+        _ => {
+            write_subframe_header(out, SUBFRAME_VERBATIM);
+            for &sample in samples {
+                out.write_signed(sample, bits);
+            }
+        }
+    }
+}
+
+/// Computes the zigzag encoded residual of the fixed predictor of `order`.
+fn fixed_residual(samples: &[i64], order: usize, residual: &mut Vec<u64>) {
+    residual.clear();
+    residual.extend((order..samples.len()).map(|i| {
+        let s = |back: usize| samples[i - back];
+        let error = match order {
+            0 => s(0),
+            1 => s(0) - s(1),
+            2 => s(0) - 2 * s(1) + s(2),
+            3 => s(0) - 3 * s(1) + 3 * s(2) - s(3),
+            _ => s(0) - 4 * s(1) + 6 * s(2) - 4 * s(3) + s(4),
+        };
+        ((error << 1) ^ (error >> 63)) as u64
+    }));
+}
+
+/// Picks the partition order with the fewest bits. Returns the order and the estimated size
+/// of the residual in bits.
+fn best_partitioning(residual: &[u64], predictor_order: usize) -> (u32, u64) {
+    let block_size = residual.len() + predictor_order;
+    let mut best = (0, u64::MAX);
+    for partition_order in 0..=MAX_PARTITION_ORDER {
+        let partitions = 1usize << partition_order;
+        if !block_size.is_multiple_of(partitions) || block_size / partitions <= predictor_order {
+            break;
+        }
+        let size = partition_bounds(block_size, predictor_order, partition_order)
+            .map(|(start, end)| {
+                let part = &residual[start..end];
+                let sum = part.iter().sum::<u64>();
+                rice_bits(
+                    part.len() as u64,
+                    sum,
+                    rice_parameter(part.len() as u64, sum),
+                )
+            })
+            .sum::<u64>();
+        // Coding method and partition order, and one 5 bit parameter per partition.
+        let size = 6 + size + 5 * partitions as u64;
+        if size < best.1 {
+            best = (partition_order, size);
+        }
+    }
+    best
+}
+
+/// Start and end in the residual of each partition. The first partition is shorter by the
+/// predictor order, as the residual does not cover the warm-up samples.
+fn partition_bounds(
+    block_size: usize,
+    predictor_order: usize,
+    partition_order: u32,
+) -> impl Iterator<Item = (usize, usize)> {
+    let partition_len = block_size >> partition_order;
+    (0..1usize << partition_order).map(move |partition| {
+        let start = (partition * partition_len).saturating_sub(predictor_order);
+        let end = (partition + 1) * partition_len - predictor_order;
+        (start, end)
+    })
+}
+
+/// Estimates the best Rice parameter for `len` values adding up to `sum`.
+fn rice_parameter(len: u64, sum: u64) -> u32 {
+    if len == 0 {
+        return 0;
+    }
+    let mean = sum / len;
+    (u64::BITS - mean.leading_zeros()).min(30)
+}
+
+/// Estimates the size of `len` values adding up to `sum`, Rice coded with parameter `k`.
+fn rice_bits(len: u64, sum: u64, k: u32) -> u64 {
+    len * (k as u64 + 1) + (sum >> k)
+}
+
+fn write_residual(out: &mut BitWriter, residual: &[u64], predictor_order: usize, order: u32) {
+    let block_size = residual.len() + predictor_order;
+    let parameters: Vec<u32> = partition_bounds(block_size, predictor_order, order)
+        .map(|(start, end)| {
+            let part = &residual[start..end];
+            rice_parameter(part.len() as u64, part.iter().sum())
+        })
+        .collect();
+
+    // Rice coding with 4 bit parameters when they fit, 5 bit parameters otherwise.
+    let wide = parameters.iter().any(|&k| k > 14);
+    let parameter_bits = if wide { 5 } else { 4 };
+    out.write(wide as u64, 2);
+    out.write(order as u64, 4);
+    for ((start, end), k) in partition_bounds(block_size, predictor_order, order).zip(parameters) {
+        out.write(k as u64, parameter_bits);
+        for &value in &residual[start..end] {
+            out.write_unary(value >> k);
+            out.write(value, k);
+        }
+    }
+}
+
+/// Writes `value` in the UTF-8 like coding of FLAC frame numbers.
+fn write_utf8_number(out: &mut BitWriter, value: u64) {
+    if value < 0x80 {
+        out.write(value, 8);
+        return;
+    }
+    // Each continuation byte holds 6 bits, the lead byte one bit less per continuation byte.
+    let bits = u64::BITS - value.leading_zeros();
+    let continuation_bytes = (1..6).find(|n| bits <= 6 - n + 6 * n).unwrap_or(6);
+    let lead_marker = (0xFF00u64 >> (continuation_bytes + 1)) & 0xFF;
+    out.write(lead_marker | (value >> (6 * continuation_bytes)), 8);
+    for byte in (0..continuation_bytes).rev() {
+        out.write(0x80 | ((value >> (6 * byte)) & 0x3F), 8);
+    }
+}
+
+/// Writes bits most significant first.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    pending: u64,
+    pending_bits: u32,
+}
+
+impl BitWriter {
+    fn clear(&mut self) {
+        self.bytes.clear();
+        self.pending = 0;
+        self.pending_bits = 0;
+    }
+
+    /// Writes the low `bits` bits of `value`, at most 32 at a time.
+    fn write(&mut self, value: u64, bits: u32) {
+        if bits > 32 {
+            self.write(value >> 32, bits - 32);
+            self.write(value & 0xFFFF_FFFF, 32);
+            return;
+        }
+        if bits == 0 {
+            return;
+        }
+        self.pending = (self.pending << bits) | (value & ((1 << bits) - 1));
+        self.pending_bits += bits;
+        while self.pending_bits >= 8 {
+            self.pending_bits -= 8;
+            self.bytes.push((self.pending >> self.pending_bits) as u8);
+        }
+    }
+
+    fn write_signed(&mut self, value: i64, bits: u32) {
+        self.write(value as u64, bits);
+    }
+
+    /// Writes `value` zeros followed by a one.
+    fn write_unary(&mut self, mut value: u64) {
+        while value >= 32 {
+            self.write(0, 32);
+            value -= 32;
+        }
+        self.write(1, value as u32 + 1);
+    }
+
+    /// Pads with zeros to a whole byte.
+    fn align(&mut self) {
+        if self.pending_bits > 0 {
+            self.write(0, 8 - self.pending_bits);
+        }
+    }
+}
+
+const fn crc_table(polynomial: u16, width: u32) -> [u16; 256] {
+    let top = 1u32 << (width - 1);
+    let mask = ((1u32 << width) - 1) as u16;
+    let mut table = [0u16; 256];
+    let mut byte = 0;
+    while byte < 256 {
+        let mut crc = (byte as u32) << (width - 8);
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & top != 0 {
+                (crc << 1) ^ polynomial as u32
+            } else {
+                crc << 1
+            };
+            bit += 1;
+        }
+        table[byte] = crc as u16 & mask;
+        byte += 1;
+    }
+    table
+}
+
+static CRC8_TABLE: [u16; 256] = crc_table(0x07, 8);
+static CRC16_TABLE: [u16; 256] = crc_table(0x8005, 16);
+
+fn crc8(bytes: &[u8]) -> u8 {
+    bytes
+        .iter()
+        .fold(0u8, |crc, &byte| CRC8_TABLE[(crc ^ byte) as usize] as u8)
+}
+
+fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0u16, |crc, &byte| {
+        (crc << 8) ^ CRC16_TABLE[((crc >> 8) as u8 ^ byte) as usize]
+    })
+}
+
+#[cfg(all(test, feature = "flac"))]
+mod tests {
+    use super::FlacEncoder;
+    use crate::encoder::{Encoder, EncoderError, SampleFormat};
+    use crate::Sample;
+
+    fn decode(data: &[u8]) -> (claxon::metadata::StreamInfo, Vec<i32>) {
+        let mut reader = claxon::FlacReader::new(data).unwrap();
+        let info = reader.streaminfo();
+        let samples = reader.samples().map(Result::unwrap).collect();
+        (info, samples)
+    }
+
+    fn encode(samples: &[Sample], channels: u16, format: SampleFormat) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut encoder = FlacEncoder::new(&mut data, channels, 44100, format).unwrap();
+        // Chunks that do not line up with blocks or frames.
+        for chunk in samples.chunks(1001) {
+            encoder.write_samples(chunk).unwrap();
+        }
+        encoder.finish().unwrap();
+        drop(encoder);
+        data
+    }
+
+    #[test]
+    fn lossless_round_trip() {
+        let samples: Vec<Sample> = (0..20_000)
+            .map(|i| {
+                let t = i as f32 / 44100.0;
+                0.5 * (t * 440.0 * std::f32::consts::TAU).sin()
+                    + 0.1 * (t * 1234.5 * std::f32::consts::TAU).cos()
+            })
+            .collect();
+
+        for (format, scale) in [(SampleFormat::I16, 32768.0), (SampleFormat::I24, 8388608.0)] {
+            let data = encode(&samples, 2, format);
+            let (info, decoded) = decode(&data);
+            assert_eq!(info.channels, 2);
+            assert_eq!(info.sample_rate, 44100);
+            assert_eq!(info.bits_per_sample, format.bits_per_sample() as u32);
+            assert_eq!(decoded.len(), samples.len());
+            for (&a, &b) in decoded.iter().zip(&samples) {
+                assert!((a as f32 / scale - b).abs() <= 1.0 / scale, "{format:?}");
+            }
+            // Smooth audio compresses.
+            let raw_len = samples.len() * format.bits_per_sample() as usize / 8;
+            assert!(data.len() < raw_len * 3 / 4, "{format:?}: {}", data.len());
+        }
+    }
+
+    #[test]
+    fn constant_noise_and_short_blocks() {
+        let mut samples = vec![0.25; 5000];
+        let mut state = 12345u32;
+        samples.extend((0..5000).map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (state >> 8) as f32 / (1 << 24) as f32 * 2.0 - 1.0
+        }));
+        // Quiet noise, which needs large Rice parameters in 24 bits.
+        samples.extend((0..5000).map(|i| ((i * 7919) % 101) as f32 / 1000.0));
+        samples.extend([0.5, -0.5, 0.1]);
+
+        let data = encode(&samples, 1, SampleFormat::I16);
+        let (_, decoded) = decode(&data);
+        let expected: Vec<i32> = samples
+            .iter()
+            .map(|&sample| dasp_sample::Sample::to_sample::<i16>(sample) as i32)
+            .collect();
+        assert_eq!(decoded, expected);
+
+        let data = encode(&samples, 1, SampleFormat::I24);
+        let (_, decoded) = decode(&data);
+        let expected: Vec<i32> = samples
+            .iter()
+            .map(|&sample| dasp_sample::Sample::to_sample::<dasp_sample::I24>(sample).inner())
+            .collect();
+        assert_eq!(decoded, expected);
+    }
+
+    #[test]
+    fn unsupported() {
+        assert!(matches!(
+            FlacEncoder::new(Vec::new(), 2, 44100, SampleFormat::F32),
+            Err(EncoderError::UnsupportedSampleFormat(SampleFormat::F32))
+        ));
+        assert!(matches!(
+            FlacEncoder::new(Vec::new(), 9, 44100, SampleFormat::I16),
+            Err(EncoderError::UnsupportedChannelCount(9))
+        ));
+    }
+}
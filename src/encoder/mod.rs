@@ -0,0 +1,214 @@
+//! Encodes audio into files.
+//!
+//! An [`Encoder`] takes interleaved samples in any amount and writes them out as they come, so
+//! it can record a sound while it plays as well as save a whole [`Source`] with [`encode`].
+//!
+//! The following formats are supported:
+//!
+//! - WAV with 16, 24 or 32-bit integer or 32-bit float samples (feature "wav"), see
+//!   [`WavEncoder`].
+//! - FLAC with 16 or 24-bit samples, see [`FlacEncoder`].
+//! - Ogg Vorbis (feature "ogg-vorbis"), see [`VorbisEncoder`]. This links to the libvorbis C
+//!   library.
+//! - Ogg Opus (feature "opus"), see [`OpusEncoder`]. This links to the libopus C library.
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::Duration;
+//! use rodio::encoder::{encode, FlacEncoder, SampleFormat};
+//! use rodio::source::{SineWave, Source};
+//!
+//! let source = SineWave::new(440.0).take_duration(Duration::from_secs(1));
+//! let mut flac_file = Vec::new();
+//! let mut encoder = FlacEncoder::new(&mut flac_file, 1, 48000, SampleFormat::I16).unwrap();
+//! encode(source, &mut encoder).unwrap();
+//! drop(encoder);
+//! # assert!(!flac_file.is_empty());
+//! ```
+
+use std::{error, fmt, io};
+
+use crate::common::{ChannelCount, SampleRate};
+use crate::{Sample, Source};
+
+mod flac;
+#[cfg(feature = "opus")]
+mod opus;
+#[cfg(feature = "ogg-vorbis")]
+mod vorbis;
+#[cfg(feature = "wav")]
+mod wav;
+
+pub use self::flac::FlacEncoder;
+#[cfg(feature = "opus")]
+pub use self::opus::OpusEncoder;
+#[cfg(feature = "ogg-vorbis")]
+pub use self::vorbis::VorbisEncoder;
+#[cfg(feature = "wav")]
+pub use self::wav::WavEncoder;
+
+/// Writes samples in an audio file format.
+pub trait Encoder {
+    /// Encodes interleaved samples.
+    ///
+    /// The samples don't have to be whole frames, the rest of a frame can follow in the next
+    /// call.
+    fn write_samples(&mut self, samples: &[Sample]) -> Result<(), EncoderError>;
+
+    /// Writes out everything that is buffered and completes the file. No samples can be
+    /// written afterwards.
+    ///
+    /// Dropping an encoder finishes it too, but ignores any error.
+    fn finish(&mut self) -> Result<(), EncoderError>;
+}
+
+/// Encodes all of `source` and finishes the encoder.
+///
+/// The encoder must have been created with the channel count and sample rate of the source.
+pub fn encode<S, E>(source: S, encoder: &mut E) -> Result<(), EncoderError>
+where
+    S: Source,
+    E: Encoder + ?Sized,
+{
+    const CHUNK_LEN: usize = 1024;
+
+    let mut source = source;
+    let mut chunk = Vec::with_capacity(CHUNK_LEN);
+    loop {
+        chunk.clear();
+        chunk.extend(source.by_ref().take(CHUNK_LEN));
+        if chunk.is_empty() {
+            break;
+        }
+        encoder.write_samples(&chunk)?;
+    }
+    encoder.finish()
+}
+
+/// How samples are stored in an encoded file.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SampleFormat {
+    /// 16-bit signed integer.
+    I16,
+    /// 24-bit signed integer.
+    I24,
+    /// 32-bit signed integer.
+    I32,
+    /// 32-bit float.
+    F32,
+}
+
+impl SampleFormat {
+    /// Returns the number of bits per sample.
+    pub fn bits_per_sample(&self) -> u16 {
+        match self {
+            SampleFormat::I16 => 16,
+            SampleFormat::I24 => 24,
+            SampleFormat::I32 | SampleFormat::F32 => 32,
+        }
+    }
+}
+
+/// Error that can happen when encoding.
+#[derive(Debug)]
+pub enum EncoderError {
+    /// Writing to the output failed.
+    IoError(io::Error),
+
+    /// The WAV writer failed.
+    #[cfg(feature = "wav")]
+    WavError(hound::Error),
+
+    /// The Opus encoder failed.
+    #[cfg(feature = "opus")]
+    OpusError(audiopus::Error),
+
+    /// The Vorbis encoder failed.
+    #[cfg(feature = "ogg-vorbis")]
+    VorbisError(vorbis_rs::VorbisError),
+
+    /// The format can not store samples in this format.
+    UnsupportedSampleFormat(SampleFormat),
+
+    /// The format can not store this many channels.
+    UnsupportedChannelCount(ChannelCount),
+
+    /// The format can not store this sample rate.
+    UnsupportedSampleRate(SampleRate),
+
+    /// Samples were written after the encoder was finished.
+    Finished,
+}
+
+impl fmt::Display for EncoderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EncoderError::IoError(e) => e.fmt(f),
+            #[cfg(feature = "wav")]
+            EncoderError::WavError(e) => e.fmt(f),
+            #[cfg(feature = "opus")]
+            EncoderError::OpusError(e) => e.fmt(f),
+            #[cfg(feature = "ogg-vorbis")]
+            EncoderError::VorbisError(e) => e.fmt(f),
+            EncoderError::UnsupportedSampleFormat(format) => {
+                write!(f, "Unsupported sample format {format:?}")
+            }
+            EncoderError::UnsupportedChannelCount(channels) => {
+                write!(f, "Unsupported channel count {channels}")
+            }
+            EncoderError::UnsupportedSampleRate(rate) => {
+                write!(f, "Unsupported sample rate {rate}")
+            }
+            EncoderError::Finished => write!(f, "Encoder already finished"),
+        }
+    }
+}
+
+impl error::Error for EncoderError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            EncoderError::IoError(e) => Some(e),
+            #[cfg(feature = "wav")]
+            EncoderError::WavError(e) => Some(e),
+            #[cfg(feature = "opus")]
+            EncoderError::OpusError(e) => Some(e),
+            #[cfg(feature = "ogg-vorbis")]
+            EncoderError::VorbisError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for EncoderError {
+    fn from(err: io::Error) -> Self {
+        EncoderError::IoError(err)
+    }
+}
+
+#[cfg(feature = "wav")]
+impl From<hound::Error> for EncoderError {
+    fn from(err: hound::Error) -> Self {
+        match err {
+            hound::Error::IoError(err) => EncoderError::IoError(err),
+            err => EncoderError::WavError(err),
+        }
+    }
+}
+
+#[cfg(feature = "opus")]
+impl From<audiopus::Error> for EncoderError {
+    fn from(err: audiopus::Error) -> Self {
+        EncoderError::OpusError(err)
+    }
+}
+
+#[cfg(feature = "ogg-vorbis")]
+impl From<vorbis_rs::VorbisError> for EncoderError {
+    fn from(err: vorbis_rs::VorbisError) -> Self {
+        match err {
+            vorbis_rs::VorbisError::Io(err) => EncoderError::IoError(err),
+            err => EncoderError::VorbisError(err),
+        }
+    }
+}
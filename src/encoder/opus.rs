@@ -0,0 +1,416 @@
+use std::collections::VecDeque;
+use std::io::Write;
+
+use audiopus::coder::Encoder as Opus;
+use audiopus::{Application, Bitrate, Channels};
+use ogg::writing::{PacketWriteEndInfo, PacketWriter};
+
+use super::{Encoder, EncoderError};
+use crate::common::{ChannelCount, SampleRate};
+use crate::conversions::Resampler;
+use crate::Sample;
+
+/// Serial number of the Ogg stream.
+const SERIAL: u32 = 0x726f_6470;
+
+const VENDOR: &str = concat!("rodio ", env!("CARGO_PKG_VERSION"));
+
+/// Granule positions of Ogg Opus count frames at this rate, whatever the rate of the input.
+const GRANULE_RATE: u32 = 48000;
+
+/// Number of packets per second, so that each one holds 20 ms.
+const PACKETS_PER_SECOND: usize = 50;
+
+/// Largest packet size recommended by the Opus documentation.
+const MAX_PACKET_LEN: usize = 4000;
+
+/// Frames left in front of the resampler until the input ends, more than it reads ahead.
+const RESAMPLER_LOOKAHEAD: usize = 256;
+
+/// Encoder for the Ogg Opus format (feature "opus").
+///
+/// This uses the libopus C library. Opus stores one or two channels, and input at a sample
+/// rate that Opus does not support is converted to 48 kHz. Pages are written as they fill
+/// up, so the output does not need to be seekable.
+pub struct OpusEncoder<W>
+where
+    W: Write,
+{
+    writer: Option<PacketWriter<W>>,
+    opus: Opus,
+    channels: usize,
+    sample_rate: SampleRate,
+    // Converts the input to 48 kHz, if Opus does not support its rate.
+    resampler: Option<Resampler<Pending>>,
+    // Interleaved samples of the next packet, at the rate of the Opus encoder.
+    frame: Vec<Sample>,
+    frame_len: usize,
+    // Granule frames per frame of the Opus encoder.
+    granule_factor: u64,
+    // Frames the decoder skips at the start, at the granule rate.
+    pre_skip: u64,
+    input_samples: u64,
+    encoded_frames: u64,
+    // The last packet with its granule position, held back until it is known whether it
+    // ends the stream.
+    last_packet: Option<(Vec<u8>, u64)>,
+    packet: Vec<u8>,
+}
+
+impl<W> OpusEncoder<W>
+where
+    W: Write,
+{
+    /// Writes the headers of an Ogg Opus stream.
+    pub fn new(
+        writer: W,
+        channels: ChannelCount,
+        sample_rate: SampleRate,
+    ) -> Result<OpusEncoder<W>, EncoderError> {
+        let opus_channels = match channels {
+            1 => Channels::Mono,
+            2 => Channels::Stereo,
+            _ => return Err(EncoderError::UnsupportedChannelCount(channels)),
+        };
+        if sample_rate == 0 {
+            return Err(EncoderError::UnsupportedSampleRate(sample_rate));
+        }
+        let opus_rate = match sample_rate {
+            8000 => audiopus::SampleRate::Hz8000,
+            12000 => audiopus::SampleRate::Hz12000,
+            16000 => audiopus::SampleRate::Hz16000,
+            24000 => audiopus::SampleRate::Hz24000,
+            _ => audiopus::SampleRate::Hz48000,
+        };
+        let rate = opus_rate as u32;
+        let opus = Opus::new(opus_rate, opus_channels, Application::Audio)?;
+        let resampler = (rate != sample_rate)
+            .then(|| Resampler::new(Pending(VecDeque::new()), sample_rate, rate, channels));
+        let granule_factor = (GRANULE_RATE / rate) as u64;
+        let pre_skip = opus.lookahead()? as u64 * granule_factor;
+
+        let mut head = b"OpusHead".to_vec();
+        head.push(1);
+        head.push(channels as u8);
+        head.extend_from_slice(&(pre_skip as u16).to_le_bytes());
+        head.extend_from_slice(&sample_rate.to_le_bytes());
+        // No output gain, mapping family 0 for mono or stereo.
+        head.extend_from_slice(&0i16.to_le_bytes());
+        head.push(0);
+
+        let mut tags = b"OpusTags".to_vec();
+        tags.extend_from_slice(&(VENDOR.len() as u32).to_le_bytes());
+        tags.extend_from_slice(VENDOR.as_bytes());
+        tags.extend_from_slice(&0u32.to_le_bytes());
+
+        let mut writer = PacketWriter::new(writer);
+        for header in [head, tags] {
+            writer.write_packet(
+                header.into_boxed_slice(),
+                SERIAL,
+                PacketWriteEndInfo::EndPage,
+                0,
+            )?;
+        }
+
+        let channels = channels as usize;
+        let frame_len = rate as usize / PACKETS_PER_SECOND * channels;
+        Ok(OpusEncoder {
+            writer: Some(writer),
+            opus,
+            channels,
+            sample_rate,
+            resampler,
+            frame: Vec::with_capacity(frame_len),
+            frame_len,
+            granule_factor,
+            pre_skip,
+            input_samples: 0,
+            encoded_frames: 0,
+            last_packet: None,
+            packet: vec![0; MAX_PACKET_LEN],
+        })
+    }
+
+    /// Sets the bitrate of the following packets in bits per second. By default Opus picks
+    /// one from the channel count and sample rate.
+    pub fn set_bitrate(&mut self, bits_per_second: u32) -> Result<(), EncoderError> {
+        let bits_per_second = bits_per_second.min(i32::MAX as u32) as i32;
+        self.opus
+            .set_bitrate(Bitrate::BitsPerSecond(bits_per_second))?;
+        Ok(())
+    }
+
+    /// Returns the next sample from the resampler, as long as it can not run out of input
+    /// before the end.
+    fn next_resampled(&mut self, at_end: bool) -> Option<Sample> {
+        let resampler = self.resampler.as_mut()?;
+        if !at_end && resampler.inner().0.len() <= RESAMPLER_LOOKAHEAD * self.channels {
+            return None;
+        }
+        resampler.next()
+    }
+
+    fn push(&mut self, sample: Sample) -> Result<(), EncoderError> {
+        self.frame.push(sample);
+        if self.frame.len() == self.frame_len {
+            self.write_packet()?;
+        }
+        Ok(())
+    }
+
+    fn write_packet(&mut self) -> Result<(), EncoderError> {
+        let len = self.opus.encode_float(&self.frame, &mut self.packet)?;
+        self.frame.clear();
+        self.encoded_frames += (self.frame_len / self.channels) as u64;
+        let granule = self.encoded_frames * self.granule_factor;
+        let packet = (self.packet[..len].to_vec(), granule);
+        if let Some((previous, granule)) = self.last_packet.replace(packet) {
+            let writer = self.writer.as_mut().ok_or(EncoderError::Finished)?;
+            writer.write_packet(
+                previous.into_boxed_slice(),
+                SERIAL,
+                PacketWriteEndInfo::NormalPacket,
+                granule,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl<W> Encoder for OpusEncoder<W>
+where
+    W: Write,
+{
+    fn write_samples(&mut self, samples: &[Sample]) -> Result<(), EncoderError> {
+        if self.writer.is_none() {
+            return Err(EncoderError::Finished);
+        }
+        self.input_samples += samples.len() as u64;
+        match &mut self.resampler {
+            Some(resampler) => {
+                resampler.inner_mut().0.extend(samples);
+                while let Some(sample) = self.next_resampled(false) {
+                    self.push(sample)?;
+                }
+            }
+            None => {
+                for &sample in samples {
+                    self.push(sample)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), EncoderError> {
+        if self.writer.is_none() {
+            return Ok(());
+        }
+        // Drop an incomplete last frame, which is still at the end of the input.
+        let partial = (self.input_samples % self.channels as u64) as usize;
+        self.input_samples -= partial as u64;
+        match &mut self.resampler {
+            Some(resampler) => {
+                let pending = &mut resampler.inner_mut().0;
+                pending.truncate(pending.len() - partial);
+                while let Some(sample) = self.next_resampled(true) {
+                    self.push(sample)?;
+                }
+            }
+            None => self.frame.truncate(self.frame.len() - partial),
+        }
+
+        let frames = self.input_samples / self.channels as u64;
+        let end = self.pre_skip
+            + match self.resampler {
+                Some(_) => (frames * GRANULE_RATE as u64).div_ceil(self.sample_rate as u64),
+                None => frames * self.granule_factor,
+            };
+        // Pad with silence until the decoder, which is behind by the pre-skip, reaches the
+        // end of the input.
+        while self.encoded_frames * self.granule_factor < end {
+            self.frame.resize(self.frame_len, 0.0);
+            self.write_packet()?;
+        }
+
+        if let Some(mut writer) = self.writer.take() {
+            // The granule position of the last packet cuts off the padding.
+            if let Some((packet, _)) = self.last_packet.take() {
+                writer.write_packet(
+                    packet.into_boxed_slice(),
+                    SERIAL,
+                    PacketWriteEndInfo::EndStream,
+                    end,
+                )?;
+            }
+            writer.into_inner().flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl<W> Drop for OpusEncoder<W>
+where
+    W: Write,
+{
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+/// Input waiting for the resampler.
+struct Pending(VecDeque<Sample>);
+
+impl Iterator for Pending {
+    type Item = Sample;
+
+    fn next(&mut self) -> Option<Sample> {
+        self.0.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::TAU;
+
+    use audiopus::coder::Decoder;
+    use audiopus::packet::Packet;
+    use audiopus::{Channels, MutSignals, SampleRate};
+
+    use super::OpusEncoder;
+    use crate::encoder::{Encoder, EncoderError};
+    use crate::Sample;
+
+    fn sine(frames: usize, channels: usize, sample_rate: u32) -> Vec<Sample> {
+        (0..frames)
+            .flat_map(|i| {
+                let t = i as f32 / sample_rate as f32;
+                (0..channels).map(move |c| 0.5 * (t * 440.0 * (c + 1) as f32 * TAU).sin())
+            })
+            .collect()
+    }
+
+    fn encode(samples: &[Sample], channels: u16, sample_rate: u32) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut encoder = OpusEncoder::new(&mut data, channels, sample_rate).unwrap();
+        // Chunks that do not line up with packets or frames.
+        for chunk in samples.chunks(1001) {
+            encoder.write_samples(chunk).unwrap();
+        }
+        encoder.finish().unwrap();
+        drop(encoder);
+        data
+    }
+
+    /// Decodes at 48 kHz and removes the pre-skip and the padding.
+    fn decode(data: &[u8]) -> (usize, Vec<Sample>) {
+        let mut reader = ogg::PacketReader::new(std::io::Cursor::new(data));
+        let head = reader.read_packet_expected().unwrap().data;
+        assert_eq!(&head[..8], b"OpusHead");
+        let channels = head[9] as usize;
+        let pre_skip = u16::from_le_bytes([head[10], head[11]]) as usize;
+        let tags = reader.read_packet_expected().unwrap().data;
+        assert_eq!(&tags[..8], b"OpusTags");
+
+        let opus_channels = if channels == 1 {
+            Channels::Mono
+        } else {
+            Channels::Stereo
+        };
+        let mut decoder = Decoder::new(SampleRate::Hz48000, opus_channels).unwrap();
+        let mut decoded = Vec::new();
+        let mut output = vec![0.0; 5760 * channels];
+        let mut end = 0;
+        while let Some(packet) = reader.read_packet().unwrap() {
+            let frames = decoder
+                .decode_float(
+                    Some(Packet::try_from(&packet.data).unwrap()),
+                    MutSignals::try_from(&mut output).unwrap(),
+                    false,
+                )
+                .unwrap();
+            decoded.extend_from_slice(&output[..frames * channels]);
+            if packet.last_in_stream() {
+                end = packet.absgp_page() as usize;
+            }
+        }
+        decoded.truncate(end * channels);
+        decoded.drain(..pre_skip * channels);
+        (channels, decoded)
+    }
+
+    /// Returns the signal to noise ratio of `decoded` in dB.
+    fn snr(original: &[Sample], decoded: &[Sample]) -> f32 {
+        let signal: f32 = original.iter().map(|x| x * x).sum();
+        let noise: f32 = original
+            .iter()
+            .zip(decoded)
+            .map(|(x, y)| (x - y) * (x - y))
+            .sum();
+        10.0 * (signal / noise).log10()
+    }
+
+    #[test]
+    fn round_trip_at_opus_rate() {
+        for channels in [1, 2] {
+            let samples = sine(48000 + 77, channels, 48000);
+            let (decoded_channels, decoded) = decode(&encode(&samples, channels as u16, 48000));
+            assert_eq!(decoded_channels, channels);
+            assert_eq!(decoded.len(), samples.len());
+            assert!(
+                snr(&samples, &decoded) > 15.0,
+                "{}",
+                snr(&samples, &decoded)
+            );
+        }
+
+        let samples = sine(16000, 1, 16000);
+        let (_, decoded) = decode(&encode(&samples, 1, 16000));
+        assert_eq!(decoded.len(), 48000);
+        assert!(snr(&sine(48000, 1, 48000), &decoded) > 15.0);
+    }
+
+    #[test]
+    fn converts_other_rates() {
+        let samples = sine(44100 + 123, 2, 44100);
+        let data = encode(&samples, 2, 44100);
+        let (channels, decoded) = decode(&data);
+        assert_eq!(channels, 2);
+        assert_eq!(
+            decoded.len(),
+            ((44100 + 123usize) * 48000).div_ceil(44100) * 2
+        );
+        assert!(snr(&sine(48000, 2, 48000), &decoded) > 15.0);
+        // Far less than 16-bit PCM.
+        assert!(data.len() < samples.len() / 4, "{}", data.len());
+    }
+
+    #[test]
+    fn bitrate_and_short_streams() {
+        let samples = sine(48000, 2, 48000);
+        let mut small = Vec::new();
+        let mut encoder = OpusEncoder::new(&mut small, 2, 48000).unwrap();
+        encoder.set_bitrate(16000).unwrap();
+        encoder.write_samples(&samples).unwrap();
+        drop(encoder);
+        assert!(small.len() < encode(&samples, 2, 48000).len());
+
+        for frames in [0, 1, 959, 960, 961] {
+            let (_, decoded) = decode(&encode(&sine(frames, 1, 48000), 1, 48000));
+            assert_eq!(decoded.len(), frames);
+        }
+    }
+
+    #[test]
+    fn unsupported() {
+        assert!(matches!(
+            OpusEncoder::new(Vec::new(), 3, 48000),
+            Err(EncoderError::UnsupportedChannelCount(3))
+        ));
+        assert!(matches!(
+            OpusEncoder::new(Vec::new(), 2, 0),
+            Err(EncoderError::UnsupportedSampleRate(0))
+        ));
+    }
+}
@@ -0,0 +1,269 @@
+use std::io::Write;
+use std::num::{NonZeroU32, NonZeroU8};
+
+use vorbis_rs::{VorbisBitrateManagementStrategy, VorbisEncoderBuilder};
+
+use super::{Encoder, EncoderError};
+use crate::common::{ChannelCount, SampleRate};
+use crate::Sample;
+
+/// Serial number of the Ogg stream.
+const SERIAL: i32 = 0x726f_6469;
+
+const VENDOR: &str = concat!("rodio ", env!("CARGO_PKG_VERSION"));
+
+/// Number of frames given to libvorbis at a time, the size its documentation suggests.
+const BLOCK_FRAMES: usize = 1024;
+
+/// Quality used until [`VorbisEncoder::set_quality`] is called.
+const DEFAULT_QUALITY: f32 = 0.5;
+
+/// Encoder for the Ogg Vorbis format (feature "ogg-vorbis").
+///
+/// This uses the libvorbis C library. The headers are written with the first samples, so the
+/// quality can be set until then. Pages are written as they fill up, so the output does not
+/// need to be seekable.
+pub struct VorbisEncoder<W>
+where
+    W: Write,
+{
+    state: State<W>,
+    // Samples of the next block, one buffer per channel.
+    block: Vec<Vec<f32>>,
+    // Channel of the next sample written.
+    next_channel: usize,
+}
+
+enum State<W>
+where
+    W: Write,
+{
+    Starting(VorbisEncoderBuilder<W>),
+    Encoding(Box<vorbis_rs::VorbisEncoder<W>>),
+    Finished,
+}
+
+impl<W> VorbisEncoder<W>
+where
+    W: Write,
+{
+    /// Prepares an Ogg Vorbis stream, the headers are written with the first samples.
+    pub fn new(
+        writer: W,
+        channels: ChannelCount,
+        sample_rate: SampleRate,
+    ) -> Result<VorbisEncoder<W>, EncoderError> {
+        let vorbis_channels = u8::try_from(channels)
+            .ok()
+            .and_then(NonZeroU8::new)
+            .ok_or(EncoderError::UnsupportedChannelCount(channels))?;
+        let vorbis_rate =
+            NonZeroU32::new(sample_rate).ok_or(EncoderError::UnsupportedSampleRate(sample_rate))?;
+
+        let mut builder =
+            VorbisEncoderBuilder::new_with_serial(vorbis_rate, vorbis_channels, writer, SERIAL);
+        let _ = builder.comment_tag("ENCODER", VENDOR);
+        let mut encoder = VorbisEncoder {
+            state: State::Starting(builder),
+            block: vec![Vec::with_capacity(BLOCK_FRAMES); channels as usize],
+            next_channel: 0,
+        };
+        encoder.set_quality(DEFAULT_QUALITY);
+        Ok(encoder)
+    }
+
+    /// Sets the quality, from `0.0` for the smallest output to `1.0` for the best quality.
+    /// The default is `0.5`. This has no effect once samples were written.
+    pub fn set_quality(&mut self, quality: f32) {
+        if let State::Starting(builder) = &mut self.state {
+            let _ =
+                builder.bitrate_management_strategy(VorbisBitrateManagementStrategy::QualityVbr {
+                    target_quality: quality.clamp(0.0, 1.0),
+                });
+        }
+    }
+
+    /// Builds the libvorbis encoder if it was not yet, which writes the headers.
+    fn start(&mut self) -> Result<&mut vorbis_rs::VorbisEncoder<W>, EncoderError> {
+        if let State::Starting(builder) = &mut self.state {
+            let encoder = builder.build();
+            self.state = State::Finished;
+            self.state = State::Encoding(Box::new(encoder?));
+        }
+        match &mut self.state {
+            State::Encoding(encoder) => Ok(encoder),
+            _ => Err(EncoderError::Finished),
+        }
+    }
+
+    /// Encodes the samples in `block`.
+    fn write_block(&mut self) -> Result<(), EncoderError> {
+        let block = std::mem::take(&mut self.block);
+        let result = self.encode(&block);
+        self.block = block;
+        for channel in &mut self.block {
+            channel.clear();
+        }
+        result
+    }
+
+    fn encode(&mut self, block: &[Vec<f32>]) -> Result<(), EncoderError> {
+        let encoder = self.start()?;
+        if !block[0].is_empty() {
+            encoder.encode_audio_block(block)?;
+        }
+        Ok(())
+    }
+}
+
+impl<W> Encoder for VorbisEncoder<W>
+where
+    W: Write,
+{
+    fn write_samples(&mut self, samples: &[Sample]) -> Result<(), EncoderError> {
+        if matches!(self.state, State::Finished) {
+            return Err(EncoderError::Finished);
+        }
+        for &sample in samples {
+            self.block[self.next_channel].push(sample);
+            self.next_channel += 1;
+            if self.next_channel == self.block.len() {
+                self.next_channel = 0;
+                if self.block[0].len() == BLOCK_FRAMES {
+                    self.write_block()?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), EncoderError> {
+        if matches!(self.state, State::Finished) {
+            return Ok(());
+        }
+        // Drop an incomplete last frame.
+        for channel in &mut self.block[..self.next_channel] {
+            channel.pop();
+        }
+        self.next_channel = 0;
+        let result = self.write_block();
+        if let State::Encoding(encoder) = std::mem::replace(&mut self.state, State::Finished) {
+            encoder.finish()?.flush()?;
+        }
+        result
+    }
+}
+
+impl<W> Drop for VorbisEncoder<W>
+where
+    W: Write,
+{
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+#[cfg(all(test, feature = "vorbis", not(feature = "symphonia-vorbis")))]
+mod tests {
+    use std::io::Cursor;
+
+    use super::VorbisEncoder;
+    use crate::decoder::{DecoderBackend, DecoderBuilder};
+    use crate::encoder::{Encoder, EncoderError};
+    use crate::{Sample, Source};
+
+    fn encode(samples: &[Sample], channels: u16, quality: f32) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut encoder = VorbisEncoder::new(&mut data, channels, 44100).unwrap();
+        encoder.set_quality(quality);
+        // Chunks that do not line up with blocks or frames.
+        for chunk in samples.chunks(1001) {
+            encoder.write_samples(chunk).unwrap();
+        }
+        encoder.finish().unwrap();
+        drop(encoder);
+        data
+    }
+
+    fn decode(data: Vec<u8>) -> (u16, u32, Vec<Sample>) {
+        // Symphonia does not read these streams, see the gate of this module.
+        let decoder = DecoderBuilder::new()
+            .with_backend(DecoderBackend::Lewton)
+            .build(Cursor::new(data))
+            .unwrap();
+        (decoder.channels(), decoder.sample_rate(), decoder.collect())
+    }
+
+    /// Returns the signal to noise ratio of `decoded` in dB.
+    fn snr(original: &[Sample], decoded: &[Sample]) -> f32 {
+        let signal: f32 = original.iter().map(|x| x * x).sum();
+        let noise: f32 = original
+            .iter()
+            .zip(decoded)
+            .map(|(x, y)| (x - y) * (x - y))
+            .sum();
+        10.0 * (signal / noise).log10()
+    }
+
+    fn music(frames: usize) -> Vec<Sample> {
+        (0..frames)
+            .flat_map(|i| {
+                let t = i as f32 / 44100.0;
+                let tone = |freq: f32| (t * freq * std::f32::consts::TAU).sin();
+                let envelope = 0.5 + 0.5 * (t * 3.0).sin();
+                [
+                    0.4 * tone(440.0) * envelope + 0.1 * tone(3520.0),
+                    0.3 * tone(660.0) + 0.05 * tone(7040.0) * envelope,
+                ]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let samples = music(44100 + 123);
+        let data = encode(&samples, 2, 0.5);
+        let (channels, sample_rate, decoded) = decode(data.clone());
+        assert_eq!(channels, 2);
+        assert_eq!(sample_rate, 44100);
+        assert_eq!(decoded.len(), samples.len());
+        assert!(snr(&samples, &decoded) > 25.0);
+        // Less than an eighth of the size of 16-bit PCM.
+        assert!(data.len() < samples.len() / 4, "{}", data.len());
+
+        let better = encode(&samples, 2, 1.0);
+        let (_, _, better_decoded) = decode(better.clone());
+        assert!(snr(&samples, &better_decoded) > 25.0);
+        assert!(better.len() > data.len());
+
+        let smaller = encode(&samples, 2, 0.0);
+        let (_, _, smaller_decoded) = decode(smaller.clone());
+        assert!(snr(&samples, &smaller_decoded) > 15.0);
+        assert!(smaller.len() < data.len());
+    }
+
+    #[test]
+    fn silence_and_short_streams() {
+        for frames in [0, 1, 1023, 1024, 1025, 5000] {
+            let mut samples = vec![0.0; frames];
+            if let Some(sample) = samples.get_mut(frames / 2) {
+                *sample = 0.5;
+            }
+            let (channels, _, decoded) = decode(encode(&samples, 1, 0.5));
+            assert_eq!(channels, 1);
+            assert_eq!(decoded.len(), frames, "{frames}");
+        }
+    }
+
+    #[test]
+    fn unsupported() {
+        assert!(matches!(
+            VorbisEncoder::new(Vec::new(), 0, 44100),
+            Err(EncoderError::UnsupportedChannelCount(0))
+        ));
+        assert!(matches!(
+            VorbisEncoder::new(Vec::new(), 2, 0),
+            Err(EncoderError::UnsupportedSampleRate(0))
+        ));
+    }
+}
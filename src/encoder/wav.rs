@@ -0,0 +1,150 @@
+use std::io::{Seek, Write};
+
+use dasp_sample::Sample as _;
+use dasp_sample::I24;
+
+use super::{Encoder, EncoderError, SampleFormat};
+use crate::common::{ChannelCount, SampleRate};
+use crate::Sample;
+
+/// Encoder for the WAV format.
+///
+/// The header is completed when the encoder finishes, which needs to seek back to the start of
+/// the output. Pass a `&mut` reference as the output to keep using it afterwards.
+pub struct WavEncoder<W>
+where
+    W: Write + Seek,
+{
+    writer: Option<hound::WavWriter<W>>,
+    format: SampleFormat,
+}
+
+impl<W> WavEncoder<W>
+where
+    W: Write + Seek,
+{
+    /// Writes the header of a WAV file with samples in `format`.
+    pub fn new(
+        writer: W,
+        channels: ChannelCount,
+        sample_rate: SampleRate,
+        format: SampleFormat,
+    ) -> Result<WavEncoder<W>, EncoderError> {
+        if channels == 0 {
+            return Err(EncoderError::UnsupportedChannelCount(channels));
+        }
+        if sample_rate == 0 {
+            return Err(EncoderError::UnsupportedSampleRate(sample_rate));
+        }
+        let spec = hound::WavSpec {
+            channels,
+            sample_rate,
+            bits_per_sample: format.bits_per_sample(),
+            sample_format: match format {
+                SampleFormat::F32 => hound::SampleFormat::Float,
+                _ => hound::SampleFormat::Int,
+            },
+        };
+        Ok(WavEncoder {
+            writer: Some(hound::WavWriter::new(writer, spec)?),
+            format,
+        })
+    }
+}
+
+impl<W> Encoder for WavEncoder<W>
+where
+    W: Write + Seek,
+{
+    fn write_samples(&mut self, samples: &[Sample]) -> Result<(), EncoderError> {
+        let writer = self.writer.as_mut().ok_or(EncoderError::Finished)?;
+        for &sample in samples {
+            match self.format {
+                SampleFormat::I16 => writer.write_sample(sample.to_sample::<i16>())?,
+                SampleFormat::I24 => writer.write_sample(sample.to_sample::<I24>().inner())?,
+                SampleFormat::I32 => writer.write_sample(sample.to_sample::<i32>())?,
+                SampleFormat::F32 => writer.write_sample(sample)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), EncoderError> {
+        match self.writer.take() {
+            Some(writer) => Ok(writer.finalize()?),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::WavEncoder;
+    use crate::encoder::{encode, Encoder, EncoderError, SampleFormat};
+    use crate::Sample;
+
+    fn round_trip(format: SampleFormat) -> (hound::WavSpec, Vec<Sample>) {
+        let samples: Vec<Sample> = (0..1000).map(|i| (i as f32 / 100.0).sin() * 0.9).collect();
+        let mut output = Cursor::new(Vec::new());
+        let mut encoder = WavEncoder::new(&mut output, 2, 44100, format).unwrap();
+        // Uneven chunks that split frames.
+        encoder.write_samples(&samples[..333]).unwrap();
+        encoder.write_samples(&samples[333..]).unwrap();
+        encoder.finish().unwrap();
+        assert!(matches!(
+            encoder.write_samples(&samples),
+            Err(EncoderError::Finished)
+        ));
+        drop(encoder);
+
+        output.set_position(0);
+        let mut reader = hound::WavReader::new(output).unwrap();
+        let spec = reader.spec();
+        let decoded = match format {
+            SampleFormat::F32 => reader.samples::<f32>().map(Result::unwrap).collect(),
+            _ => {
+                let scale = (1i64 << (spec.bits_per_sample - 1)) as f32;
+                reader
+                    .samples::<i32>()
+                    .map(|sample| sample.unwrap() as f32 / scale)
+                    .collect()
+            }
+        };
+        (spec, decoded)
+    }
+
+    #[test]
+    fn formats() {
+        let expected: Vec<Sample> = (0..1000).map(|i| (i as f32 / 100.0).sin() * 0.9).collect();
+        for (format, bits, tolerance) in [
+            (SampleFormat::I16, 16, 1e-4),
+            (SampleFormat::I24, 24, 1e-6),
+            (SampleFormat::I32, 32, 1e-6),
+            (SampleFormat::F32, 32, 0.0),
+        ] {
+            let (spec, decoded) = round_trip(format);
+            assert_eq!(spec.channels, 2);
+            assert_eq!(spec.sample_rate, 44100);
+            assert_eq!(spec.bits_per_sample, bits);
+            assert_eq!(decoded.len(), expected.len());
+            for (a, b) in decoded.iter().zip(&expected) {
+                assert!((a - b).abs() <= tolerance, "{format:?}: {a} != {b}");
+            }
+        }
+    }
+
+    #[test]
+    fn encode_source() {
+        let source = crate::buffer::SamplesBuffer::new(1, 8000, vec![0.5; 100]);
+        let mut output = Cursor::new(Vec::new());
+        let mut encoder = WavEncoder::new(&mut output, 1, 8000, SampleFormat::I16).unwrap();
+        encode(source, &mut encoder).unwrap();
+        drop(encoder);
+
+        output.set_position(0);
+        let reader = hound::WavReader::new(output).unwrap();
+        assert_eq!(reader.len(), 100);
+    }
+}
@@ -0,0 +1,856 @@
+//! Playback of internet radio streams that use the Icecast/SHOUTcast protocol.
+//!
+//! When the request has the `Icy-MetaData: 1` header, the server interleaves the audio,
+//! usually MP3 or Ogg, with "now playing" metadata. Connecting is left to the HTTP client of
+//! your choice: [`IcyStream`] takes a function that returns an [`IcyConnection`] and calls it
+//! again whenever the connection drops. The connection is read on a background thread and
+//! playback continues with the new connection, so the sink the stream plays in keeps going.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use std::io::Write;
+//! use std::net::TcpStream;
+//!
+//! use rodio::icy::{IcyConnection, IcyStream};
+//!
+//! let mut radio = IcyStream::new(|| {
+//!     let mut connection = TcpStream::connect("radio.example.com:8000")?;
+//!     connection.write_all(b"GET /stream HTTP/1.0\r\nIcy-MetaData: 1\r\n\r\n")?;
+//!     IcyConnection::from_response(connection)
+//! })?;
+//! radio.on_metadata_change(|metadata| {
+//!     println!("Now playing: {}", metadata.stream_title.as_deref().unwrap_or("?"));
+//! });
+//!
+//! let (sink, output) = rodio::Sink::new();
+//! sink.append(radio);
+//! // Play `output`, for example by adding it to the mixer of an output stream.
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::collections::VecDeque;
+use std::io::{self, BufReader, Read};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::common::{ChannelCount, SampleRate};
+use crate::conversions::ChannelLayout;
+use crate::decoder::{Decoder, DecoderBuilder, ReadOnlySource};
+use crate::realtime::{self, Inbox, List, Node, Slot};
+use crate::source::SeekError;
+use crate::{Sample, Source};
+
+/// Playback pauses for buffering when less than this many bytes are left, or half the
+/// prebuffer if that is less.
+const LOW_WATER: usize = 16 * 1024;
+
+/// Bytes the decoder reads at once.
+const READ_SIZE: usize = 4096;
+
+/// How often the background thread checks whether playback got to new metadata while it
+/// waits.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Longest response head accepted by [`IcyConnection::from_response`].
+const MAX_RESPONSE_HEAD: usize = 16 * 1024;
+
+/// "Now playing" information sent by the server.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IcyMetadata {
+    /// Usually the artist and title of the song, like `"Artist - Title"`.
+    pub stream_title: Option<String>,
+    /// Web page about the song or the station.
+    pub stream_url: Option<String>,
+    /// All fields in the order they were sent, including the two above.
+    pub fields: Vec<(String, String)>,
+}
+
+impl IcyMetadata {
+    /// Parses a metadata block like `StreamTitle='Artist - Title';StreamUrl='';`.
+    pub fn parse(text: &str) -> IcyMetadata {
+        let mut metadata = IcyMetadata::default();
+        let mut rest = text.trim_end_matches('\0');
+        while let Some(start) = rest.find("='") {
+            let key = rest[..start].trim_matches(|c: char| c == ';' || c.is_whitespace());
+            let after = &rest[start + 2..];
+            // Quotes within a value are not escaped, only `';` ends it.
+            let (value, next) = match after.find("';") {
+                Some(end) => (&after[..end], &after[end + 2..]),
+                None => (after.trim_end_matches(';').trim_end_matches('\''), ""),
+            };
+            match key {
+                "StreamTitle" => metadata.stream_title = Some(value.to_owned()),
+                "StreamUrl" => metadata.stream_url = Some(value.to_owned()),
+                _ => (),
+            }
+            metadata.fields.push((key.to_owned(), value.to_owned()));
+            rest = next;
+        }
+        metadata
+    }
+}
+
+/// Removes the metadata that the server interleaves with the audio.
+///
+/// Reading returns only the audio. Each read stops at the next metadata block, after the
+/// read that passes it [`take_metadata`](IcyReader::take_metadata) returns the new metadata.
+pub struct IcyReader<R> {
+    inner: R,
+    metaint: Option<usize>,
+    until_metadata: usize,
+    metadata: Option<IcyMetadata>,
+}
+
+impl<R: Read> IcyReader<R> {
+    /// `metaint` is the number of audio bytes between two metadata blocks, the value of the
+    /// `icy-metaint` response header. `None` if the server does not send metadata.
+    ///
+    /// # Panic
+    /// Panics if `metaint` is zero.
+    pub fn new(inner: R, metaint: Option<usize>) -> IcyReader<R> {
+        assert_ne!(metaint, Some(0), "metaint is greater than zero");
+        IcyReader {
+            inner,
+            metaint,
+            until_metadata: metaint.unwrap_or(0),
+            metadata: None,
+        }
+    }
+
+    /// Returns the metadata that was read since the last call, if any.
+    #[inline]
+    pub fn take_metadata(&mut self) -> Option<IcyMetadata> {
+        self.metadata.take()
+    }
+
+    /// Returns the inner reader.
+    #[inline]
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for IcyReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let Some(metaint) = self.metaint else {
+            return self.inner.read(buf);
+        };
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.until_metadata == 0 {
+            let mut len = [0];
+            if self.inner.read(&mut len)? == 0 {
+                return Ok(0);
+            }
+            let mut block = vec![0; len[0] as usize * 16];
+            self.inner.read_exact(&mut block)?;
+            if !block.is_empty() {
+                self.metadata = Some(IcyMetadata::parse(&decode_text(&block)));
+            }
+            self.until_metadata = metaint;
+        }
+        let len = buf.len().min(self.until_metadata);
+        let read = self.inner.read(&mut buf[..len])?;
+        self.until_metadata -= read;
+        Ok(read)
+    }
+}
+
+/// Metadata is meant to be UTF-8, but older servers send Latin-1.
+fn decode_text(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_owned(),
+        Err(_) => bytes.iter().map(|&byte| byte as char).collect(),
+    }
+}
+
+/// A connection to the server, returned by the function passed to [`IcyStream::new`].
+pub struct IcyConnection {
+    reader: Box<dyn Read + Send>,
+    metaint: Option<usize>,
+    content_type: Option<String>,
+}
+
+impl IcyConnection {
+    /// Audio read from `reader`, which starts right after the headers of the response.
+    pub fn new<R>(reader: R) -> IcyConnection
+    where
+        R: Read + Send + 'static,
+    {
+        IcyConnection {
+            reader: Box::new(reader),
+            metaint: None,
+            content_type: None,
+        }
+    }
+
+    /// Sets the number of audio bytes between two metadata blocks, the value of the
+    /// `icy-metaint` response header. Without it the stream is expected to have no metadata.
+    ///
+    /// # Panic
+    /// Panics if `metaint` is zero.
+    pub fn with_metaint(mut self, metaint: usize) -> IcyConnection {
+        assert!(metaint > 0, "metaint is greater than zero");
+        self.metaint = Some(metaint);
+        self
+    }
+
+    /// Sets the MIME type of the audio, the value of the `Content-Type` response header. It
+    /// tells the decoder what format to expect.
+    pub fn with_content_type(mut self, content_type: &str) -> IcyConnection {
+        self.content_type = Some(content_type.to_owned());
+        self
+    }
+
+    /// Reads the status line and headers of the response from `reader`, for servers that are
+    /// talked to without an HTTP client. The request should have the `Icy-MetaData: 1`
+    /// header.
+    ///
+    /// Fails if the status is not `200`.
+    pub fn from_response<R>(mut reader: R) -> io::Result<IcyConnection>
+    where
+        R: Read + Send + 'static,
+    {
+        // Read byte by byte, the audio right after the head must stay in the reader.
+        let mut head = Vec::new();
+        let mut byte = [0];
+        while !head.ends_with(b"\r\n\r\n") && !head.ends_with(b"\n\n") {
+            if head.len() >= MAX_RESPONSE_HEAD {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "response head is too long",
+                ));
+            }
+            reader.read_exact(&mut byte)?;
+            head.push(byte[0]);
+        }
+
+        let head = decode_text(&head);
+        let mut lines = head.lines();
+        let status = lines.next().unwrap_or_default();
+        if status.split_whitespace().nth(1) != Some("200") {
+            return Err(io::Error::other(format!(
+                "server responded with `{status}`"
+            )));
+        }
+        let mut connection = IcyConnection::new(reader);
+        for line in lines {
+            let Some((name, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            if name.eq_ignore_ascii_case("icy-metaint") {
+                connection.metaint = value.parse().ok().filter(|&metaint| metaint > 0);
+            } else if name.eq_ignore_ascii_case("content-type") {
+                connection.content_type = Some(value.to_owned());
+            }
+        }
+        Ok(connection)
+    }
+}
+
+/// Buffering and reconnection settings of an [`IcyStream`].
+#[derive(Copy, Clone, Debug)]
+pub struct IcyConfig {
+    buffer_size: usize,
+    prebuffer: usize,
+    reconnect_attempts: u32,
+    reconnect_delay: Duration,
+}
+
+impl Default for IcyConfig {
+    fn default() -> Self {
+        Self {
+            buffer_size: 1024 * 1024,
+            prebuffer: 64 * 1024,
+            reconnect_attempts: 5,
+            reconnect_delay: Duration::from_secs(1),
+        }
+    }
+}
+
+impl IcyConfig {
+    /// Sets how many bytes of a connection are read ahead of playback at most. The default is
+    /// 1 MiB.
+    pub fn with_buffer_size(mut self, bytes: usize) -> IcyConfig {
+        assert!(bytes > 0, "buffer size is greater than zero");
+        self.buffer_size = bytes;
+        self.prebuffer = self.prebuffer.min(bytes);
+        self
+    }
+
+    /// Sets how many bytes are buffered before playback starts, and before it continues
+    /// after the data ran out. Silence is played while buffering. The default is 64 KiB.
+    pub fn with_prebuffer(mut self, bytes: usize) -> IcyConfig {
+        self.prebuffer = bytes.min(self.buffer_size);
+        self
+    }
+
+    /// Sets how often connecting is tried after the connection dropped, and how long to
+    /// wait before each attempt. The stream ends when all attempts failed. The default is
+    /// 5 attempts, one second apart.
+    pub fn with_reconnect(mut self, attempts: u32, delay: Duration) -> IcyConfig {
+        self.reconnect_attempts = attempts;
+        self.reconnect_delay = delay;
+        self
+    }
+}
+
+/// Data shared by the stream and the thread that reads the connections.
+struct Shared {
+    /// Decoders of the connections in order, built by the feeding thread.
+    decoders: Inbox<Playback>,
+    /// No decoders are added anymore.
+    finished: AtomicBool,
+    /// The stream was dropped.
+    stopped: AtomicBool,
+    /// Metadata of the part that is playing, until the stream takes it.
+    now_playing: Slot<IcyMetadata>,
+    /// Only used by the feeding thread and by `IcyStream::on_metadata_change`.
+    callback: Mutex<Option<MetadataCallback>>,
+}
+
+impl Shared {
+    fn new() -> Shared {
+        Shared {
+            decoders: Inbox::new(),
+            finished: AtomicBool::new(false),
+            stopped: AtomicBool::new(false),
+            now_playing: Slot::new(),
+            callback: Mutex::new(None),
+        }
+    }
+}
+
+/// The data of one connection.
+///
+/// `read` and `written` count bytes since the start and only grow, the slot of a byte is its
+/// count modulo the capacity. Only the feeding thread advances `written` and only the reader
+/// of the decoder advances `read`.
+struct Ring {
+    bytes: Box<[AtomicU8]>,
+    read: AtomicU64,
+    written: AtomicU64,
+    /// The connection has ended, everything was written.
+    ended: AtomicBool,
+    /// The stream plays the data.
+    started: AtomicBool,
+    /// The stream is done with the data, because it was played or its decoder failed.
+    closed: AtomicBool,
+}
+
+impl Ring {
+    fn new(capacity: usize) -> Ring {
+        Ring {
+            bytes: (0..capacity).map(|_| AtomicU8::new(0)).collect(),
+            read: AtomicU64::new(0),
+            written: AtomicU64::new(0),
+            ended: AtomicBool::new(false),
+            started: AtomicBool::new(false),
+            closed: AtomicBool::new(false),
+        }
+    }
+
+    /// Bytes written and not read yet.
+    #[inline]
+    fn available(&self) -> usize {
+        let written = self.written.load(Ordering::Acquire);
+        (written - self.read.load(Ordering::Acquire)) as usize
+    }
+
+    /// Writes as much of `data` as fits, returns the number of bytes written.
+    fn write(&self, data: &[u8]) -> usize {
+        let written = self.written.load(Ordering::Relaxed);
+        let free = self.bytes.len() - (written - self.read.load(Ordering::Acquire)) as usize;
+        let len = free.min(data.len());
+        for (position, &byte) in (written..).zip(&data[..len]) {
+            self.bytes[(position % self.bytes.len() as u64) as usize]
+                .store(byte, Ordering::Relaxed);
+        }
+        self.written.store(written + len as u64, Ordering::Release);
+        len
+    }
+
+    /// Reads as much as is available into `buf`, returns the number of bytes read.
+    fn read(&self, buf: &mut [u8]) -> usize {
+        let read = self.read.load(Ordering::Relaxed);
+        let len = buf
+            .len()
+            .min((self.written.load(Ordering::Acquire) - read) as usize);
+        for (position, byte) in (read..).zip(&mut buf[..len]) {
+            *byte =
+                self.bytes[(position % self.bytes.len() as u64) as usize].load(Ordering::Relaxed);
+        }
+        self.read.store(read + len as u64, Ordering::Release);
+        len
+    }
+}
+
+type Connect = Box<dyn FnMut() -> io::Result<IcyConnection> + Send>;
+
+type MetadataCallback = Box<dyn FnMut(&IcyMetadata) + Send>;
+
+/// Reads the connections into rings and builds their decoders, on its own thread.
+struct Feeder {
+    shared: Arc<Shared>,
+    connect: Connect,
+    config: IcyConfig,
+    /// Metadata and the number of audio bytes before it, until playback gets there.
+    metadata: VecDeque<(Arc<Ring>, u64, IcyMetadata)>,
+    now_playing: Option<IcyMetadata>,
+}
+
+impl Feeder {
+    /// Reads the connections until the stream is dropped or connecting fails.
+    fn run(mut self, first: IcyConnection) {
+        let mut connection = Some(first);
+        loop {
+            let Some(connection) = connection.take().or_else(|| self.reconnect()) else {
+                break;
+            };
+            if !self.feed(connection) {
+                self.deliver_metadata();
+                return;
+            }
+        }
+        self.shared.finished.store(true, Ordering::Release);
+        // Playback may still have to get to some of the metadata.
+        while !self.metadata.is_empty() && self.sleep(POLL_INTERVAL) {}
+    }
+
+    /// Reads a connection until it ends or its data can not be played. Returns `false` if
+    /// the stream was dropped.
+    fn feed(&mut self, connection: IcyConnection) -> bool {
+        let ring = Arc::new(Ring::new(self.config.buffer_size));
+        let mut decoding = false;
+        let mut reader = IcyReader::new(connection.reader, connection.metaint);
+        let mut chunk = [0; 4096];
+        loop {
+            let read = match reader.read(&mut chunk) {
+                Ok(read) => read,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                // A broken connection ends the data like a closed one.
+                Err(_) => 0,
+            };
+            if let Some(metadata) = reader.take_metadata() {
+                let position = ring.written.load(Ordering::Relaxed);
+                self.metadata.push_back((ring.clone(), position, metadata));
+            }
+
+            let mut data = &chunk[..read];
+            loop {
+                data = &data[ring.write(data)..];
+                if read == 0 {
+                    ring.ended.store(true, Ordering::Release);
+                }
+                // Decoding starts once enough is buffered, the ring may be full by then.
+                let ready = read == 0 || ring.available() >= self.config.prebuffer;
+                if ready && !decoding {
+                    if !self.start_decoder(connection.content_type.as_deref(), &ring) {
+                        // Not playable, connect again.
+                        return !self.shared.stopped.load(Ordering::Acquire);
+                    }
+                    decoding = true;
+                }
+                self.deliver_metadata();
+                if self.shared.stopped.load(Ordering::Acquire) {
+                    return false;
+                }
+                if ring.closed.load(Ordering::Acquire) {
+                    // The decoder failed, connect again.
+                    return true;
+                }
+                if data.is_empty() {
+                    break;
+                }
+                // The ring is full.
+                thread::sleep(POLL_INTERVAL);
+            }
+            if read == 0 {
+                return true;
+            }
+        }
+    }
+
+    /// Builds the decoder of `ring` and hands it to the stream. Returns `false` if the data
+    /// can not be decoded.
+    fn start_decoder(&self, content_type: Option<&str>, ring: &Arc<Ring>) -> bool {
+        let mut builder = DecoderBuilder::new();
+        if let Some(content_type) = content_type {
+            builder = builder.with_mime_type(content_type);
+        }
+        let reader = BufReader::with_capacity(READ_SIZE, SegmentReader { ring: ring.clone() });
+        match builder.build_unseekable(reader) {
+            Ok(decoder) => {
+                self.shared.decoders.push(Node::new(Playback {
+                    decoder,
+                    ring: ring.clone(),
+                }));
+                true
+            }
+            Err(_) => {
+                ring.closed.store(true, Ordering::Release);
+                false
+            }
+        }
+    }
+
+    /// Reports the metadata that playback has got to.
+    fn deliver_metadata(&mut self) {
+        while let Some((ring, position, _)) = self.metadata.front() {
+            let reached = ring.started.load(Ordering::Acquire)
+                && ring.read.load(Ordering::Acquire) >= *position;
+            if !reached && !ring.closed.load(Ordering::Acquire) {
+                return;
+            }
+            let (_, _, metadata) = self.metadata.pop_front().unwrap();
+            // Servers repeat the metadata, only changes are reported. Metadata of data that
+            // was not played is left out.
+            if !reached || self.now_playing.as_ref() == Some(&metadata) {
+                continue;
+            }
+            if let Some(callback) = self.shared.callback.lock().unwrap().as_mut() {
+                callback(&metadata);
+            }
+            self.shared.now_playing.put(metadata.clone());
+            self.now_playing = Some(metadata);
+        }
+    }
+
+    fn reconnect(&mut self) -> Option<IcyConnection> {
+        for _ in 0..self.config.reconnect_attempts {
+            if !self.sleep(self.config.reconnect_delay) {
+                return None;
+            }
+            if let Ok(connection) = (self.connect)() {
+                return Some(connection);
+            }
+        }
+        None
+    }
+
+    /// Waits for `delay` while delivering metadata. Returns `false` if the stream was
+    /// dropped.
+    fn sleep(&mut self, delay: Duration) -> bool {
+        let end = Instant::now() + delay;
+        loop {
+            self.deliver_metadata();
+            if self.shared.stopped.load(Ordering::Acquire) {
+                return false;
+            }
+            let now = Instant::now();
+            if now >= end {
+                return true;
+            }
+            thread::sleep(POLL_INTERVAL.min(end - now));
+        }
+    }
+}
+
+/// Reads the data of one connection for its decoder. Never waits: when nothing is buffered
+/// it returns no data, which the stream avoids by buffering in time.
+struct SegmentReader {
+    ring: Arc<Ring>,
+}
+
+impl Read for SegmentReader {
+    #[inline]
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        Ok(self.ring.read(buf))
+    }
+}
+
+type SegmentDecoder = Decoder<ReadOnlySource<BufReader<SegmentReader>>>;
+
+/// A decoder and the data it reads.
+struct Playback {
+    decoder: SegmentDecoder,
+    ring: Arc<Ring>,
+}
+
+/// Source that plays an internet radio stream, see the [module documentation](self).
+///
+/// Silence is played while buffering and while reconnecting. The stream ends when
+/// reconnecting failed, seeking is not supported.
+///
+/// Playing the stream never waits for the connection, nor does it allocate or free memory.
+/// Decoders are built on the background thread that reads the connection.
+pub struct IcyStream {
+    shared: Arc<Shared>,
+    prebuffer: usize,
+    /// Playback pauses for buffering when less than this many bytes are left.
+    low_water: usize,
+    /// The decoder that plays and those that follow it.
+    current: Option<Box<Node<Playback>>>,
+    next: List<Playback>,
+    /// The decoder waits for more data.
+    buffering: bool,
+    /// Format of the latest decoder, also used for the silence.
+    channels: ChannelCount,
+    sample_rate: SampleRate,
+    layout: Option<ChannelLayout>,
+    frame_offset: usize,
+    metadata: Option<Box<Node<IcyMetadata>>>,
+}
+
+impl IcyStream {
+    /// Connects by calling `connect`, which is called again whenever the connection drops.
+    ///
+    /// Fails if the first connection fails.
+    pub fn new<F>(connect: F) -> io::Result<IcyStream>
+    where
+        F: FnMut() -> io::Result<IcyConnection> + Send + 'static,
+    {
+        IcyStream::with_config(connect, IcyConfig::default())
+    }
+
+    /// Same as [`new`](IcyStream::new) with other buffering and reconnection settings.
+    pub fn with_config<F>(mut connect: F, config: IcyConfig) -> io::Result<IcyStream>
+    where
+        F: FnMut() -> io::Result<IcyConnection> + Send + 'static,
+    {
+        let first = connect()?;
+        realtime::start_collector();
+        let shared = Arc::new(Shared::new());
+        let feeder = Feeder {
+            shared: shared.clone(),
+            connect: Box::new(connect),
+            config,
+            metadata: VecDeque::new(),
+            now_playing: None,
+        };
+        thread::Builder::new()
+            .name("rodio-icy".to_owned())
+            .spawn(move || feeder.run(first))?;
+        Ok(IcyStream {
+            shared,
+            prebuffer: config.prebuffer,
+            low_water: LOW_WATER.min(config.prebuffer / 2),
+            current: None,
+            next: List::new(),
+            buffering: true,
+            channels: 2,
+            sample_rate: 44100,
+            layout: None,
+            frame_offset: 0,
+            metadata: None,
+        })
+    }
+
+    /// Returns the metadata of what is playing, if the server sent any.
+    #[inline]
+    pub fn metadata(&self) -> Option<&IcyMetadata> {
+        self.metadata.as_ref().map(|metadata| &metadata.value)
+    }
+
+    /// Calls `callback` with the new metadata whenever what is playing changes.
+    ///
+    /// The callback runs on the background thread that reads the connection, shortly after
+    /// playback got to the change. Replaces a previously set callback.
+    pub fn on_metadata_change<F>(&mut self, callback: F)
+    where
+        F: FnMut(&IcyMetadata) + Send + 'static,
+    {
+        *self.shared.callback.lock().unwrap() = Some(Box::new(callback));
+    }
+
+    fn playing(&self) -> Option<&SegmentDecoder> {
+        self.current
+            .as_ref()
+            .filter(|_| !self.buffering)
+            .map(|playback| &playback.value.decoder)
+    }
+
+    /// Takes new metadata and decoders, and pauses or resumes the decoder depending on the
+    /// buffered data. Returns `false` once everything was played.
+    fn update(&mut self) -> bool {
+        if let Some(metadata) = self.shared.now_playing.take() {
+            if let Some(previous) = self.metadata.replace(metadata) {
+                realtime::defer_drop(previous);
+            }
+        }
+
+        if self.current.is_none() {
+            // Checked first, decoders added before are taken below.
+            let finished = self.shared.finished.load(Ordering::Acquire);
+            if !self.shared.decoders.is_empty() {
+                self.next.append(self.shared.decoders.take_all());
+            }
+            let Some(playback) = self.next.pop_front() else {
+                self.buffering = true;
+                return !finished;
+            };
+            playback.value.ring.started.store(true, Ordering::Release);
+            self.current = Some(playback);
+            self.buffering = true;
+        }
+
+        let playback = &self.current.as_ref().unwrap().value;
+        let ended = playback.ring.ended.load(Ordering::Acquire);
+        let available = playback.ring.available();
+        self.buffering = if self.buffering {
+            !ended && available < self.prebuffer
+        } else {
+            // Pause before the decoder runs out of data, it would take that as the end.
+            !ended && available < self.low_water.max(1)
+        };
+        if !self.buffering {
+            self.channels = playback.decoder.channels();
+            self.sample_rate = playback.decoder.sample_rate();
+            self.layout = playback.decoder.channel_layout();
+        }
+        true
+    }
+
+    /// Lets go of the decoder that played, the feeding thread connects again if its
+    /// connection has not ended.
+    fn finish_current(&mut self) {
+        if let Some(playback) = self.current.take() {
+            playback.value.ring.closed.store(true, Ordering::Release);
+            realtime::defer_drop(playback);
+        }
+    }
+}
+
+impl Drop for IcyStream {
+    fn drop(&mut self) {
+        self.shared.stopped.store(true, Ordering::Release);
+    }
+}
+
+impl Iterator for IcyStream {
+    type Item = Sample;
+
+    fn next(&mut self) -> Option<Sample> {
+        loop {
+            if self.frame_offset == 0 && !self.update() {
+                return None;
+            }
+
+            let decoder = self
+                .current
+                .as_mut()
+                .filter(|_| !self.buffering)
+                .map(|playback| &mut playback.value.decoder);
+            let sample = match decoder {
+                Some(decoder) => match decoder.next() {
+                    Some(sample) => sample,
+                    None => {
+                        // Continue with the next connection, if there is one.
+                        self.finish_current();
+                        self.frame_offset = 0;
+                        continue;
+                    }
+                },
+                None => 0.0,
+            };
+            self.frame_offset += 1;
+            if self.frame_offset >= self.channels as usize {
+                self.frame_offset = 0;
+            }
+            return Some(sample);
+        }
+    }
+}
+
+impl Source for IcyStream {
+    #[inline]
+    fn current_span_len(&self) -> Option<usize> {
+        match self.playing() {
+            Some(decoder) => decoder.current_span_len(),
+            // The silence can end after any frame.
+            None => Some(self.channels as usize - self.frame_offset),
+        }
+    }
+
+    #[inline]
+    fn channels(&self) -> ChannelCount {
+        self.playing()
+            .map_or(self.channels, |decoder| decoder.channels())
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> SampleRate {
+        self.playing()
+            .map_or(self.sample_rate, |decoder| decoder.sample_rate())
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+
+    #[inline]
+    fn channel_layout(&self) -> Option<ChannelLayout> {
+        self.playing()
+            .map_or(self.layout, |decoder| decoder.channel_layout())
+    }
+
+    #[inline]
+    fn try_seek(&mut self, _: Duration) -> Result<(), SeekError> {
+        Err(SeekError::NotSupported {
+            underlying_source: std::any::type_name::<Self>(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::{IcyConnection, IcyMetadata, IcyReader};
+
+    #[test]
+    fn parses_metadata() {
+        let metadata =
+            IcyMetadata::parse("StreamTitle='Artist - It's a Title';StreamUrl='';\0\0\0");
+        assert_eq!(
+            metadata.stream_title.as_deref(),
+            Some("Artist - It's a Title")
+        );
+        assert_eq!(metadata.stream_url.as_deref(), Some(""));
+        assert_eq!(metadata.fields.len(), 2);
+    }
+
+    #[test]
+    fn removes_metadata() {
+        let mut data = b"abc".to_vec();
+        let block = b"StreamTitle='A';";
+        data.push(1);
+        data.extend(block);
+        data.extend(b"def");
+        data.push(0);
+        data.extend(b"g");
+
+        let mut reader = IcyReader::new(&data[..], Some(3));
+        let mut buffer = [0; 16];
+        assert_eq!(reader.read(&mut buffer).unwrap(), 3);
+        assert_eq!(reader.take_metadata(), None);
+        assert_eq!(reader.read(&mut buffer).unwrap(), 3);
+        assert_eq!(&buffer[..3], b"def");
+        let metadata = reader.take_metadata().unwrap();
+        assert_eq!(metadata.stream_title.as_deref(), Some("A"));
+        assert_eq!(reader.read(&mut buffer).unwrap(), 1);
+        assert_eq!(reader.take_metadata(), None);
+        assert_eq!(reader.read(&mut buffer).unwrap(), 0);
+    }
+
+    #[test]
+    fn reads_response_head() {
+        let response = b"ICY 200 OK\r\nicy-name: Radio\r\nIcy-MetaInt: 8192\r\n\
+            Content-Type: audio/mpeg\r\n\r\naudio";
+        let connection = IcyConnection::from_response(&response[..]).unwrap();
+        assert_eq!(connection.metaint, Some(8192));
+        assert_eq!(connection.content_type.as_deref(), Some("audio/mpeg"));
+        let mut audio = String::new();
+        { connection.reader }.read_to_string(&mut audio).unwrap();
+        assert_eq!(audio, "audio");
+
+        let response = b"HTTP/1.1 404 Not Found\r\n\r\n";
+        assert!(IcyConnection::from_response(&response[..]).is_err());
+    }
+}
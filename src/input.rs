@@ -0,0 +1,576 @@
+use crate::common::{ChannelCount, SampleRate};
+use crate::realtime::{self, Inbox, List, Node};
+use crate::source::SeekError;
+use crate::stream::{clamp_supported_buffer_size, fallback_configs, StreamError, HZ_44100};
+use crate::{Sample, Source};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{BufferSize, FromSample, SampleFormat, SizedSample, StreamConfig};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How much audio an [`InputSource`] keeps when it is not read fast enough.
+const MAX_BUFFERED: Duration = Duration::from_secs(1);
+
+/// `cpal::Stream` container for a capture device.
+/// Use `source()` to read the recorded audio.
+/// If this is dropped, recording will end and the sources will run out.
+pub struct InputStream {
+    shared: Arc<Shared>,
+    _stream: cpal::Stream,
+}
+
+impl InputStream {
+    /// Returns a new source that plays the audio recorded from now on.
+    ///
+    /// Every source gets all samples. Reading never waits for the device: when nothing new
+    /// was recorded the source plays silence, so it should be read at the pace of the device,
+    /// for example by playing it. Use [`InputSource::available`] to read only what has been
+    /// recorded. A source that is not read for a while keeps one second and leaves out what
+    /// is recorded after. The source ends once the stream is dropped.
+    pub fn source(&self) -> InputSource {
+        InputSource::new(self.shared.clone())
+    }
+
+    /// Returns the number of channels recorded.
+    pub fn channels(&self) -> ChannelCount {
+        self.shared.channels
+    }
+
+    /// Returns the sample rate of the recording.
+    pub fn sample_rate(&self) -> SampleRate {
+        self.shared.sample_rate
+    }
+}
+
+impl Drop for InputStream {
+    fn drop(&mut self) {
+        self.shared.closed.store(true, Ordering::Release);
+    }
+}
+
+/// State shared by the data callback and the sources.
+struct Shared {
+    /// Rings of new sources, until the data callback takes them over.
+    new_rings: Inbox<Arc<Ring>>,
+    closed: AtomicBool,
+    channels: ChannelCount,
+    sample_rate: SampleRate,
+}
+
+/// Recorded samples for one source.
+///
+/// `read` and `written` count samples since the start and only grow, the slot of a sample is
+/// its count modulo the capacity. Only the data callback advances `written` and only the
+/// source advances `read`. The capacity is a multiple of the channel count.
+struct Ring {
+    slots: Box<[AtomicU32]>,
+    read: AtomicUsize,
+    written: AtomicUsize,
+}
+
+/// State of the data callback, which must neither wait nor allocate.
+struct Recorder {
+    shared: Arc<Shared>,
+    rings: List<Arc<Ring>>,
+}
+
+impl Recorder {
+    fn push<T>(&mut self, data: &[T])
+    where
+        T: SizedSample,
+        f32: FromSample<T>,
+    {
+        if !self.shared.new_rings.is_empty() {
+            self.rings.append(self.shared.new_rings.take_all());
+        }
+        // Forget the rings of sources that were dropped, they are freed on another thread.
+        self.rings
+            .retain(|ring| Arc::strong_count(ring) > 1, realtime::defer_drop);
+
+        let channels = self.shared.channels as usize;
+        for ring in self.rings.iter() {
+            let written = ring.written.load(Ordering::Relaxed);
+            let free = ring.slots.len() - (written - ring.read.load(Ordering::Acquire));
+            // Only whole frames, the rest is left out when the source falls behind.
+            let len = free.min(data.len()) / channels * channels;
+            for (offset, &sample) in data[..len].iter().enumerate() {
+                let slot = (written + offset) % ring.slots.len();
+                ring.slots[slot].store(f32::from_sample_(sample).to_bits(), Ordering::Relaxed);
+            }
+            ring.written.store(written + len, Ordering::Release);
+        }
+    }
+}
+
+/// Audio recorded by an [`InputStream`]. Implements `Source`.
+pub struct InputSource {
+    ring: Arc<Ring>,
+    shared: Arc<Shared>,
+    // Local copy of `ring.read`.
+    read: usize,
+    // Samples left in a frame of silence played because nothing was recorded.
+    silence_left: usize,
+}
+
+impl InputSource {
+    fn new(shared: Arc<Shared>) -> InputSource {
+        let channels = shared.channels as usize;
+        let max_frames = MAX_BUFFERED.as_secs() as usize * shared.sample_rate as usize;
+        let ring = Arc::new(Ring {
+            slots: (0..max_frames * channels)
+                .map(|_| AtomicU32::new(0))
+                .collect(),
+            read: AtomicUsize::new(0),
+            written: AtomicUsize::new(0),
+        });
+        shared.new_rings.push(Node::new(ring.clone()));
+        InputSource {
+            ring,
+            shared,
+            read: 0,
+            silence_left: 0,
+        }
+    }
+
+    /// Returns the number of recorded samples waiting to be read.
+    pub fn available(&self) -> usize {
+        self.ring.written.load(Ordering::Acquire) - self.read
+    }
+}
+
+impl fmt::Debug for InputSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InputSource")
+            .field("channels", &self.shared.channels)
+            .field("sample_rate", &self.shared.sample_rate)
+            .finish()
+    }
+}
+
+impl Iterator for InputSource {
+    type Item = Sample;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.silence_left > 0 {
+            self.silence_left -= 1;
+            return Some(0.0);
+        }
+        // Checked first, samples recorded before the stream was dropped are still played.
+        let closed = self.shared.closed.load(Ordering::Acquire);
+        if self.ring.written.load(Ordering::Acquire) == self.read {
+            if closed {
+                return None;
+            }
+            // Nothing was recorded yet, play a frame of silence instead of waiting.
+            self.silence_left = self.shared.channels as usize - 1;
+            return Some(0.0);
+        }
+        let slot = self.read % self.ring.slots.len();
+        let sample = f32::from_bits(self.ring.slots[slot].load(Ordering::Relaxed));
+        self.read += 1;
+        self.ring.read.store(self.read, Ordering::Release);
+        Some(sample)
+    }
+}
+
+impl Source for InputSource {
+    #[inline]
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    #[inline]
+    fn channels(&self) -> ChannelCount {
+        self.shared.channels
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> SampleRate {
+        self.shared.sample_rate
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+
+    #[inline]
+    fn try_seek(&mut self, _: Duration) -> Result<(), SeekError> {
+        Err(SeekError::NotSupported {
+            underlying_source: std::any::type_name::<Self>(),
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+struct InputStreamConfig {
+    channel_count: ChannelCount,
+    sample_rate: SampleRate,
+    buffer_size: BufferSize,
+    sample_format: SampleFormat,
+}
+
+impl Default for InputStreamConfig {
+    fn default() -> Self {
+        Self {
+            channel_count: 1,
+            sample_rate: HZ_44100,
+            buffer_size: BufferSize::Default,
+            sample_format: SampleFormat::F32,
+        }
+    }
+}
+
+impl From<&InputStreamConfig> for StreamConfig {
+    fn from(config: &InputStreamConfig) -> Self {
+        cpal::StreamConfig {
+            channels: config.channel_count as cpal::ChannelCount,
+            sample_rate: cpal::SampleRate(config.sample_rate),
+            buffer_size: config.buffer_size,
+        }
+    }
+}
+
+/// Convenience builder for audio input stream, the recording counterpart of
+/// [`OutputStreamBuilder`](crate::OutputStreamBuilder).
+///
+/// # Example
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use rodio::{InputStreamBuilder, OutputStreamBuilder};
+///
+/// let input = InputStreamBuilder::open_default_stream().expect("open default input stream");
+/// let output = OutputStreamBuilder::open_default_stream().expect("open default output stream");
+/// // Plays back what is recorded for five seconds.
+/// output.mixer().add(input.source());
+/// std::thread::sleep(Duration::from_secs(5));
+/// ```
+#[derive(Default)]
+pub struct InputStreamBuilder {
+    device: Option<cpal::Device>,
+    config: InputStreamConfig,
+}
+
+impl core::fmt::Debug for InputStreamBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let device = if let Some(device) = &self.device {
+            "Some(".to_owned() + device.name().as_deref().unwrap_or("UnNamed") + ")"
+        } else {
+            "None".to_owned()
+        };
+
+        f.debug_struct("InputStreamBuilder")
+            .field("device", &device)
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+impl InputStreamBuilder {
+    /// Sets input device and its default parameters.
+    pub fn from_device(device: cpal::Device) -> Result<InputStreamBuilder, StreamError> {
+        let default_config = device
+            .default_input_config()
+            .map_err(StreamError::DefaultStreamConfigError)?;
+        Ok(Self::default()
+            .with_device(device)
+            .with_supported_config(&default_config))
+    }
+
+    /// Sets default input stream parameters for default input audio device.
+    pub fn from_default_device() -> Result<InputStreamBuilder, StreamError> {
+        let default_device = cpal::default_host()
+            .default_input_device()
+            .ok_or(StreamError::NoDevice)?;
+        Self::from_device(default_device)
+    }
+
+    /// Sets input audio device keeping all existing stream parameters intact.
+    /// This method is useful if you want to set other parameters yourself.
+    /// To also set parameters that are appropriate for the device use [Self::from_device()] instead.
+    pub fn with_device(mut self, device: cpal::Device) -> InputStreamBuilder {
+        self.device = Some(device);
+        self
+    }
+
+    /// Sets number of input stream's channels.
+    pub fn with_channels(mut self, channel_count: ChannelCount) -> InputStreamBuilder {
+        assert!(channel_count > 0);
+        self.config.channel_count = channel_count;
+        self
+    }
+
+    /// Sets input stream's sample rate.
+    pub fn with_sample_rate(mut self, sample_rate: SampleRate) -> InputStreamBuilder {
+        self.config.sample_rate = sample_rate;
+        self
+    }
+
+    /// Sets preferred input buffer size.
+    /// Larger buffer size causes longer recording delays. Buffer sizes that are too small
+    /// may cause higher CPU usage or dropouts.
+    pub fn with_buffer_size(mut self, buffer_size: cpal::BufferSize) -> InputStreamBuilder {
+        self.config.buffer_size = buffer_size;
+        self
+    }
+
+    /// Select scalar type that the device delivers samples in.
+    pub fn with_sample_format(mut self, sample_format: SampleFormat) -> InputStreamBuilder {
+        self.config.sample_format = sample_format;
+        self
+    }
+
+    /// Set available parameters from a CPAL supported config. You can get a list of
+    /// such configurations for an input device using [cpal::traits::DeviceTrait::supported_input_configs()]
+    pub fn with_supported_config(
+        mut self,
+        config: &cpal::SupportedStreamConfig,
+    ) -> InputStreamBuilder {
+        self.config = InputStreamConfig {
+            channel_count: config.channels() as ChannelCount,
+            sample_rate: config.sample_rate().0 as SampleRate,
+            // In case of supported range limit buffer size to avoid unexpectedly long delays.
+            buffer_size: clamp_supported_buffer_size(config.buffer_size(), 1024),
+            sample_format: config.sample_format(),
+        };
+        self
+    }
+
+    /// Set all input stream parameters at once from CPAL stream config.
+    pub fn with_config(mut self, config: &cpal::StreamConfig) -> InputStreamBuilder {
+        self.config = InputStreamConfig {
+            channel_count: config.channels as ChannelCount,
+            sample_rate: config.sample_rate.0 as SampleRate,
+            buffer_size: config.buffer_size,
+            ..self.config
+        };
+        self
+    }
+
+    /// Open input stream using parameters configured so far.
+    pub fn open_stream(&self) -> Result<InputStream, StreamError> {
+        let device = self.device.as_ref().expect("input device specified");
+        InputStream::open(device, &self.config)
+    }
+
+    /// Try opening a new input stream with the builder's current stream configuration.
+    /// Failing that attempt to open stream with other available configurations
+    /// supported by the device.
+    /// If all attempts fail returns initial error.
+    pub fn open_stream_or_fallback(&self) -> Result<InputStream, StreamError> {
+        let device = self.device.as_ref().expect("input device specified");
+        InputStream::open(device, &self.config).or_else(|err| {
+            for supported_config in supported_input_configs(device)? {
+                if let Ok(handle) = Self::default()
+                    .with_device(device.clone())
+                    .with_supported_config(&supported_config)
+                    .open_stream()
+                {
+                    return Ok(handle);
+                }
+            }
+            Err(err)
+        })
+    }
+
+    /// Try to open a new input stream for the default input device with its default configuration.
+    /// Failing that attempt to open input stream with alternative configuration and/or non default
+    /// input devices. Returns stream for first of the tried configurations that succeeds.
+    /// If all attempts fail return the initial error.
+    pub fn open_default_stream() -> Result<InputStream, StreamError> {
+        Self::from_default_device()
+            .and_then(|x| x.open_stream())
+            .or_else(|original_err| {
+                let mut devices = match cpal::default_host().input_devices() {
+                    Ok(devices) => devices,
+                    Err(err) => {
+                        #[cfg(feature = "tracing")]
+                        tracing::error!("error getting list of input devices: {err}");
+                        #[cfg(not(feature = "tracing"))]
+                        eprintln!("error getting list of input devices: {err}");
+                        return Err(original_err);
+                    }
+                };
+                devices
+                    .find_map(|d| {
+                        Self::from_device(d)
+                            .and_then(|x| x.open_stream_or_fallback())
+                            .ok()
+                    })
+                    .ok_or(original_err)
+            })
+    }
+}
+
+impl InputStream {
+    fn open(device: &cpal::Device, config: &InputStreamConfig) -> Result<InputStream, StreamError> {
+        if let BufferSize::Fixed(sz) = config.buffer_size {
+            assert!(sz > 0, "fixed buffer size is greater than zero");
+        }
+        assert!(config.sample_rate > 0, "sample rate is greater than zero");
+        assert!(
+            config.channel_count > 0,
+            "channel number is greater than zero"
+        );
+
+        // The data callback passes the rings of dropped sources to it.
+        realtime::start_collector();
+        let shared = Arc::new(Shared {
+            new_rings: Inbox::new(),
+            closed: AtomicBool::new(false),
+            channels: config.channel_count,
+            sample_rate: config.sample_rate,
+        });
+        let stream = match config.sample_format {
+            SampleFormat::F32 => Self::init_stream::<f32>(device, config, shared.clone()),
+            SampleFormat::F64 => Self::init_stream::<f64>(device, config, shared.clone()),
+            SampleFormat::I8 => Self::init_stream::<i8>(device, config, shared.clone()),
+            SampleFormat::I16 => Self::init_stream::<i16>(device, config, shared.clone()),
+            SampleFormat::I32 => Self::init_stream::<i32>(device, config, shared.clone()),
+            SampleFormat::I64 => Self::init_stream::<i64>(device, config, shared.clone()),
+            SampleFormat::U8 => Self::init_stream::<u8>(device, config, shared.clone()),
+            SampleFormat::U16 => Self::init_stream::<u16>(device, config, shared.clone()),
+            SampleFormat::U32 => Self::init_stream::<u32>(device, config, shared.clone()),
+            SampleFormat::U64 => Self::init_stream::<u64>(device, config, shared.clone()),
+            _ => return Err(StreamError::UnsupportedSampleFormat),
+        }?;
+        stream.play().map_err(StreamError::PlayStreamError)?;
+        Ok(InputStream {
+            shared,
+            _stream: stream,
+        })
+    }
+
+    fn init_stream<T>(
+        device: &cpal::Device,
+        config: &InputStreamConfig,
+        shared: Arc<Shared>,
+    ) -> Result<cpal::Stream, StreamError>
+    where
+        T: SizedSample,
+        f32: FromSample<T>,
+    {
+        let error_callback = |err| {
+            #[cfg(feature = "tracing")]
+            tracing::error!("Recording error: {err}");
+            #[cfg(not(feature = "tracing"))]
+            eprintln!("Recording error: {err}");
+        };
+        let mut recorder = Recorder {
+            shared,
+            rings: List::new(),
+        };
+        device
+            .build_input_stream::<T, _, _>(
+                &config.into(),
+                move |data, _| recorder.push(data),
+                error_callback,
+                None,
+            )
+            .map_err(StreamError::BuildStreamError)
+    }
+}
+
+/// Return all formats supported by the device.
+fn supported_input_configs(
+    device: &cpal::Device,
+) -> Result<impl Iterator<Item = cpal::SupportedStreamConfig>, StreamError> {
+    let supported: Vec<_> = device
+        .supported_input_configs()
+        .map_err(StreamError::SupportedStreamConfigsError)?
+        .collect();
+    Ok(fallback_configs(supported))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    use super::{InputSource, Recorder, Shared};
+    use crate::realtime::{Inbox, List};
+
+    #[test]
+    fn plays_silence_instead_of_waiting() {
+        // Room for two stereo frames.
+        let shared = Arc::new(Shared {
+            new_rings: Inbox::new(),
+            closed: AtomicBool::new(false),
+            channels: 2,
+            sample_rate: 2,
+        });
+        let mut recorder = Recorder {
+            shared: shared.clone(),
+            rings: List::new(),
+        };
+        let mut source = InputSource::new(shared.clone());
+
+        assert_eq!(source.next(), Some(0.0));
+        recorder.push(&[1.0f32, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        // The frame of silence is finished first, the frame that did not fit is left out.
+        assert_eq!(source.next(), Some(0.0));
+        assert_eq!(source.available(), 4);
+        assert_eq!(source.by_ref().take(3).collect::<Vec<_>>(), [1.0, 2.0, 3.0]);
+
+        // Room for one frame again, also when only part of it is read.
+        recorder.push(&[7.0f32, 8.0, 9.0, 10.0]);
+        assert_eq!(source.available(), 3);
+        shared.closed.store(true, Ordering::Release);
+        assert_eq!(source.collect::<Vec<_>>(), [4.0, 7.0, 8.0]);
+
+        // The ring of the dropped source is forgotten.
+        recorder.push(&[11.0f32, 12.0]);
+        assert!(recorder.rings.is_empty());
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod device_tests {
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use cpal::traits::{DeviceTrait, HostTrait};
+
+    use super::InputStreamBuilder;
+    use crate::Source;
+
+    #[test]
+    #[ignore = "needs the ALSA null device"]
+    fn records_from_alsa_null_device() {
+        let device = cpal::default_host()
+            .input_devices()
+            .expect("list input devices")
+            .find(|device| device.name().is_ok_and(|name| name == "null"))
+            .expect("ALSA null device");
+
+        let stream = InputStreamBuilder::default()
+            .with_device(device)
+            .with_channels(2)
+            .with_sample_rate(48000)
+            .open_stream_or_fallback()
+            .expect("open null input");
+        let source = stream.source();
+        assert_eq!(source.channels(), stream.channels());
+        assert_eq!(source.sample_rate(), stream.sample_rate());
+
+        // Captured frames arrive, instead of the silence played while waiting for them.
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while source.available() == 0 {
+            assert!(Instant::now() < deadline, "nothing recorded");
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let recorded: Vec<f32> = source.take(1024).collect();
+        assert_eq!(recorded.len(), 1024);
+
+        // Ends once the stream is gone, with at most the last second left.
+        let source = stream.source();
+        let max_len = 2 * stream.sample_rate() as usize;
+        drop(stream);
+        assert!(source.count() <= max_len);
+    }
+}
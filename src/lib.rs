@@ -139,6 +139,12 @@
 //!
 //! The "playback" feature adds support for playing audio. This feature requires the "cpal" crate.
 //!
+//! ### Feature "icy"
+//!
+//! The "icy" feature adds the [`icy`] module to play internet radio streams with their "now
+//! playing" metadata. It has no extra dependencies, the connection is made by an HTTP client of
+//! your choice.
+//!
 //! ## How it works under the hood
 //!
 //! Rodio spawns a background thread that is dedicated to reading from the sources and sending
@@ -158,7 +164,11 @@ pub use cpal::{
 };
 
 mod common;
+#[cfg(feature = "playback")]
+mod input;
 mod math;
+mod realtime;
+mod render;
 mod sink;
 mod spatial_sink;
 #[cfg(feature = "playback")]
@@ -169,6 +179,9 @@ mod wav_output;
 pub mod buffer;
 pub mod conversions;
 pub mod decoder;
+pub mod encoder;
+#[cfg(feature = "icy")]
+pub mod icy;
 pub mod mixer;
 pub mod queue;
 pub mod source;
@@ -176,10 +189,15 @@ pub mod static_buffer;
 
 pub use crate::common::{ChannelCount, Sample, SampleRate};
 pub use crate::decoder::Decoder;
+#[cfg(feature = "playback")]
+pub use crate::input::{InputSource, InputStream, InputStreamBuilder};
+pub use crate::render::OfflineRenderer;
 pub use crate::sink::Sink;
 pub use crate::source::Source;
 pub use crate::spatial_sink::SpatialSink;
 #[cfg(feature = "playback")]
-pub use crate::stream::{play, OutputStream, OutputStreamBuilder, PlayError, StreamError};
+pub use crate::stream::{
+    play, NullStreamConfig, OutputStream, OutputStreamBuilder, PlayError, StreamError,
+};
 #[cfg(feature = "wav")]
 pub use crate::wav_output::output_to_wav;
@@ -1,10 +1,11 @@
 //! Mixer that plays multiple sounds at the same time.
 
 use crate::common::{ChannelCount, SampleRate};
+use crate::realtime::{self, Inbox, List, Node};
 use crate::source::{SeekError, Source, UniformSourceIterator};
 use crate::Sample;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 use std::time::Duration;
 
 /// Builds a new mixer.
@@ -13,20 +14,24 @@ use std::time::Duration;
 /// added to the mixer will be converted to these values.
 ///
 /// After creating a mixer, you can add new sounds with the controller.
+///
+/// The output never waits for the controller, and neither allocates nor frees memory while
+/// playing. Sounds that end are dropped on a background thread.
 pub fn mixer(channels: ChannelCount, sample_rate: SampleRate) -> (Mixer, MixerSource) {
+    realtime::start_collector();
     let input = Mixer(Arc::new(Inner {
-        has_pending: AtomicBool::new(false),
-        pending_sources: Mutex::new(Vec::new()),
+        pending_sources: Inbox::new(),
+        sample_count: AtomicU64::new(0),
         channels,
         sample_rate,
     }));
 
     let output = MixerSource {
-        current_sources: Vec::with_capacity(16),
+        current_sources: List::new(),
         input: input.clone(),
         sample_count: 0,
-        still_pending: vec![],
-        still_current: vec![],
+        scheduled: List::new(),
+        next_start: u64::MAX,
     };
 
     (input, output)
@@ -37,12 +42,19 @@ pub fn mixer(channels: ChannelCount, sample_rate: SampleRate) -> (Mixer, MixerSo
 pub struct Mixer(Arc<Inner>);
 
 struct Inner {
-    has_pending: AtomicBool,
-    pending_sources: Mutex<Vec<Box<dyn Source + Send>>>,
+    pending_sources: Inbox<Scheduled>,
+    // Published copy of `MixerSource::sample_count`.
+    sample_count: AtomicU64,
     channels: ChannelCount,
     sample_rate: SampleRate,
 }
 
+/// A source waiting to be started at a sample of the mixer.
+struct Scheduled {
+    start: u64,
+    source: Box<dyn Source + Send>,
+}
+
 impl Mixer {
     /// Adds a new source to mix to the existing ones.
     #[inline]
@@ -50,35 +62,143 @@ impl Mixer {
     where
         T: Source + Send + 'static,
     {
-        let uniform_source =
-            UniformSourceIterator::new(source, self.0.channels, self.0.sample_rate);
+        self.schedule(source, 0);
+    }
+
+    /// Adds a new source that starts playing at the given frame of the mixer clock.
+    ///
+    /// A frame holds one sample for each channel, see [`Mixer::frames_played`] for the
+    /// current frame. Frames that have already been played start the source right away.
+    #[inline]
+    pub fn add_at_frame<T>(&self, source: T, frame: u64)
+    where
+        T: Source + Send + 'static,
+    {
+        self.schedule(source, frame * self.0.channels as u64);
+    }
+
+    /// Adds a new source that starts playing at the given position of the mixer clock.
+    ///
+    /// The position is rounded to the nearest frame. See [`Mixer::position`] for the current
+    /// position.
+    #[inline]
+    pub fn add_at<T>(&self, source: T, at: Duration)
+    where
+        T: Source + Send + 'static,
+    {
+        self.add_at_frame(source, duration_to_frames(at, self.0.sample_rate));
+    }
+
+    /// Returns the number of frames the mixer has produced so far.
+    ///
+    /// This is the clock used by [`Mixer::add_at_frame`]. It runs ahead of what can be heard
+    /// by the buffering of the output.
+    #[inline]
+    pub fn frames_played(&self) -> u64 {
+        self.0.sample_count.load(Ordering::Relaxed) / self.0.channels as u64
+    }
+
+    /// Returns the time the mixer has produced so far, the clock used by [`Mixer::add_at`].
+    #[inline]
+    pub fn position(&self) -> Duration {
+        frames_to_duration(self.frames_played(), self.0.sample_rate)
+    }
+
+    /// Returns the channel count and sample rate that all sounds are converted to.
+    #[inline]
+    pub(crate) fn format(&self) -> (ChannelCount, SampleRate) {
+        (self.0.channels, self.0.sample_rate)
+    }
+
+    fn schedule<T>(&self, source: T, start: u64)
+    where
+        T: Source + Send + 'static,
+    {
+        let source = Box::new(UniformSourceIterator::new(
+            source,
+            self.0.channels,
+            self.0.sample_rate,
+        ));
         self.0
             .pending_sources
-            .lock()
-            .unwrap()
-            .push(Box::new(uniform_source) as Box<_>);
-        self.0.has_pending.store(true, Ordering::SeqCst); // TODO: can we relax this ordering?
+            .push(Node::new(Scheduled { start, source }));
     }
 }
 
 /// The output of the mixer. Implements `Source`.
 pub struct MixerSource {
-    // The current iterator that produces samples.
-    current_sources: Vec<Box<dyn Source + Send>>,
+    // The sources that are playing.
+    current_sources: List<Scheduled>,
 
     // The pending sounds.
     input: Mixer,
 
     // The number of samples produced so far.
-    sample_count: usize,
+    sample_count: u64,
 
-    // A temporary vec used in start_pending_sources.
-    still_pending: Vec<Box<dyn Source + Send>>,
+    // Sources that wait for their start sample.
+    scheduled: List<Scheduled>,
 
-    // A temporary vec used in sum_current_sources.
-    still_current: Vec<Box<dyn Source + Send>>,
+    // The earliest start of the scheduled sources, `u64::MAX` if there are none.
+    next_start: u64,
 }
 
+impl MixerSource {
+    /// Adds a new source that starts playing at the given frame of the mixer clock.
+    ///
+    /// Like [`Mixer::add_at_frame`], without going through the shared input. Useful from
+    /// code that already runs on the audio thread, note that this allocates.
+    pub fn add_at_frame<T>(&mut self, source: T, frame: u64)
+    where
+        T: Source + Send + 'static,
+    {
+        let channels = self.channels();
+        let source = Box::new(UniformSourceIterator::new(
+            source,
+            channels,
+            self.sample_rate(),
+        ));
+        let start = frame * channels as u64;
+        self.next_start = self.next_start.min(start);
+        self.scheduled
+            .push_back(Node::new(Scheduled { start, source }));
+    }
+
+    /// Adds a new source that starts playing at the given position of the mixer clock.
+    pub fn add_at<T>(&mut self, source: T, at: Duration)
+    where
+        T: Source + Send + 'static,
+    {
+        let frame = duration_to_frames(at, self.sample_rate());
+        self.add_at_frame(source, frame);
+    }
+
+    /// Returns the number of frames produced so far.
+    #[inline]
+    pub fn frames_played(&self) -> u64 {
+        self.sample_count / self.channels() as u64
+    }
+
+    /// Returns the time produced so far.
+    #[inline]
+    pub fn position(&self) -> Duration {
+        frames_to_duration(self.frames_played(), self.sample_rate())
+    }
+}
+
+fn duration_to_frames(duration: Duration, sample_rate: SampleRate) -> u64 {
+    let nanos = duration.as_nanos() * sample_rate as u128;
+    ((nanos + NANOS_PER_SEC / 2) / NANOS_PER_SEC) as u64
+}
+
+fn frames_to_duration(frames: u64, sample_rate: SampleRate) -> Duration {
+    let rate = sample_rate as u64;
+    let nanos = (frames % rate) * NANOS_PER_SEC as u64 / rate;
+    Duration::new(frames / rate, nanos as u32)
+}
+
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
 impl Source for MixerSource {
     #[inline]
     fn current_span_len(&self) -> Option<usize> {
@@ -142,15 +262,22 @@ impl Iterator for MixerSource {
 
     #[inline]
     fn next(&mut self) -> Option<Self::Item> {
-        if self.input.0.has_pending.load(Ordering::SeqCst) {
+        if !self.input.0.pending_sources.is_empty() {
             self.start_pending_sources();
         }
+        if self.sample_count >= self.next_start {
+            self.start_scheduled_sources();
+        }
 
         self.sample_count += 1;
+        self.input
+            .0
+            .sample_count
+            .store(self.sample_count, Ordering::Relaxed);
 
         let sum = self.sum_current_sources();
 
-        if self.current_sources.is_empty() {
+        if self.current_sources.is_empty() && self.scheduled.is_empty() {
             None
         } else {
             Some(sum)
@@ -164,41 +291,48 @@ impl Iterator for MixerSource {
 }
 
 impl MixerSource {
+    fn start_pending_sources(&mut self) {
+        let pending = self.input.0.pending_sources.take_all();
+        for scheduled in pending.iter() {
+            self.next_start = self.next_start.min(scheduled.start);
+        }
+        self.scheduled.append(pending);
+    }
+
     // Samples from the #next() function are interlaced for each of the channels.
     // We need to ensure we start playing sources so that their samples are
     // in-step with the modulo of the samples produced so far. Otherwise, the
     // sound will play on the wrong channels, e.g. left / right will be reversed.
-    fn start_pending_sources(&mut self) {
-        let mut pending = self.input.0.pending_sources.lock().unwrap(); // TODO: relax ordering?
-
-        for source in pending.drain(..) {
-            let in_step = self.sample_count % source.channels() as usize == 0;
-
-            if in_step {
-                self.current_sources.push(source);
-            } else {
-                self.still_pending.push(source);
-            }
-        }
-        std::mem::swap(&mut self.still_pending, &mut pending);
-
-        let has_pending = !pending.is_empty();
-        self.input
-            .0
-            .has_pending
-            .store(has_pending, Ordering::SeqCst); // TODO: relax ordering?
+    fn start_scheduled_sources(&mut self) {
+        let sample_count = self.sample_count;
+        let next_start = &mut self.next_start;
+        let current_sources = &mut self.current_sources;
+        *next_start = u64::MAX;
+        self.scheduled.retain(
+            |scheduled| {
+                let in_step = sample_count.is_multiple_of(scheduled.source.channels() as u64);
+                let keep = scheduled.start > sample_count || !in_step;
+                if keep {
+                    *next_start = (*next_start).min(scheduled.start);
+                }
+                keep
+            },
+            |started| current_sources.push_back(started),
+        );
     }
 
     fn sum_current_sources(&mut self) -> Sample {
         let mut sum = 0.0;
-        for mut source in self.current_sources.drain(..) {
-            if let Some(value) = source.next() {
-                sum += value;
-                self.still_current.push(source);
-            }
-        }
-        std::mem::swap(&mut self.still_current, &mut self.current_sources);
-
+        self.current_sources.retain(
+            |playing| match playing.source.next() {
+                Some(value) => {
+                    sum += value;
+                    true
+                }
+                None => false,
+            },
+            realtime::defer_drop,
+        );
         sum
     }
 }
@@ -208,6 +342,7 @@ mod tests {
     use crate::buffer::SamplesBuffer;
     use crate::mixer;
     use crate::source::Source;
+    use std::time::Duration;
 
     #[test]
     fn basic() {
@@ -254,13 +389,17 @@ mod tests {
 
         assert_eq!(rx.channels(), 1);
         assert_eq!(rx.sample_rate(), 96000);
-        assert_eq!(rx.next(), Some(15.0));
-        assert_eq!(rx.next(), Some(5.0));
-        assert_eq!(rx.next(), Some(-5.0));
-        assert_eq!(rx.next(), Some(5.0));
-        assert_eq!(rx.next(), Some(15.0));
-        assert_eq!(rx.next(), Some(5.0));
-        assert_eq!(rx.next(), Some(-5.0));
+
+        // The band-limited resampler doubles the length. The alternating source is at the
+        // Nyquist frequency and mostly filtered out, the sum rings at the edges.
+        let expected = [
+            11.29929, 3.0968902, -1.142273, 4.43075, 11.1959, 8.432327, -1.4524426, -6.8333006,
+        ];
+        let output: Vec<_> = rx.by_ref().take(8).collect();
+        assert_eq!(output.len(), expected.len());
+        for (sample, expected) in output.into_iter().zip(expected) {
+            assert!((sample - expected).abs() < 1e-4, "{sample} != {expected}");
+        }
         assert_eq!(rx.next(), None);
     }
 
@@ -293,4 +432,48 @@ mod tests {
 
         assert_eq!(rx.next(), None);
     }
+
+    #[test]
+    fn scheduled_start() {
+        let (tx, mut rx) = mixer::mixer(2, 4);
+
+        tx.add(SamplesBuffer::new(2, 4, vec![1.0; 8]));
+        tx.add_at_frame(SamplesBuffer::new(2, 4, vec![10.0, 20.0]), 2);
+        tx.add_at(
+            SamplesBuffer::new(2, 4, vec![100.0, 200.0]),
+            Duration::from_millis(1250),
+        );
+
+        assert_eq!(tx.frames_played(), 0);
+        assert_eq!(
+            rx.by_ref().take(8).collect::<Vec<_>>(),
+            vec![1.0, 1.0, 1.0, 1.0, 11.0, 21.0, 1.0, 1.0]
+        );
+        assert_eq!(tx.frames_played(), 4);
+        assert_eq!(tx.position(), Duration::from_secs(1));
+
+        // Nothing plays until the last scheduled source starts.
+        assert_eq!(rx.next(), Some(0.0));
+        assert_eq!(rx.next(), Some(0.0));
+        assert_eq!(rx.next(), Some(100.0));
+        assert_eq!(rx.next(), Some(200.0));
+        assert_eq!(rx.next(), None);
+        assert_eq!(rx.position(), Duration::from_millis(1500));
+    }
+
+    #[test]
+    fn scheduled_in_the_past() {
+        let (tx, mut rx) = mixer::mixer(1, 48000);
+
+        tx.add(SamplesBuffer::new(1, 48000, vec![1.0; 4]));
+        assert_eq!(rx.next(), Some(1.0));
+        assert_eq!(rx.next(), Some(1.0));
+
+        tx.add_at_frame(SamplesBuffer::new(1, 48000, vec![5.0]), 1);
+        rx.add_at_frame(SamplesBuffer::new(1, 48000, vec![7.0]), 3);
+
+        assert_eq!(rx.next(), Some(6.0));
+        assert_eq!(rx.next(), Some(8.0));
+        assert_eq!(rx.next(), None);
+    }
 }
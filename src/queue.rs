@@ -1,17 +1,20 @@
 //! Queue that plays sounds one after the other.
 
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, Mutex};
+use std::mem;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
 use std::time::Duration;
 
-use crate::source::{Empty, SeekError, Source, Zero};
+use crate::realtime::{self, Inbox, List, Node, Slot};
+use crate::source::{Empty, FadeCurve, SeekError, Source, UniformSourceIterator, Zero};
 use crate::Sample;
 
 use crate::common::{ChannelCount, SampleRate};
+use crate::conversions::ChannelLayout;
 #[cfg(feature = "crossbeam-channel")]
-use crossbeam_channel::{unbounded as channel, Receiver, Sender};
+use crossbeam_channel::{bounded, Receiver, Sender};
 #[cfg(not(feature = "crossbeam-channel"))]
-use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::mpsc::{sync_channel as bounded, Receiver, SyncSender as Sender};
 
 /// Builds a new queue. It consists of an input and an output.
 ///
@@ -24,15 +27,34 @@ use std::sync::mpsc::{channel, Receiver, Sender};
 ///   a new sound.
 /// - If you pass `false`, then the queue will report that it has finished playing.
 ///
+/// Sounds that are added before the previous one ends follow it without a gap. The silence
+/// has the channels and sample rate of the sound before it, and a sound added during the
+/// silence starts at the next frame.
+///
+/// The output never waits for the input, and neither allocates nor frees memory while playing.
+/// Sounds are prepared for a crossfade when they are added, and dropped on a background thread
+/// once they are done.
+///
 pub fn queue(keep_alive_if_empty: bool) -> (Arc<SourcesQueueInput>, SourcesQueueOutput) {
+    realtime::start_collector();
     let input = Arc::new(SourcesQueueInput {
-        next_sounds: Mutex::new(Vec::new()),
+        next_sounds: Inbox::new(),
+        waiting: AtomicUsize::new(0),
+        appended: AtomicU64::new(0),
+        cleared: AtomicU64::new(0),
         keep_alive_if_empty: AtomicBool::new(keep_alive_if_empty),
+        sound_added: AtomicBool::new(false),
+        format: AtomicU64::new(0),
+        fixed_format: AtomicBool::new(false),
+        crossfade: Slot::new(),
     });
 
     let output = SourcesQueueOutput {
-        current: Box::new(Empty::new()) as Box<_>,
-        signal_after_end: None,
+        current: Current::Empty(Empty::new()),
+        next_sounds: List::new(),
+        cleared: 0,
+        frame_offset: 0,
+        crossfade: None,
         input: input.clone(),
     };
 
@@ -41,15 +63,149 @@ pub fn queue(keep_alive_if_empty: bool) -> (Arc<SourcesQueueInput>, SourcesQueue
 
 // TODO: consider reimplementing this with `from_factory`
 
-type Sound = Box<dyn Source + Send>;
-type SignalDone = Option<Sender<()>>;
+type BoxedSource = Box<dyn Source + Send>;
+
+// The format of a sound in one number, channels above the sample rate.
+type Format = u64;
+
+fn format(source: &dyn Source) -> Format {
+    (source.channels() as u64) << 32 | source.sample_rate() as u64
+}
+
+// A sound with the converter that a crossfade needs, built when the sound is added.
+enum Sound {
+    // Plays in the format of the queue.
+    Plain(BoxedSource),
+    // Plays as it is until it fades in, then in the target format of the converter.
+    Convertible {
+        converter: Box<UniformSourceIterator<BoxedSource>>,
+        converting: bool,
+    },
+}
+
+impl Sound {
+    // Prepares `source` for crossfading from a sound with the format `target`.
+    fn new(source: BoxedSource, target: Format) -> Sound {
+        if format(&*source) == target {
+            return Sound::Plain(source);
+        }
+        let converter = Box::new(UniformSourceIterator::new(
+            source,
+            (target >> 32) as ChannelCount,
+            target as SampleRate,
+        ));
+        Sound::Convertible {
+            converter,
+            converting: false,
+        }
+    }
+
+    #[inline]
+    fn source(&self) -> &dyn Source {
+        match self {
+            Sound::Plain(source) => source,
+            Sound::Convertible {
+                converter,
+                converting: true,
+            } => &**converter,
+            Sound::Convertible { converter, .. } => converter.inner(),
+        }
+    }
+
+    #[inline]
+    fn source_mut(&mut self) -> &mut dyn Source {
+        match self {
+            Sound::Plain(source) => source,
+            Sound::Convertible {
+                converter,
+                converting: true,
+            } => &mut **converter,
+            Sound::Convertible { converter, .. } => converter.inner_mut(),
+        }
+    }
+
+    // Makes a sound that has not started play in the format of a crossfade. Returns `false`
+    // if that would need a converter that was not prepared.
+    fn conform(&mut self, target: Format) -> bool {
+        if format(self.source()) == target {
+            return true;
+        }
+        match self {
+            Sound::Convertible {
+                converter,
+                converting,
+            } if format(&**converter) == target => {
+                *converting = true;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+// A sound in the queue.
+struct Entry {
+    sound: Sound,
+    done: Done,
+    // Number of sounds appended before this one.
+    number: u64,
+}
+
+// Reports that a sound is done as soon as the queue is done with it. The sound itself is
+// dropped later on another thread.
+struct Done {
+    signal: Option<Sender<()>>,
+    count: Option<Arc<AtomicUsize>>,
+    reported: bool,
+}
+
+impl Done {
+    fn report(&mut self) {
+        if mem::replace(&mut self.reported, true) {
+            return;
+        }
+        if let Some(count) = &self.count {
+            count.fetch_sub(1, Ordering::AcqRel);
+        }
+        if let Some(signal) = &self.signal {
+            // The channel has room for the one message, this does not block.
+            let _ = signal.try_send(());
+        }
+    }
+}
+
+impl Drop for Done {
+    fn drop(&mut self) {
+        self.report();
+    }
+}
 
 /// The input of the queue.
 pub struct SourcesQueueInput {
-    next_sounds: Mutex<Vec<(Sound, SignalDone)>>,
+    next_sounds: Inbox<Entry>,
+
+    // Number of sounds in `next_sounds` and those the output has received but not started.
+    waiting: AtomicUsize,
+
+    // Number of sounds appended so far, and of those that are cleared.
+    appended: AtomicU64,
+    cleared: AtomicU64,
 
     // See constructor.
     keep_alive_if_empty: AtomicBool,
+
+    // Set when a sound is added, so that the output can end its silence without locking.
+    sound_added: AtomicBool,
+
+    // Format of the sounds the output plays, which stays the same during a crossfade. Zero
+    // before the first sound.
+    format: AtomicU64,
+
+    // Set when every sound is converted to `format`, see `set_format`.
+    fixed_format: AtomicBool,
+
+    // Crossfade to the next sound that the output has yet to start.
+    crossfade: Slot<(Duration, FadeCurve)>,
 }
 
 impl SourcesQueueInput {
@@ -59,10 +215,7 @@ impl SourcesQueueInput {
     where
         T: Source + Send + 'static,
     {
-        self.next_sounds
-            .lock()
-            .unwrap()
-            .push((Box::new(source) as Box<_>, None));
+        self.push(Box::new(source) as Box<_>, None, None);
     }
 
     /// Adds a new source to the end of the queue.
@@ -75,14 +228,54 @@ impl SourcesQueueInput {
     where
         T: Source + Send + 'static,
     {
-        let (tx, rx) = channel();
-        self.next_sounds
-            .lock()
-            .unwrap()
-            .push((Box::new(source) as Box<_>, Some(tx)));
+        let (tx, rx) = bounded(1);
+        self.push(Box::new(source) as Box<_>, Some(tx), None);
         rx
     }
 
+    /// Like `append_with_signal`, and decrements `count` when the sound is done, before
+    /// signalling.
+    pub(crate) fn append_counted<T>(&self, source: T, count: Arc<AtomicUsize>) -> Receiver<()>
+    where
+        T: Source + Send + 'static,
+    {
+        let (tx, rx) = bounded(1);
+        self.push(Box::new(source) as Box<_>, Some(tx), Some(count));
+        rx
+    }
+
+    fn push(
+        &self,
+        source: BoxedSource,
+        signal: Option<Sender<()>>,
+        count: Option<Arc<AtomicUsize>>,
+    ) {
+        let number = self.appended.fetch_add(1, Ordering::AcqRel);
+        // The first sound sets the format, the others are prepared to fade in after it.
+        let target = match self.format.compare_exchange(
+            0,
+            format(&*source),
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        ) {
+            Ok(_) => format(&*source),
+            Err(format) => format,
+        };
+        let sound = Sound::new(source, target);
+        let done = Done {
+            signal,
+            count,
+            reported: false,
+        };
+        self.waiting.fetch_add(1, Ordering::AcqRel);
+        self.next_sounds.push(Node::new(Entry {
+            sound,
+            done,
+            number,
+        }));
+        self.sound_added.store(true, Ordering::Release);
+    }
+
     /// Sets whether the queue stays alive if there's no more sound to play.
     ///
     /// See also the constructor.
@@ -91,26 +284,106 @@ impl SourcesQueueInput {
             .store(keep_alive_if_empty, Ordering::Release);
     }
 
+    /// Converts every sound that is added from now on to this format, instead of playing the
+    /// sounds in their own format. The converters are built when the sounds are added, so
+    /// that the consumer, like a mixer, never sees the format change and does not have to
+    /// rebuild its own converter while playing.
+    pub(crate) fn set_format(&self, channels: ChannelCount, sample_rate: SampleRate) {
+        self.format.store(
+            (channels as u64) << 32 | sample_rate as u64,
+            Ordering::Release,
+        );
+        self.fixed_format.store(true, Ordering::Release);
+    }
+
+    /// Fades out the sound that is playing over `duration` while the next sound fades in. If
+    /// there is no next sound, the sound fades out to silence.
+    ///
+    /// The next sound is converted to the channels and sample rate of the sound that is
+    /// playing if they differ. It is prepared for that when it is added, based on the format
+    /// the queue plays in at that time. Should the format have changed since, the sound that
+    /// is playing fades out to silence and the next sound follows. The crossfade starts at
+    /// the next frame, or after the crossfade that is going on.
+    pub fn crossfade_to_next(&self, duration: Duration, curve: FadeCurve) {
+        self.request_crossfade(Node::new((duration, curve)));
+    }
+
+    /// Like [`crossfade_to_next`](SourcesQueueInput::crossfade_to_next) with the duration and
+    /// curve in a node allocated beforehand, so that the audio thread can request a crossfade.
+    pub(crate) fn request_crossfade(&self, request: Box<Node<(Duration, FadeCurve)>>) {
+        if let Some(previous) = self.crossfade.replace(request) {
+            realtime::defer_drop(previous);
+        }
+    }
+
     /// Removes all the sounds from the queue. Returns the number of sounds cleared.
+    ///
+    /// The output removes them before it plays its next frame.
     pub fn clear(&self) -> usize {
-        let mut sounds = self.next_sounds.lock().unwrap();
-        let len = sounds.len();
-        sounds.clear();
-        len
+        let waiting = self.waiting.load(Ordering::Acquire);
+        self.cleared
+            .fetch_max(self.appended.load(Ordering::Acquire), Ordering::AcqRel);
+        waiting
     }
 }
 /// The output of the queue. Implements `Source`.
 pub struct SourcesQueueOutput {
     // The current iterator that produces samples.
-    current: Box<dyn Source + Send>,
+    current: Current,
+
+    // Sounds received from the input.
+    next_sounds: List<Entry>,
+
+    // The sounds appended before this number have been cleared.
+    cleared: u64,
+
+    // Position of the next sample of `current` in its frame.
+    frame_offset: usize,
 
-    // Signal this sender before picking from `next`.
-    signal_after_end: Option<Sender<()>>,
+    // The previous sound while it fades out and `current` fades in.
+    crossfade: Option<Crossfade>,
 
     // The next sounds.
     input: Arc<SourcesQueueInput>,
 }
 
+// What the queue plays.
+enum Current {
+    // Before the first sound.
+    Empty(Empty),
+    // While the queue is empty.
+    Silence(Zero),
+    Sound(Box<Node<Entry>>),
+}
+
+impl Current {
+    #[inline]
+    fn source(&self) -> &dyn Source {
+        match self {
+            Current::Empty(empty) => empty,
+            Current::Silence(silence) => silence,
+            Current::Sound(entry) => entry.value.sound.source(),
+        }
+    }
+
+    #[inline]
+    fn source_mut(&mut self) -> &mut dyn Source {
+        match self {
+            Current::Empty(empty) => empty,
+            Current::Silence(silence) => silence,
+            Current::Sound(entry) => entry.value.sound.source_mut(),
+        }
+    }
+
+    // Reports the sound done and drops it on another thread.
+    fn retire(self) {
+        if let Current::Sound(mut entry) = self {
+            entry.value.done.report();
+            realtime::defer_drop(entry);
+        }
+    }
+}
+
 const THRESHOLD: usize = 512;
 
 impl Source for SourcesQueueOutput {
@@ -127,20 +400,37 @@ impl Source for SourcesQueueOutput {
         // situation we force a span to have a maximum number of samples indicate by this
         // constant.
 
+        let current = self.current.source();
+
+        // Nothing was played yet, the first sound starts a new span.
+        if matches!(self.current, Current::Empty(_)) {
+            return Some(0);
+        }
+
+        // The silence can end after any frame.
+        if matches!(self.current, Current::Silence(_)) {
+            return Some(current.channels() as usize - self.frame_offset);
+        }
+
         // Try the current `current_span_len`.
-        if let Some(val) = self.current.current_span_len() {
+        if let Some(val) = current.current_span_len() {
             if val != 0 {
                 return Some(val);
             } else if self.input.keep_alive_if_empty.load(Ordering::Acquire)
-                && self.input.next_sounds.lock().unwrap().is_empty()
+                && self.next_sounds.is_empty()
+                && self.input.next_sounds.is_empty()
             {
-                // The next source will be a filler silence which will have the length of `THRESHOLD`
-                return Some(THRESHOLD);
+                // The next source will be a filler silence, which can end after its first frame.
+                return Some(current.channels() as usize);
             }
         }
 
         // Try the size hint.
-        let (lower_bound, _) = self.current.size_hint();
+        let (lower_bound, upper_bound) = current.size_hint();
+        if upper_bound == Some(0) {
+            // The sound has ended, the next sample starts the next one.
+            return Some(0);
+        }
         // The iterator default implementation just returns 0.
         // That's a problematic value, so skip it.
         if lower_bound > 0 {
@@ -153,12 +443,12 @@ impl Source for SourcesQueueOutput {
 
     #[inline]
     fn channels(&self) -> ChannelCount {
-        self.current.channels()
+        self.current.source().channels()
     }
 
     #[inline]
     fn sample_rate(&self) -> SampleRate {
-        self.current.sample_rate()
+        self.current.source().sample_rate()
     }
 
     #[inline]
@@ -166,6 +456,11 @@ impl Source for SourcesQueueOutput {
         None
     }
 
+    #[inline]
+    fn channel_layout(&self) -> Option<ChannelLayout> {
+        self.current.source().channel_layout()
+    }
+
     /// Only seeks within the current source.
     // We can not go back to previous sources. We could implement seek such
     // that it advances the queue if the position is beyond the current song.
@@ -177,7 +472,7 @@ impl Source for SourcesQueueOutput {
     // next few songs.
     #[inline]
     fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
-        self.current.try_seek(pos)
+        self.current.source_mut().try_seek(pos)
     }
 }
 
@@ -187,8 +482,45 @@ impl Iterator for SourcesQueueOutput {
     #[inline]
     fn next(&mut self) -> Option<Self::Item> {
         loop {
+            if self.frame_offset == 0 {
+                if self.input.cleared.load(Ordering::Acquire) > self.cleared {
+                    self.clear_next_sounds();
+                }
+                if matches!(self.current, Current::Silence(_))
+                    && self.crossfade.is_none()
+                    && self.input.sound_added.swap(false, Ordering::AcqRel)
+                {
+                    // Start the new sound right away instead of finishing the silence. A fade
+                    // out to silence is finished first.
+                    if self.go_next().is_err() {
+                        return None;
+                    }
+                    continue;
+                }
+                if self.crossfade.is_none() {
+                    if let Some(request) = self.input.crossfade.take() {
+                        let (duration, curve) = request.value;
+                        realtime::defer_drop(request);
+                        self.start_crossfade(duration, curve);
+                    }
+                }
+            }
+
             // Basic situation that will happen most of the time.
-            if let Some(sample) = self.current.next() {
+            let current = self.current.source_mut();
+            if let Some(sample) = current.next() {
+                self.frame_offset += 1;
+                if self.frame_offset >= current.channels() as usize {
+                    self.frame_offset = 0;
+                }
+                let Some(crossfade) = &mut self.crossfade else {
+                    return Some(sample);
+                };
+                let sample = crossfade.mix(sample);
+                if crossfade.pos == crossfade.len {
+                    crossfade.end();
+                    self.crossfade = None;
+                }
                 return Some(sample);
             }
 
@@ -202,7 +534,7 @@ impl Iterator for SourcesQueueOutput {
 
     #[inline]
     fn size_hint(&self) -> (usize, Option<usize>) {
-        (self.current.size_hint().0, None)
+        (self.current.source().size_hint().0, None)
     }
 }
 
@@ -212,37 +544,164 @@ impl SourcesQueueOutput {
     //
     // This method is separate so that it is not inlined.
     fn go_next(&mut self) -> Result<(), ()> {
-        if let Some(signal_after_end) = self.signal_after_end.take() {
-            let _ = signal_after_end.send(());
+        let channels = self.current.source().channels();
+        let sample_rate = self.current.source().sample_rate();
+        mem::replace(&mut self.current, Current::Empty(Empty::new())).retire();
+
+        let Some(mut next) = self.next_sound() else {
+            if self.input.keep_alive_if_empty.load(Ordering::Acquire) {
+                // Play a short silence in order to avoid spinlocking. Keeping the format
+                // of the previous sound spares the consumer a format change.
+                let silence = Zero::new_samples(
+                    channels,
+                    sample_rate,
+                    THRESHOLD - THRESHOLD % channels as usize,
+                );
+                self.current = Current::Silence(silence);
+                self.frame_offset = 0;
+                return Ok(());
+            } else {
+                return Err(());
+            }
+        };
+
+        if let Some(crossfade) = &mut self.crossfade {
+            // The crossfade mixes samples of the same format. A sound that can not be converted
+            // cuts it short.
+            if !next.value.sound.conform(crossfade.format) {
+                crossfade.end();
+                self.crossfade = None;
+            }
         }
+        if self.crossfade.is_none() {
+            if self.input.fixed_format.load(Ordering::Acquire) {
+                // Converted when it was added, unless its format already matched.
+                next.value
+                    .sound
+                    .conform(self.input.format.load(Ordering::Acquire));
+            } else {
+                self.input
+                    .format
+                    .store(format(next.value.sound.source()), Ordering::Release);
+            }
+        }
+        self.current = Current::Sound(next);
+        self.frame_offset = 0;
+        Ok(())
+    }
 
-        let (next, signal_after_end) = {
-            let mut next = self.input.next_sounds.lock().unwrap();
+    // Takes the next sound that was not cleared.
+    fn next_sound(&mut self) -> Option<Box<Node<Entry>>> {
+        if !self.input.next_sounds.is_empty() {
+            self.next_sounds.append(self.input.next_sounds.take_all());
+        }
+        loop {
+            let mut next = self.next_sounds.pop_front()?;
+            self.input.waiting.fetch_sub(1, Ordering::AcqRel);
+            // A sound that was appended while clearing may arrive after the others.
+            if next.value.number >= self.cleared {
+                return Some(next);
+            }
+            next.value.done.report();
+            realtime::defer_drop(next);
+        }
+    }
+
+    // Drops the sounds that were appended before the queue was cleared.
+    fn clear_next_sounds(&mut self) {
+        self.cleared = self.input.cleared.load(Ordering::Acquire);
+        self.next_sounds.append(self.input.next_sounds.take_all());
+        let cleared = self.cleared;
+        let waiting = &self.input.waiting;
+        self.next_sounds.retain(
+            |entry| entry.number >= cleared,
+            |mut entry| {
+                waiting.fetch_sub(1, Ordering::AcqRel);
+                entry.value.done.report();
+                realtime::defer_drop(entry);
+            },
+        );
+    }
 
-            if next.len() == 0 {
-                let silence = Box::new(Zero::new_samples(1, 44100, THRESHOLD)) as Box<_>;
-                if self.input.keep_alive_if_empty.load(Ordering::Acquire) {
-                    // Play a short silence in order to avoid spinlocking.
-                    (silence, None)
+    // Moves the current sound into a crossfade with the next one.
+    fn start_crossfade(&mut self, duration: Duration, curve: FadeCurve) {
+        let channels = self.current.source().channels();
+        let sample_rate = self.current.source().sample_rate();
+        let frames = (duration.as_secs_f64() * sample_rate as f64).round() as usize;
+        let len = frames.max(1) * channels as usize;
+
+        let format = format(self.current.source());
+        let next = match self.next_sound() {
+            Some(mut next) => {
+                if next.value.sound.conform(format) {
+                    Current::Sound(next)
                 } else {
-                    return Err(());
+                    // Fade out to silence, the sound that can not be converted plays after it.
+                    self.input.waiting.fetch_add(1, Ordering::AcqRel);
+                    self.next_sounds.push_front(next);
+                    Current::Silence(Zero::new_samples(channels, sample_rate, len))
                 }
-            } else {
-                next.remove(0)
             }
+            None => Current::Silence(Zero::new_samples(channels, sample_rate, len)),
         };
 
-        self.current = next;
-        self.signal_after_end = signal_after_end;
-        Ok(())
+        let previous = mem::replace(&mut self.current, next);
+        self.crossfade = Some(Crossfade {
+            previous: Some(previous),
+            curve,
+            channels,
+            format,
+            pos: 0,
+            len,
+        });
+        self.frame_offset = 0;
+    }
+}
+
+// A sound that fades out while the current sound of the queue fades in.
+struct Crossfade {
+    // `None` once the sound has ended.
+    previous: Option<Current>,
+    curve: FadeCurve,
+    channels: ChannelCount,
+    format: Format,
+    // Number of samples mixed so far, and in total.
+    pos: usize,
+    len: usize,
+}
+
+impl Crossfade {
+    // Mixes the next sample of the previous sound into a sample of the current one.
+    #[inline]
+    fn mix(&mut self, sample: Sample) -> Sample {
+        let channels = self.channels as usize;
+        let progress = (self.pos / channels) as f32 / (self.len / channels) as f32;
+        self.pos += 1;
+        let previous = match &mut self.previous {
+            Some(previous) => previous.source_mut().next().unwrap_or_else(|| {
+                self.end();
+                0.0
+            }),
+            None => 0.0,
+        };
+        sample * self.curve.gain(progress) + previous * self.curve.gain(1.0 - progress)
+    }
+
+    // Lets go of the previous sound and signals that it is done.
+    fn end(&mut self) {
+        if let Some(previous) = self.previous.take() {
+            previous.retire();
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::time::Duration;
+
     use crate::buffer::SamplesBuffer;
     use crate::queue;
-    use crate::source::Source;
+    use crate::source::{FadeCurve, Source};
 
     #[test]
     #[ignore] // FIXME: samples rate and channel not updated immediately after transition
@@ -289,7 +748,57 @@ mod tests {
     }
 
     #[test]
-    #[ignore] // TODO: not yet implemented
+    fn silence_keeps_format() {
+        let (tx, mut rx) = queue::queue(true);
+        tx.append(SamplesBuffer::new(2, 96000, vec![1.0, 2.0]));
+        assert_eq!(rx.next(), Some(1.0));
+        assert_eq!(rx.next(), Some(2.0));
+
+        // Silence of the previous format, ending after a frame once a sound is added.
+        assert_eq!(rx.next(), Some(0.0));
+        assert_eq!((rx.channels(), rx.sample_rate()), (2, 96000));
+        assert_eq!(rx.current_span_len(), Some(1));
+        tx.append(SamplesBuffer::new(2, 96000, vec![3.0, 4.0]));
+        assert_eq!(rx.next(), Some(0.0));
+        assert_eq!(rx.next(), Some(3.0));
+        assert_eq!(rx.next(), Some(4.0));
+    }
+
+    #[test]
+    fn crossfade_to_next() {
+        let (tx, mut rx) = queue::queue(false);
+        tx.append(SamplesBuffer::new(1, 48000, vec![1.0; 6]));
+        // Converted to mono for the crossfade.
+        tx.append(SamplesBuffer::new(2, 48000, vec![0.5; 12]));
+
+        assert_eq!(rx.next(), Some(1.0));
+        tx.crossfade_to_next(Duration::from_secs(4) / 48000, FadeCurve::Linear);
+        let fade: Vec<f32> = rx.by_ref().take(4).collect();
+        assert_eq!(fade, [1.0, 0.875, 0.75, 0.625]);
+        // The rest of the first sound was dropped after the fade.
+        assert_eq!(rx.channels(), 1);
+        assert_eq!(rx.collect::<Vec<_>>(), [0.5; 2]);
+    }
+
+    #[test]
+    fn crossfade_after_format_change() {
+        let (tx, mut rx) = queue::queue(false);
+        tx.append(SamplesBuffer::new(1, 48000, vec![1.0; 2]));
+        tx.append(SamplesBuffer::new(2, 48000, vec![0.5; 8]));
+        // Prepared for the mono sound, but follows the stereo one.
+        tx.append(SamplesBuffer::new(1, 96000, vec![0.25; 2]));
+
+        let start: Vec<f32> = rx.by_ref().take(4).collect();
+        assert_eq!(start, [1.0, 1.0, 0.5, 0.5]);
+        tx.crossfade_to_next(Duration::from_secs(2) / 48000, FadeCurve::Linear);
+        let fade: Vec<f32> = rx.by_ref().take(4).collect();
+        assert_eq!(fade, [0.5, 0.5, 0.25, 0.25]);
+        assert_eq!(rx.next(), Some(0.25));
+        assert_eq!((rx.channels(), rx.sample_rate()), (1, 96000));
+        assert_eq!(rx.collect::<Vec<_>>(), [0.25]);
+    }
+
+    #[test]
     fn no_delay_when_added() {
         let (tx, mut rx) = queue::queue(true);
 
@@ -0,0 +1,485 @@
+//! Building blocks that let the audio thread exchange data with other threads without waiting.
+//!
+//! The audio thread must never block on a lock held by another thread, and should neither
+//! allocate nor free memory since the allocator may lock too. Sounds and messages are
+//! therefore allocated by the thread that sends them, passed along in [`Node`]s that can be
+//! linked into an [`Inbox`] or a [`List`] without allocating, and freed on a separate thread
+//! with [`defer_drop`].
+
+use std::marker::PhantomData;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, AtomicU32, AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::thread::{self, Thread};
+use std::time::Duration;
+
+/// A value in its own allocation, with room for the link that puts it in a list.
+#[repr(C)]
+pub(crate) struct Node<T> {
+    // Must be the first field, nodes are linked by pointers to it.
+    link: Link,
+    pub(crate) value: T,
+}
+
+// A node that is not in a list is owned like a box, and a list owns its nodes.
+unsafe impl<T: Send> Send for Node<T> {}
+
+// Type erased start of a node.
+struct Link {
+    next: *mut Link,
+    drop: unsafe fn(*mut Link),
+}
+
+impl<T> Node<T> {
+    pub(crate) fn new(value: T) -> Box<Node<T>> {
+        Box::new(Node {
+            link: Link {
+                next: ptr::null_mut(),
+                drop: drop_link::<T>,
+            },
+            value,
+        })
+    }
+}
+
+fn into_link<T>(node: Box<Node<T>>) -> *mut Link {
+    Box::into_raw(node).cast()
+}
+
+/// # Safety
+/// `link` must come from `into_link::<T>` and be owned by the caller.
+unsafe fn from_link<T>(link: *mut Link) -> Box<Node<T>> {
+    Box::from_raw(link.cast())
+}
+
+/// # Safety
+/// See `from_link`.
+unsafe fn drop_link<T>(link: *mut Link) {
+    drop(from_link::<T>(link));
+}
+
+/// Nodes that any thread can add, and that one thread takes all at once.
+///
+/// Adding retries when another thread adds at the same moment, taking never waits.
+pub(crate) struct Inbox<T> {
+    head: AtomicPtr<Link>,
+    _marker: PhantomData<Box<Node<T>>>,
+}
+
+// The nodes are only ever accessed by one thread at a time.
+unsafe impl<T: Send> Send for Inbox<T> {}
+unsafe impl<T: Send> Sync for Inbox<T> {}
+
+impl<T> Inbox<T> {
+    pub(crate) fn new() -> Inbox<T> {
+        Inbox {
+            head: AtomicPtr::new(ptr::null_mut()),
+            _marker: PhantomData,
+        }
+    }
+
+    pub(crate) fn push(&self, node: Box<Node<T>>) {
+        let link = into_link(node);
+        let mut head = self.head.load(Ordering::Relaxed);
+        loop {
+            // Safety: the node is not shared until the exchange succeeds.
+            unsafe { (*link).next = head };
+            match self
+                .head
+                .compare_exchange_weak(head, link, Ordering::Release, Ordering::Relaxed)
+            {
+                Ok(_) => return,
+                Err(current) => head = current,
+            }
+        }
+    }
+
+    #[inline]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.head.load(Ordering::Relaxed).is_null()
+    }
+
+    /// Takes all the nodes, the oldest first.
+    pub(crate) fn take_all(&self) -> List<T> {
+        let mut link = self.head.swap(ptr::null_mut(), Ordering::Acquire);
+        // The inbox is a stack, reverse it.
+        let tail = link;
+        let mut head = ptr::null_mut();
+        while !link.is_null() {
+            // Safety: the nodes were taken out of the inbox, they are ours now.
+            unsafe {
+                let next = (*link).next;
+                (*link).next = head;
+                head = link;
+                link = next;
+            }
+        }
+        List {
+            head,
+            tail,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T> Drop for Inbox<T> {
+    fn drop(&mut self) {
+        drop(self.take_all());
+    }
+}
+
+/// Queue of nodes owned by one thread, moving nodes in and out never allocates.
+pub(crate) struct List<T> {
+    head: *mut Link,
+    tail: *mut Link,
+    _marker: PhantomData<Box<Node<T>>>,
+}
+
+unsafe impl<T: Send> Send for List<T> {}
+
+impl<T> List<T> {
+    pub(crate) fn new() -> List<T> {
+        List {
+            head: ptr::null_mut(),
+            tail: ptr::null_mut(),
+            _marker: PhantomData,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.head.is_null()
+    }
+
+    pub(crate) fn push_back(&mut self, node: Box<Node<T>>) {
+        let link = into_link(node);
+        if self.tail.is_null() {
+            self.head = link;
+        } else {
+            // Safety: the list owns its nodes.
+            unsafe { (*self.tail).next = link };
+        }
+        self.tail = link;
+    }
+
+    pub(crate) fn push_front(&mut self, node: Box<Node<T>>) {
+        let link = into_link(node);
+        // Safety: the node is not in a list yet.
+        unsafe { (*link).next = self.head };
+        if self.tail.is_null() {
+            self.tail = link;
+        }
+        self.head = link;
+    }
+
+    pub(crate) fn pop_front(&mut self) -> Option<Box<Node<T>>> {
+        if self.head.is_null() {
+            return None;
+        }
+        let link = self.head;
+        // Safety: the list owns its nodes, and they were made from `Node<T>`.
+        unsafe {
+            self.head = (*link).next;
+            (*link).next = ptr::null_mut();
+            if self.head.is_null() {
+                self.tail = ptr::null_mut();
+            }
+            Some(from_link(link))
+        }
+    }
+
+    /// Moves all the nodes of `other` to the end of this list.
+    pub(crate) fn append(&mut self, mut other: List<T>) {
+        if other.is_empty() {
+            return;
+        }
+        if self.tail.is_null() {
+            self.head = other.head;
+        } else {
+            // Safety: the list owns its nodes.
+            unsafe { (*self.tail).next = other.head };
+        }
+        self.tail = other.tail;
+        other.head = ptr::null_mut();
+        other.tail = ptr::null_mut();
+    }
+
+    /// Keeps the values for which `keep` returns `true` in order, and passes the nodes of the
+    /// others to `removed`.
+    pub(crate) fn retain(
+        &mut self,
+        mut keep: impl FnMut(&mut T) -> bool,
+        mut removed: impl FnMut(Box<Node<T>>),
+    ) {
+        let mut nodes = std::mem::replace(self, List::new());
+        while let Some(mut node) = nodes.pop_front() {
+            if keep(&mut node.value) {
+                self.push_back(node);
+            } else {
+                removed(node);
+            }
+        }
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &T> {
+        let mut link = self.head;
+        std::iter::from_fn(move || {
+            if link.is_null() {
+                return None;
+            }
+            // Safety: the list owns its nodes and is borrowed.
+            unsafe {
+                let node = &*link.cast::<Node<T>>();
+                link = (*link).next;
+                Some(&node.value)
+            }
+        })
+    }
+}
+
+impl<T> Drop for List<T> {
+    fn drop(&mut self) {
+        while self.pop_front().is_some() {}
+    }
+}
+
+/// Holds at most one value, which the audio thread can take without waiting.
+pub(crate) struct Slot<T> {
+    node: AtomicPtr<Link>,
+    _marker: PhantomData<Box<Node<T>>>,
+}
+
+unsafe impl<T: Send> Send for Slot<T> {}
+unsafe impl<T: Send> Sync for Slot<T> {}
+
+impl<T> Slot<T> {
+    pub(crate) fn new() -> Slot<T> {
+        Slot {
+            node: AtomicPtr::new(ptr::null_mut()),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Replaces the value, dropping the previous one on this thread.
+    pub(crate) fn put(&self, value: T) {
+        drop(self.replace(Node::new(value)));
+    }
+
+    /// Replaces the value with one allocated beforehand, returns the previous one.
+    pub(crate) fn replace(&self, node: Box<Node<T>>) -> Option<Box<Node<T>>> {
+        let previous = self.node.swap(into_link(node), Ordering::AcqRel);
+        // Safety: swapped out of the slot, so it is ours.
+        (!previous.is_null()).then(|| unsafe { from_link(previous) })
+    }
+
+    /// Takes the value out of the slot. Pass the node to [`defer_drop`] when done with it.
+    #[inline]
+    pub(crate) fn take(&self) -> Option<Box<Node<T>>> {
+        if self.node.load(Ordering::Relaxed).is_null() {
+            return None;
+        }
+        let link = self.node.swap(ptr::null_mut(), Ordering::AcqRel);
+        // Safety: swapped out of the slot, so it is ours.
+        (!link.is_null()).then(|| unsafe { from_link(link) })
+    }
+
+    /// Passes the value to `read` and leaves it in the slot, unless a new value was put in
+    /// the meantime. The value that was replaced is then dropped with [`defer_drop`].
+    ///
+    /// Only one thread at a time may read, others may put new values.
+    #[inline]
+    pub(crate) fn read<R>(&self, read: impl FnOnce(&T) -> R) -> Option<R>
+    where
+        T: Send + 'static,
+    {
+        let node = self.take()?;
+        let result = read(&node.value);
+        let link = into_link(node);
+        if self
+            .node
+            .compare_exchange(ptr::null_mut(), link, Ordering::AcqRel, Ordering::Relaxed)
+            .is_err()
+        {
+            // Safety: the exchange failed, so the node is still ours.
+            defer_drop(unsafe { from_link::<T>(link) });
+        }
+        Some(result)
+    }
+}
+
+impl<T> Drop for Slot<T> {
+    fn drop(&mut self) {
+        drop(self.take());
+    }
+}
+
+static GARBAGE: AtomicPtr<Link> = AtomicPtr::new(ptr::null_mut());
+// `None` if no thread could be started, then nodes are dropped right away.
+static COLLECTOR: OnceLock<Option<Thread>> = OnceLock::new();
+
+/// Starts the thread that drops what is passed to [`defer_drop`], if it does not run yet.
+///
+/// Must be called before the audio thread uses `defer_drop`, since starting a thread
+/// allocates.
+pub(crate) fn start_collector() {
+    COLLECTOR.get_or_init(|| {
+        thread::Builder::new()
+            .name("rodio-collector".to_owned())
+            .spawn(|| loop {
+                thread::park();
+                collect_garbage();
+            })
+            .ok()
+            .map(|handle| handle.thread().clone())
+    });
+}
+
+/// Drops `node` on the collector thread, so that the calling thread does not free memory.
+pub(crate) fn defer_drop<T>(node: Box<Node<T>>)
+where
+    T: Send + 'static,
+{
+    let Some(Some(collector)) = COLLECTOR.get() else {
+        drop(node);
+        return;
+    };
+    let link = into_link(node);
+    let mut head = GARBAGE.load(Ordering::Relaxed);
+    loop {
+        // Safety: the node is not shared until the exchange succeeds.
+        unsafe { (*link).next = head };
+        match GARBAGE.compare_exchange_weak(head, link, Ordering::Release, Ordering::Relaxed) {
+            Ok(_) => break,
+            Err(current) => head = current,
+        }
+    }
+    collector.unpark();
+}
+
+fn collect_garbage() {
+    let mut link = GARBAGE.swap(ptr::null_mut(), Ordering::Acquire);
+    while !link.is_null() {
+        // Safety: the nodes were taken out of the garbage, and each knows how to drop itself.
+        unsafe {
+            let next = (*link).next;
+            ((*link).drop)(link);
+            link = next;
+        }
+    }
+}
+
+/// An `f32` that can be shared between threads.
+pub(crate) struct AtomicF32(AtomicU32);
+
+impl AtomicF32 {
+    pub(crate) fn new(value: f32) -> AtomicF32 {
+        AtomicF32(AtomicU32::new(value.to_bits()))
+    }
+
+    #[inline]
+    pub(crate) fn load(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    #[inline]
+    pub(crate) fn store(&self, value: f32) {
+        self.0.store(value.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// A `Duration` of up to 584 years that can be shared between threads.
+pub(crate) struct AtomicDuration(AtomicU64);
+
+impl AtomicDuration {
+    pub(crate) fn new(value: Duration) -> AtomicDuration {
+        AtomicDuration(AtomicU64::new(duration_to_nanos(value)))
+    }
+
+    #[inline]
+    pub(crate) fn load(&self) -> Duration {
+        Duration::from_nanos(self.0.load(Ordering::Relaxed))
+    }
+
+    #[inline]
+    pub(crate) fn store(&self, value: Duration) {
+        self.0.store(duration_to_nanos(value), Ordering::Relaxed);
+    }
+}
+
+fn duration_to_nanos(duration: Duration) -> u64 {
+    duration.as_nanos().try_into().unwrap_or(u64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use super::{Inbox, List, Node, Slot};
+
+    #[test]
+    fn inbox_keeps_order() {
+        let inbox = Inbox::new();
+        for value in 0..5 {
+            inbox.push(Node::new(value));
+        }
+        let mut list = inbox.take_all();
+        assert!(inbox.is_empty());
+        assert_eq!(list.iter().copied().collect::<Vec<_>>(), [0, 1, 2, 3, 4]);
+
+        let mut removed = Vec::new();
+        list.retain(|value| *value % 2 == 0, |node| removed.push(node.value));
+        assert_eq!(removed, [1, 3]);
+        list.append(List::new());
+        inbox.push(Node::new(6));
+        list.append(inbox.take_all());
+        assert_eq!(list.iter().copied().collect::<Vec<_>>(), [0, 2, 4, 6]);
+        assert_eq!(list.pop_front().map(|node| node.value), Some(0));
+        list.push_front(Node::new(1));
+        assert_eq!(list.iter().copied().collect::<Vec<_>>(), [1, 2, 4, 6]);
+    }
+
+    #[test]
+    fn drops_everything() {
+        struct Counted(Arc<AtomicUsize>);
+        impl Drop for Counted {
+            fn drop(&mut self) {
+                self.0.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let dropped = Arc::new(AtomicUsize::new(0));
+        let inbox = Inbox::new();
+        let mut list = List::new();
+        for _ in 0..3 {
+            inbox.push(Node::new(Counted(dropped.clone())));
+            list.push_back(Node::new(Counted(dropped.clone())));
+        }
+        drop(inbox);
+        drop(list);
+        assert_eq!(dropped.load(Ordering::Relaxed), 6);
+    }
+
+    #[test]
+    fn slot() {
+        let slot = Slot::new();
+        assert_eq!(slot.read(|value| *value), None);
+        slot.put(1);
+        assert_eq!(slot.read(|value| *value), Some(1));
+        assert_eq!(slot.read(|value| *value), Some(1));
+        assert_eq!(slot.replace(Node::new(2)).map(|node| node.value), Some(1));
+        assert_eq!(slot.take().map(|node| node.value), Some(2));
+        assert!(slot.take().is_none());
+
+        // A value put while reading replaces the one that was read.
+        slot.put(3);
+        assert_eq!(
+            slot.read(|value| {
+                slot.put(4);
+                *value
+            }),
+            Some(3)
+        );
+        assert_eq!(slot.read(|value| *value), Some(4));
+    }
+}
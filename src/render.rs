@@ -0,0 +1,236 @@
+//! Rendering a mixer without an audio device.
+
+use std::time::Duration;
+
+use crate::buffer::SamplesBuffer;
+use crate::common::{ChannelCount, SampleRate};
+use crate::mixer::{mixer, Mixer, MixerSource};
+use crate::{Sample, Source};
+
+const DEFAULT_BLOCK_FRAMES: usize = 512;
+
+/// Plays a [`Mixer`] as fast as possible instead of in real time, for example to render audio
+/// to a file in a test.
+///
+/// The renderer takes the place of the output stream: it owns the output of the mixer and pulls
+/// it in blocks, just like the callback of an audio device does. Everything that is played
+/// through the mixer behaves like it would live, including [`Sink`](crate::Sink)s connected
+/// with [`Sink::connect_new`](crate::Sink::connect_new), sources scheduled with
+/// [`Mixer::add_at`] and [`Source::periodic_access`] callbacks. Only time passes faster.
+///
+/// Rendering happens on the thread that calls it, so methods that wait for the audio thread,
+/// like [`Sink::try_seek`](crate::Sink::try_seek) and
+/// [`Sink::sleep_until_end`](crate::Sink::sleep_until_end), must be called from another
+/// thread.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use rodio::source::{SineWave, Source};
+/// use rodio::{OfflineRenderer, Sink};
+///
+/// let mut renderer = OfflineRenderer::new(2, 44_100);
+/// let sink = Sink::connect_new(renderer.mixer());
+/// sink.append(SineWave::new(440.0).take_duration(Duration::from_secs(1)));
+/// sink.set_volume(0.5);
+///
+/// let rendered = renderer.render(Duration::from_secs(2));
+/// assert_eq!(rendered.total_duration(), Some(Duration::from_secs(2)));
+/// ```
+pub struct OfflineRenderer {
+    mixer: Mixer,
+    source: MixerSource,
+    block_frames: usize,
+    frames_rendered: u64,
+}
+
+impl OfflineRenderer {
+    /// Creates a renderer with a new mixer of the given format.
+    pub fn new(channels: ChannelCount, sample_rate: SampleRate) -> OfflineRenderer {
+        assert!(channels > 0, "channel number is greater than zero");
+        assert!(sample_rate > 0, "sample rate is greater than zero");
+        let (mixer, source) = mixer(channels, sample_rate);
+        OfflineRenderer {
+            mixer,
+            source,
+            block_frames: DEFAULT_BLOCK_FRAMES,
+            frames_rendered: 0,
+        }
+    }
+
+    /// Sets the number of frames rendered at once, the buffer size of a real device.
+    ///
+    /// Changes made between calls to [`render_block`](OfflineRenderer::render_block) take
+    /// effect at a block boundary, so matching the block size of the device makes the output
+    /// closer to live playback. The default is 512 frames.
+    pub fn with_block_size(mut self, frames: usize) -> OfflineRenderer {
+        assert!(frames > 0, "block size is greater than zero");
+        self.block_frames = frames;
+        self
+    }
+
+    /// Access the renderer's mixer.
+    #[inline]
+    pub fn mixer(&self) -> &Mixer {
+        &self.mixer
+    }
+
+    /// Returns the number of channels of the output.
+    #[inline]
+    pub fn channels(&self) -> ChannelCount {
+        self.source.channels()
+    }
+
+    /// Returns the sample rate of the output.
+    #[inline]
+    pub fn sample_rate(&self) -> SampleRate {
+        self.source.sample_rate()
+    }
+
+    /// Returns the number of frames rendered so far.
+    #[inline]
+    pub fn frames_rendered(&self) -> u64 {
+        self.frames_rendered
+    }
+
+    /// Returns the time rendered so far.
+    #[inline]
+    pub fn position(&self) -> Duration {
+        self.source.position()
+    }
+
+    /// Fills `block` with the next samples of the mixer, silence when nothing is playing.
+    ///
+    /// The length of `block` must be a multiple of the channel count. Returns `true` if a
+    /// source was playing during the block.
+    pub fn render_block(&mut self, block: &mut [Sample]) -> bool {
+        let channels = self.channels() as usize;
+        assert!(
+            block.len().is_multiple_of(channels),
+            "block holds whole frames"
+        );
+
+        let mut playing = false;
+        for sample in block.iter_mut() {
+            *sample = match self.source.next() {
+                Some(value) => {
+                    playing = true;
+                    value
+                }
+                None => 0.0,
+            };
+        }
+        self.frames_rendered += (block.len() / channels) as u64;
+        playing
+    }
+
+    /// Renders `duration` of audio in blocks and returns it. The duration is rounded to the
+    /// nearest frame.
+    pub fn render(&mut self, duration: Duration) -> SamplesBuffer {
+        let mut samples = Vec::new();
+        self.render_with(duration, |block| samples.extend_from_slice(block));
+        SamplesBuffer::new(self.channels(), self.sample_rate(), samples)
+    }
+
+    /// Renders `duration` of audio into a 32-bit float WAV file. The duration is rounded to
+    /// the nearest frame.
+    #[cfg(feature = "wav")]
+    pub fn render_to_wav(
+        &mut self,
+        duration: Duration,
+        wav_file: impl AsRef<std::path::Path>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let format = hound::WavSpec {
+            channels: self.channels(),
+            sample_rate: self.sample_rate(),
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        let mut writer = hound::WavWriter::create(wav_file, format)?;
+        let mut result = Ok(());
+        self.render_with(duration, |block| {
+            for &sample in block {
+                if result.is_ok() {
+                    result = writer.write_sample(sample);
+                }
+            }
+        });
+        result?;
+        writer.finalize()?;
+        Ok(())
+    }
+
+    fn render_with(&mut self, duration: Duration, mut on_block: impl FnMut(&[Sample])) {
+        let channels = self.channels() as usize;
+        let rate = self.sample_rate() as u128;
+        let mut frames_left = ((duration.as_nanos() * rate + 500_000_000) / 1_000_000_000) as u64;
+
+        let mut block = vec![0.0; self.block_frames * channels];
+        while frames_left > 0 {
+            let frames = frames_left.min(self.block_frames as u64) as usize;
+            let block = &mut block[..frames * channels];
+            self.render_block(block);
+            on_block(block);
+            frames_left -= frames as u64;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::OfflineRenderer;
+    use crate::buffer::SamplesBuffer;
+    use crate::{Sample, Sink, Source};
+
+    #[test]
+    fn renders_sinks_and_scheduled_sources() {
+        let mut renderer = OfflineRenderer::new(1, 48000).with_block_size(3);
+        let sink = Sink::connect_new(renderer.mixer());
+        sink.append(SamplesBuffer::new(1, 48000, vec![1.0; 4]));
+        sink.set_volume(0.5);
+        renderer
+            .mixer()
+            .add_at_frame(SamplesBuffer::new(1, 48000, vec![0.25; 2]), 6);
+
+        let rendered: Vec<Sample> = renderer
+            .render(Duration::from_micros(1000) * 10 / 48)
+            .collect();
+        assert_eq!(
+            rendered,
+            vec![0.5, 0.5, 0.5, 0.5, 0.0, 0.0, 0.25, 0.25, 0.0, 0.0]
+        );
+        assert_eq!(renderer.frames_rendered(), 10);
+        assert_eq!(renderer.position(), Duration::from_nanos(208_333));
+        assert!(sink.empty());
+    }
+
+    #[test]
+    fn periodic_access_runs_per_block() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut renderer = OfflineRenderer::new(2, 1000);
+        let counter = calls.clone();
+        renderer.mixer().add(
+            SamplesBuffer::new(2, 1000, vec![0.1; 2000]).periodic_access(
+                Duration::from_millis(100),
+                move |_| {
+                    counter.fetch_add(1, Ordering::Relaxed);
+                },
+            ),
+        );
+
+        let mut block = [0.0; 200];
+        assert!(renderer.render_block(&mut block));
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+        let _ = renderer.render(Duration::from_millis(900));
+        assert_eq!(calls.load(Ordering::Relaxed), 10);
+
+        // The source ended, only silence is left.
+        assert!(!renderer.render_block(&mut block));
+        assert!(block.iter().all(|&sample| sample == 0.0));
+    }
+}
@@ -1,4 +1,4 @@
-use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
@@ -6,11 +6,13 @@ use std::time::Duration;
 use crossbeam_channel::{Receiver, Sender};
 use dasp_sample::FromSample;
 #[cfg(not(feature = "crossbeam-channel"))]
-use std::sync::mpsc::{Receiver, Sender};
+use std::sync::mpsc::{Receiver, SyncSender as Sender};
 
 use crate::mixer::Mixer;
+use crate::realtime::{self, AtomicDuration, AtomicF32, Node, Slot};
+use crate::source::FadeCurve;
 use crate::source::SeekError;
-use crate::{queue, source::Done, Source};
+use crate::{queue, Source};
 
 /// Handle to a device that outputs sounds.
 ///
@@ -36,7 +38,7 @@ impl SeekOrder {
         #[cfg(not(feature = "crossbeam-channel"))]
         let (tx, rx) = {
             use std::sync::mpsc;
-            mpsc::channel()
+            mpsc::sync_channel(1)
         };
 
         #[cfg(feature = "crossbeam-channel")]
@@ -47,30 +49,57 @@ impl SeekOrder {
         (Self { pos, feedback: tx }, rx)
     }
 
-    fn attempt<S>(self, maybe_seekable: &mut S)
+    fn attempt<S>(&self, maybe_seekable: &mut S)
     where
         S: Source,
     {
         let res = maybe_seekable.try_seek(self.pos);
-        let _ignore_receiver_dropped = self.feedback.send(res);
+        // The channel has room for the one result, this does not block.
+        let _ignore_receiver_dropped = self.feedback.try_send(res);
     }
 }
 
+// Read by the sounds on the audio thread, which never waits for them.
 struct Controls {
     pause: AtomicBool,
-    volume: Mutex<f32>,
+    volume: AtomicF32,
     stopped: AtomicBool,
-    speed: Mutex<f32>,
-    to_clear: Mutex<u32>,
-    seek: Mutex<Option<SeekOrder>>,
-    position: Mutex<Duration>,
+    speed: AtomicF32,
+    tempo: AtomicF32,
+    to_clear: AtomicU32,
+    seek: Slot<SeekOrder>,
+    position: AtomicDuration,
+    // Only read by the audio thread, `crossfade_set` tells the others whether there is one.
+    crossfade: Slot<Option<(Duration, FadeCurve)>>,
+    crossfade_set: AtomicBool,
+    to_crossfade: AtomicU32,
+}
+
+// Takes one from a request counter, returns whether there was one.
+fn take_request(counter: &AtomicU32) -> bool {
+    counter
+        .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| n.checked_sub(1))
+        .is_ok()
+}
+
+// Adds one to a request counter unless there are as many requests as sounds.
+fn add_request(counter: &AtomicU32, sounds: u32) {
+    let _ = counter.fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| {
+        (sounds > n).then_some(n + 1)
+    });
 }
 
 impl Sink {
     /// Builds a new `Sink`, beginning playback on a stream.
+    ///
+    /// Sounds are converted to the channels and sample rate of the mixer when they are
+    /// appended, so that switching between sounds of different formats does not rebuild a
+    /// converter while playing.
     #[inline]
     pub fn connect_new(mixer: &Mixer) -> Sink {
         let (sink, source) = Sink::new();
+        let (channels, sample_rate) = mixer.format();
+        sink.queue_tx.set_format(channels, sample_rate);
         mixer.add(source);
         sink
     }
@@ -85,12 +114,16 @@ impl Sink {
             sleep_until_end: Mutex::new(None),
             controls: Arc::new(Controls {
                 pause: AtomicBool::new(false),
-                volume: Mutex::new(1.0),
+                volume: AtomicF32::new(1.0),
                 stopped: AtomicBool::new(false),
-                speed: Mutex::new(1.0),
-                to_clear: Mutex::new(0),
-                seek: Mutex::new(None),
-                position: Mutex::new(Duration::ZERO),
+                speed: AtomicF32::new(1.0),
+                tempo: AtomicF32::new(1.0),
+                to_clear: AtomicU32::new(0),
+                seek: Slot::new(),
+                position: AtomicDuration::new(Duration::ZERO),
+                crossfade: Slot::new(),
+                crossfade_set: AtomicBool::new(false),
+                to_crossfade: AtomicU32::new(0),
             }),
             sound_count: Arc::new(AtomicUsize::new(0)),
             detached: false,
@@ -114,10 +147,17 @@ impl Sink {
         }
 
         let controls = self.controls.clone();
+        let queue_tx = self.queue_tx.clone();
+        self.sound_count.fetch_add(1, Ordering::Relaxed);
+        let sound_count = self.sound_count.clone();
+        // The request to fade out, allocated here for the audio thread. Taken once the sound
+        // fades out, from then on the next sound is the one that plays.
+        let mut fade_out = Some(Node::new((Duration::ZERO, FadeCurve::Linear)));
 
         let start_played = AtomicBool::new(false);
 
         let source = source
+            .time_stretch(1.0)
             .speed(1.0)
             // must be placed before pausable but after speed & delay
             .track_position()
@@ -129,34 +169,56 @@ impl Sink {
             .periodic_access(Duration::from_millis(5), move |src| {
                 if controls.stopped.load(Ordering::SeqCst) {
                     src.stop();
-                    *controls.position.lock().unwrap() = Duration::ZERO;
+                    controls.position.store(Duration::ZERO);
                 }
-                {
-                    let mut to_clear = controls.to_clear.lock().unwrap();
-                    if *to_clear > 0 {
-                        src.inner_mut().skip();
-                        *to_clear -= 1;
-                        *controls.position.lock().unwrap() = Duration::ZERO;
+                if take_request(&controls.to_clear) {
+                    src.inner_mut().skip();
+                    controls.position.store(Duration::ZERO);
+                } else if fade_out.is_some() {
+                    controls
+                        .position
+                        .store(src.inner().inner().inner().inner().get_pos());
+                }
+                let crossfade = fade_out
+                    .as_ref()
+                    .and_then(|_| controls.crossfade.read(|crossfade| *crossfade).flatten());
+                if let Some((duration, curve)) = crossfade {
+                    // Overlap the end with the next sound, if there is one.
+                    let pos = src.inner().inner().inner().inner().get_pos();
+                    let remaining = src
+                        .total_duration()
+                        .map(|total| total.saturating_sub(pos))
+                        .filter(|_| sound_count.load(Ordering::Relaxed) > 1);
+                    let fade = if take_request(&controls.to_crossfade) {
+                        Some(duration)
                     } else {
-                        *controls.position.lock().unwrap() = src.inner().inner().inner().inner().get_pos();
+                        // End the fade together with the sound.
+                        remaining.filter(|remaining| *remaining <= duration)
+                    };
+                    if let Some(duration) = fade {
+                        if let Some(mut request) = fade_out.take() {
+                            request.value = (duration, curve);
+                            queue_tx.request_crossfade(request);
+                        }
                     }
                 }
                 let amp = src.inner_mut().inner_mut();
-                amp.set_factor(*controls.volume.lock().unwrap());
+                amp.set_factor(controls.volume.load());
                 amp.inner_mut()
                     .set_paused(controls.pause.load(Ordering::SeqCst));
-                amp.inner_mut()
-                    .inner_mut()
-                    .inner_mut()
-                    .set_factor(*controls.speed.lock().unwrap());
-                if let Some(seek) = controls.seek.lock().unwrap().take() {
-                    seek.attempt(amp)
+                let speed = amp.inner_mut().inner_mut().inner_mut();
+                speed.set_factor(controls.speed.load());
+                speed.inner_mut().set_ratio(controls.tempo.load());
+                if let Some(seek) = controls.seek.take() {
+                    seek.value.attempt(amp);
+                    realtime::defer_drop(seek);
                 }
                 start_played.store(true, Ordering::SeqCst);
             });
-        self.sound_count.fetch_add(1, Ordering::Relaxed);
-        let source = Done::new(source, self.sound_count.clone());
-        *self.sleep_until_end.lock().unwrap() = Some(self.queue_tx.append_with_signal(source));
+        *self.sleep_until_end.lock().unwrap() = Some(
+            self.queue_tx
+                .append_counted(source, self.sound_count.clone()),
+        );
     }
 
     /// Gets the volume of the sound.
@@ -165,7 +227,7 @@ impl Sink {
     /// multiply each sample by this value.
     #[inline]
     pub fn volume(&self) -> f32 {
-        *self.controls.volume.lock().unwrap()
+        self.controls.volume.load()
     }
 
     /// Changes the volume of the sound.
@@ -174,7 +236,7 @@ impl Sink {
     /// multiply each sample by this value.
     #[inline]
     pub fn set_volume(&self, value: f32) {
-        *self.controls.volume.lock().unwrap() = value;
+        self.controls.volume.store(value);
     }
 
     /// Changes the play speed of the sound. Does not adjust the samples, only the playback speed.
@@ -193,7 +255,7 @@ impl Sink {
     /// See [`Speed`] for details
     #[inline]
     pub fn speed(&self) -> f32 {
-        *self.controls.speed.lock().unwrap()
+        self.controls.speed.load()
     }
 
     /// Changes the speed of the sound.
@@ -208,9 +270,58 @@ impl Sink {
     /// 2. **Change in the speed would affect your total duration inversely**
     /// - if you set the speed by 0.5, your total duration would be (2x the original total duration) longer.
     /// - Also if you set the speed to 2 the total duration would be (0.5 the original total_duration) shorter
+    ///
+    /// The new speed takes effect within a few milliseconds and the pitch glides to it, so the
+    /// speed can be changed continuously while playing.
     #[inline]
     pub fn set_speed(&self, value: f32) {
-        *self.controls.speed.lock().unwrap() = value;
+        self.controls.speed.store(value);
+    }
+
+    /// Gets the tempo of the sound.
+    ///
+    /// The value `1.0` is the "normal" tempo. See [`set_tempo`](Sink::set_tempo).
+    #[inline]
+    pub fn tempo(&self) -> f32 {
+        self.controls.tempo.load()
+    }
+
+    /// Changes the tempo of the sound without changing its pitch.
+    ///
+    /// The value `1.0` is the "normal" tempo (unfiltered input). A value of `2.0` plays the
+    /// sound twice as fast, `0.5` at half the speed. Like with [`set_speed`](Sink::set_speed)
+    /// the total duration changes inversely and [`get_pos`](Sink::get_pos) and
+    /// [`try_seek`](Sink::try_seek) take the tempo into account. Both can be combined, the
+    /// resulting playback rate is the product of speed and tempo.
+    ///
+    /// See [`TimeStretch`](crate::source::TimeStretch) for details.
+    ///
+    /// # Panic
+    /// Panics if `value` is not a positive finite number.
+    #[inline]
+    pub fn set_tempo(&self, value: f32) {
+        assert!(value > 0.0 && value.is_finite());
+        self.controls.tempo.store(value);
+    }
+
+    /// Overlaps consecutive sounds: the end of a sound fades out over `duration` while the
+    /// next one fades in. [`skip_one`](Sink::skip_one) fades as well. A `duration` of zero
+    /// plays the sounds one after the other again, which is the default.
+    ///
+    /// The end of a sound can only be anticipated if its [total
+    /// duration](Source::total_duration) is known, other sounds are followed without overlap.
+    /// A sound that is appended when the previous one is already that close to its end
+    /// starts fading in right away. If the next sound has different channels or sample rate,
+    /// it is converted to those of the previous one.
+    ///
+    /// See [`FadeCurve`] for the shapes of the fades, [`FadeCurve::EqualPower`] keeps the
+    /// loudness constant for unrelated sounds.
+    pub fn set_crossfade(&self, duration: Duration, curve: FadeCurve) {
+        let crossfade = (!duration.is_zero()).then_some((duration, curve));
+        self.controls.crossfade.put(crossfade);
+        self.controls
+            .crossfade_set
+            .store(crossfade.is_some(), Ordering::SeqCst);
     }
 
     /// Resumes playback of a paused sink.
@@ -245,7 +356,7 @@ impl Sink {
     /// function might return an error if the duration of the source is not known.
     pub fn try_seek(&self, pos: Duration) -> Result<(), SeekError> {
         let (order, feedback) = SeekOrder::new(pos);
-        *self.controls.seek.lock().unwrap() = Some(order);
+        self.controls.seek.put(order);
 
         if self.sound_count.load(Ordering::Acquire) == 0 {
             // No sound is playing, seek will not be performed
@@ -254,7 +365,7 @@ impl Sink {
 
         match feedback.recv() {
             Ok(seek_res) => {
-                *self.controls.position.lock().unwrap() = pos;
+                self.controls.position.store(pos);
                 seek_res
             }
             // The feedback channel closed. Probably another SeekOrder was set
@@ -286,7 +397,8 @@ impl Sink {
     /// See `pause()` for information about pausing a `Sink`.
     pub fn clear(&self) {
         let len = self.sound_count.load(Ordering::SeqCst) as u32;
-        *self.controls.to_clear.lock().unwrap() = len;
+        self.controls.to_crossfade.store(0, Ordering::SeqCst);
+        self.controls.to_clear.store(len, Ordering::SeqCst);
         self.sleep_until_end();
         self.pause();
     }
@@ -296,11 +408,15 @@ impl Sink {
     /// If there are more `Source`s appended to the `Sink` at the time,
     /// it will play the next one. Otherwise, the `Sink` will finish as if
     /// it had finished playing a `Source` all the way through.
+    ///
+    /// With a [crossfade](Sink::set_crossfade) the current `Source` fades out while the next
+    /// one fades in, or fades out to silence if there is none.
     pub fn skip_one(&self) {
         let len = self.sound_count.load(Ordering::SeqCst) as u32;
-        let mut to_clear = self.controls.to_clear.lock().unwrap();
-        if len > *to_clear {
-            *to_clear += 1;
+        if self.controls.crossfade_set.load(Ordering::SeqCst) {
+            add_request(&self.controls.to_crossfade, len);
+        } else {
+            add_request(&self.controls.to_clear, len);
         }
     }
 
@@ -339,14 +455,14 @@ impl Sink {
 
     /// Returns the position of the sound that's being played.
     ///
-    /// This takes into account any speedup or delay applied.
+    /// This takes into account any speedup, tempo change or delay applied.
     ///
     /// Example: if you apply a speedup of *2* to an mp3 decoder source and
     /// [`get_pos()`](Sink::get_pos) returns *5s* then the position in the mp3
     /// recording is *10s* from its start.
     #[inline]
     pub fn get_pos(&self) -> Duration {
-        *self.controls.position.lock().unwrap()
+        self.controls.position.load()
     }
 }
 
@@ -363,9 +479,13 @@ impl Drop for Sink {
 
 #[cfg(test)]
 mod tests {
-    use std::sync::atomic::Ordering;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    use std::time::Duration;
 
     use crate::buffer::SamplesBuffer;
+    use crate::source::{FadeCurve, SineWave};
     use crate::{Sink, Source};
 
     #[test]
@@ -401,7 +521,7 @@ mod tests {
 
         assert_eq!(source.next(), Some(0.0));
 
-        assert_eq!(sink.empty(), true);
+        assert!(sink.empty());
     }
 
     #[test]
@@ -432,6 +552,26 @@ mod tests {
         assert_eq!(queue_rx.next(), src.next());
     }
 
+    #[test]
+    fn test_tempo() {
+        let (sink, mut queue_rx) = Sink::new();
+
+        let samples: Vec<f32> = SineWave::new(440.0)
+            .take_duration(Duration::from_secs(1))
+            .collect();
+        sink.set_tempo(2.0);
+        sink.append(SamplesBuffer::new(1, 48000, samples));
+
+        let output: Vec<f32> = queue_rx.by_ref().take(24_100).collect();
+        assert!(sink.empty());
+        // Half a second of output still has the original pitch.
+        let crossings = output[..23_000]
+            .windows(2)
+            .filter(|pair| pair[0] < 0.0 && pair[1] >= 0.0)
+            .count();
+        assert!(crossings.abs_diff(211) <= 2, "{crossings}");
+    }
+
     #[test]
     fn test_volume() {
         let (sink, mut queue_rx) = Sink::new();
@@ -449,4 +589,79 @@ mod tests {
             assert_eq!(queue_rx.next(), src.next());
         }
     }
+
+    fn constant(value: f32, samples: usize) -> SamplesBuffer {
+        SamplesBuffer::new(1, 1000, vec![value; samples])
+    }
+
+    #[test]
+    fn test_crossfade_at_end() {
+        let (sink, queue_rx) = Sink::new();
+        sink.set_crossfade(Duration::from_millis(100), FadeCurve::Linear);
+        sink.append(constant(1.0, 1000));
+        sink.append(constant(0.5, 1000));
+
+        let output: Vec<f32> = queue_rx.skip_while(|x| *x == 0.0).take(2000).collect();
+        let sounding = output.iter().take_while(|x| **x != 0.0).count();
+        // The fade starts within one period of the sink controls.
+        assert!((1895..=1905).contains(&sounding), "{sounding}");
+        assert!(output[..895].iter().all(|x| *x == 1.0));
+        assert!(output[1010..sounding].iter().all(|x| *x == 0.5));
+        // The fade ends within a few samples of the end of the first sound.
+        assert!(output[905..998].windows(2).all(|pair| pair[1] < pair[0]));
+        assert!(output[998..1010].iter().all(|x| (x - 0.5).abs() < 0.02));
+        assert!(sink.empty());
+    }
+
+    #[test]
+    fn test_crossfade_on_skip() {
+        let (sink, mut queue_rx) = Sink::new();
+        sink.set_crossfade(Duration::from_millis(50), FadeCurve::EqualPower);
+        sink.append(constant(1.0, 10_000));
+        sink.append(constant(0.5, 10_000));
+
+        let mut queue_rx = queue_rx.by_ref().skip_while(|x| *x == 0.0);
+        assert!(queue_rx.by_ref().take(100).all(|x| x == 1.0));
+        sink.skip_one();
+        let fade: Vec<f32> = queue_rx.by_ref().take(60).collect();
+        assert!(fade.iter().any(|x| *x > 0.5 && *x < 1.0));
+        assert_eq!(fade[59], 0.5);
+        assert_eq!(sink.len(), 1);
+
+        // Without a next sound the fade goes to silence.
+        sink.skip_one();
+        let fade: Vec<f32> = queue_rx.take(60).collect();
+        assert_eq!(fade[59], 0.0);
+        assert!(sink.empty());
+    }
+
+    #[test]
+    fn test_clear_during_crossfade() {
+        let (sink, mut queue_rx) = Sink::new();
+        sink.set_crossfade(Duration::from_millis(500), FadeCurve::Linear);
+        sink.append(constant(1.0, 10_000));
+        sink.append(constant(0.5, 10_000));
+        sink.append(constant(0.25, 10_000));
+
+        queue_rx.by_ref().take(100).for_each(drop);
+        sink.skip_one();
+        queue_rx.by_ref().take(100).for_each(drop);
+        assert_eq!(sink.len(), 3);
+
+        let cleared = Arc::new(AtomicBool::new(false));
+        let player = std::thread::spawn({
+            let cleared = cleared.clone();
+            move || {
+                while !cleared.load(Ordering::Acquire) {
+                    queue_rx.next();
+                }
+                queue_rx
+            }
+        });
+        sink.clear();
+        cleared.store(true, Ordering::Release);
+        let mut queue_rx = player.join().unwrap();
+        assert!(sink.empty());
+        assert!(queue_rx.by_ref().take(100).all(|x| x == 0.0));
+    }
 }
@@ -24,6 +24,7 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use crate::common::{ChannelCount, SampleRate};
+use crate::conversions::ChannelLayout;
 #[cfg(feature = "tracing")]
 use tracing;
 
@@ -478,6 +479,11 @@ where
         self.input.total_duration()
     }
 
+    #[inline]
+    fn channel_layout(&self) -> Option<ChannelLayout> {
+        self.input.channel_layout()
+    }
+
     #[inline]
     fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
         self.input.try_seek(pos)
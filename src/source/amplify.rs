@@ -2,6 +2,7 @@ use std::time::Duration;
 
 use super::SeekError;
 use crate::common::{ChannelCount, SampleRate};
+use crate::conversions::ChannelLayout;
 use crate::Source;
 
 /// Internal function that builds a `Amplify` object.
@@ -88,6 +89,11 @@ where
         self.input.total_duration()
     }
 
+    #[inline]
+    fn channel_layout(&self) -> Option<ChannelLayout> {
+        self.input.channel_layout()
+    }
+
     #[inline]
     fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
         self.input.try_seek(pos)
@@ -1,4 +1,5 @@
 use crate::common::{ChannelCount, SampleRate};
+use crate::conversions::ChannelLayout;
 use crate::Source;
 use std::f32::consts::PI;
 use std::time::Duration;
@@ -173,6 +174,11 @@ where
         self.input.total_duration()
     }
 
+    #[inline]
+    fn channel_layout(&self) -> Option<ChannelLayout> {
+        self.input.channel_layout()
+    }
+
     #[inline]
     fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
         self.input.try_seek(pos)
@@ -5,6 +5,7 @@ use std::time::Duration;
 
 use super::SeekError;
 use crate::common::{ChannelCount, SampleRate};
+use crate::conversions::ChannelLayout;
 use crate::Source;
 
 /// Internal function that builds a `Buffered` object.
@@ -61,6 +62,7 @@ where
     data: Vec<I::Item>,
     channels: ChannelCount,
     rate: SampleRate,
+    layout: Option<ChannelLayout>,
     next: Mutex<Arc<Span<I>>>,
 }
 
@@ -106,6 +108,7 @@ where
 
     let channels = input.channels();
     let rate = input.sample_rate();
+    let layout = input.channel_layout();
     let data: Vec<I::Item> = input
         .by_ref()
         .take(cmp::min(span_len.unwrap_or(32768), 32768))
@@ -119,6 +122,7 @@ where
         data,
         channels,
         rate,
+        layout,
         next: Mutex::new(Arc::new(Span::Input(Mutex::new(Some(input))))),
     }))
 }
@@ -233,6 +237,15 @@ where
         self.total_duration
     }
 
+    #[inline]
+    fn channel_layout(&self) -> Option<ChannelLayout> {
+        match *self.current_span {
+            Span::Data(SpanData { layout, .. }) => layout,
+            Span::End => None,
+            Span::Input(_) => unreachable!(),
+        }
+    }
+
     /// Can not support seek, in the end state we lose the underlying source
     /// which makes seeking back impossible.
     #[inline]
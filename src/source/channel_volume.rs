@@ -2,10 +2,14 @@ use std::time::Duration;
 
 use super::SeekError;
 use crate::common::{ChannelCount, SampleRate};
+use crate::conversions::{ChannelLayout, ChannelMatrix};
 use crate::{Sample, Source};
 
 /// Combines channels in input into a single mono source, then plays that mono sound
 /// to each channel at the volume given for that channel.
+///
+/// The input is downmixed according to its [layout](Source::channel_layout), see
+/// [`ChannelMatrix::between_layouts`]. Discrete channels are averaged.
 #[derive(Clone, Debug)]
 pub struct ChannelVolume<I>
 where
@@ -15,6 +19,10 @@ where
     channel_volumes: Vec<f32>,
     current_channel: usize,
     current_sample: Option<Sample>,
+    /// Layout of the input the downmix gains were computed for.
+    input_layout: Option<ChannelLayout>,
+    /// Gain of each input channel in the mono downmix.
+    downmix: Vec<f32>,
 }
 
 impl<I> ChannelVolume<I>
@@ -34,6 +42,8 @@ where
             channel_volumes,
             current_channel: channel_count,
             current_sample: None,
+            input_layout: None,
+            downmix: Vec::new(),
         }
     }
 
@@ -43,6 +53,24 @@ where
         self.channel_volumes[channel] = volume;
     }
 
+    fn update_downmix(&mut self) {
+        let channels = self.input.channels();
+        let layout = ChannelLayout::resolve(self.input.channel_layout(), channels);
+        if self.input_layout == Some(layout) {
+            return;
+        }
+        self.input_layout = Some(layout);
+        self.downmix.clear();
+        if layout.speakers().is_some() {
+            let matrix = ChannelMatrix::between_layouts(layout, ChannelLayout::Mono);
+            self.downmix
+                .extend((0..channels).map(|channel| matrix.gain(channel, 0)));
+        } else {
+            self.downmix
+                .resize(channels as usize, 1.0 / channels as f32);
+        }
+    }
+
     /// Returns a reference to the inner source.
     #[inline]
     pub fn inner(&self) -> &I {
@@ -70,17 +98,15 @@ where
 
     #[inline]
     fn next(&mut self) -> Option<Self::Item> {
-        // TODO Need a test for this
         if self.current_channel >= self.channel_volumes.len() {
             self.current_channel = 0;
             self.current_sample = None;
-            let num_channels = self.input.channels();
-            for _ in 0..num_channels {
+            self.update_downmix();
+            for gain in &self.downmix {
                 if let Some(s) = self.input.next() {
-                    self.current_sample = Some(self.current_sample.unwrap_or(0.0) + s);
+                    self.current_sample = Some(self.current_sample.unwrap_or(0.0) + s * gain);
                 }
             }
-            self.current_sample.map(|s| s / num_channels as f32);
         }
         let result = self
             .current_sample
@@ -126,3 +152,26 @@ where
         self.input.try_seek(pos)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::ChannelVolume;
+    use crate::buffer::SamplesBuffer;
+    use crate::Sample;
+
+    #[test]
+    fn averages_stereo() {
+        let input = SamplesBuffer::new(2, 48000, vec![1.0, 3.0, -1.0, 0.0]);
+        let output: Vec<Sample> = ChannelVolume::new(input, vec![1.0, 0.5]).collect();
+        assert_eq!(output, [2.0, 1.0, -0.5, -0.25]);
+    }
+
+    #[test]
+    fn downmixes_surround_by_layout() {
+        // Front left, front right, center, low frequency, side left, side right.
+        let input = SamplesBuffer::new(6, 48000, vec![1.0, 1.0, 0.0, 1.0, 0.0, 0.0]);
+        let output: Vec<Sample> = ChannelVolume::new(input, vec![1.0]).collect();
+        // The low frequency channel is dropped.
+        assert_eq!(output, [1.0]);
+    }
+}
@@ -1,4 +1,4 @@
-use crate::source::{FadeIn, Mix, TakeDuration};
+use crate::source::{FadeCurve, FadeIn, Mix, TakeDuration};
 use crate::Source;
 use std::time::Duration;
 
@@ -12,13 +12,29 @@ pub fn crossfade<I1, I2>(
     input_fadein: I2,
     duration: Duration,
 ) -> Crossfade<I1, I2>
+where
+    I1: Source,
+    I2: Source,
+{
+    crossfade_with_curve(input_fadeout, input_fadein, duration, FadeCurve::Linear)
+}
+
+/// Like [`crossfade`], with the shape of both fades given by `curve`.
+pub fn crossfade_with_curve<I1, I2>(
+    input_fadeout: I1,
+    input_fadein: I2,
+    duration: Duration,
+    curve: FadeCurve,
+) -> Crossfade<I1, I2>
 where
     I1: Source,
     I2: Source,
 {
     let mut input_fadeout = input_fadeout.take_duration(duration);
-    input_fadeout.set_filter_fadeout();
-    let input_fadein = input_fadein.take_duration(duration).fade_in(duration);
+    input_fadeout.set_filter_fadeout_with_curve(curve);
+    let input_fadein = input_fadein
+        .take_duration(duration)
+        .fade_in_with_curve(duration, curve);
     input_fadeout.mix(input_fadein)
 }
 
@@ -73,4 +89,19 @@ mod tests {
             .zip(vec![1.0, 2.0 * 0.8, 3.0 * 0.6, 4.0 * 0.4, 5.0 * 0.2])
             .all(|(a, b)| (a - b).abs() < 1e-6));
     }
+
+    #[test]
+    fn test_crossfade_equal_power() {
+        let constant = || SamplesBuffer::new(1, 1, vec![1.0; 10]);
+        let duration = Duration::from_secs(5) + Duration::from_nanos(1);
+        let fade_out =
+            crossfade_with_curve(constant(), Zero::new(1, 1), duration, FadeCurve::EqualPower);
+        let fade_in =
+            crossfade_with_curve(Zero::new(1, 1), constant(), duration, FadeCurve::EqualPower);
+        let result: Vec<(f32, f32)> = fade_out.zip(fade_in).collect();
+        assert_eq!(result.len(), 5);
+        for (out, into) in result {
+            assert!((out * out + into * into - 1.0).abs() < 1e-3);
+        }
+    }
 }
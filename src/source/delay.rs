@@ -2,6 +2,7 @@ use std::time::Duration;
 
 use super::SeekError;
 use crate::common::{ChannelCount, SampleRate};
+use crate::conversions::ChannelLayout;
 use crate::Source;
 
 fn remaining_samples(
@@ -111,6 +112,11 @@ where
             .map(|val| val + self.requested_duration)
     }
 
+    #[inline]
+    fn channel_layout(&self) -> Option<ChannelLayout> {
+        self.input.channel_layout()
+    }
+
     /// Pos is seen from the perspective of the api user.
     ///
     /// # Example
@@ -4,6 +4,7 @@ use std::time::Duration;
 
 use super::SeekError;
 use crate::common::{ChannelCount, SampleRate};
+use crate::conversions::ChannelLayout;
 use crate::Source;
 
 /// When the inner source is empty this decrements a `AtomicUsize`.
@@ -90,6 +91,11 @@ where
         self.input.total_duration()
     }
 
+    #[inline]
+    fn channel_layout(&self) -> Option<ChannelLayout> {
+        self.input.channel_layout()
+    }
+
     #[inline]
     fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
         self.input.try_seek(pos)
@@ -0,0 +1,95 @@
+use std::f32::consts::{FRAC_PI_2, PI};
+
+/// Shape of the gain during a fade.
+///
+/// A curve maps the progress of a fade in, from `0.0` to `1.0`, to a gain factor. Fade outs
+/// use the same curve backwards, so that the gains of a crossfade are mirror images.
+///
+/// Used by [`fade_in_with_curve`](crate::Source::fade_in_with_curve),
+/// [`fade_out_with_curve`](crate::Source::fade_out_with_curve),
+/// [`take_crossfade_with_curve`](crate::Source::take_crossfade_with_curve),
+/// [`LinearGainRamp::set_curve`](super::LinearGainRamp::set_curve) and
+/// [`Sink::set_crossfade`](crate::Sink::set_crossfade).
+#[derive(Copy, Clone, Debug, Default)]
+pub enum FadeCurve {
+    /// The amplitude changes at a constant rate. The two sounds of a crossfade add up to a
+    /// constant amplitude, which sounds like a dip in the middle unless they are correlated.
+    #[default]
+    Linear,
+    /// Sine and cosine gains. The power of two uncorrelated sounds in a crossfade adds up to
+    /// a constant, so the loudness stays the same.
+    EqualPower,
+    /// The gain rises by the same number of decibels in equal times, from -60 dB to 0 dB.
+    /// Sounds even to the ear, but most of the change happens at the end of a fade in.
+    Exponential,
+    /// Starts and ends slowly and changes fastest in the middle (a raised cosine).
+    SCurve,
+    /// A user supplied curve. It should map `0.0` to `0.0`, `1.0` to `1.0` and rise in
+    /// between.
+    Custom(fn(f32) -> f32),
+}
+
+/// Range of [`FadeCurve::Exponential`].
+const EXPONENTIAL_RANGE_DB: f32 = 60.0;
+
+impl FadeCurve {
+    /// Gain at `progress` through a fade in, `0.0` at the start and `1.0` at the end.
+    ///
+    /// `progress` is clamped to the range `0.0..=1.0`.
+    #[inline]
+    pub fn gain(&self, progress: f32) -> f32 {
+        let progress = progress.clamp(0.0, 1.0);
+        match self {
+            FadeCurve::Linear => progress,
+            FadeCurve::EqualPower => (progress * FRAC_PI_2).sin(),
+            FadeCurve::Exponential if progress == 0.0 => 0.0,
+            FadeCurve::Exponential => 10f32.powf((1.0 - progress) * -EXPONENTIAL_RANGE_DB / 20.0),
+            FadeCurve::SCurve => 0.5 - 0.5 * (progress * PI).cos(),
+            FadeCurve::Custom(curve) => curve(progress),
+        }
+    }
+
+    /// Gain at `progress` through a fade out, the mirror image of [`gain`](FadeCurve::gain).
+    #[inline]
+    pub fn fade_out_gain(&self, progress: f32) -> f32 {
+        self.gain(1.0 - progress.clamp(0.0, 1.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FadeCurve;
+
+    #[test]
+    fn equal_power() {
+        let curve = FadeCurve::EqualPower;
+        assert_eq!(curve.gain(0.0), 0.0);
+        assert_eq!(curve.gain(1.0), 1.0);
+        for progress in [0.1, 0.25, 0.5, 0.9] {
+            let fade_in = curve.gain(progress);
+            let fade_out = curve.fade_out_gain(progress);
+            assert!((fade_in * fade_in + fade_out * fade_out - 1.0).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn end_points_and_shapes() {
+        let curves = [
+            FadeCurve::Linear,
+            FadeCurve::EqualPower,
+            FadeCurve::Exponential,
+            FadeCurve::SCurve,
+            FadeCurve::Custom(|progress| progress * progress),
+        ];
+        for curve in curves {
+            assert_eq!(curve.gain(0.0), 0.0, "{curve:?}");
+            assert!((curve.gain(1.0) - 1.0).abs() < 1e-6, "{curve:?}");
+            assert_eq!(curve.gain(2.0), curve.gain(1.0), "{curve:?}");
+            assert_eq!(curve.fade_out_gain(0.0), curve.gain(1.0), "{curve:?}");
+        }
+        // -30 dB halfway.
+        assert!((FadeCurve::Exponential.gain(0.5) - 0.031_622_775).abs() < 1e-6);
+        assert!((FadeCurve::SCurve.gain(0.5) - 0.5).abs() < 1e-6);
+        assert!(FadeCurve::SCurve.gain(0.1) < FadeCurve::Linear.gain(0.1));
+    }
+}
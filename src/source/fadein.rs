@@ -1,7 +1,8 @@
 use std::time::Duration;
 
-use super::{linear_ramp::linear_gain_ramp, LinearGainRamp, SeekError};
+use super::{linear_ramp::linear_gain_ramp, FadeCurve, LinearGainRamp, SeekError};
 use crate::common::{ChannelCount, SampleRate};
+use crate::conversions::ChannelLayout;
 use crate::Source;
 
 /// Internal function that builds a `FadeIn` object.
@@ -9,9 +10,16 @@ pub fn fadein<I>(input: I, duration: Duration) -> FadeIn<I>
 where
     I: Source,
 {
-    FadeIn {
-        input: linear_gain_ramp(input, duration, 0.0f32, 1.0f32, false),
-    }
+    fadein_with_curve(input, duration, FadeCurve::Linear)
+}
+
+pub fn fadein_with_curve<I>(input: I, duration: Duration, curve: FadeCurve) -> FadeIn<I>
+where
+    I: Source,
+{
+    let mut input = linear_gain_ramp(input, duration, 0.0f32, 1.0f32, false);
+    input.set_curve(curve);
+    FadeIn { input }
 }
 
 /// Filter that modifies raises the volume from silence over a time period.
@@ -86,6 +94,11 @@ where
         self.inner().total_duration()
     }
 
+    #[inline]
+    fn channel_layout(&self) -> Option<ChannelLayout> {
+        self.inner().channel_layout()
+    }
+
     #[inline]
     fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
         self.inner_mut().try_seek(pos)
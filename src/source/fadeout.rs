@@ -1,7 +1,8 @@
 use std::time::Duration;
 
-use super::{linear_ramp::linear_gain_ramp, LinearGainRamp, SeekError};
+use super::{linear_ramp::linear_gain_ramp, FadeCurve, LinearGainRamp, SeekError};
 use crate::common::{ChannelCount, SampleRate};
+use crate::conversions::ChannelLayout;
 use crate::Source;
 
 /// Internal function that builds a `FadeOut` object.
@@ -9,9 +10,16 @@ pub fn fadeout<I>(input: I, duration: Duration) -> FadeOut<I>
 where
     I: Source,
 {
-    FadeOut {
-        input: linear_gain_ramp(input, duration, 1.0f32, 0.0f32, true),
-    }
+    fadeout_with_curve(input, duration, FadeCurve::Linear)
+}
+
+pub fn fadeout_with_curve<I>(input: I, duration: Duration, curve: FadeCurve) -> FadeOut<I>
+where
+    I: Source,
+{
+    let mut input = linear_gain_ramp(input, duration, 1.0f32, 0.0f32, true);
+    input.set_curve(curve);
+    FadeOut { input }
 }
 
 /// Filter that modifies lowers the volume to silence over a time period.
@@ -86,6 +94,11 @@ where
         self.inner().total_duration()
     }
 
+    #[inline]
+    fn channel_layout(&self) -> Option<ChannelLayout> {
+        self.inner().channel_layout()
+    }
+
     #[inline]
     fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
         self.inner_mut().try_seek(pos)
@@ -2,6 +2,7 @@ use std::time::Duration;
 
 use super::SeekError;
 use crate::common::{ChannelCount, SampleRate};
+use crate::conversions::ChannelLayout;
 use crate::Source;
 
 /// Builds a source that chains sources provided by an iterator.
@@ -136,6 +137,13 @@ where
         None
     }
 
+    #[inline]
+    fn channel_layout(&self) -> Option<ChannelLayout> {
+        self.current_source
+            .as_ref()
+            .and_then(|src| src.channel_layout())
+    }
+
     #[inline]
     fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
         if let Some(source) = self.current_source.as_mut() {
@@ -1,7 +1,8 @@
 use std::time::Duration;
 
-use super::SeekError;
+use super::{FadeCurve, SeekError};
 use crate::common::{ChannelCount, SampleRate};
+use crate::conversions::ChannelLayout;
 use crate::Source;
 
 /// Internal function that builds a `LinearRamp` object.
@@ -25,6 +26,7 @@ where
         start_gain,
         end_gain,
         clamp_end,
+        curve: FadeCurve::Linear,
         sample_idx: 0u64,
     }
 }
@@ -38,6 +40,7 @@ pub struct LinearGainRamp<I> {
     start_gain: f32,
     end_gain: f32,
     clamp_end: bool,
+    curve: FadeCurve,
     sample_idx: u64,
 }
 
@@ -45,6 +48,15 @@ impl<I> LinearGainRamp<I>
 where
     I: Source,
 {
+    /// Sets the shape of the ramp. The default is [`FadeCurve::Linear`].
+    ///
+    /// A rising ramp follows the curve, a falling one follows it backwards, so that a fade
+    /// out mirrors a fade in.
+    #[inline]
+    pub fn set_curve(&mut self, curve: FadeCurve) {
+        self.curve = curve;
+    }
+
     /// Returns a reference to the innner source.
     #[inline]
     pub fn inner(&self) -> &I {
@@ -85,10 +97,16 @@ where
             self.sample_idx += 1;
 
             let p = self.elapsed_ns / self.total_ns;
-            factor = self.start_gain * (1.0f32 - p) + self.end_gain * p;
+            factor = if self.end_gain >= self.start_gain {
+                let p = self.curve.gain(p);
+                self.start_gain * (1.0f32 - p) + self.end_gain * p
+            } else {
+                let p = self.curve.fade_out_gain(p);
+                self.end_gain * (1.0f32 - p) + self.start_gain * p
+            };
         }
 
-        if self.sample_idx % (self.channels() as u64) == 0 {
+        if self.sample_idx.is_multiple_of(self.channels() as u64) {
             self.elapsed_ns += 1000000000.0 / (self.input.sample_rate() as f32);
         }
 
@@ -127,6 +145,11 @@ where
         self.input.total_duration()
     }
 
+    #[inline]
+    fn channel_layout(&self) -> Option<ChannelLayout> {
+        self.input.channel_layout()
+    }
+
     #[inline]
     fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
         self.elapsed_ns = pos.as_nanos() as f32;
@@ -228,4 +251,25 @@ mod tests {
             panic!("try_seek() failed!");
         }
     }
+
+    #[test]
+    fn test_ramp_with_curve() {
+        let source1 = const_source(6, 1.0f32);
+        let mut faded = linear_gain_ramp(source1, Duration::from_secs(4), 0.0, 1.0, true);
+        faded.set_curve(FadeCurve::EqualPower);
+        let rising: Vec<Sample> = faded.collect();
+
+        let source2 = const_source(6, 1.0f32);
+        let mut faded = linear_gain_ramp(source2, Duration::from_secs(4), 1.0, 0.0, true);
+        faded.set_curve(FadeCurve::EqualPower);
+        let falling: Vec<Sample> = faded.collect();
+
+        assert_abs_diff_eq!(rising[2], std::f32::consts::FRAC_1_SQRT_2);
+        for (up, down) in rising.iter().zip(falling.iter()).take(5) {
+            // Equal power: the squared gains of the two ramps add up to one.
+            assert_abs_diff_eq!(up * up + down * down, 1.0, epsilon = 1e-6);
+        }
+        assert_eq!(rising[5], 1.0);
+        assert_eq!(falling[5], 0.0);
+    }
 }
@@ -0,0 +1,724 @@
+//! Loudness measurement according to ITU-R BS.1770-4 and EBU R128.
+//!
+//! The signal is K-weighted and its power is summed over 100 ms blocks. Momentary loudness
+//! covers the last 400 ms and short-term loudness the last 3 s. Integrated loudness gates the
+//! 400 ms blocks at -70 LUFS and 10 LU below their average, loudness range (EBU Tech 3342)
+//! takes the spread of the short-term values gated at 20 LU below their average. Both use
+//! histograms with 0.1 LU resolution, so memory use does not grow with the duration.
+//!
+//! True peak is measured with 4 times oversampling.
+
+use std::f64::consts::PI;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::SeekError;
+use crate::common::{ChannelCount, SampleRate};
+use crate::conversions::{ChannelLayout, Speaker};
+use crate::{Sample, Source};
+
+/// Length of the blocks the power is summed over.
+const BLOCK_SECONDS: f64 = 0.1;
+/// Number of blocks of the momentary window.
+const MOMENTARY_BLOCKS: usize = 4;
+/// Number of blocks of the short-term window.
+const SHORT_TERM_BLOCKS: usize = 30;
+const ABSOLUTE_GATE: f64 = -70.0;
+const INTEGRATED_RELATIVE_GATE: f64 = -10.0;
+const RANGE_RELATIVE_GATE: f64 = -20.0;
+/// Histogram bins per LU.
+const BINS_PER_LU: f64 = 10.0;
+/// Loudness of the upper end of the histograms.
+const HISTOGRAM_MAX: f64 = 30.0;
+const OVERSAMPLING: usize = 4;
+const TAPS_PER_PHASE: usize = 12;
+
+/// Internal function that builds a `LoudnessMeter` object.
+pub fn loudness_meter<I>(input: I) -> LoudnessMeter<I>
+where
+    I: Source,
+{
+    let mut meter = LoudnessMeter {
+        channels: 0,
+        sample_rate: 0,
+        layout: None,
+        span_left: Some(0),
+        channel: 0,
+        weights: Vec::new(),
+        filters: Vec::new(),
+        peak_filters: Vec::new(),
+        peak_kernel: peak_kernel(),
+        block_len: 0,
+        block_pos: 0,
+        block_power: 0.0,
+        blocks: Vec::with_capacity(SHORT_TERM_BLOCKS),
+        blocks_seen: 0,
+        integrated: Histogram::new(),
+        range: Histogram::new(),
+        sample_peak: 0.0,
+        true_peak: 0.0,
+        max_momentary: f64::NEG_INFINITY,
+        max_short_term: f64::NEG_INFINITY,
+        shared: Arc::new(Shared::new()),
+        input,
+    };
+    meter.configure();
+    meter
+}
+
+/// Loudness values of a signal.
+///
+/// Loudness is in LUFS and loudness range in LU, peaks are in dB relative to full scale
+/// (dBTP and dBFS). Values that are not known yet, like the integrated loudness of a signal
+/// that is shorter than 400 ms or silent, are negative infinity.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LoudnessReport {
+    /// Loudness over the whole signal, gated.
+    pub integrated: f32,
+    /// Spread of the short-term loudness, in LU.
+    pub loudness_range: f32,
+    /// Highest momentary loudness.
+    pub max_momentary: f32,
+    /// Highest short-term loudness.
+    pub max_short_term: f32,
+    /// Highest level between the samples, in dBTP.
+    ///
+    /// Never lower than the sample peak.
+    pub true_peak: f32,
+    /// Highest sample level, in dBFS.
+    pub sample_peak: f32,
+}
+
+/// Measures the loudness of a source by playing it to the end.
+///
+/// The source should be finite.
+pub fn measure_loudness<I>(source: I) -> LoudnessReport
+where
+    I: Source,
+{
+    let meter = loudness_meter(source);
+    let handle = meter.handle();
+    meter.for_each(drop);
+    handle.report()
+}
+
+/// Passes the samples through unchanged while measuring their loudness.
+///
+/// Use [`handle`](LoudnessMeter::handle) to read the values from another thread, for example
+/// while the source plays in a [`Sink`](crate::Sink). The values are updated every 100 ms of
+/// audio.
+///
+/// The channels are weighted according to their [layout](Source::channel_layout): the low
+/// frequency channel is not counted and the surround channels count 1.41 times.
+#[derive(Clone, Debug)]
+pub struct LoudnessMeter<I> {
+    input: I,
+    channels: ChannelCount,
+    sample_rate: SampleRate,
+    layout: Option<ChannelLayout>,
+    /// Samples left in the current span of the input.
+    span_left: Option<usize>,
+    /// Channel of the next sample.
+    channel: usize,
+    weights: Vec<f64>,
+    /// K-weighting filters, two per channel.
+    filters: Vec<[Biquad; 2]>,
+    peak_filters: Vec<PeakFilter>,
+    peak_kernel: [[f32; TAPS_PER_PHASE]; OVERSAMPLING],
+    /// Frames per 100 ms block.
+    block_len: usize,
+    block_pos: usize,
+    block_power: f64,
+    /// Mean power of the last blocks, newest last.
+    blocks: Vec<f64>,
+    /// Blocks since the measurement (re)started.
+    blocks_seen: usize,
+    integrated: Histogram,
+    range: Histogram,
+    sample_peak: f32,
+    true_peak: f32,
+    max_momentary: f64,
+    max_short_term: f64,
+    shared: Arc<Shared>,
+}
+
+impl<I> LoudnessMeter<I>
+where
+    I: Source,
+{
+    /// Returns a handle to read the measured values.
+    #[inline]
+    pub fn handle(&self) -> LoudnessMeterHandle {
+        LoudnessMeterHandle {
+            shared: self.shared.clone(),
+        }
+    }
+
+    /// Returns the values measured so far.
+    pub fn report(&self) -> LoudnessReport {
+        LoudnessReport {
+            integrated: self.integrated.gated_loudness(INTEGRATED_RELATIVE_GATE) as f32,
+            loudness_range: self.range.range() as f32,
+            max_momentary: self.max_momentary as f32,
+            max_short_term: self.max_short_term as f32,
+            true_peak: to_db(self.true_peak.max(self.sample_peak)),
+            sample_peak: to_db(self.sample_peak),
+        }
+    }
+
+    /// Returns a reference to the inner source.
+    #[inline]
+    pub fn inner(&self) -> &I {
+        &self.input
+    }
+
+    /// Returns a mutable reference to the inner source.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut I {
+        &mut self.input
+    }
+
+    /// Returns the inner source.
+    #[inline]
+    pub fn into_inner(self) -> I {
+        self.input
+    }
+
+    /// Adapts to the format of the input, keeping the measurements.
+    fn configure(&mut self) {
+        let channels = self.input.channels();
+        let sample_rate = self.input.sample_rate();
+        let layout = self.input.channel_layout();
+        self.span_left = self.input.current_span_len();
+        if channels == self.channels && sample_rate == self.sample_rate && layout == self.layout {
+            return;
+        }
+        self.channels = channels;
+        self.sample_rate = sample_rate;
+        self.layout = layout;
+        self.channel = 0;
+
+        self.weights = channel_weights(ChannelLayout::resolve(layout, channels));
+        let k_weighting = k_weighting(sample_rate.max(1) as f64);
+        self.filters = vec![k_weighting; channels as usize];
+        self.peak_filters = vec![PeakFilter::default(); channels as usize];
+        // Restart the windows, the new format might not continue the old signal.
+        self.block_len = ((sample_rate as f64 * BLOCK_SECONDS).round() as usize).max(1);
+        self.block_pos = 0;
+        self.block_power = 0.0;
+        self.blocks.clear();
+        self.blocks_seen = 0;
+    }
+
+    #[inline]
+    fn measure(&mut self, sample: Sample) {
+        let channel = self.channel;
+        let abs = sample.abs();
+        if abs > self.sample_peak {
+            self.sample_peak = abs;
+        }
+        let peak = self.peak_filters[channel].process(sample, &self.peak_kernel);
+        if peak > self.true_peak {
+            self.true_peak = peak;
+        }
+
+        let [shelf, high_pass] = &mut self.filters[channel];
+        let weighted = high_pass.process(shelf.process(sample as f64));
+        self.block_power += self.weights[channel] * weighted * weighted;
+
+        self.channel += 1;
+        if self.channel == self.channels as usize {
+            self.channel = 0;
+            self.block_pos += 1;
+            if self.block_pos == self.block_len {
+                self.finish_block();
+            }
+        }
+    }
+
+    fn finish_block(&mut self) {
+        let power = self.block_power / self.block_len as f64;
+        self.block_power = 0.0;
+        self.block_pos = 0;
+        if self.blocks.len() == SHORT_TERM_BLOCKS {
+            self.blocks.remove(0);
+        }
+        self.blocks.push(power);
+        self.blocks_seen += 1;
+
+        let momentary = window_power(&self.blocks, MOMENTARY_BLOCKS);
+        let short_term = window_power(&self.blocks, SHORT_TERM_BLOCKS);
+        if let Some(power) = momentary {
+            self.integrated.add(power);
+            self.max_momentary = self.max_momentary.max(loudness(power));
+        }
+        // Tech 3342 asks for short-term values at least ten times per second.
+        if let Some(power) = short_term {
+            self.range.add(power);
+            self.max_short_term = self.max_short_term.max(loudness(power));
+        }
+        self.publish(momentary, short_term);
+    }
+
+    fn publish(&self, momentary: Option<f64>, short_term: Option<f64>) {
+        let shared = &self.shared;
+        let report = self.report();
+        let level = |power: Option<f64>| power.map_or(f32::NEG_INFINITY, |p| loudness(p) as f32);
+        store(&shared.momentary, level(momentary));
+        store(&shared.short_term, level(short_term));
+        store(&shared.integrated, report.integrated);
+        store(&shared.loudness_range, report.loudness_range);
+        store(&shared.max_momentary, report.max_momentary);
+        store(&shared.max_short_term, report.max_short_term);
+        store(&shared.true_peak, report.true_peak);
+        store(&shared.sample_peak, report.sample_peak);
+    }
+}
+
+impl<I> Iterator for LoudnessMeter<I>
+where
+    I: Source,
+{
+    type Item = Sample;
+
+    #[inline]
+    fn next(&mut self) -> Option<Sample> {
+        if self.span_left == Some(0) && self.channel == 0 {
+            self.configure();
+        }
+        let Some(sample) = self.input.next() else {
+            // The peaks of the last, incomplete block.
+            self.publish(
+                window_power(&self.blocks, MOMENTARY_BLOCKS),
+                window_power(&self.blocks, SHORT_TERM_BLOCKS),
+            );
+            return None;
+        };
+        if let Some(left) = &mut self.span_left {
+            *left = left.saturating_sub(1);
+        }
+        self.measure(sample);
+        Some(sample)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<I> ExactSizeIterator for LoudnessMeter<I> where I: Source + ExactSizeIterator {}
+
+impl<I> Source for LoudnessMeter<I>
+where
+    I: Source,
+{
+    #[inline]
+    fn current_span_len(&self) -> Option<usize> {
+        self.input.current_span_len()
+    }
+
+    #[inline]
+    fn channels(&self) -> ChannelCount {
+        self.input.channels()
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> SampleRate {
+        self.input.sample_rate()
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    #[inline]
+    fn channel_layout(&self) -> Option<ChannelLayout> {
+        self.input.channel_layout()
+    }
+
+    /// Keeps the measurements, the windows start over at the new position.
+    #[inline]
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)?;
+        // Force `configure` to reset the filters and windows.
+        self.channels = 0;
+        self.configure();
+        Ok(())
+    }
+}
+
+/// Reads the values of a [`LoudnessMeter`] from any thread.
+#[derive(Clone, Debug)]
+pub struct LoudnessMeterHandle {
+    shared: Arc<Shared>,
+}
+
+impl LoudnessMeterHandle {
+    /// Loudness of the last 400 ms, in LUFS.
+    #[inline]
+    pub fn momentary(&self) -> f32 {
+        load(&self.shared.momentary)
+    }
+
+    /// Loudness of the last 3 s, in LUFS.
+    #[inline]
+    pub fn short_term(&self) -> f32 {
+        load(&self.shared.short_term)
+    }
+
+    /// Loudness since the start of the measurement, in LUFS.
+    #[inline]
+    pub fn integrated(&self) -> f32 {
+        load(&self.shared.integrated)
+    }
+
+    /// Highest level between the samples since the start of the measurement, in dBTP.
+    #[inline]
+    pub fn true_peak(&self) -> f32 {
+        load(&self.shared.true_peak)
+    }
+
+    /// All values measured so far.
+    pub fn report(&self) -> LoudnessReport {
+        let shared = &self.shared;
+        LoudnessReport {
+            integrated: load(&shared.integrated),
+            loudness_range: load(&shared.loudness_range),
+            max_momentary: load(&shared.max_momentary),
+            max_short_term: load(&shared.max_short_term),
+            true_peak: load(&shared.true_peak),
+            sample_peak: load(&shared.sample_peak),
+        }
+    }
+}
+
+/// Values shared with the handles, `f32` stored as bits.
+#[derive(Debug)]
+struct Shared {
+    momentary: AtomicU32,
+    short_term: AtomicU32,
+    integrated: AtomicU32,
+    loudness_range: AtomicU32,
+    max_momentary: AtomicU32,
+    max_short_term: AtomicU32,
+    true_peak: AtomicU32,
+    sample_peak: AtomicU32,
+}
+
+impl Shared {
+    fn new() -> Shared {
+        let unknown = || AtomicU32::new(f32::NEG_INFINITY.to_bits());
+        Shared {
+            momentary: unknown(),
+            short_term: unknown(),
+            integrated: unknown(),
+            loudness_range: unknown(),
+            max_momentary: unknown(),
+            max_short_term: unknown(),
+            true_peak: unknown(),
+            sample_peak: unknown(),
+        }
+    }
+}
+
+#[inline]
+fn store(atomic: &AtomicU32, value: f32) {
+    atomic.store(value.to_bits(), Ordering::Relaxed);
+}
+
+#[inline]
+fn load(atomic: &AtomicU32) -> f32 {
+    f32::from_bits(atomic.load(Ordering::Relaxed))
+}
+
+#[inline]
+fn loudness(power: f64) -> f64 {
+    -0.691 + 10.0 * power.log10()
+}
+
+#[inline]
+fn to_db(level: f32) -> f32 {
+    20.0 * level.log10()
+}
+
+/// Mean power of the last `blocks` blocks, if there are that many.
+fn window_power(powers: &[f64], blocks: usize) -> Option<f64> {
+    (powers.len() >= blocks)
+        .then(|| powers[powers.len() - blocks..].iter().sum::<f64>() / blocks as f64)
+}
+
+/// Weight of each channel, table 3 of BS.1770-4.
+fn channel_weights(layout: ChannelLayout) -> Vec<f64> {
+    match layout.speakers() {
+        Some(speakers) => speakers
+            .iter()
+            .map(|speaker| match speaker {
+                Speaker::LowFrequency => 0.0,
+                Speaker::BackLeft | Speaker::BackRight | Speaker::SideLeft | Speaker::SideRight => {
+                    1.41
+                }
+                _ => 1.0,
+            })
+            .collect(),
+        None => vec![1.0; layout.channel_count() as usize],
+    }
+}
+
+/// Second order IIR filter, transposed direct form II.
+#[derive(Copy, Clone, Debug)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    state: [f64; 2],
+}
+
+impl Biquad {
+    #[inline]
+    fn process(&mut self, input: f64) -> f64 {
+        let output = self.b[0] * input + self.state[0];
+        self.state[0] = self.b[1] * input - self.a[0] * output + self.state[1];
+        self.state[1] = self.b[2] * input - self.a[1] * output;
+        output
+    }
+}
+
+/// The K-weighting filters of BS.1770: a high shelf modelling the head and a high pass.
+/// The coefficients of the standard are given for 48 kHz, these are derived from their
+/// analog prototypes so that they work at any sample rate.
+fn k_weighting(sample_rate: f64) -> [Biquad; 2] {
+    let frequency = 1681.974450955533;
+    let gain = 3.999843853973347;
+    let q = 0.7071752369554196;
+    let k = (PI * frequency / sample_rate).tan();
+    let vh = 10f64.powf(gain / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad {
+        b: [
+            (vh + vb * k / q + k * k) / a0,
+            2.0 * (k * k - vh) / a0,
+            (vh - vb * k / q + k * k) / a0,
+        ],
+        a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        state: [0.0; 2],
+    };
+
+    let frequency = 38.13547087602444;
+    let q = 0.5003270373238773;
+    let k = (PI * frequency / sample_rate).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad {
+        b: [1.0, -2.0, 1.0],
+        a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        state: [0.0; 2],
+    };
+    [shelf, high_pass]
+}
+
+/// Polyphase interpolation filter for true peak measurement: a Hann windowed sinc with its
+/// cutoff at the original Nyquist frequency.
+fn peak_kernel() -> [[f32; TAPS_PER_PHASE]; OVERSAMPLING] {
+    let len = OVERSAMPLING * TAPS_PER_PHASE;
+    let center = (len - 1) as f64 / 2.0;
+    let mut kernel = [[0.0; TAPS_PER_PHASE]; OVERSAMPLING];
+    for (phase, taps) in kernel.iter_mut().enumerate() {
+        for (tap, coefficient) in taps.iter_mut().enumerate() {
+            let n = (phase + tap * OVERSAMPLING) as f64;
+            let x = (n - center) / OVERSAMPLING as f64;
+            let sinc = if x == 0.0 {
+                1.0
+            } else {
+                (PI * x).sin() / (PI * x)
+            };
+            let window = 0.5 - 0.5 * (2.0 * PI * (n + 0.5) / len as f64).cos();
+            *coefficient = (sinc * window) as f32;
+        }
+        let sum: f32 = taps.iter().sum();
+        taps.iter_mut().for_each(|coefficient| *coefficient /= sum);
+    }
+    kernel
+}
+
+/// Interpolates one channel and returns the highest absolute value between the last two
+/// samples.
+#[derive(Copy, Clone, Debug, Default)]
+struct PeakFilter {
+    history: [f32; TAPS_PER_PHASE],
+    pos: usize,
+}
+
+impl PeakFilter {
+    #[inline]
+    fn process(&mut self, sample: Sample, kernel: &[[f32; TAPS_PER_PHASE]; OVERSAMPLING]) -> f32 {
+        self.pos = (self.pos + 1) % TAPS_PER_PHASE;
+        self.history[self.pos] = sample;
+        let mut peak = 0.0f32;
+        for taps in kernel {
+            let mut value = 0.0;
+            for (tap, coefficient) in taps.iter().enumerate() {
+                let index = (self.pos + TAPS_PER_PHASE - tap) % TAPS_PER_PHASE;
+                value += coefficient * self.history[index];
+            }
+            peak = peak.max(value.abs());
+        }
+        peak
+    }
+}
+
+/// Counts and total power of loudness values, in bins of 0.1 LU from the absolute gate up.
+#[derive(Clone, Debug)]
+struct Histogram {
+    counts: Vec<u64>,
+    powers: Vec<f64>,
+}
+
+impl Histogram {
+    fn new() -> Histogram {
+        let bins = ((HISTOGRAM_MAX - ABSOLUTE_GATE) * BINS_PER_LU) as usize;
+        Histogram {
+            counts: vec![0; bins],
+            powers: vec![0.0; bins],
+        }
+    }
+
+    /// The bin of a loudness value, `None` below the absolute gate.
+    #[inline]
+    fn bin(&self, loudness: f64) -> Option<usize> {
+        let bin = ((loudness - ABSOLUTE_GATE) * BINS_PER_LU).floor();
+        (bin >= 0.0).then(|| (bin as usize).min(self.counts.len() - 1))
+    }
+
+    /// Loudness at the middle of a bin.
+    #[inline]
+    fn bin_loudness(bin: usize) -> f64 {
+        ABSOLUTE_GATE + (bin as f64 + 0.5) / BINS_PER_LU
+    }
+
+    fn add(&mut self, power: f64) {
+        if let Some(bin) = self.bin(loudness(power)) {
+            self.counts[bin] += 1;
+            self.powers[bin] += power;
+        }
+    }
+
+    /// First bin of the values that pass the relative gate, if there are values.
+    fn relative_gate(&self, gate: f64) -> Option<usize> {
+        let count: u64 = self.counts.iter().sum();
+        let power: f64 = self.powers.iter().sum();
+        (count > 0).then(|| {
+            let threshold = loudness(power / count as f64) + gate;
+            self.bin(threshold).unwrap_or(0)
+        })
+    }
+
+    /// Loudness of the average power of the values that pass the relative gate.
+    fn gated_loudness(&self, gate: f64) -> f64 {
+        let Some(first) = self.relative_gate(gate) else {
+            return f64::NEG_INFINITY;
+        };
+        let count: u64 = self.counts[first..].iter().sum();
+        let power: f64 = self.powers[first..].iter().sum();
+        loudness(power / count as f64)
+    }
+
+    /// Difference between the 95th and the 10th percentile of the values that pass the
+    /// relative gate of loudness range.
+    fn range(&self) -> f64 {
+        let Some(first) = self.relative_gate(RANGE_RELATIVE_GATE) else {
+            return f64::NEG_INFINITY;
+        };
+        let counts = &self.counts[first..];
+        let total: u64 = counts.iter().sum();
+        let percentile = |fraction: f64| {
+            let rank = (fraction * (total - 1) as f64).round() as u64;
+            let mut seen = 0;
+            for (bin, count) in counts.iter().enumerate() {
+                seen += count;
+                if seen > rank {
+                    return Histogram::bin_loudness(first + bin);
+                }
+            }
+            unreachable!()
+        };
+        percentile(0.95) - percentile(0.10)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::measure_loudness;
+    use crate::buffer::SamplesBuffer;
+    use crate::source::{SineWave, Source};
+    use crate::Sample;
+
+    fn sine(frequency: f32, amplitude: f32, seconds: f32, channels: u16) -> SamplesBuffer {
+        let samples: Vec<Sample> = SineWave::new(frequency)
+            .amplify(amplitude)
+            .take_duration(Duration::from_secs_f32(seconds))
+            .flat_map(|sample| std::iter::repeat_n(sample, channels as usize))
+            .collect();
+        SamplesBuffer::new(channels, 48000, samples)
+    }
+
+    #[test]
+    fn reference_tone() {
+        // A 1 kHz stereo sine at -18 dBFS measures -18 LUFS (EBU Tech 3341 case 1 uses a
+        // -23 dBFS tone).
+        let amplitude = 10f32.powf(-18.0 / 20.0);
+        let report = measure_loudness(sine(1000.0, amplitude, 5.0, 2));
+        assert!((report.integrated + 18.0).abs() < 0.1, "{report:?}");
+        assert!((report.max_momentary + 18.0).abs() < 0.1, "{report:?}");
+        assert!((report.max_short_term + 18.0).abs() < 0.1, "{report:?}");
+        assert!(report.loudness_range.abs() < 0.2, "{report:?}");
+        assert!((report.sample_peak + 18.0).abs() < 0.01, "{report:?}");
+        assert!((report.true_peak + 18.0).abs() < 0.1, "{report:?}");
+    }
+
+    #[test]
+    fn gates_silence_and_quiet_parts() {
+        let loud = 10f32.powf(-20.0 / 20.0);
+        let quiet = 10f32.powf(-50.0 / 20.0);
+        let plain = measure_loudness(sine(1000.0, loud, 8.0, 2));
+        let mut samples: Vec<Sample> = sine(1000.0, loud, 8.0, 2).collect();
+        samples.extend(sine(1000.0, 0.0, 4.0, 2));
+        samples.extend(sine(1000.0, quiet, 4.0, 2));
+        let gated = measure_loudness(SamplesBuffer::new(2, 48000, samples));
+        assert!(
+            (gated.integrated - plain.integrated).abs() < 0.1,
+            "{gated:?} {plain:?}"
+        );
+        // The quiet part is more than 20 LU below the average, only the transitions count for
+        // the range.
+        assert!(gated.loudness_range < 10.0, "{gated:?}");
+    }
+
+    #[test]
+    fn true_peak_between_samples() {
+        // A sine at a quarter of the sample rate, sampled at 45 degrees off its peaks.
+        let samples: Vec<Sample> = (0..48000)
+            .map(|n| (std::f32::consts::PI * (n as f32 / 2.0 + 0.25)).sin())
+            .collect();
+        let report = measure_loudness(SamplesBuffer::new(1, 48000, samples));
+        assert!((report.sample_peak + 3.01).abs() < 0.1, "{report:?}");
+        assert!(report.true_peak > -0.5, "{report:?}");
+    }
+
+    #[test]
+    fn handle_follows_playback() {
+        let amplitude = 10f32.powf(-18.0 / 20.0);
+        let mut meter = sine(1000.0, amplitude, 5.0, 2).loudness_meter();
+        let handle = meter.handle();
+        assert_eq!(handle.momentary(), f32::NEG_INFINITY);
+        meter.by_ref().take(2 * 48000).for_each(drop);
+        assert!((handle.momentary() + 18.0).abs() < 0.1);
+        assert_eq!(handle.short_term(), f32::NEG_INFINITY);
+        meter.for_each(drop);
+        assert!((handle.short_term() + 18.0).abs() < 0.1);
+        assert!((handle.integrated() + 18.0).abs() < 0.1);
+    }
+}
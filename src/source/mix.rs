@@ -2,6 +2,7 @@ use std::cmp;
 use std::time::Duration;
 
 use crate::common::{ChannelCount, SampleRate};
+use crate::conversions::ChannelLayout;
 use crate::source::uniform::UniformSourceIterator;
 use crate::source::SeekError;
 use crate::Source;
@@ -111,6 +112,11 @@ where
         }
     }
 
+    #[inline]
+    fn channel_layout(&self) -> Option<ChannelLayout> {
+        self.input1.channel_layout()
+    }
+
     /// Will only attempt a seek if both underlying sources support seek.
     #[inline]
     fn try_seek(&mut self, _: Duration) -> Result<(), SeekError> {
@@ -4,6 +4,7 @@ use core::fmt;
 use core::time::Duration;
 
 use crate::common::{ChannelCount, SampleRate};
+use crate::conversions::ChannelLayout;
 use crate::Sample;
 use dasp_sample::FromSample;
 
@@ -18,14 +19,17 @@ pub use self::delay::Delay;
 pub use self::done::Done;
 pub use self::empty::Empty;
 pub use self::empty_callback::EmptyCallback;
+pub use self::fade_curve::FadeCurve;
 pub use self::fadein::FadeIn;
 pub use self::fadeout::FadeOut;
 pub use self::from_factory::{from_factory, FromFactoryIter};
 pub use self::from_iter::{from_iter, FromIter};
 pub use self::linear_ramp::LinearGainRamp;
+pub use self::loudness::{measure_loudness, LoudnessMeter, LoudnessMeterHandle, LoudnessReport};
 pub use self::mix::Mix;
 pub use self::pausable::Pausable;
 pub use self::periodic::PeriodicAccess;
+pub use self::pitch_shift::PitchShift;
 pub use self::position::TrackPosition;
 pub use self::repeat::Repeat;
 pub use self::sawtooth::SawtoothWave;
@@ -38,6 +42,8 @@ pub use self::speed::Speed;
 pub use self::square::SquareWave;
 pub use self::stoppable::Stoppable;
 pub use self::take::TakeDuration;
+pub use self::tap::{Tap, TapReceiver};
+pub use self::time_stretch::TimeStretch;
 pub use self::triangle::TriangleWave;
 pub use self::uniform::UniformSourceIterator;
 pub use self::zero::Zero;
@@ -53,14 +59,17 @@ mod delay;
 mod done;
 mod empty;
 mod empty_callback;
+mod fade_curve;
 mod fadein;
 mod fadeout;
 mod from_factory;
 mod from_iter;
 mod linear_ramp;
+mod loudness;
 mod mix;
 mod pausable;
 mod periodic;
+mod pitch_shift;
 mod position;
 mod repeat;
 mod sawtooth;
@@ -73,6 +82,8 @@ mod speed;
 mod square;
 mod stoppable;
 mod take;
+mod tap;
+mod time_stretch;
 mod triangle;
 mod uniform;
 mod zero;
@@ -170,6 +181,18 @@ pub trait Source: Iterator<Item = Sample> {
     /// `None` indicates at the same time "infinite" or "unknown".
     fn total_duration(&self) -> Option<Duration>;
 
+    /// Returns the speaker positions of the channels of the current span, if known.
+    ///
+    /// `None` means the layout commonly used for the channel count, see
+    /// [`ChannelLayout::from_channel_count`]. A layout with a different number of channels
+    /// than [`channels`](Source::channels) is ignored.
+    ///
+    /// Like the channel count, the layout may only change at span boundaries.
+    #[inline]
+    fn channel_layout(&self) -> Option<ChannelLayout> {
+        None
+    }
+
     /// Stores the source in a buffer in addition to returning it. This iterator can be cloned.
     #[inline]
     fn buffered(self) -> Buffered<Self>
@@ -351,6 +374,23 @@ pub trait Source: Iterator<Item = Sample> {
         crossfade::crossfade(self, other, duration)
     }
 
+    /// Like [`take_crossfade_with`](Source::take_crossfade_with), with the shape of both fades
+    /// given by `curve`. [`FadeCurve::EqualPower`] keeps the loudness even when the two sounds
+    /// are unrelated.
+    #[inline]
+    fn take_crossfade_with_curve<S: Source>(
+        self,
+        other: S,
+        duration: Duration,
+        curve: FadeCurve,
+    ) -> Crossfade<Self, S>
+    where
+        Self: Sized,
+        Self::Item: FromSample<S::Item>,
+    {
+        crossfade::crossfade_with_curve(self, other, duration, curve)
+    }
+
     /// Fades in the sound.
     #[inline]
     fn fade_in(self, duration: Duration) -> FadeIn<Self>
@@ -360,6 +400,24 @@ pub trait Source: Iterator<Item = Sample> {
         fadein::fadein(self, duration)
     }
 
+    /// Fades in the sound, with the shape of the fade given by `curve`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use rodio::source::{FadeCurve, SineWave, Source};
+    ///
+    /// let source = SineWave::new(440.0).fade_in_with_curve(Duration::from_secs(2), FadeCurve::SCurve);
+    /// ```
+    #[inline]
+    fn fade_in_with_curve(self, duration: Duration, curve: FadeCurve) -> FadeIn<Self>
+    where
+        Self: Sized,
+    {
+        fadein::fadein_with_curve(self, duration, curve)
+    }
+
     /// Fades out the sound.
     #[inline]
     fn fade_out(self, duration: Duration) -> FadeOut<Self>
@@ -369,11 +427,24 @@ pub trait Source: Iterator<Item = Sample> {
         fadeout::fadeout(self, duration)
     }
 
+    /// Fades out the sound, with the shape of the fade given by `curve`.
+    ///
+    /// The curve is played backwards, so a fade out mirrors a fade in with the same curve.
+    #[inline]
+    fn fade_out_with_curve(self, duration: Duration, curve: FadeCurve) -> FadeOut<Self>
+    where
+        Self: Sized,
+    {
+        fadeout::fadeout_with_curve(self, duration, curve)
+    }
+
     /// Applies a linear gain ramp to the sound.
     ///
     /// If `clamp_end` is `true`, all samples subsequent to the end of the ramp
     /// will be scaled by the `end_value`. If `clamp_end` is `false`, all
     /// subsequent samples will not have any scaling applied.
+    ///
+    /// Use [`LinearGainRamp::set_curve`] for a ramp that is not linear.
     #[inline]
     fn linear_gain_ramp(
         self,
@@ -388,6 +459,34 @@ pub trait Source: Iterator<Item = Sample> {
         linear_ramp::linear_gain_ramp(self, duration, start_value, end_value, clamp_end)
     }
 
+    /// Measures the loudness of the sound according to EBU R128, without changing it.
+    ///
+    /// Get a [`LoudnessMeterHandle`] with [`LoudnessMeter::handle`] to read the momentary,
+    /// short-term and integrated loudness and the true peak while the sound plays. To measure
+    /// a whole file at once use [`measure_loudness`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rodio::source::{SineWave, Source};
+    /// use std::time::Duration;
+    ///
+    /// let meter = SineWave::new(1000.0)
+    ///     .amplify(0.5)
+    ///     .take_duration(Duration::from_secs(1))
+    ///     .loudness_meter();
+    /// let handle = meter.handle();
+    /// meter.for_each(drop);
+    /// println!("{} LUFS", handle.integrated());
+    /// ```
+    #[inline]
+    fn loudness_meter(self) -> LoudnessMeter<Self>
+    where
+        Self: Sized,
+    {
+        loudness::loudness_meter(self)
+    }
+
     /// Calls the `access` closure on `Self` the first time the source is iterated and every
     /// time `period` elapses.
     ///
@@ -429,6 +528,40 @@ pub trait Source: Iterator<Item = Sample> {
         speed::speed(self, ratio)
     }
 
+    /// Changes the tempo of the sound without changing its pitch.
+    ///
+    /// The ratio is the playback rate, like with [`speed`](Source::speed): `2.0` plays twice
+    /// as fast and halves the total duration, `0.5` plays at half the speed. Unlike
+    /// [`speed`](Source::speed) the pitch stays the same. Seeking takes the ratio into
+    /// account the same way.
+    ///
+    /// # Panic
+    /// Panics if `ratio` is not a positive finite number.
+    ///
+    /// See [`TimeStretch`] for details
+    #[inline]
+    fn time_stretch(self, ratio: f32) -> TimeStretch<Self>
+    where
+        Self: Sized,
+    {
+        time_stretch::time_stretch(self, ratio)
+    }
+
+    /// Transposes the sound by the given number of semitones without changing its duration.
+    ///
+    /// Negative values lower the pitch. The transposition can be changed while playing with
+    /// [`PitchShift::set_semitones`], for example from [`periodic_access`](Source::periodic_access).
+    ///
+    /// # Panic
+    /// Panics if `semitones` is not finite.
+    #[inline]
+    fn pitch_shift(self, semitones: f32) -> PitchShift<Self>
+    where
+        Self: Sized,
+    {
+        pitch_shift::pitch_shift(self, semitones)
+    }
+
     /// Adds a basic reverb effect.
     ///
     /// This function requires the source to implement `Clone`. This can be done by using
@@ -497,6 +630,48 @@ pub trait Source: Iterator<Item = Sample> {
         position::track_position(self)
     }
 
+    /// Sends a copy of every sample to a [`TapReceiver`] while passing the samples on
+    /// unchanged, for example to record what a sink plays.
+    ///
+    /// `capacity` is the number of samples the receiver can fall behind. The tap never waits
+    /// for the receiver, once the buffer is full whole frames are left out of the copy and
+    /// counted in [`TapReceiver::dropped_frames`].
+    ///
+    /// The copy does not record where the channel count or sample rate changes. Tap a source
+    /// with a fixed format, like the output of a
+    /// [`UniformSourceIterator`](crate::source::UniformSourceIterator), if the sounds may
+    /// differ, as those of a sink do.
+    ///
+    /// # Example
+    ///
+    /// Records a sink after its volume is applied, in the format of the mixer:
+    ///
+    /// ```
+    /// use rodio::source::{Source, UniformSourceIterator};
+    /// use rodio::{OfflineRenderer, Sink};
+    /// use std::time::Duration;
+    ///
+    /// let mut renderer = OfflineRenderer::new(2, 48000);
+    /// let (sink, output) = Sink::new();
+    /// let (tapped, mut receiver) = UniformSourceIterator::new(output, 2, 48000).tap(48000);
+    /// renderer.mixer().add(tapped);
+    ///
+    /// sink.set_volume(0.5);
+    /// sink.append(rodio::source::SineWave::new(440.0).take_duration(Duration::from_millis(100)));
+    /// renderer.render(Duration::from_millis(100));
+    ///
+    /// let mut recorded = vec![0.0; 48000];
+    /// let len = receiver.read(&mut recorded);
+    /// assert!(len > 0);
+    /// ```
+    #[inline]
+    fn tap(self, capacity: usize) -> (Tap<Self>, TapReceiver)
+    where
+        Self: Sized,
+    {
+        tap::tap(self, capacity)
+    }
+
     /// Applies a low-pass filter to the source.
     /// **Warning**: Probably buggy.
     #[inline]
@@ -584,6 +759,12 @@ pub enum SeekError {
     #[cfg(feature = "wav")]
     /// The hound (wav) decoder ran into an issue
     HoundDecoder(std::io::Error),
+    #[cfg(feature = "minimp3")]
+    /// The minimp3 (mp3) decoder could not read the data
+    Minimp3Decoder(std::io::Error),
+    #[cfg(feature = "vorbis")]
+    /// The lewton (vorbis) decoder ran into an issue
+    LewtonDecoder(lewton::VorbisError),
     // Prefer adding an enum variant to using this. It's meant for end users their
     // own `try_seek` implementations.
     /// Any other error probably in a custom Source
@@ -603,6 +784,10 @@ impl fmt::Display for SeekError {
             SeekError::SymphoniaDecoder(err) => write!(f, "Error seeking: {}", err),
             #[cfg(feature = "wav")]
             SeekError::HoundDecoder(err) => write!(f, "Error seeking in wav source: {}", err),
+            #[cfg(feature = "minimp3")]
+            SeekError::Minimp3Decoder(err) => write!(f, "Error seeking in mp3 source: {}", err),
+            #[cfg(feature = "vorbis")]
+            SeekError::LewtonDecoder(err) => write!(f, "Error seeking in ogg source: {}", err),
             SeekError::Other(_) => write!(f, "An error occurred"),
         }
     }
@@ -615,6 +800,10 @@ impl std::error::Error for SeekError {
             SeekError::SymphoniaDecoder(err) => Some(err),
             #[cfg(feature = "wav")]
             SeekError::HoundDecoder(err) => Some(err),
+            #[cfg(feature = "minimp3")]
+            SeekError::Minimp3Decoder(err) => Some(err),
+            #[cfg(feature = "vorbis")]
+            SeekError::LewtonDecoder(err) => Some(err),
             SeekError::Other(err) => Some(err.as_ref()),
         }
     }
@@ -637,6 +826,10 @@ impl SeekError {
             SeekError::SymphoniaDecoder(_) => false,
             #[cfg(feature = "wav")]
             SeekError::HoundDecoder(_) => false,
+            #[cfg(feature = "minimp3")]
+            SeekError::Minimp3Decoder(_) => false,
+            #[cfg(feature = "vorbis")]
+            SeekError::LewtonDecoder(_) => false,
             SeekError::Other(_) => false,
         }
     }
@@ -665,6 +858,11 @@ macro_rules! source_pointer_impl {
                 (**self).total_duration()
             }
 
+            #[inline]
+            fn channel_layout(&self) -> Option<ChannelLayout> {
+                (**self).channel_layout()
+            }
+
             #[inline]
             fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
                 (**self).try_seek(pos)
@@ -0,0 +1,194 @@
+use std::time::Duration;
+
+use super::SeekError;
+use crate::common::{ChannelCount, SampleRate};
+use crate::conversions::ChannelLayout;
+use crate::source::{Speed, TimeStretch, UniformSourceIterator};
+use crate::{Sample, Source};
+
+/// Internal function that builds a `PitchShift` object.
+pub fn pitch_shift<I>(input: I, semitones: f32) -> PitchShift<I>
+where
+    I: Source,
+{
+    assert!(semitones.is_finite());
+    let factor = semitones_to_factor(semitones);
+    let channels = input.channels();
+    let sample_rate = input.sample_rate();
+    // Stretching by the inverse of the factor and then playing faster by the factor keeps
+    // the duration while changing the pitch. The uniform source iterator follows the sample
+    // rate reported by `Speed` and resamples back to the original rate.
+    let input = input.time_stretch(1.0 / factor).speed(factor);
+    PitchShift {
+        input: UniformSourceIterator::new(input, channels, sample_rate),
+        semitones,
+    }
+}
+
+#[inline]
+fn semitones_to_factor(semitones: f32) -> f32 {
+    2.0f32.powf(semitones / 12.0)
+}
+
+/// Transposes a source by a number of semitones without changing its duration.
+///
+/// The output has the channel count and sample rate the input had when the adapter was
+/// created, later changes of the input format are converted.
+#[derive(Clone)]
+pub struct PitchShift<I>
+where
+    I: Source,
+{
+    input: UniformSourceIterator<Speed<TimeStretch<I>>>,
+    semitones: f32,
+}
+
+impl<I> PitchShift<I>
+where
+    I: Source,
+{
+    /// Modifies the transposition, in semitones. Negative values lower the pitch.
+    ///
+    /// The new pitch is reached within a few milliseconds.
+    ///
+    /// # Panic
+    /// Panics if `semitones` is not finite.
+    #[inline]
+    pub fn set_semitones(&mut self, semitones: f32) {
+        assert!(semitones.is_finite());
+        self.semitones = semitones;
+        let factor = semitones_to_factor(semitones);
+        let speed = self.input.inner_mut();
+        speed.set_factor(factor);
+        speed.inner_mut().set_ratio(1.0 / factor);
+    }
+
+    /// Returns the transposition, in semitones.
+    #[inline]
+    pub fn semitones(&self) -> f32 {
+        self.semitones
+    }
+
+    /// Returns a reference to the inner source.
+    #[inline]
+    pub fn inner(&self) -> &I {
+        self.input.inner().inner().inner()
+    }
+
+    /// Returns a mutable reference to the inner source.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut I {
+        self.input.inner_mut().inner_mut().inner_mut()
+    }
+}
+
+impl<I> Iterator for PitchShift<I>
+where
+    I: Source,
+{
+    type Item = Sample;
+
+    #[inline]
+    fn next(&mut self) -> Option<Sample> {
+        self.input.next()
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<I> Source for PitchShift<I>
+where
+    I: Source,
+{
+    #[inline]
+    fn current_span_len(&self) -> Option<usize> {
+        self.input.current_span_len()
+    }
+
+    #[inline]
+    fn channels(&self) -> ChannelCount {
+        self.input.channels()
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> SampleRate {
+        self.input.sample_rate()
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    #[inline]
+    fn channel_layout(&self) -> Option<ChannelLayout> {
+        self.input.channel_layout()
+    }
+
+    #[inline]
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::buffer::SamplesBuffer;
+    use crate::source::{SineWave, Source};
+    use crate::Sample;
+
+    /// Counts the times the signal goes from negative to positive.
+    fn rising_zero_crossings(samples: &[Sample]) -> usize {
+        samples
+            .windows(2)
+            .filter(|pair| pair[0] < 0.0 && pair[1] >= 0.0)
+            .count()
+    }
+
+    fn sine(seconds: u64) -> SamplesBuffer {
+        let samples: Vec<Sample> = SineWave::new(440.0)
+            .take_duration(Duration::from_secs(seconds))
+            .collect();
+        SamplesBuffer::new(1, 48000, samples)
+    }
+
+    #[test]
+    fn changes_pitch_but_not_duration() {
+        for (semitones, frequency) in [(12.0, 880.0), (-12.0, 220.0), (7.0, 659.3)] {
+            let input_len = sine(2).count();
+            let output: Vec<Sample> = sine(2).pitch_shift(semitones).collect();
+            assert!(output.len().abs_diff(input_len) <= 100, "{semitones}");
+
+            // Half a second of output in the middle, away from the edges.
+            let middle = &output[output.len() / 2 - 12000..output.len() / 2 + 12000];
+            let crossings = rising_zero_crossings(middle) as f32;
+            assert!(
+                (crossings - frequency / 2.0).abs() <= 2.0,
+                "{semitones}: {crossings}"
+            );
+        }
+    }
+
+    #[test]
+    fn zero_semitones_passes_samples_through() {
+        let output: Vec<Sample> = sine(1).pitch_shift(0.0).collect();
+        let input: Vec<Sample> = sine(1).collect();
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    fn semitones_can_change_while_playing() {
+        let mut source = sine(2).pitch_shift(0.0);
+        let before: Vec<Sample> = source.by_ref().take(24000).collect();
+        source.set_semitones(12.0);
+        assert_eq!(source.semitones(), 12.0);
+        let after: Vec<Sample> = source.by_ref().skip(2400).take(24000).collect();
+        assert!(rising_zero_crossings(&before).abs_diff(220) <= 2);
+        assert!(rising_zero_crossings(&after).abs_diff(440) <= 2);
+    }
+}
@@ -2,6 +2,7 @@ use std::time::Duration;
 
 use super::SeekError;
 use crate::common::{ChannelCount, SampleRate};
+use crate::conversions::ChannelLayout;
 use crate::Source;
 
 /// Internal function that builds a `TrackPosition` object. See trait docs for
@@ -141,6 +142,11 @@ where
         self.input.total_duration()
     }
 
+    #[inline]
+    fn channel_layout(&self) -> Option<ChannelLayout> {
+        self.input.channel_layout()
+    }
+
     #[inline]
     fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
         let result = self.input.try_seek(pos);
@@ -174,7 +180,7 @@ mod tests {
         source.next();
         assert_eq!(source.get_pos().as_secs_f32(), 2.0);
 
-        assert_eq!(source.try_seek(Duration::new(1, 0)).is_ok(), true);
+        assert!(source.try_seek(Duration::new(1, 0)).is_ok());
         assert_eq!(source.get_pos().as_secs_f32(), 1.0);
     }
 
@@ -190,7 +196,7 @@ mod tests {
         source.next();
         assert_eq!(source.get_pos().as_secs_f32(), 1.0);
 
-        assert_eq!(source.try_seek(Duration::new(1, 0)).is_ok(), true);
+        assert!(source.try_seek(Duration::new(1, 0)).is_ok());
         assert_eq!(source.get_pos().as_secs_f32(), 1.0);
     }
 }
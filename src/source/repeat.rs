@@ -4,6 +4,7 @@ use crate::source::buffered::Buffered;
 
 use super::SeekError;
 use crate::common::{ChannelCount, SampleRate};
+use crate::conversions::ChannelLayout;
 use crate::Source;
 
 /// Internal function that builds a `Repeat` object.
@@ -83,6 +84,14 @@ where
         None
     }
 
+    #[inline]
+    fn channel_layout(&self) -> Option<ChannelLayout> {
+        match self.inner.current_span_len() {
+            Some(0) => self.next.channel_layout(),
+            _ => self.inner.channel_layout(),
+        }
+    }
+
     #[inline]
     fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
         self.inner.try_seek(pos)
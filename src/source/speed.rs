@@ -8,6 +8,10 @@
 //! - Updates the total duration function to cover for the new factor by dividing by the factor.
 //! - Updates the try_seek function by multiplying the audio position by the factor.
 //!
+//! The factor can be changed while the source is playing. The mixer notices the new sample
+//! rate within a few samples, even in the middle of a span, and glides to the new pitch
+//! without clicks.
+//!
 //! To speed up a source from sink all you need to do is call the   `set_speed(factor: f32)` function
 //! For example, here is how you speed up your sound by using sink or playing raw:
 //!
@@ -44,11 +48,14 @@
 //! Notice the increase in pitch as the factor increases
 //!
 //! Since the samples are played faster the audio wave get shorter increasing their frequencies
+//!
+//! To change the tempo without changing the pitch use [`Source::time_stretch`] instead.
 
 use std::time::Duration;
 
 use super::SeekError;
 use crate::common::{ChannelCount, SampleRate};
+use crate::conversions::ChannelLayout;
 use crate::Source;
 
 /// Internal function that builds a `Speed` object.
@@ -68,6 +75,9 @@ where
     I: Source,
 {
     /// Modifies the speed factor.
+    ///
+    /// The change is heard right away when the source is played through a
+    /// [`Mixer`](crate::mixer::Mixer) or [`UniformSourceIterator`](crate::source::UniformSourceIterator).
     #[inline]
     pub fn set_factor(&mut self, factor: f32) {
         self.factor = factor;
@@ -135,6 +145,11 @@ where
         self.input.total_duration().map(|d| d.div_f32(self.factor))
     }
 
+    #[inline]
+    fn channel_layout(&self) -> Option<ChannelLayout> {
+        self.input.channel_layout()
+    }
+
     #[inline]
     fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
         let pos_accounting_for_speedup = pos.mul_f32(self.factor);
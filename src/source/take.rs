@@ -1,7 +1,8 @@
 use std::time::Duration;
 
-use super::SeekError;
+use super::{FadeCurve, SeekError};
 use crate::common::{ChannelCount, SampleRate};
+use crate::conversions::ChannelLayout;
 use crate::{Sample, Source};
 
 /// Internal function that builds a `TakeDuration` object.
@@ -22,15 +23,15 @@ where
 /// A filter that can be applied to a `TakeDuration`.
 #[derive(Clone, Debug)]
 enum DurationFilter {
-    FadeOut,
+    FadeOut(FadeCurve),
 }
 impl DurationFilter {
     fn apply<I: Iterator>(&self, sample: Sample, parent: &TakeDuration<I>) -> Sample {
         match self {
-            DurationFilter::FadeOut => {
+            DurationFilter::FadeOut(curve) => {
                 let remaining = parent.remaining_duration.as_millis() as f32;
                 let total = parent.requested_duration.as_millis() as f32;
-                sample * remaining / total
+                sample * curve.gain(remaining / total)
             }
         }
     }
@@ -84,7 +85,13 @@ where
     /// Make the truncated source end with a FadeOut. The fadeout covers the
     /// entire length of the take source.
     pub fn set_filter_fadeout(&mut self) {
-        self.filter = Some(DurationFilter::FadeOut);
+        self.set_filter_fadeout_with_curve(FadeCurve::Linear);
+    }
+
+    /// Like [`set_filter_fadeout`](TakeDuration::set_filter_fadeout), with the shape of the
+    /// fade given by `curve`.
+    pub fn set_filter_fadeout_with_curve(&mut self, curve: FadeCurve) {
+        self.filter = Some(DurationFilter::FadeOut(curve));
     }
 
     /// Remove any filter set.
@@ -170,6 +177,11 @@ where
         }
     }
 
+    #[inline]
+    fn channel_layout(&self) -> Option<ChannelLayout> {
+        self.input.channel_layout()
+    }
+
     #[inline]
     fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
         self.input.try_seek(pos)
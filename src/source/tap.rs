@@ -0,0 +1,321 @@
+use std::sync::atomic::{AtomicBool, AtomicU16, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::SeekError;
+use crate::common::{ChannelCount, SampleRate};
+use crate::conversions::ChannelLayout;
+use crate::{Sample, Source};
+
+/// Internal function that builds a `Tap` object.
+pub fn tap<I>(input: I, capacity: usize) -> (Tap<I>, TapReceiver)
+where
+    I: Source,
+{
+    assert!(capacity > 0, "capacity is greater than zero");
+    let shared = Arc::new(Shared {
+        slots: (0..capacity).map(|_| AtomicU32::new(0)).collect(),
+        read: AtomicUsize::new(0),
+        written: AtomicUsize::new(0),
+        dropped_frames: AtomicU64::new(0),
+        channels: AtomicU16::new(input.channels()),
+        sample_rate: AtomicU32::new(input.sample_rate()),
+        closed: AtomicBool::new(false),
+    });
+    let tap = Tap {
+        input,
+        shared: Closer(shared.clone()),
+        written: 0,
+        frame_left: 0,
+        keep_frame: true,
+    };
+    (tap, TapReceiver { shared, read: 0 })
+}
+
+/// Ring buffer shared by the tap and its receiver.
+///
+/// `read` and `written` count samples since the start and only grow, the slot of a sample is
+/// its count modulo the capacity. Only the tap advances `written` and only the receiver
+/// advances `read`.
+struct Shared {
+    slots: Box<[AtomicU32]>,
+    read: AtomicUsize,
+    written: AtomicUsize,
+    dropped_frames: AtomicU64,
+    channels: AtomicU16,
+    sample_rate: AtomicU32,
+    closed: AtomicBool,
+}
+
+/// Marks the receiver closed when the tap is dropped.
+struct Closer(Arc<Shared>);
+
+impl Drop for Closer {
+    fn drop(&mut self) {
+        self.0.closed.store(true, Ordering::Release);
+    }
+}
+
+/// Source that passes the samples of its input through unchanged and sends a copy to a
+/// [`TapReceiver`].
+///
+/// Sending never waits or allocates. When the receiver falls behind and the buffer is full,
+/// whole frames are left out of the copy.
+pub struct Tap<I> {
+    input: I,
+    shared: Closer,
+    // Local copy of `shared.written`.
+    written: usize,
+    // Samples left in the current frame.
+    frame_left: usize,
+    keep_frame: bool,
+}
+
+impl<I> Tap<I> {
+    /// Returns a reference to the inner source.
+    #[inline]
+    pub fn inner(&self) -> &I {
+        &self.input
+    }
+
+    /// Returns a mutable reference to the inner source.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut I {
+        &mut self.input
+    }
+
+    /// Returns the inner source.
+    #[inline]
+    pub fn into_inner(self) -> I {
+        self.input
+    }
+}
+
+impl<I> Iterator for Tap<I>
+where
+    I: Source,
+{
+    type Item = Sample;
+
+    #[inline]
+    fn next(&mut self) -> Option<Sample> {
+        // Read before `next`, which may move on to a span with other parameters.
+        let channels = self.input.channels();
+        let sample_rate = self.input.sample_rate();
+        let sample = self.input.next()?;
+
+        if self.frame_left == 0 {
+            let shared = &self.shared.0;
+            shared.channels.store(channels, Ordering::Relaxed);
+            shared.sample_rate.store(sample_rate, Ordering::Relaxed);
+            self.frame_left = channels as usize;
+
+            let read = shared.read.load(Ordering::Acquire);
+            let free = shared.slots.len() - (self.written - read);
+            self.keep_frame = free >= channels as usize;
+            if !self.keep_frame {
+                shared.dropped_frames.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        self.frame_left -= 1;
+        if self.keep_frame {
+            let slot = self.written % self.shared.0.slots.len();
+            self.shared.0.slots[slot].store(sample.to_bits(), Ordering::Relaxed);
+            self.written += 1;
+            self.shared.0.written.store(self.written, Ordering::Release);
+        }
+        Some(sample)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<I> ExactSizeIterator for Tap<I> where I: Source + ExactSizeIterator {}
+
+impl<I> Source for Tap<I>
+where
+    I: Source,
+{
+    #[inline]
+    fn current_span_len(&self) -> Option<usize> {
+        self.input.current_span_len()
+    }
+
+    #[inline]
+    fn channels(&self) -> ChannelCount {
+        self.input.channels()
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> SampleRate {
+        self.input.sample_rate()
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    #[inline]
+    fn channel_layout(&self) -> Option<ChannelLayout> {
+        self.input.channel_layout()
+    }
+
+    #[inline]
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)
+    }
+}
+
+/// Receives the copy of the samples of a [`Tap`], usually on another thread.
+pub struct TapReceiver {
+    shared: Arc<Shared>,
+    // Local copy of `shared.read`.
+    read: usize,
+}
+
+impl TapReceiver {
+    /// Moves as many received samples as fit into `buffer` and returns how many that were.
+    ///
+    /// Never waits, returns `0` when nothing was received since the last call. Frames are
+    /// always copied whole, although a frame may be split over two calls.
+    pub fn read(&mut self, buffer: &mut [Sample]) -> usize {
+        let written = self.shared.written.load(Ordering::Acquire);
+        let len = (written - self.read).min(buffer.len());
+        for (offset, sample) in buffer[..len].iter_mut().enumerate() {
+            let slot = (self.read + offset) % self.shared.slots.len();
+            *sample = f32::from_bits(self.shared.slots[slot].load(Ordering::Relaxed));
+        }
+        self.read += len;
+        self.shared.read.store(self.read, Ordering::Release);
+        len
+    }
+
+    /// Returns the number of samples waiting to be read.
+    pub fn available(&self) -> usize {
+        self.shared.written.load(Ordering::Acquire) - self.read
+    }
+
+    /// Returns the number of frames left out because the buffer was full.
+    pub fn dropped_frames(&self) -> u64 {
+        self.shared.dropped_frames.load(Ordering::Relaxed)
+    }
+
+    /// Returns the channel count of the latest samples.
+    ///
+    /// Samples that are still waiting to be read may have another one, see
+    /// [`Source::tap`](crate::Source::tap).
+    pub fn channels(&self) -> ChannelCount {
+        self.shared.channels.load(Ordering::Relaxed)
+    }
+
+    /// Returns the sample rate of the latest samples.
+    ///
+    /// Samples that are still waiting to be read may have another one, see
+    /// [`Source::tap`](crate::Source::tap).
+    pub fn sample_rate(&self) -> SampleRate {
+        self.shared.sample_rate.load(Ordering::Relaxed)
+    }
+
+    /// Returns `true` once the [`Tap`] was dropped. Samples may still be waiting to be read.
+    pub fn is_closed(&self) -> bool {
+        self.shared.closed.load(Ordering::Acquire)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use crate::buffer::SamplesBuffer;
+    use crate::source::UniformSourceIterator;
+    use crate::{queue, Sample, Source};
+
+    #[test]
+    fn passes_and_copies() {
+        let samples: Vec<Sample> = (0..10).map(|i| i as f32).collect();
+        let (tap, mut receiver) = SamplesBuffer::new(2, 1000, samples.clone()).tap(16);
+        assert_eq!(receiver.channels(), 2);
+        assert_eq!(receiver.sample_rate(), 1000);
+
+        let played: Vec<Sample> = tap.collect();
+        assert_eq!(played, samples);
+        assert!(receiver.is_closed());
+
+        let mut buffer = [0.0; 4];
+        assert_eq!(receiver.read(&mut buffer), 4);
+        assert_eq!(buffer, [0.0, 1.0, 2.0, 3.0]);
+        let mut buffer = [0.0; 16];
+        assert_eq!(receiver.read(&mut buffer), 6);
+        assert_eq!(&buffer[..6], &samples[4..]);
+        assert_eq!(receiver.dropped_frames(), 0);
+    }
+
+    #[test]
+    fn drops_whole_frames_when_full() {
+        let samples: Vec<Sample> = (0..12).map(|i| i as f32).collect();
+        // Room for two and a half stereo frames.
+        let (mut tap, mut receiver) = SamplesBuffer::new(2, 1000, samples.clone()).tap(5);
+
+        let played: Vec<Sample> = tap.by_ref().take(8).collect();
+        assert_eq!(played, &samples[..8]);
+        assert_eq!(receiver.available(), 4);
+        assert_eq!(receiver.dropped_frames(), 2);
+
+        let mut buffer = [0.0; 8];
+        assert_eq!(receiver.read(&mut buffer), 4);
+        assert_eq!(&buffer[..4], &samples[..4]);
+
+        assert_eq!(tap.count(), 4);
+        assert_eq!(receiver.read(&mut buffer), 4);
+        assert_eq!(&buffer[..4], &samples[8..]);
+    }
+
+    /// Tapped after a converter, the copy of sounds with different formats stays readable.
+    #[test]
+    fn fixed_format_after_converter() {
+        let (input, output) = queue::queue(false);
+        input.append(SamplesBuffer::new(1, 1000, vec![0.5; 10]));
+        input.append(SamplesBuffer::new(2, 2000, vec![0.5; 40]));
+        let (tap, mut receiver) = UniformSourceIterator::new(output, 2, 1000).tap(64);
+
+        let played: Vec<Sample> = tap.collect();
+        assert_eq!((receiver.channels(), receiver.sample_rate()), (2, 1000));
+        let mut buffer = [0.0; 64];
+        assert_eq!(receiver.read(&mut buffer), played.len());
+        assert_eq!(&buffer[..played.len()], played);
+        // Both sounds take 10 ms.
+        assert_eq!(played.len(), 40);
+    }
+
+    #[test]
+    fn across_threads() {
+        let len = 100_000;
+        let source = SamplesBuffer::new(1, 48000, (0..len).map(|i| i as f32).collect::<Vec<_>>());
+        let (tap, mut receiver) = source.tap(1024);
+
+        let player = thread::spawn(move || tap.count());
+        let mut received = Vec::new();
+        let mut buffer = [0.0; 256];
+        loop {
+            let closed = receiver.is_closed();
+            let read = receiver.read(&mut buffer);
+            received.extend_from_slice(&buffer[..read]);
+            if closed && read == 0 {
+                break;
+            }
+        }
+        assert_eq!(player.join().unwrap(), len);
+
+        // Whatever arrived is in order, the rest was counted as dropped.
+        assert_eq!(
+            received.len() as u64 + receiver.dropped_frames(),
+            len as u64
+        );
+        assert!(received.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+}
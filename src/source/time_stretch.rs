@@ -0,0 +1,604 @@
+//! Tempo change without pitch change.
+//!
+//! [`TimeStretch`] uses WSOLA (waveform similarity overlap-add): the output is built from
+//! overlapping, windowed chunks of the input. Speeding up skips parts of the input and slowing
+//! down repeats them. Each chunk is picked within a small tolerance around its nominal
+//! position such that it lines up with the waveform of the previous chunk, which avoids the
+//! phase jumps that plain overlap-add would produce.
+//!
+//! This works best for speech and other monophonic material, strongly polyphonic music can
+//! sound slightly smeared at large ratios.
+
+use std::time::Duration;
+
+use super::SeekError;
+use crate::common::{ChannelCount, SampleRate};
+use crate::conversions::ChannelLayout;
+use crate::{Sample, Source};
+
+/// Length of the overlap between neighbouring chunks, in milliseconds.
+const HOP_MS: usize = 20;
+
+/// How far a chunk may be moved away from its nominal position, in milliseconds.
+const TOLERANCE_MS: usize = 10;
+
+/// Step used when looking for the best chunk position, the best candidate is refined
+/// afterwards.
+const COARSE_STEP: usize = 4;
+
+/// Internal function that builds a `TimeStretch` object.
+pub fn time_stretch<I>(input: I, ratio: f32) -> TimeStretch<I>
+where
+    I: Source,
+{
+    assert!(ratio > 0.0 && ratio.is_finite());
+    let mut stretch = TimeStretch {
+        channels: input.channels(),
+        sample_rate: input.sample_rate(),
+        layout: input.channel_layout(),
+        span_left: input.current_span_len(),
+        input,
+        ratio,
+        hop: 0,
+        tolerance: 0,
+        window: Vec::new(),
+        samples: Vec::new(),
+        mono: Vec::new(),
+        buffer_start: 0,
+        input_end: None,
+        format_changed: false,
+        analysis_pos: 0.0,
+        natural: 0,
+        restart: true,
+        overlap: Vec::new(),
+        output: Vec::new(),
+        output_pos: 0,
+        passthrough: ratio == 1.0,
+        frame_pos: 0,
+    };
+    stretch.configure();
+    stretch
+}
+
+/// Changes the tempo of a source without changing its pitch.
+///
+/// The ratio is the playback rate: `2.0` plays twice as fast and `0.5` at half the speed. At
+/// a ratio of exactly `1.0` the samples, spans and format of the input are passed through
+/// unchanged.
+#[derive(Clone, Debug)]
+pub struct TimeStretch<I> {
+    input: I,
+    ratio: f32,
+    /// Format of the part of the input that is being stretched.
+    channels: ChannelCount,
+    sample_rate: SampleRate,
+    layout: Option<ChannelLayout>,
+    /// Samples left in the current span of the input.
+    span_left: Option<usize>,
+    /// Frames output per chunk, also the length of the overlap between two chunks.
+    hop: usize,
+    /// Maximum distance in frames between the nominal and the actual position of a chunk.
+    tolerance: usize,
+    /// Hann window of two hops.
+    window: Vec<f32>,
+    /// Buffered input, interleaved. The first frame has index `buffer_start` in the input.
+    samples: Vec<Sample>,
+    /// Buffered input mixed down to mono, used to compare waveforms.
+    mono: Vec<f32>,
+    buffer_start: usize,
+    /// Index of the frame after the last one of the current format, once known.
+    input_end: Option<usize>,
+    /// `true` if the input continues with a different channel count, sample rate or layout.
+    format_changed: bool,
+    /// Nominal input position of the next chunk.
+    analysis_pos: f64,
+    /// Input position that continues the previous chunk seamlessly.
+    natural: usize,
+    /// `true` if the next chunk does not have to line up with a previous one.
+    restart: bool,
+    /// Windowed second half of the previous chunk, interleaved.
+    overlap: Vec<Sample>,
+    output: Vec<Sample>,
+    output_pos: usize,
+    /// `true` while the input is forwarded as is, at a ratio of `1.0`.
+    passthrough: bool,
+    /// Position within the current frame while passing through, stretching starts at the
+    /// next frame.
+    frame_pos: usize,
+}
+
+impl<I> TimeStretch<I>
+where
+    I: Source,
+{
+    /// Modifies the playback rate.
+    ///
+    /// # Panic
+    /// Panics if `ratio` is not a positive finite number.
+    #[inline]
+    pub fn set_ratio(&mut self, ratio: f32) {
+        assert!(ratio > 0.0 && ratio.is_finite());
+        self.ratio = ratio;
+    }
+
+    /// Returns the playback rate.
+    #[inline]
+    pub fn ratio(&self) -> f32 {
+        self.ratio
+    }
+
+    /// Returns a reference to the inner source.
+    #[inline]
+    pub fn inner(&self) -> &I {
+        &self.input
+    }
+
+    /// Returns a mutable reference to the inner source.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut I {
+        &mut self.input
+    }
+
+    /// Returns the inner source.
+    #[inline]
+    pub fn into_inner(self) -> I {
+        self.input
+    }
+
+    /// Starts stretching at the current position of the input.
+    fn start_stretching(&mut self) {
+        self.channels = self.input.channels();
+        self.sample_rate = self.input.sample_rate();
+        self.layout = self.input.channel_layout();
+        self.span_left = self.input.current_span_len();
+        self.output.clear();
+        self.output_pos = 0;
+        self.configure();
+        self.passthrough = false;
+    }
+
+    /// Sets up the chunk sizes for the current format and forgets about the input read so far.
+    fn configure(&mut self) {
+        let rate = self.sample_rate as usize;
+        self.hop = (rate * HOP_MS / 1000).max(COARSE_STEP);
+        self.tolerance = (rate * TOLERANCE_MS / 1000).max(COARSE_STEP);
+        let len = 2 * self.hop;
+        self.window.clear();
+        self.window.extend((0..len).map(|n| {
+            let phase = 2.0 * std::f32::consts::PI * n as f32 / len as f32;
+            0.5 - 0.5 * phase.cos()
+        }));
+        self.samples.clear();
+        self.mono.clear();
+        // Reserve the buffers up front for ratios up to about four, so that playing does not
+        // allocate.
+        let frames = 12 * self.hop + 2 * self.tolerance;
+        let channels = self.channels as usize;
+        self.samples.reserve(frames * channels);
+        self.mono.reserve(frames);
+        self.overlap.reserve(self.hop * channels);
+        self.output.reserve(self.hop * channels);
+        self.buffer_start = 0;
+        self.input_end = None;
+        self.format_changed = false;
+        self.analysis_pos = 0.0;
+        self.natural = 0;
+        self.restart = true;
+    }
+
+    /// Number of frames read from the input of the current format.
+    #[inline]
+    fn frames_read(&self) -> usize {
+        self.buffer_start + self.mono.len()
+    }
+
+    /// Reads one frame from the input. Returns `false` if the current format has no more
+    /// frames.
+    fn read_frame(&mut self) -> bool {
+        if self.span_left == Some(0) {
+            if self.input.channels() != self.channels
+                || self.input.sample_rate() != self.sample_rate
+                || self.input.channel_layout() != self.layout
+            {
+                self.format_changed = true;
+                return false;
+            }
+            self.span_left = self.input.current_span_len();
+        }
+
+        let channels = self.channels as usize;
+        let mut sum = 0.0;
+        for channel in 0..channels {
+            let sample = match self.input.next() {
+                Some(sample) => sample,
+                None if channel == 0 => return false,
+                // The input has ended in the middle of a frame, pad it with silence.
+                None => 0.0,
+            };
+            self.samples.push(sample);
+            sum += sample;
+        }
+        self.mono.push(sum / channels as f32);
+        if let Some(left) = self.span_left.as_mut() {
+            *left = left.saturating_sub(channels);
+        }
+        true
+    }
+
+    /// Reads the input until the frame at index `last` is buffered or the input has ended.
+    fn fill(&mut self, last: usize) {
+        while self.input_end.is_none() && self.frames_read() <= last {
+            if !self.read_frame() {
+                self.input_end = Some(self.frames_read());
+            }
+        }
+    }
+
+    /// Sample of the given channel in the frame at `index`, silence outside of the input.
+    #[inline]
+    fn sample(&self, index: usize, channel: usize) -> Sample {
+        let channels = self.channels as usize;
+        index
+            .checked_sub(self.buffer_start)
+            .and_then(|index| self.samples.get(index * channels + channel))
+            .copied()
+            .unwrap_or(0.0)
+    }
+
+    #[inline]
+    fn mono(&self, index: usize) -> f32 {
+        index
+            .checked_sub(self.buffer_start)
+            .and_then(|index| self.mono.get(index))
+            .copied()
+            .unwrap_or(0.0)
+    }
+
+    /// How well a chunk starting at `candidate` continues the previous chunk, comparing
+    /// every `stride`th frame.
+    fn similarity(&self, candidate: usize, stride: usize) -> f32 {
+        let (mut correlation, mut energy) = (0.0, 0.0);
+        for i in (0..self.hop).step_by(stride) {
+            let sample = self.mono(candidate + i);
+            correlation += sample * self.mono(self.natural + i);
+            energy += sample * sample;
+        }
+        correlation / (energy + 1e-9).sqrt()
+    }
+
+    /// Position within the tolerance around `nominal` that best continues the previous chunk.
+    fn best_position(&self, nominal: usize) -> usize {
+        let lowest = nominal
+            .saturating_sub(self.tolerance)
+            .max(self.buffer_start);
+        let mut best = (nominal, self.similarity(nominal, COARSE_STEP));
+        let consider = |candidate: usize, stride: usize, best: &mut (usize, f32)| {
+            let score = self.similarity(candidate, stride);
+            // Ties go to the candidate closest to the nominal position.
+            if score > best.1
+                || (score == best.1 && candidate.abs_diff(nominal) < best.0.abs_diff(nominal))
+            {
+                *best = (candidate, score);
+            }
+        };
+
+        let mut offset = COARSE_STEP;
+        while offset <= self.tolerance {
+            if nominal >= lowest + offset {
+                consider(nominal - offset, COARSE_STEP, &mut best);
+            }
+            consider(nominal + offset, COARSE_STEP, &mut best);
+            offset += COARSE_STEP;
+        }
+
+        let coarse = best.0;
+        best.1 = self.similarity(coarse, 1);
+        for candidate in coarse.saturating_sub(COARSE_STEP - 1)..coarse + COARSE_STEP {
+            if candidate != coarse && candidate >= lowest {
+                consider(candidate, 1, &mut best);
+            }
+        }
+        best.0
+    }
+
+    /// Computes the next chunk of output. Returns `false` if the input has been used up.
+    fn process_chunk(&mut self) -> bool {
+        self.output.clear();
+        self.output_pos = 0;
+        let channels = self.channels as usize;
+
+        loop {
+            let nominal = self.analysis_pos.round() as usize;
+            if self.ratio == 1.0 {
+                // Copy the input as is, continuing exactly where the previous chunk ends.
+                let start = if self.restart { nominal } else { self.natural };
+                self.fill(start + self.hop - 1);
+                let end = self.frames_read().min(start + self.hop);
+                for index in start..end {
+                    for channel in 0..channels {
+                        self.output.push(self.sample(index, channel));
+                    }
+                }
+                self.natural = end.max(start);
+                self.analysis_pos = self.natural as f64;
+                self.restart = true;
+            } else {
+                self.fill(nominal + self.tolerance + 2 * self.hop);
+                let frames = match self.input_end {
+                    Some(end) if self.analysis_pos >= end as f64 => 0,
+                    Some(end) => {
+                        let left = (end as f64 - self.analysis_pos) / self.ratio as f64;
+                        (left.ceil() as usize).min(self.hop)
+                    }
+                    None => self.hop,
+                };
+                if frames > 0 {
+                    let start = if self.restart {
+                        // Fade in from the input itself, the first chunk is output unchanged.
+                        self.overlap.clear();
+                        for i in 0..self.hop {
+                            for channel in 0..channels {
+                                let sample = self.sample(nominal + i, channel);
+                                self.overlap.push(sample * self.window[self.hop + i]);
+                            }
+                        }
+                        nominal
+                    } else {
+                        self.best_position(nominal)
+                    };
+                    for i in 0..frames {
+                        for channel in 0..channels {
+                            let sample = self.sample(start + i, channel);
+                            let overlap = self.overlap[i * channels + channel];
+                            self.output.push(overlap + sample * self.window[i]);
+                        }
+                    }
+                    for i in 0..self.hop {
+                        for channel in 0..channels {
+                            let sample = self.sample(start + self.hop + i, channel);
+                            self.overlap[i * channels + channel] =
+                                sample * self.window[self.hop + i];
+                        }
+                    }
+                    self.natural = start + self.hop;
+                    self.analysis_pos += self.hop as f64 * self.ratio as f64;
+                    self.restart = false;
+                }
+            }
+            self.discard_old_frames();
+
+            if !self.output.is_empty() {
+                return true;
+            }
+            if !self.format_changed {
+                return false;
+            }
+            // Continue with the rest of the input in its new format.
+            self.channels = self.input.channels();
+            self.sample_rate = self.input.sample_rate();
+            self.layout = self.input.channel_layout();
+            self.span_left = self.input.current_span_len();
+            self.configure();
+        }
+    }
+
+    /// Drops the buffered frames that no following chunk can use.
+    fn discard_old_frames(&mut self) {
+        let nominal = self.analysis_pos.round() as usize;
+        let keep_from = self.natural.min(nominal.saturating_sub(self.tolerance));
+        let discard = keep_from
+            .saturating_sub(self.buffer_start)
+            .min(self.mono.len());
+        // Only compact once in a while, draining is linear in the buffer size.
+        if discard >= 4 * self.hop {
+            self.samples.drain(..discard * self.channels as usize);
+            self.mono.drain(..discard);
+            self.buffer_start += discard;
+        }
+    }
+}
+
+impl<I> Iterator for TimeStretch<I>
+where
+    I: Source,
+{
+    type Item = Sample;
+
+    #[inline]
+    fn next(&mut self) -> Option<Sample> {
+        if self.passthrough {
+            if self.ratio == 1.0 || self.frame_pos != 0 {
+                let channels = self.input.channels() as usize;
+                let sample = self.input.next()?;
+                self.frame_pos = (self.frame_pos + 1) % channels;
+                return Some(sample);
+            }
+            self.start_stretching();
+        }
+
+        if self.output_pos == self.output.len() && !self.process_chunk() {
+            return None;
+        }
+        let sample = self.output[self.output_pos];
+        self.output_pos += 1;
+        if self.output_pos == self.output.len() {
+            if self.ratio == 1.0 && self.natural >= self.frames_read() {
+                // Everything read from the input has been output, forward it directly again.
+                self.output.clear();
+                self.output_pos = 0;
+                self.frame_pos = 0;
+                self.passthrough = true;
+            } else {
+                // Prepare the next chunk right away, so that the span length and format
+                // reported in between are those of the samples that follow.
+                self.process_chunk();
+            }
+        }
+        Some(sample)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.passthrough {
+            return self.input.size_hint();
+        }
+        (self.output.len() - self.output_pos, None)
+    }
+}
+
+impl<I> Source for TimeStretch<I>
+where
+    I: Source,
+{
+    #[inline]
+    fn current_span_len(&self) -> Option<usize> {
+        if self.passthrough {
+            return self.input.current_span_len();
+        }
+        // Every chunk is reported as a span, the format can change after any of them.
+        match self.output.len() - self.output_pos {
+            0 if self.output_pos > 0 => Some(0),
+            0 => Some(self.hop * self.channels as usize),
+            remaining => Some(remaining),
+        }
+    }
+
+    #[inline]
+    fn channels(&self) -> ChannelCount {
+        if self.passthrough {
+            return self.input.channels();
+        }
+        self.channels
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> SampleRate {
+        if self.passthrough {
+            return self.input.sample_rate();
+        }
+        self.sample_rate
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration().map(|d| d.div_f32(self.ratio))
+    }
+
+    #[inline]
+    fn channel_layout(&self) -> Option<ChannelLayout> {
+        if self.passthrough {
+            return self.input.channel_layout();
+        }
+        self.layout
+    }
+
+    #[inline]
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos.mul_f32(self.ratio))?;
+        if self.ratio == 1.0 {
+            self.output.clear();
+            self.output_pos = 0;
+            self.frame_pos = 0;
+            self.passthrough = true;
+        } else {
+            self.start_stretching();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::buffer::SamplesBuffer;
+    use crate::source::{SineWave, Source};
+    use crate::Sample;
+
+    /// Counts the times the signal goes from negative to positive.
+    fn rising_zero_crossings(samples: &[Sample]) -> usize {
+        samples
+            .windows(2)
+            .filter(|pair| pair[0] < 0.0 && pair[1] >= 0.0)
+            .count()
+    }
+
+    #[test]
+    fn unity_ratio_passes_samples_through() {
+        let samples: Vec<Sample> = (0..10_001).map(|i| (i % 97) as Sample / 97.0).collect();
+        let source = SamplesBuffer::new(1, 8000, samples.clone());
+        let output: Vec<Sample> = source.time_stretch(1.0).collect();
+        assert_eq!(output, samples);
+    }
+
+    #[test]
+    fn returns_to_passing_through_at_unity_ratio() {
+        let samples: Vec<Sample> = (0..48000).map(|i| i as Sample).collect();
+        let mut source = SamplesBuffer::new(1, 8000, samples).time_stretch(1.0);
+        assert_eq!(source.by_ref().take(100).count(), 100);
+        assert_eq!(source.current_span_len(), None);
+
+        source.set_ratio(2.0);
+        assert_eq!(source.by_ref().take(1000).count(), 1000);
+        assert!(source.current_span_len().is_some());
+
+        source.set_ratio(1.0);
+        assert_eq!(source.by_ref().take(1000).count(), 1000);
+        assert_eq!(source.current_span_len(), None);
+        let rest: Vec<Sample> = source.collect();
+        assert!(rest.windows(2).all(|pair| pair[1] - pair[0] == 1.0));
+        assert_eq!(rest.last(), Some(&47999.0));
+    }
+
+    #[test]
+    fn changes_duration_but_not_pitch() {
+        for ratio in [0.5, 0.8, 1.25, 2.0, 3.0] {
+            let samples: Vec<Sample> = SineWave::new(440.0)
+                .take_duration(Duration::from_secs(2))
+                .collect();
+            let input_len = samples.len();
+            let source = SamplesBuffer::new(1, 48000, samples);
+            let duration = source.total_duration().unwrap();
+            let stretched = source.time_stretch(ratio);
+            assert_eq!(stretched.total_duration(), Some(duration.div_f32(ratio)));
+            let output: Vec<Sample> = stretched.collect();
+
+            let expected_len = (input_len as f32 / ratio) as usize;
+            assert!(output.len().abs_diff(expected_len) <= 2, "{ratio}");
+
+            // Half a second of output in the middle, away from the edges.
+            let middle = &output[output.len() / 2 - 12000..output.len() / 2 + 12000];
+            let crossings = rising_zero_crossings(middle);
+            assert!(crossings.abs_diff(220) <= 2, "{ratio}: {crossings}");
+
+            // The chunks line up, so there are no phase jumps.
+            for pair in output[..output.len() - 1000].windows(2) {
+                let diff = (pair[1] - pair[0]).abs();
+                assert!(diff < 0.07, "{ratio}: jump of {diff}");
+            }
+        }
+    }
+
+    #[test]
+    fn keeps_channels_apart() {
+        let samples: Vec<Sample> = (0..48000).flat_map(|_| [0.5, -0.25]).collect();
+        let output: Vec<Sample> = SamplesBuffer::new(2, 48000, samples)
+            .time_stretch(1.5)
+            .collect();
+        assert_eq!(output.len() % 2, 0);
+        // Skip the fade out at the end.
+        for frame in output[..output.len() - 2000].chunks(2) {
+            assert!((frame[0] - 0.5).abs() < 1e-4, "{frame:?}");
+            assert!((frame[1] + 0.25).abs() < 1e-4, "{frame:?}");
+        }
+    }
+
+    #[test]
+    fn seek_accounts_for_ratio() {
+        let samples: Vec<Sample> = (0..48000).map(|i| i as Sample).collect();
+        let mut source = SamplesBuffer::new(1, 1000, samples).time_stretch(2.0);
+        source.try_seek(Duration::from_secs(5)).unwrap();
+        let sample = source.next().unwrap();
+        assert!((sample - 10_000.0).abs() < 0.01, "{sample}");
+    }
+}
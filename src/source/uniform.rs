@@ -3,25 +3,42 @@ use std::time::Duration;
 
 use super::SeekError;
 use crate::common::{ChannelCount, SampleRate};
-use crate::conversions::{ChannelCountConverter, SampleRateConverter};
-use crate::Source;
+use crate::conversions::{
+    ChannelCountConverter, ChannelLayout, ChannelMatrix, ResampleQuality, Resampler,
+};
+use crate::{Sample, Source};
 
 /// An iterator that reads from a `Source` and converts the samples to a
 /// specific type, sample-rate and channels count.
 ///
 /// It implements `Source` as well, but all the data is guaranteed to be in a
 /// single span whose channels and samples rate have been passed to `new`.
+///
+/// A new sample rate at a span boundary applies from the first sample of the span on.
+/// Changes in the middle of a span, like with [`Speed`](crate::source::Speed), are followed
+/// smoothly.
+///
+/// Channels are mixed with the standard matrix for the [layout](Source::channel_layout) of
+/// the input and the common layout of the target channel count, see
+/// [`ChannelMatrix::between_layouts`]. Use
+/// [`with_channel_matrix`](UniformSourceIterator::with_channel_matrix) to supply your own.
 #[derive(Clone)]
 pub struct UniformSourceIterator<I>
 where
     I: Source,
 {
-    inner: Option<ChannelCountConverter<SampleRateConverter<Take<I>>>>,
+    inner: Option<ChannelCountConverter<Resampler<Take<I>>>>,
     target_channels: ChannelCount,
     target_sample_rate: SampleRate,
+    quality: ResampleQuality,
+    channel_matrix: Option<ChannelMatrix>,
     total_duration: Option<Duration>,
+    samples_until_rate_check: usize,
 }
 
+/// Number of output samples between two checks of the input sample rate within a span.
+const RATE_CHECK_INTERVAL: usize = 64;
+
 impl<I> UniformSourceIterator<I>
 where
     I: Source,
@@ -33,37 +50,139 @@ where
         input: I,
         target_channels: ChannelCount,
         target_sample_rate: SampleRate,
+    ) -> UniformSourceIterator<I> {
+        Self::with_quality(
+            input,
+            target_channels,
+            target_sample_rate,
+            ResampleQuality::default(),
+        )
+    }
+
+    /// Same as [`new`](UniformSourceIterator::new) but lets you choose the trade-off
+    /// between resampling quality and CPU usage.
+    #[inline]
+    pub fn with_quality(
+        input: I,
+        target_channels: ChannelCount,
+        target_sample_rate: SampleRate,
+        quality: ResampleQuality,
     ) -> UniformSourceIterator<I> {
         let total_duration = input.total_duration();
-        let input = UniformSourceIterator::bootstrap(input, target_channels, target_sample_rate);
+        let input = UniformSourceIterator::bootstrap(
+            input,
+            None,
+            target_channels,
+            target_sample_rate,
+            quality,
+            None,
+        );
 
         UniformSourceIterator {
             inner: Some(input),
             target_channels,
             target_sample_rate,
+            quality,
+            channel_matrix: None,
             total_duration,
+            samples_until_rate_check: RATE_CHECK_INTERVAL,
         }
     }
 
+    /// Mixes the channels with `matrix` whenever the input has as many channels as the
+    /// matrix has inputs. Input with a different channel count is still mixed with the
+    /// standard matrix.
+    ///
+    /// # Panic
+    /// Panics if the matrix does not have the target channel count as its output.
+    pub fn with_channel_matrix(mut self, matrix: ChannelMatrix) -> UniformSourceIterator<I> {
+        assert_eq!(matrix.to_channels(), self.target_channels);
+        let resampler = self.inner.take().unwrap().into_inner();
+        let take = resampler.inner();
+        let from = ChannelLayout::resolve(take.layout, take.channels);
+        self.channel_matrix = Some(matrix);
+        self.inner = Some(ChannelCountConverter::with_matrix(
+            resampler,
+            mix_matrix(self.channel_matrix.as_ref(), from, self.target_channels),
+        ));
+        self
+    }
+
+    /// Returns a reference to the inner source.
+    #[inline]
+    pub fn inner(&self) -> &I {
+        &self.inner.as_ref().unwrap().inner().inner().iter
+    }
+
+    /// Returns a mutable reference to the inner source.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut I {
+        &mut self.inner.as_mut().unwrap().inner_mut().inner_mut().iter
+    }
+
     #[inline]
     fn bootstrap(
         input: I,
+        pending: Option<Sample>,
         target_channels: ChannelCount,
         target_sample_rate: SampleRate,
-    ) -> ChannelCountConverter<SampleRateConverter<Take<I>>> {
-        // Limit the span length to something reasonable
-        let span_len = input.current_span_len().map(|x| x.min(32768));
-
+        quality: ResampleQuality,
+        channel_matrix: Option<&ChannelMatrix>,
+    ) -> ChannelCountConverter<Resampler<Take<I>>> {
         let from_channels = input.channels();
         let from_sample_rate = input.sample_rate();
+        let from_layout = input.channel_layout();
 
         let input = Take {
+            n: span_len(&input),
+            exhausted: false,
+            pending,
             iter: input,
-            n: span_len,
+            channels: from_channels,
+            layout: from_layout,
+            sample_rate: from_sample_rate,
+            rate_change: None,
         };
-        let input =
-            SampleRateConverter::new(input, from_sample_rate, target_sample_rate, from_channels);
-        ChannelCountConverter::new(input, from_channels, target_channels)
+        let input = Resampler::with_quality(
+            input,
+            from_sample_rate,
+            target_sample_rate,
+            from_channels,
+            quality,
+        );
+        let from = ChannelLayout::resolve(from_layout, from_channels);
+        let matrix = mix_matrix(channel_matrix, from, target_channels);
+        ChannelCountConverter::with_matrix(input, matrix)
+    }
+
+    /// Switches the resampler to the sample rate of a span that has started.
+    #[inline]
+    fn follow_span_sample_rate(&mut self) {
+        let resampler = self.inner.as_mut().unwrap().inner_mut();
+        if let Some((sample_rate, read)) = resampler.inner_mut().rate_change.take() {
+            let frames = read.div_ceil(resampler.inner().channels as usize);
+            resampler.switch_ratio(sample_rate as f64 / self.target_sample_rate as f64, frames);
+        }
+    }
+
+    /// Adjusts the resampler if the sample rate of the input has changed within a span.
+    fn follow_sample_rate(&mut self) {
+        let Some(inner) = self.inner.as_mut() else {
+            return;
+        };
+        let resampler = inner.inner_mut();
+        let take = resampler.inner_mut();
+        let sample_rate = take.iter.sample_rate();
+        // At the end of a span the input already reports the format of the next one, which
+        // `Take` applies once it gets there.
+        if take.n != Some(0)
+            && sample_rate != take.sample_rate
+            && sample_rate != 0
+            && take.iter.channels() == take.channels
+        {
+            take.sample_rate = sample_rate;
+            resampler.set_ratio(sample_rate as f64 / self.target_sample_rate as f64);
+        }
     }
 }
 
@@ -75,14 +194,30 @@ where
 
     #[inline]
     fn next(&mut self) -> Option<Self::Item> {
+        self.samples_until_rate_check -= 1;
+        if self.samples_until_rate_check == 0 {
+            self.samples_until_rate_check = RATE_CHECK_INTERVAL;
+            self.follow_sample_rate();
+        }
+
         if let Some(value) = self.inner.as_mut().unwrap().next() {
+            self.follow_span_sample_rate();
             return Some(value);
         }
 
-        let input = self.inner.take().unwrap().into_inner().into_inner().iter;
+        if self.inner.as_ref().unwrap().inner().inner().exhausted {
+            return None;
+        }
+        let take = self.inner.take().unwrap().into_inner().into_inner();
 
-        let mut input =
-            UniformSourceIterator::bootstrap(input, self.target_channels, self.target_sample_rate);
+        let mut input = UniformSourceIterator::bootstrap(
+            take.iter,
+            take.pending,
+            self.target_channels,
+            self.target_sample_rate,
+            self.quality,
+            self.channel_matrix.as_ref(),
+        );
 
         let value = input.next();
         self.inner = Some(input);
@@ -119,6 +254,18 @@ where
         self.total_duration
     }
 
+    #[inline]
+    fn channel_layout(&self) -> Option<ChannelLayout> {
+        // The layout survives only if the channels are passed through unchanged.
+        let take = self.inner.as_ref().unwrap().inner().inner();
+        let passthrough = take.channels == self.target_channels
+            && self
+                .channel_matrix
+                .as_ref()
+                .is_none_or(|matrix| matrix.from_channels() != take.channels);
+        take.layout.filter(|_| passthrough)
+    }
+
     #[inline]
     fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
         if let Some(input) = self.inner.as_mut() {
@@ -129,56 +276,220 @@ where
     }
 }
 
+/// The user supplied matrix if it fits, the standard one otherwise.
+fn mix_matrix(
+    user: Option<&ChannelMatrix>,
+    from: ChannelLayout,
+    to: ChannelCount,
+) -> ChannelMatrix {
+    match user {
+        Some(matrix) if matrix.from_channels() == from.channel_count() => matrix.clone(),
+        _ => ChannelMatrix::between_layouts(from, ChannelLayout::resolve(None, to)),
+    }
+}
+
+/// Limit the span length to something reasonable
+#[inline]
+fn span_len<I: Source>(input: &I) -> Option<usize> {
+    input.current_span_len().map(|x| x.min(32768))
+}
+
+/// Yields the samples of the input for as long as its channel count and layout stay the same.
 #[derive(Clone, Debug)]
 struct Take<I> {
     iter: I,
     n: Option<usize>,
+    /// Set once the input has ended, there is nothing left to convert then.
+    exhausted: bool,
+    /// First sample of a new format, pulled before the format change could be noticed.
+    pending: Option<Sample>,
+    channels: ChannelCount,
+    layout: Option<ChannelLayout>,
+    /// Sample rate the resampler is currently set to.
+    sample_rate: SampleRate,
+    /// Sample rate of a span that has started together with the number of samples read
+    /// since, until the resampler has been switched to it.
+    rate_change: Option<(SampleRate, usize)>,
 }
 
-impl<I> Take<I> {
+impl<I> Take<I>
+where
+    I: Source,
+{
     #[inline]
     pub fn inner_mut(&mut self) -> &mut I {
         &mut self.iter
     }
+
+    /// Checks the sample rate of the span that starts with the next sample.
+    #[inline]
+    fn start_span(&mut self) {
+        let sample_rate = self.iter.sample_rate();
+        if sample_rate != self.sample_rate && sample_rate != 0 {
+            self.sample_rate = sample_rate;
+            self.rate_change = Some((sample_rate, 0));
+        }
+    }
+
+    fn next_sample(&mut self) -> Option<Sample> {
+        if let Some(sample) = self.pending.take() {
+            return Some(sample);
+        }
+        if self.n == Some(0) {
+            // Keep going if the next span has the same channels, so that the resampler does
+            // not have to start over at every span boundary. A different sample rate is
+            // handled by switching the resampler.
+            if self.iter.channels() != self.channels || self.iter.channel_layout() != self.layout {
+                return None;
+            }
+            self.n = span_len(&self.iter);
+            if self.n == Some(0) {
+                // Usually the end of the input, which does not need a new converter.
+                let sample = self.iter.next();
+                self.exhausted = sample.is_none();
+                if self.iter.channels() != self.channels
+                    || self.iter.channel_layout() != self.layout
+                {
+                    // Leave the sample to the converter of the new format.
+                    self.pending = sample;
+                    return None;
+                }
+                // The input only reports the format of the sample after it has been read.
+                self.start_span();
+                self.n = span_len(&self.iter);
+                return sample;
+            }
+            self.start_span();
+        }
+        if let Some(n) = &mut self.n {
+            *n -= 1;
+        }
+        let sample = self.iter.next();
+        self.exhausted = sample.is_none();
+        sample
+    }
 }
 
 impl<I> Iterator for Take<I>
 where
-    I: Iterator,
+    I: Source,
 {
     type Item = <I as Iterator>::Item;
 
     #[inline]
     fn next(&mut self) -> Option<<I as Iterator>::Item> {
-        if let Some(n) = &mut self.n {
-            if *n != 0 {
-                *n -= 1;
-                self.iter.next()
-            } else {
-                None
-            }
-        } else {
-            self.iter.next()
+        let sample = self.next_sample();
+        if let Some((_, read)) = self.rate_change.as_mut().filter(|_| sample.is_some()) {
+            *read += 1;
         }
+        sample
     }
 
     #[inline]
     fn size_hint(&self) -> (usize, Option<usize>) {
-        if let Some(n) = self.n {
-            let (lower, upper) = self.iter.size_hint();
+        let (lower, upper) = self.iter.size_hint();
+        match self.n {
+            // Following spans might have the same channel count, so only the lower bound is limited.
+            Some(n) => (cmp::min(lower, n), upper),
+            None => (lower, upper),
+        }
+    }
+}
 
-            let lower = cmp::min(lower, n);
+impl<I> ExactSizeIterator for Take<I> where I: Source + ExactSizeIterator {}
 
-            let upper = match upper {
-                Some(x) if x < n => Some(x),
-                _ => Some(n),
-            };
+#[cfg(test)]
+mod tests {
+    use crate::buffer::SamplesBuffer;
+    use crate::conversions::ChannelMatrix;
+    use crate::source::{from_iter, SineWave, Source, UniformSourceIterator};
+    use crate::Sample;
+    use std::time::Duration;
 
-            (lower, upper)
-        } else {
-            self.iter.size_hint()
+    #[test]
+    fn resampling_continues_across_spans() {
+        let samples: Vec<f32> = SineWave::new(440.0)
+            .take_duration(Duration::from_millis(100))
+            .collect();
+        let whole = SamplesBuffer::new(1, 48000, samples.clone());
+        let split = from_iter(
+            samples
+                .chunks(100)
+                .map(|chunk| SamplesBuffer::new(1, 48000, chunk.to_vec()))
+                .collect::<Vec<_>>(),
+        );
+
+        let expected: Vec<f32> = UniformSourceIterator::new(whole, 1, 44100).collect();
+        let actual: Vec<f32> = UniformSourceIterator::new(split, 1, 44100).collect();
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(&expected) {
+            assert!((a - e).abs() < 1e-6, "{a} != {e}");
         }
     }
-}
 
-impl<I> ExactSizeIterator for Take<I> where I: ExactSizeIterator {}
+    /// Counts the times the signal goes from negative to positive.
+    fn rising_zero_crossings(samples: &[Sample]) -> usize {
+        samples
+            .windows(2)
+            .filter(|pair| pair[0] < 0.0 && pair[1] >= 0.0)
+            .count()
+    }
+
+    #[test]
+    fn follows_sample_rate_changes_within_a_span() {
+        // The sine wave is one endless span, the new speed is picked up right away anyway.
+        let source = SineWave::new(440.0)
+            .speed(1.0)
+            .periodic_access(Duration::from_secs(10), |speed| speed.set_factor(2.0));
+        let output: Vec<Sample> = UniformSourceIterator::new(source, 1, 48000)
+            .skip(4800)
+            .take(48000)
+            .collect();
+        let crossings = rising_zero_crossings(&output);
+        assert!(crossings.abs_diff(880) <= 1, "{crossings}");
+    }
+
+    /// Samples `range` of a 1 kHz sine at the given sample rate.
+    fn tone(sample_rate: u32, range: std::ops::Range<usize>) -> Vec<Sample> {
+        range
+            .map(|n| (2.0 * std::f32::consts::PI * 1000.0 * n as f32 / sample_rate as f32).sin())
+            .collect()
+    }
+
+    #[test]
+    fn switches_sample_rate_at_span_boundary() {
+        // 100 ms at 48 kHz, continued for another 100 ms at 24 kHz.
+        let (tx, rx) = crate::queue::queue(false);
+        tx.append(SamplesBuffer::new(1, 48000, tone(48000, 0..4800)));
+        tx.append(SamplesBuffer::new(1, 24000, tone(24000, 2400..4800)));
+        let output: Vec<Sample> = UniformSourceIterator::new(rx, 1, 48000).collect();
+        assert!(output.len().abs_diff(9600) <= 1, "{}", output.len());
+
+        let expected = tone(48000, 0..9600);
+        // The filter is affected by the change of sample rate close to the boundary.
+        for n in (0..4700).chain(4900..9400) {
+            let diff = (output[n] - expected[n]).abs();
+            assert!(diff < 0.01, "{diff} at {n}");
+        }
+    }
+
+    #[test]
+    fn mixes_with_custom_matrix() {
+        let source = SamplesBuffer::new(2, 48000, vec![1.0, 2.0, 3.0, 4.0]);
+        let matrix = ChannelMatrix::new(2, 1, vec![1.0, -1.0]);
+        let output: Vec<Sample> = UniformSourceIterator::new(source, 1, 48000)
+            .with_channel_matrix(matrix)
+            .collect();
+        assert_eq!(output, [-1.0, -1.0]);
+    }
+
+    #[test]
+    fn converts_first_sound_of_empty_queue() {
+        // Built while the queue is still empty and reports the format of `Empty`.
+        let (tx, rx) = crate::queue::queue(false);
+        let output = UniformSourceIterator::new(rx, 1, 48000);
+        tx.append(SamplesBuffer::new(2, 48000, vec![1.0, 3.0, 2.0, 4.0]));
+        let output: Vec<Sample> = output.collect();
+        assert_eq!(output, [2.0, 3.0]);
+    }
+}
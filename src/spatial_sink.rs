@@ -1,10 +1,11 @@
 use std::f32;
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 use std::time::Duration;
 
 use dasp_sample::FromSample;
 
 use crate::mixer::Mixer;
+use crate::realtime::AtomicF32;
 use crate::source::{SeekError, Spatial};
 use crate::{Sink, Source};
 
@@ -13,13 +14,32 @@ use crate::{Sink, Source};
 /// spatial effect. See [`Spatial`] for details.
 pub struct SpatialSink {
     sink: Sink,
-    positions: Arc<Mutex<SoundPositions>>,
+    positions: Arc<SoundPositions>,
 }
 
+// Read by the sounds on the audio thread, which never waits for them.
 struct SoundPositions {
-    emitter_position: [f32; 3],
-    left_ear: [f32; 3],
-    right_ear: [f32; 3],
+    emitter_position: AtomicPosition,
+    left_ear: AtomicPosition,
+    right_ear: AtomicPosition,
+}
+
+struct AtomicPosition([AtomicF32; 3]);
+
+impl AtomicPosition {
+    fn new(pos: [f32; 3]) -> AtomicPosition {
+        AtomicPosition(pos.map(AtomicF32::new))
+    }
+
+    fn load(&self) -> [f32; 3] {
+        [self.0[0].load(), self.0[1].load(), self.0[2].load()]
+    }
+
+    fn store(&self, pos: [f32; 3]) {
+        for (coordinate, value) in self.0.iter().zip(pos) {
+            coordinate.store(value);
+        }
+    }
 }
 
 impl SpatialSink {
@@ -32,27 +52,27 @@ impl SpatialSink {
     ) -> SpatialSink {
         SpatialSink {
             sink: Sink::connect_new(mixer),
-            positions: Arc::new(Mutex::new(SoundPositions {
-                emitter_position,
-                left_ear,
-                right_ear,
-            })),
+            positions: Arc::new(SoundPositions {
+                emitter_position: AtomicPosition::new(emitter_position),
+                left_ear: AtomicPosition::new(left_ear),
+                right_ear: AtomicPosition::new(right_ear),
+            }),
         }
     }
 
     /// Sets the position of the sound emitter in 3 dimensional space.
     pub fn set_emitter_position(&self, pos: [f32; 3]) {
-        self.positions.lock().unwrap().emitter_position = pos;
+        self.positions.emitter_position.store(pos);
     }
 
     /// Sets the position of the left ear in 3 dimensional space.
     pub fn set_left_ear_position(&self, pos: [f32; 3]) {
-        self.positions.lock().unwrap().left_ear = pos;
+        self.positions.left_ear.store(pos);
     }
 
     /// Sets the position of the right ear in 3 dimensional space.
     pub fn set_right_ear_position(&self, pos: [f32; 3]) {
-        self.positions.lock().unwrap().right_ear = pos;
+        self.positions.right_ear.store(pos);
     }
 
     /// Appends a sound to the queue of sounds to play.
@@ -63,16 +83,18 @@ impl SpatialSink {
         f32: FromSample<S::Item>,
     {
         let positions = self.positions.clone();
-        let pos_lock = self.positions.lock().unwrap();
         let source = Spatial::new(
             source,
-            pos_lock.emitter_position,
-            pos_lock.left_ear,
-            pos_lock.right_ear,
+            positions.emitter_position.load(),
+            positions.left_ear.load(),
+            positions.right_ear.load(),
         )
         .periodic_access(Duration::from_millis(10), move |i| {
-            let pos = positions.lock().unwrap();
-            i.set_positions(pos.emitter_position, pos.left_ear, pos.right_ear);
+            i.set_positions(
+                positions.emitter_position.load(),
+                positions.left_ear.load(),
+                positions.right_ear.load(),
+            );
         });
         self.sink.append(source);
     }
@@ -123,6 +145,25 @@ impl SpatialSink {
         self.sink.set_speed(value)
     }
 
+    /// Gets the tempo of the sound.
+    ///
+    /// See [`Sink::tempo`] for details.
+    #[inline]
+    pub fn tempo(&self) -> f32 {
+        self.sink.tempo()
+    }
+
+    /// Changes the tempo of the sound without changing its pitch.
+    ///
+    /// See [`Sink::set_tempo`] for details.
+    ///
+    /// # Panic
+    /// Panics if `value` is not a positive finite number.
+    #[inline]
+    pub fn set_tempo(&self, value: f32) {
+        self.sink.set_tempo(value)
+    }
+
     /// Resumes playback of a paused sound.
     ///
     /// No effect if not paused.
@@ -3,19 +3,35 @@ use crate::decoder;
 use crate::mixer::{mixer, Mixer, MixerSource};
 use crate::sink::Sink;
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use cpal::{BufferSize, FrameCount, Sample, SampleFormat, StreamConfig, SupportedBufferSize};
+use cpal::{
+    BufferSize, FrameCount, OutputCallbackInfo, OutputStreamTimestamp, Sample, SampleFormat,
+    StreamConfig, SupportedBufferSize,
+};
 use std::io::{Read, Seek};
 use std::marker::Sync;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 use std::{error, fmt};
 
-const HZ_44100: SampleRate = 44_100;
+pub(crate) const HZ_44100: SampleRate = 44_100;
 
 /// `cpal::Stream` container.
 /// Use `mixer()` method to control output.
 /// If this is dropped, playback will end, and the associated output stream will be disposed.
 pub struct OutputStream {
     mixer: Mixer,
-    _stream: cpal::Stream,
+    clock: Arc<PlaybackClock>,
+    captured: Option<Arc<Mutex<Vec<f32>>>>,
+    _stream: Backend,
+}
+
+/// What pulls the samples out of the mixer. Only kept to be dropped with the stream.
+#[allow(dead_code)]
+enum Backend {
+    Device(cpal::Stream),
+    Null(NullStream),
 }
 
 impl OutputStream {
@@ -23,8 +39,227 @@ impl OutputStream {
     pub fn mixer(&self) -> &Mixer {
         &self.mixer
     }
+
+    /// Returns the number of frames handed to the device so far.
+    ///
+    /// The counter only goes up. It includes the silence played when nothing was added to the
+    /// mixer, and the frames of the last buffer that have not been heard yet, see
+    /// [`OutputStream::latency`].
+    pub fn frames_played(&self) -> u64 {
+        self.clock.frames_played()
+    }
+
+    /// Returns the timestamps the device passed with the last buffer it asked for.
+    ///
+    /// `callback` is when the buffer was requested and `playback` when its first frame is
+    /// expected to be heard, both on the clock of the device. `None` before the first buffer.
+    pub fn last_timestamp(&self) -> Option<OutputStreamTimestamp> {
+        self.clock.last_timestamp()
+    }
+
+    /// Returns the estimated time between writing a frame and hearing it.
+    ///
+    /// This is the difference between the timestamps of [`OutputStream::last_timestamp`].
+    /// `None` before the first buffer or when the device does not report a playback time.
+    pub fn latency(&self) -> Option<Duration> {
+        self.clock.latency()
+    }
+
+    /// Returns the samples played since the last call, for a null stream opened with
+    /// [`NullStreamConfig::with_capture`]. Always empty for other streams.
+    pub fn take_captured(&self) -> Vec<f32> {
+        match &self.captured {
+            Some(captured) => std::mem::take(&mut *captured.lock().unwrap()),
+            None => Vec::new(),
+        }
+    }
+}
+
+/// Timestamps of a buffer, as the device reports them. Tests make up their own, those of
+/// cpal can only come from a device.
+trait BufferTimestamp: Copy {
+    /// Time between requesting the buffer and hearing its first frame.
+    fn latency(&self) -> Option<Duration>;
+}
+
+impl BufferTimestamp for OutputStreamTimestamp {
+    fn latency(&self) -> Option<Duration> {
+        self.playback.duration_since(&self.callback)
+    }
+}
+
+/// Playback progress, written by the data callback.
+struct PlaybackClock<T = OutputStreamTimestamp> {
+    channels: ChannelCount,
+    frames_played: AtomicU64,
+    timestamp: Mutex<Option<T>>,
+}
+
+impl<T> PlaybackClock<T>
+where
+    T: BufferTimestamp,
+{
+    fn new(channels: ChannelCount) -> Self {
+        Self {
+            channels,
+            frames_played: AtomicU64::new(0),
+            timestamp: Mutex::new(None),
+        }
+    }
+
+    /// Records a buffer of `len` samples. Never blocks the audio thread: when a reader holds
+    /// the lock the timestamp is updated with the next buffer instead.
+    fn record_timestamp(&self, len: usize, timestamp: T) {
+        if let Ok(mut last) = self.timestamp.try_lock() {
+            *last = Some(timestamp);
+        }
+        self.add_samples(len);
+    }
+
+    fn add_samples(&self, len: usize) {
+        let frames = (len / self.channels as usize) as u64;
+        self.frames_played.fetch_add(frames, Ordering::Relaxed);
+    }
+
+    fn frames_played(&self) -> u64 {
+        self.frames_played.load(Ordering::Relaxed)
+    }
+
+    fn last_timestamp(&self) -> Option<T> {
+        *self.timestamp.lock().unwrap()
+    }
+
+    fn latency(&self) -> Option<Duration> {
+        self.last_timestamp()?.latency()
+    }
+}
+
+impl PlaybackClock {
+    fn record(&self, len: usize, info: &OutputCallbackInfo) {
+        self.record_timestamp(len, info.timestamp());
+    }
+}
+
+/// Options of a stream opened with [`OutputStreamBuilder::open_null_stream`].
+#[derive(Copy, Clone, Debug)]
+pub struct NullStreamConfig {
+    speed: f32,
+    capture: bool,
+}
+
+impl Default for NullStreamConfig {
+    fn default() -> Self {
+        Self {
+            speed: 1.0,
+            capture: false,
+        }
+    }
+}
+
+impl NullStreamConfig {
+    /// Sets how fast the samples are pulled compared to real time. The default is `1.0`.
+    ///
+    /// A speed that the machine can not keep up with pulls the samples as fast as possible,
+    /// which keeps one core busy while the stream is open.
+    ///
+    /// # Panic
+    /// Panics if `speed` is not a finite number of at least `0.001`.
+    pub fn with_speed(mut self, speed: f32) -> NullStreamConfig {
+        assert!(
+            speed.is_finite() && speed >= MIN_NULL_STREAM_SPEED,
+            "null stream speed must be finite and at least {MIN_NULL_STREAM_SPEED}, got {speed}"
+        );
+        self.speed = speed;
+        self
+    }
+
+    /// Keeps the played samples for [`OutputStream::take_captured`] instead of discarding them.
+    ///
+    /// The samples are kept until they are taken, including the silence played when nothing
+    /// was added to the mixer.
+    pub fn with_capture(mut self, capture: bool) -> NullStreamConfig {
+        self.capture = capture;
+        self
+    }
+}
+
+/// A thread that plays the mixer to nowhere.
+struct NullStream {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl NullStream {
+    fn spawn(
+        config: &OutputStreamConfig,
+        null_config: NullStreamConfig,
+        mut samples: MixerSource,
+        clock: Arc<PlaybackClock>,
+        captured: Option<Arc<Mutex<Vec<f32>>>>,
+    ) -> NullStream {
+        let stop = Arc::new(AtomicBool::new(false));
+        let block_frames = match config.buffer_size {
+            BufferSize::Fixed(frames) => frames as usize,
+            BufferSize::Default => NULL_STREAM_BUFFER_FRAMES,
+        };
+        let block_len = block_frames * config.channel_count as usize;
+        let block_duration =
+            Duration::from_secs_f64(block_frames as f64 / config.sample_rate as f64);
+        let pace = block_duration.div_f64(null_config.speed as f64);
+
+        let thread = {
+            let stop = stop.clone();
+            thread::Builder::new()
+                .name("rodio null output".to_owned())
+                .spawn(move || {
+                    let mut block = vec![0f32; block_len];
+                    let mut deadline = Instant::now();
+                    while !stop.load(Ordering::Relaxed) {
+                        block
+                            .iter_mut()
+                            .for_each(|d| *d = samples.next().unwrap_or(0f32));
+                        clock.add_samples(block.len());
+                        if let Some(captured) = &captured {
+                            captured.lock().unwrap().extend_from_slice(&block);
+                        }
+
+                        deadline += pace;
+                        let now = Instant::now();
+                        if deadline > now {
+                            thread::sleep(deadline - now);
+                        } else {
+                            // Behind, let other threads run before the next block.
+                            deadline = now;
+                            thread::yield_now();
+                        }
+                    }
+                })
+                .expect("spawn null output thread")
+        };
+
+        NullStream {
+            stop,
+            thread: Some(thread),
+        }
+    }
 }
 
+impl Drop for NullStream {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Block size of a null stream when the buffer size is not fixed.
+const NULL_STREAM_BUFFER_FRAMES: usize = 512;
+
+/// Slowest speed of a null stream, which plays a block of the default size at 44.1 kHz in
+/// about 12 seconds.
+const MIN_NULL_STREAM_SPEED: f32 = 0.001;
+
 #[derive(Copy, Clone, Debug)]
 struct OutputStreamConfig {
     channel_count: ChannelCount,
@@ -155,6 +390,28 @@ impl OutputStreamBuilder {
         OutputStream::open(device, &self.config)
     }
 
+    /// Open an output stream that plays to no device, using the channels, sample rate and
+    /// buffer size configured so far.
+    ///
+    /// A thread pulls the samples from the mixer in blocks of the buffer size and throws them
+    /// away, or keeps them when capturing. This works without any audio hardware, for example
+    /// on a headless server or in tests.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rodio::{NullStreamConfig, OutputStreamBuilder};
+    ///
+    /// // Keep running without sound when there is no audio device.
+    /// let stream = OutputStreamBuilder::open_default_stream().unwrap_or_else(|_| {
+    ///     OutputStreamBuilder::default().open_null_stream(NullStreamConfig::default())
+    /// });
+    /// let sink = rodio::Sink::connect_new(stream.mixer());
+    /// ```
+    pub fn open_null_stream(&self, null_config: NullStreamConfig) -> OutputStream {
+        OutputStream::open_null(&self.config, null_config)
+    }
+
     /// Try opening a new output stream with the builder's current stream configuration.
     /// Failing that attempt to open stream with other available configurations
     /// supported by the device.
@@ -204,7 +461,7 @@ impl OutputStreamBuilder {
     }
 }
 
-fn clamp_supported_buffer_size(
+pub(crate) fn clamp_supported_buffer_size(
     buffer_size: &SupportedBufferSize,
     preferred_size: FrameCount,
 ) -> BufferSize {
@@ -287,7 +544,7 @@ pub enum StreamError {
     /// Could not list supported stream configs for the device. Maybe it
     /// disconnected. For details see: [cpal::SupportedStreamConfigsError].
     SupportedStreamConfigsError(cpal::SupportedStreamConfigsError),
-    /// Could not find any output device
+    /// Could not find any output or input device
     NoDevice,
     /// New cpal sample format that rodio does not yet support please open
     /// an issue if you run into this.
@@ -338,19 +595,40 @@ impl OutputStream {
     ) -> Result<OutputStream, StreamError> {
         Self::validate_config(config);
         let (controller, source) = mixer(config.channel_count, config.sample_rate);
-        Self::init_stream(device, config, source).and_then(|stream| {
+        let clock = Arc::new(PlaybackClock::new(config.channel_count));
+        Self::init_stream(device, config, source, clock.clone()).and_then(|stream| {
             stream.play().map_err(StreamError::PlayStreamError)?;
             Ok(Self {
-                _stream: stream,
+                _stream: Backend::Device(stream),
                 mixer: controller,
+                clock,
+                captured: None,
             })
         })
     }
 
+    fn open_null(config: &OutputStreamConfig, null_config: NullStreamConfig) -> OutputStream {
+        Self::validate_config(config);
+        let (controller, source) = mixer(config.channel_count, config.sample_rate);
+        let clock = Arc::new(PlaybackClock::new(config.channel_count));
+        let captured = null_config
+            .capture
+            .then(|| Arc::new(Mutex::new(Vec::new())));
+        let stream =
+            NullStream::spawn(config, null_config, source, clock.clone(), captured.clone());
+        Self {
+            _stream: Backend::Null(stream),
+            mixer: controller,
+            clock,
+            captured,
+        }
+    }
+
     fn init_stream(
         device: &cpal::Device,
         config: &OutputStreamConfig,
         mut samples: MixerSource,
+        clock: Arc<PlaybackClock>,
     ) -> Result<cpal::Stream, StreamError> {
         let error_callback = |err| {
             #[cfg(feature = "tracing")]
@@ -363,7 +641,8 @@ impl OutputStream {
         match sample_format {
             cpal::SampleFormat::F32 => device.build_output_stream::<f32, _, _>(
                 &config,
-                move |data, _| {
+                move |data, info| {
+                    clock.record(data.len(), info);
                     data.iter_mut()
                         .for_each(|d| *d = samples.next().unwrap_or(0f32))
                 },
@@ -372,7 +651,8 @@ impl OutputStream {
             ),
             cpal::SampleFormat::F64 => device.build_output_stream::<f64, _, _>(
                 &config,
-                move |data, _| {
+                move |data, info| {
+                    clock.record(data.len(), info);
                     data.iter_mut()
                         .for_each(|d| *d = samples.next().map(Sample::from_sample).unwrap_or(0f64))
                 },
@@ -381,7 +661,8 @@ impl OutputStream {
             ),
             cpal::SampleFormat::I8 => device.build_output_stream::<i8, _, _>(
                 &config,
-                move |data, _| {
+                move |data, info| {
+                    clock.record(data.len(), info);
                     data.iter_mut()
                         .for_each(|d| *d = samples.next().map(Sample::from_sample).unwrap_or(0i8))
                 },
@@ -390,7 +671,8 @@ impl OutputStream {
             ),
             cpal::SampleFormat::I16 => device.build_output_stream::<i16, _, _>(
                 &config,
-                move |data, _| {
+                move |data, info| {
+                    clock.record(data.len(), info);
                     data.iter_mut()
                         .for_each(|d| *d = samples.next().map(Sample::from_sample).unwrap_or(0i16))
                 },
@@ -399,7 +681,8 @@ impl OutputStream {
             ),
             cpal::SampleFormat::I32 => device.build_output_stream::<i32, _, _>(
                 &config,
-                move |data, _| {
+                move |data, info| {
+                    clock.record(data.len(), info);
                     data.iter_mut()
                         .for_each(|d| *d = samples.next().map(Sample::from_sample).unwrap_or(0i32))
                 },
@@ -408,7 +691,8 @@ impl OutputStream {
             ),
             cpal::SampleFormat::I64 => device.build_output_stream::<i64, _, _>(
                 &config,
-                move |data, _| {
+                move |data, info| {
+                    clock.record(data.len(), info);
                     data.iter_mut()
                         .for_each(|d| *d = samples.next().map(Sample::from_sample).unwrap_or(0i64))
                 },
@@ -417,7 +701,8 @@ impl OutputStream {
             ),
             cpal::SampleFormat::U8 => device.build_output_stream::<u8, _, _>(
                 &config,
-                move |data, _| {
+                move |data, info| {
+                    clock.record(data.len(), info);
                     data.iter_mut().for_each(|d| {
                         *d = samples
                             .next()
@@ -430,7 +715,8 @@ impl OutputStream {
             ),
             cpal::SampleFormat::U16 => device.build_output_stream::<u16, _, _>(
                 &config,
-                move |data, _| {
+                move |data, info| {
+                    clock.record(data.len(), info);
                     data.iter_mut().for_each(|d| {
                         *d = samples
                             .next()
@@ -443,7 +729,8 @@ impl OutputStream {
             ),
             cpal::SampleFormat::U32 => device.build_output_stream::<u32, _, _>(
                 &config,
-                move |data, _| {
+                move |data, info| {
+                    clock.record(data.len(), info);
                     data.iter_mut().for_each(|d| {
                         *d = samples
                             .next()
@@ -456,7 +743,8 @@ impl OutputStream {
             ),
             cpal::SampleFormat::U64 => device.build_output_stream::<u64, _, _>(
                 &config,
-                move |data, _| {
+                move |data, info| {
+                    clock.record(data.len(), info);
                     data.iter_mut().for_each(|d| {
                         *d = samples
                             .next()
@@ -477,13 +765,21 @@ impl OutputStream {
 fn supported_output_configs(
     device: &cpal::Device,
 ) -> Result<impl Iterator<Item = cpal::SupportedStreamConfig>, StreamError> {
-    let mut supported: Vec<_> = device
+    let supported: Vec<_> = device
         .supported_output_configs()
         .map_err(StreamError::SupportedStreamConfigsError)?
         .collect();
+    Ok(fallback_configs(supported))
+}
+
+/// Sorts supported config ranges from most to least preferred and picks sample rates to try
+/// from each of them.
+pub(crate) fn fallback_configs(
+    mut supported: Vec<cpal::SupportedStreamConfigRange>,
+) -> impl Iterator<Item = cpal::SupportedStreamConfig> {
     supported.sort_by(|a, b| b.cmp_default_heuristics(a));
 
-    Ok(supported.into_iter().flat_map(|sf| {
+    supported.into_iter().flat_map(|sf| {
         let max_rate = sf.max_sample_rate();
         let min_rate = sf.min_sample_rate();
         let mut formats = vec![sf.with_max_sample_rate()];
@@ -493,5 +789,109 @@ fn supported_output_configs(
         }
         formats.push(sf.with_sample_rate(min_rate));
         formats
-    }))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{BufferTimestamp, NullStreamConfig, OutputStreamBuilder, PlaybackClock};
+    use crate::buffer::SamplesBuffer;
+    use crate::Sink;
+
+    #[test]
+    fn null_stream_captures() {
+        let stream = OutputStreamBuilder::default()
+            .with_channels(1)
+            .with_sample_rate(48000)
+            .with_buffer_size(cpal::BufferSize::Fixed(64))
+            .open_null_stream(NullStreamConfig::default().with_capture(true));
+
+        let sink = Sink::connect_new(stream.mixer());
+        sink.append(SamplesBuffer::new(1, 48000, vec![0.5; 100]));
+        sink.sleep_until_end();
+        // The sound ends in the middle of a block, which is captured once it is complete.
+        std::thread::sleep(Duration::from_millis(50));
+
+        let captured = stream.take_captured();
+        assert!(captured.len() >= 100);
+        assert!(captured.len().is_multiple_of(64));
+        assert_eq!(
+            captured.iter().filter(|&&sample| sample == 0.5).count(),
+            100
+        );
+        assert!(stream.frames_played() >= captured.len() as u64);
+        assert_eq!(stream.latency(), None);
+    }
+
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    struct Timestamp(Option<Duration>);
+
+    impl BufferTimestamp for Timestamp {
+        fn latency(&self) -> Option<Duration> {
+            self.0
+        }
+    }
+
+    #[test]
+    fn playback_clock() {
+        let clock = PlaybackClock::<Timestamp>::new(2);
+        assert_eq!(clock.frames_played(), 0);
+        assert_eq!(clock.last_timestamp(), None);
+        assert_eq!(clock.latency(), None);
+
+        let first = Timestamp(Some(Duration::from_millis(20)));
+        clock.record_timestamp(512, first);
+        // An odd sample count does not count the incomplete frame.
+        clock.record_timestamp(257, Timestamp(None));
+        assert_eq!(clock.frames_played(), 384);
+        assert_eq!(clock.last_timestamp(), Some(Timestamp(None)));
+        assert_eq!(clock.latency(), None);
+
+        clock.record_timestamp(1024, first);
+        assert_eq!(clock.latency(), Some(Duration::from_millis(20)));
+
+        // While a reader holds the lock the frames are counted, the timestamp is skipped.
+        let reader = clock.timestamp.lock().unwrap();
+        clock.record_timestamp(128, Timestamp(Some(Duration::from_millis(5))));
+        drop(reader);
+        assert_eq!(clock.frames_played(), 960);
+        assert_eq!(clock.last_timestamp(), Some(first));
+
+        let second = Timestamp(Some(Duration::from_millis(5)));
+        clock.record_timestamp(128, second);
+        assert_eq!(clock.last_timestamp(), Some(second));
+        assert_eq!(clock.latency(), Some(Duration::from_millis(5)));
+        assert_eq!(clock.frames_played(), 1024);
+    }
+
+    #[test]
+    fn null_stream_speed() {
+        let stream = OutputStreamBuilder::default()
+            .with_sample_rate(1000)
+            .open_null_stream(NullStreamConfig::default().with_speed(1000.0));
+
+        let start = Instant::now();
+        while stream.frames_played() < 10_000 {
+            assert!(
+                start.elapsed() < Duration::from_secs(5),
+                "null stream too slow"
+            );
+            std::thread::yield_now();
+        }
+        assert!(stream.take_captured().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "null stream speed must be finite")]
+    fn null_stream_infinite_speed() {
+        let _ = NullStreamConfig::default().with_speed(f32::INFINITY);
+    }
+
+    #[test]
+    #[should_panic(expected = "at least 0.001")]
+    fn null_stream_tiny_speed() {
+        let _ = NullStreamConfig::default().with_speed(1e-30);
+    }
 }
@@ -0,0 +1,64 @@
+#![cfg(feature = "symphonia-mp3")]
+
+use std::fs::File;
+use std::io::BufReader;
+use std::time::Duration;
+
+use rodio::decoder::{DecoderBackend, DecoderBuilder, SeekMode};
+use rodio::Source;
+
+fn music_mp3() -> BufReader<File> {
+    BufReader::new(File::open("assets/music.mp3").unwrap())
+}
+
+#[test]
+fn gapless_removes_encoder_delay_and_padding() {
+    let gapless = DecoderBuilder::new().build(music_mp3()).unwrap().count();
+    let with_padding = DecoderBuilder::new()
+        .with_gapless(false)
+        .build(music_mp3())
+        .unwrap()
+        .count();
+    assert!(with_padding > gapless);
+}
+
+#[test]
+fn coarse_seek_lands_near_the_position() {
+    let remaining_after_seek = |seek_mode| {
+        let mut decoder = DecoderBuilder::new()
+            .with_hint("mp3")
+            .with_seek_mode(seek_mode)
+            .build(music_mp3())
+            .unwrap();
+        decoder.try_seek(Duration::from_secs(2)).unwrap();
+        let samples_per_second = decoder.sample_rate() as f32 * decoder.channels() as f32;
+        decoder.count() as f32 / samples_per_second
+    };
+    let accurate = remaining_after_seek(SeekMode::Accurate);
+    let coarse = remaining_after_seek(SeekMode::Coarse);
+    assert!((accurate - coarse).abs() < 0.5, "{accurate} {coarse}");
+}
+
+#[test]
+fn selects_track() {
+    let decoder = DecoderBuilder::new().with_track(0).build(music_mp3());
+    assert!(decoder.is_ok());
+    let decoder = DecoderBuilder::new().with_track(1).build(music_mp3());
+    assert!(decoder.is_err());
+}
+
+#[test]
+fn prefers_backend() {
+    let decoder = DecoderBuilder::new()
+        .with_backend(DecoderBackend::Symphonia)
+        .build(music_mp3())
+        .unwrap();
+    assert_eq!(decoder.channels(), 2);
+
+    // Falls back to detection when the backend can not decode the data.
+    let decoder = DecoderBuilder::new()
+        .with_backend(DecoderBackend::Hound)
+        .build(music_mp3())
+        .unwrap();
+    assert_eq!(decoder.channels(), 2);
+}
@@ -27,3 +27,20 @@ fn test_flac_encodings() {
         assert_eq!(decoder.total_duration(), Some(Duration::from_secs(3)));
     }
 }
+
+#[cfg(any(feature = "flac", feature = "symphonia-flac"))]
+#[test]
+fn test_flac_channel_layout() {
+    use rodio::conversions::ChannelLayout;
+    use rodio::Source;
+    use std::io::BufReader;
+
+    for (asset, layout) in [
+        ("assets/audacity16bit_level5.flac", ChannelLayout::Mono),
+        ("assets/music.flac", ChannelLayout::Stereo),
+    ] {
+        let file = std::fs::File::open(asset).unwrap();
+        let decoder = rodio::Decoder::new(BufReader::new(file)).unwrap();
+        assert_eq!(decoder.channel_layout(), Some(layout), "{asset}");
+    }
+}
@@ -0,0 +1,156 @@
+use std::io::{BufReader, Cursor};
+
+use rodio::{Decoder, Sample, Sink};
+
+fn decode_file(path: &str) -> Vec<Sample> {
+    let file = std::fs::File::open(path).unwrap();
+    Decoder::new(BufReader::new(file)).unwrap().collect()
+}
+
+/// Splits a WAV file into parts that end at the given frames.
+#[cfg(feature = "wav")]
+fn split_wav(path: &str, ends: &[usize]) -> Vec<Vec<u8>> {
+    let mut reader = hound::WavReader::open(path).unwrap();
+    let spec = reader.spec();
+    let samples: Vec<i16> = reader.samples().map(Result::unwrap).collect();
+    let channels = spec.channels as usize;
+
+    let mut parts = Vec::new();
+    let mut start = 0;
+    for &end in ends.iter().chain([&(samples.len() / channels)]) {
+        let mut data = Cursor::new(Vec::new());
+        let mut writer = hound::WavWriter::new(&mut data, spec).unwrap();
+        for &sample in &samples[start * channels..end * channels] {
+            writer.write_sample(sample).unwrap();
+        }
+        writer.finalize().unwrap();
+        parts.push(data.into_inner());
+        start = end;
+    }
+    parts
+}
+
+#[cfg(feature = "wav")]
+#[test]
+fn sink_plays_split_file_without_gaps() {
+    let path = "assets/music.wav";
+    let expected = decode_file(path);
+
+    // Odd frame counts, so that the parts do not end at a span boundary.
+    let (sink, output) = Sink::new();
+    for part in split_wav(path, &[12_345, 100_003, 100_004]) {
+        sink.append(Decoder::new(Cursor::new(part)).unwrap());
+    }
+
+    let actual: Vec<Sample> = output.take(expected.len() + 1000).collect();
+    assert_eq!(actual.len(), expected.len() + 1000);
+    assert!(actual[..expected.len()] == expected[..], "output differs");
+    // The queue stays alive with silence once the parts are played.
+    assert!(actual[expected.len()..].iter().all(|&sample| sample == 0.0));
+}
+
+#[cfg(feature = "wav")]
+#[test]
+fn sink_plays_parts_appended_while_playing_without_gaps() {
+    let path = "assets/music.wav";
+    let expected = decode_file(path);
+    let mut parts = split_wav(path, &[12_345, 100_003]).into_iter();
+
+    let (sink, mut output) = Sink::new();
+    sink.append(Decoder::new(Cursor::new(parts.next().unwrap())).unwrap());
+    let mut actual: Vec<Sample> = output.by_ref().take(10_001).collect();
+    // The next parts arrive while the first one is still playing.
+    for part in parts {
+        sink.append(Decoder::new(Cursor::new(part)).unwrap());
+        actual.extend(output.by_ref().take(50_001));
+    }
+    actual.extend(output.take(expected.len() - actual.len()));
+    assert!(actual == expected, "output differs");
+}
+
+/// `assets/music.mp3` has a LAME tag with an encoder delay of 576 and a padding of 984
+/// samples in 390 frames of 1152 samples, after the tag frame.
+#[cfg(all(feature = "minimp3", not(feature = "symphonia-mp3")))]
+#[test]
+fn mp3_removes_encoder_delay_and_padding() {
+    use rodio::decoder::DecoderBuilder;
+    use rodio::Source;
+
+    let decode = |gapless| {
+        let file = std::fs::File::open("assets/music.mp3").unwrap();
+        let decoder = DecoderBuilder::new()
+            .with_gapless(gapless)
+            .build(BufReader::new(file))
+            .unwrap();
+        let channels = decoder.channels() as usize;
+        (channels, decoder.collect::<Vec<Sample>>())
+    };
+    let (channels, trimmed) = decode(true);
+    let (_, untrimmed) = decode(false);
+
+    assert_eq!(trimmed.len(), (390 * 1152 - 576 - 984) * channels);
+    // The tag frame, the encoder delay and the delay of the decoder are removed.
+    let start = (1152 + 576 + 529) * channels;
+    assert!(trimmed[..] == untrimmed[start..start + trimmed.len()]);
+}
+
+/// Moves the granule positions of the audio pages back by `delay`, which makes the first
+/// page end before the samples decoded up to there, as after an encoder delay.
+#[cfg(all(feature = "vorbis", not(feature = "symphonia-vorbis")))]
+fn add_vorbis_delay(mut data: Vec<u8>, delay: u64) -> Vec<u8> {
+    let mut page = 0;
+    while page < data.len() {
+        let segments = data[page + 26] as usize;
+        let body: usize = data[page + 27..page + 27 + segments]
+            .iter()
+            .map(|&len| len as usize)
+            .sum();
+        let end = page + 27 + segments + body;
+
+        let granule = u64::from_le_bytes(data[page + 6..page + 14].try_into().unwrap());
+        if granule != 0 && granule != u64::MAX {
+            data[page + 6..page + 14].copy_from_slice(&(granule - delay).to_le_bytes());
+        }
+        data[page + 22..page + 26].fill(0);
+        let crc = ogg_crc(&data[page..end]);
+        data[page + 22..page + 26].copy_from_slice(&crc.to_le_bytes());
+        page = end;
+    }
+    data
+}
+
+#[cfg(all(feature = "vorbis", not(feature = "symphonia-vorbis")))]
+fn ogg_crc(data: &[u8]) -> u32 {
+    data.iter().fold(0, |crc, &byte| {
+        (0..8).fold(crc ^ ((byte as u32) << 24), |crc, _| {
+            if crc & 0x8000_0000 != 0 {
+                (crc << 1) ^ 0x04c1_1db7
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+#[cfg(all(feature = "vorbis", not(feature = "symphonia-vorbis")))]
+#[test]
+fn vorbis_removes_encoder_delay() {
+    use rodio::decoder::DecoderBuilder;
+    use rodio::Source;
+
+    const DELAY: u64 = 1000;
+    let untouched = decode_file("assets/music.ogg");
+    let delayed = add_vorbis_delay(std::fs::read("assets/music.ogg").unwrap(), DELAY);
+
+    let decoder = Decoder::new(Cursor::new(delayed.clone())).unwrap();
+    let start = DELAY as usize * decoder.channels() as usize;
+    let trimmed: Vec<Sample> = decoder.collect();
+    assert!(trimmed[..] == untouched[start..]);
+
+    let kept: Vec<Sample> = DecoderBuilder::new()
+        .with_gapless(false)
+        .build(Cursor::new(delayed))
+        .unwrap()
+        .collect();
+    assert!(kept == untouched);
+}
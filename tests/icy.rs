@@ -0,0 +1,148 @@
+#![cfg(all(feature = "icy", feature = "wav"))]
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use rodio::icy::{IcyConfig, IcyConnection, IcyStream};
+use rodio::{Decoder, Source};
+
+const METAINT: usize = 1000;
+
+/// Serves the file once per title like a radio station, then stops accepting connections.
+fn radio(path: &'static str, titles: &'static [&'static str]) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    thread::spawn(move || {
+        let data = std::fs::read(path).unwrap();
+        for title in titles {
+            let (mut connection, _) = listener.accept().unwrap();
+            // Closing the connection with an unread request would reset it.
+            let mut request = BufReader::new(connection.try_clone().unwrap());
+            let mut line = String::new();
+            while request.read_line(&mut line).unwrap() > 2 {
+                line.clear();
+            }
+            let mut response =
+                format!("ICY 200 OK\r\nicy-metaint: {METAINT}\r\ncontent-type: audio/wav\r\n\r\n")
+                    .into_bytes();
+            for (index, chunk) in data.chunks(METAINT).enumerate() {
+                response.extend(chunk);
+                if chunk.len() < METAINT {
+                    break;
+                }
+                if index == 0 {
+                    let mut metadata = format!("StreamTitle='{title}';").into_bytes();
+                    metadata.resize(metadata.len().div_ceil(16) * 16, 0);
+                    response.push((metadata.len() / 16) as u8);
+                    response.extend(metadata);
+                } else {
+                    response.push(0);
+                }
+            }
+            // The stream may be dropped before everything was sent.
+            let _ = connection.write_all(&response);
+        }
+    });
+    address
+}
+
+fn connect(address: &str) -> std::io::Result<IcyConnection> {
+    let mut connection = TcpStream::connect(address)?;
+    connection.write_all(b"GET / HTTP/1.0\r\nIcy-MetaData: 1\r\n\r\n")?;
+    IcyConnection::from_response(connection)
+}
+
+/// Frames that are not silence, playback starts and pauses with silence.
+fn sound_frames(source: impl Source) -> Vec<Vec<f32>> {
+    let channels = source.channels() as usize;
+    let samples: Vec<f32> = source.collect();
+    samples
+        .chunks(channels)
+        .filter(|frame| frame.iter().any(|&sample| sample != 0.0))
+        .map(<[f32]>::to_vec)
+        .collect()
+}
+
+#[test]
+fn plays_stream_with_metadata_and_reconnects() {
+    let address = radio("assets/music.wav", &["Song 1", "Song 2"]);
+    let config = IcyConfig::default()
+        .with_prebuffer(16 * 1024)
+        .with_reconnect(2, Duration::from_millis(10));
+    let mut stream = IcyStream::with_config(move || connect(&address), config).unwrap();
+    let titles = Arc::new(Mutex::new(Vec::new()));
+    let playing_thread = thread::current().id();
+    stream.on_metadata_change({
+        let titles = titles.clone();
+        move |metadata| {
+            // Reported by the thread that reads the connection, not the one that plays.
+            assert_ne!(thread::current().id(), playing_thread);
+            titles
+                .lock()
+                .unwrap()
+                .push(metadata.stream_title.clone().unwrap())
+        }
+    });
+
+    let played = sound_frames(stream);
+
+    let file = std::fs::File::open("assets/music.wav").unwrap();
+    let song = sound_frames(Decoder::new(BufReader::new(file)).unwrap());
+    assert_eq!(played.len(), song.len() * 2);
+    assert!(played == [song.clone(), song].concat());
+    // The last change may be reported shortly after playback got to it.
+    for _ in 0..100 {
+        if titles.lock().unwrap().len() == 2 {
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(*titles.lock().unwrap(), ["Song 1", "Song 2"]);
+}
+
+#[test]
+fn fails_when_server_is_not_reachable() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    drop(listener);
+    assert!(IcyStream::new(move || connect(&address)).is_err());
+}
+
+#[test]
+fn plays_silence_while_the_server_is_slow() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    thread::spawn(move || {
+        let data = std::fs::read("assets/music.wav").unwrap();
+        let (mut connection, _) = listener.accept().unwrap();
+        let mut request = BufReader::new(connection.try_clone().unwrap());
+        let mut line = String::new();
+        while request.read_line(&mut line).unwrap() > 2 {
+            line.clear();
+        }
+        let (first, second) = data.split_at(data.len() / 2);
+        let _ = connection.write_all(b"ICY 200 OK\r\ncontent-type: audio/wav\r\n\r\n");
+        let _ = connection.write_all(first);
+        thread::sleep(Duration::from_millis(300));
+        let _ = connection.write_all(second);
+    });
+    let config = IcyConfig::default().with_reconnect(0, Duration::ZERO);
+    let stream = IcyStream::with_config(move || connect(&address), config).unwrap();
+    let channels = stream.channels() as usize;
+
+    let samples: Vec<f32> = stream.collect();
+    let file = std::fs::File::open("assets/music.wav").unwrap();
+    let song: Vec<f32> = Decoder::new(BufReader::new(file)).unwrap().collect();
+    // Playback went on with silence instead of waiting for the rest.
+    assert!(samples.len() > song.len());
+    let sound = |samples: &[f32]| {
+        samples
+            .chunks(channels)
+            .filter(|frame| frame.iter().any(|&sample| sample != 0.0))
+            .count()
+    };
+    assert_eq!(sound(&samples), sound(&song));
+}
@@ -0,0 +1,66 @@
+#[cfg(any(feature = "flac", feature = "symphonia-flac"))]
+#[test]
+fn test_flac_metadata() {
+    let file = std::fs::File::open("assets/music.flac").unwrap();
+    let decoder = rodio::Decoder::new(std::io::BufReader::new(file)).unwrap();
+    let metadata = decoder.metadata();
+    assert_eq!(metadata.title(), Some("Corelli Trio Sonata 11, m1"));
+    assert_eq!(metadata.artist(), Some("RP and E Goldstein"));
+}
+
+#[cfg(feature = "symphonia-mp3")]
+#[test]
+fn test_mp3_metadata() {
+    let file = std::fs::File::open("assets/music.mp3").unwrap();
+    let decoder = rodio::Decoder::new(std::io::BufReader::new(file)).unwrap();
+    let metadata = decoder.metadata();
+    assert_eq!(metadata.title(), Some("Corelli Trio Sonata 11, m1"));
+    assert_eq!(metadata.artist(), Some("RP and E Goldstein"));
+}
+
+#[cfg(all(feature = "vorbis", not(feature = "symphonia-vorbis")))]
+#[test]
+fn test_vorbis_metadata_changes_in_chained_stream() {
+    use std::io::Cursor;
+    use std::sync::{Arc, Mutex};
+
+    let mut chained = std::fs::read("assets/music.ogg").unwrap();
+    chained.extend(std::fs::read("assets/beep3.ogg").unwrap());
+
+    let mut decoder = rodio::Decoder::new(Cursor::new(chained)).unwrap();
+    assert_eq!(
+        decoder.metadata().title(),
+        Some("Corelli Trio Sonata 11, m1")
+    );
+
+    let changes = Arc::new(Mutex::new(Vec::new()));
+    let recorded = changes.clone();
+    decoder.on_metadata_change(move |metadata| recorded.lock().unwrap().push(metadata.clone()));
+    decoder.by_ref().for_each(drop);
+
+    let changes = changes.lock().unwrap();
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].title(), None);
+    assert_eq!(decoder.metadata(), &changes[0]);
+}
+
+#[cfg(any(feature = "flac", feature = "symphonia-flac"))]
+#[test]
+fn test_replay_gain_fallback() {
+    use rodio::decoder::ReplayGainSettings;
+
+    let open = || {
+        let file = std::fs::File::open("assets/music.flac").unwrap();
+        rodio::Decoder::new(std::io::BufReader::new(file)).unwrap()
+    };
+    // The file has no ReplayGain tags.
+    let settings = ReplayGainSettings {
+        fallback_gain: -20.0,
+        ..Default::default()
+    };
+    let decoder = open().with_replay_gain(settings);
+    assert!((decoder.replay_gain_factor() - 0.1).abs() < 1e-6);
+    for (normalized, original) in decoder.zip(open()).take(10_000) {
+        assert!((normalized - original * 0.1).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,158 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use rodio::buffer::SamplesBuffer;
+use rodio::source::{FadeCurve, SineWave, Source};
+use rodio::{mixer, Sink, SpatialSink};
+
+// Counts the allocations and deallocations of the threads that are marked as audio threads.
+struct CountingAllocator;
+
+static AUDIO_THREAD_ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static IS_AUDIO_THREAD: Cell<bool> = const { Cell::new(false) };
+}
+
+fn count() {
+    if IS_AUDIO_THREAD.with(Cell::get) {
+        AUDIO_THREAD_ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count();
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        count();
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count();
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn sound(value: f32, millis: usize) -> SamplesBuffer {
+    SamplesBuffer::new(2, 48000, vec![value; millis * 96])
+}
+
+// Plays the output of a stereo mixer at 48 kHz on this thread as if it were the audio thread.
+fn play(output: &mut impl Iterator<Item = f32>, millis: usize) {
+    IS_AUDIO_THREAD.with(|is_audio_thread| is_audio_thread.set(true));
+    output.take(millis * 96).for_each(drop);
+    IS_AUDIO_THREAD.with(|is_audio_thread| is_audio_thread.set(false));
+}
+
+#[test]
+fn audio_thread_does_not_allocate() {
+    let (mixer, mut output) = mixer::mixer(2, 48000);
+    let sink = Sink::connect_new(&mixer);
+    let spatial_sink =
+        SpatialSink::connect_new(&mixer, [0.0; 3], [-1.0, 0.0, 0.0], [1.0, 0.0, 0.0]);
+    for value in [0.1, 0.2, 0.3] {
+        sink.append(sound(value, 30));
+    }
+    spatial_sink.append(sound(0.5, 100));
+    // Prepare the converters of the mixer and the sounds before counting.
+    output.by_ref().take(96).for_each(drop);
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let audio_thread = thread::spawn({
+        let stop = stop.clone();
+        move || {
+            IS_AUDIO_THREAD.with(|is_audio_thread| is_audio_thread.set(true));
+            let mut sum = 0.0;
+            while !stop.load(Ordering::Acquire) {
+                sum += output.next().unwrap();
+            }
+            IS_AUDIO_THREAD.with(|is_audio_thread| is_audio_thread.set(false));
+            (output, sum)
+        }
+    });
+
+    for _ in 0..5 {
+        sink.set_volume(0.5);
+        sink.set_speed(1.5);
+        spatial_sink.set_emitter_position([1.0, 1.0, 0.0]);
+        sink.pause();
+        thread::sleep(Duration::from_millis(10));
+        sink.play();
+        sink.set_volume(1.0);
+        sink.set_speed(1.0);
+        mixer.add(sound(0.01, 5));
+        sink.append(sound(0.4, 5));
+        sink.skip_one();
+        let _ = sink.try_seek(Duration::from_millis(1));
+        thread::sleep(Duration::from_millis(10));
+    }
+    sink.clear();
+    spatial_sink.stop();
+    mixer.add(SineWave::new(440.0).take_duration(Duration::from_millis(5)));
+    thread::sleep(Duration::from_millis(50));
+
+    stop.store(true, Ordering::Release);
+    let (output, _) = audio_thread.join().unwrap();
+    assert!(sink.empty());
+    assert_eq!(AUDIO_THREAD_ALLOCATIONS.load(Ordering::Relaxed), 0);
+    drop(output);
+}
+
+#[test]
+fn crossfade_and_tempo_do_not_allocate() {
+    let (mixer, mut output) = mixer::mixer(2, 48000);
+    let sink = Sink::connect_new(&mixer);
+    sink.set_crossfade(Duration::from_millis(10), FadeCurve::EqualPower);
+    sink.append(sound(0.1, 30));
+    // Converted to the format of the sound before it when it fades in.
+    sink.append(SamplesBuffer::new(1, 44100, vec![0.2; 44100 * 60 / 1000]));
+    sink.append(sound(0.3, 30));
+    sink.append(sound(0.4, 30));
+    // Prepare the converters of the mixer and the sounds before counting.
+    output.by_ref().take(96).for_each(drop);
+
+    sink.set_tempo(1.5);
+    play(&mut output, 20);
+    sink.set_tempo(0.8);
+    play(&mut output, 20);
+    sink.skip_one();
+    play(&mut output, 20);
+    sink.set_crossfade(Duration::from_millis(5), FadeCurve::Linear);
+    sink.set_tempo(1.0);
+    play(&mut output, 40);
+    sink.set_crossfade(Duration::ZERO, FadeCurve::Linear);
+    play(&mut output, 100);
+
+    assert!(sink.empty());
+    assert_eq!(AUDIO_THREAD_ALLOCATIONS.load(Ordering::Relaxed), 0);
+}
+
+#[test]
+fn sounds_of_different_formats_do_not_allocate() {
+    let (mixer, mut output) = mixer::mixer(2, 48000);
+    let sink = Sink::connect_new(&mixer);
+    sink.append(sound(0.1, 20));
+    // Converted to the format of the mixer when they are appended.
+    sink.append(SamplesBuffer::new(1, 44100, vec![0.2; 441 * 2]));
+    sink.append(SamplesBuffer::new(6, 22050, vec![0.3; 6 * 441]));
+    sink.append(sound(0.4, 20));
+    sink.append(SamplesBuffer::new(1, 8000, vec![0.5; 160]));
+    // Prepare the converters of the mixer and the sounds before counting.
+    output.by_ref().take(96).for_each(drop);
+
+    play(&mut output, 150);
+
+    assert!(sink.empty());
+    assert_eq!(AUDIO_THREAD_ALLOCATIONS.load(Ordering::Relaxed), 0);
+}
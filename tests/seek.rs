@@ -11,7 +11,11 @@ use std::time::Duration;
 // #[cfg_attr(feature = "symphonia-vorbis"), case("ogg", true, "symphonia")],
 #[cfg_attr(
     all(feature = "minimp3", not(feature = "symphonia-mp3")),
-    case("mp3", false, "minimp3")
+    case("mp3", true, "minimp3")
+)]
+#[cfg_attr(
+    all(feature = "vorbis", not(feature = "symphonia-vorbis")),
+    case("ogg", true, "lewton")
 )]
 #[cfg_attr(
     all(feature = "wav", not(feature = "symphonia-wav")),
@@ -37,6 +41,14 @@ fn all_decoders(
 #[rstest]
 // note: disabled, broken decoder see issue: #516 and #539
 // #[cfg_attr(feature = "symphonia-vorbis"), case("ogg", true, "symphonia")],
+#[cfg_attr(
+    all(feature = "minimp3", not(feature = "symphonia-mp3")),
+    case("mp3", "minimp3")
+)]
+#[cfg_attr(
+    all(feature = "vorbis", not(feature = "symphonia-vorbis")),
+    case("ogg", "lewton")
+)]
 #[cfg_attr(
     all(feature = "wav", not(feature = "symphonia-wav")),
     case("wav", "hound")
@@ -142,7 +154,7 @@ fn seek_does_not_break_channel_order(
 
         source.try_seek(beep_start + offset).unwrap();
         let samples: Vec<_> = source.by_ref().take(100).collect();
-        let channel0 = 0 + channel_offset;
+        let channel0 = channel_offset;
         assert!(
             is_silent(&samples, source.channels(), channel0),
             "channel0 should be silent,
@@ -161,9 +173,9 @@ fn seek_does_not_break_channel_order(
     }
 }
 
-fn second_channel_beep_range<R: rodio::Source>(source: &mut R) -> std::ops::Range<usize>
+fn second_channel_beep_range<R>(source: &mut R) -> std::ops::Range<usize>
 where
-    R: Iterator<Item = f32>,
+    R: rodio::Source + Iterator<Item = f32>,
 {
     let channels = source.channels() as usize;
     let samples: Vec<f32> = source.by_ref().collect();
@@ -242,3 +254,75 @@ fn get_rl(format: &str) -> Decoder<impl Read + Seek> {
     let file = std::fs::File::open(asset).unwrap();
     Decoder::new(BufReader::new(file)).unwrap()
 }
+
+#[cfg(all(feature = "minimp3", not(feature = "symphonia-mp3")))]
+#[test]
+fn minimp3_seek_is_sample_accurate() {
+    assert_seek_is_sample_accurate("mp3");
+}
+
+#[cfg(all(feature = "vorbis", not(feature = "symphonia-vorbis")))]
+#[test]
+fn lewton_seek_is_sample_accurate() {
+    assert_seek_is_sample_accurate("ogg");
+}
+
+#[cfg(all(feature = "vorbis", not(feature = "symphonia-vorbis")))]
+#[test]
+fn lewton_reports_total_duration() {
+    let decoder = get_music("ogg");
+    let total_duration = decoder.total_duration().unwrap();
+    let difference = total_duration.as_secs_f64() - time_remaining(decoder).as_secs_f64();
+    assert!(difference.abs() < 1e-6, "difference {difference}");
+}
+
+#[cfg(all(feature = "vorbis", not(feature = "symphonia-vorbis")))]
+#[test]
+fn lewton_seeks_in_chained_stream() {
+    let mut chained = std::fs::read("assets/music.ogg").unwrap();
+    chained.extend(std::fs::read("assets/beep3.ogg").unwrap());
+    let decoder = Decoder::new(std::io::Cursor::new(chained.clone())).unwrap();
+    let channels = decoder.channels() as usize;
+    let sample_rate = decoder.sample_rate() as usize;
+    let samples: Vec<f32> = decoder.collect();
+
+    let mut decoder = Decoder::new(std::io::Cursor::new(chained)).unwrap();
+    assert_eq!(decoder.total_duration(), None);
+    decoder.by_ref().for_each(drop);
+    assert_eq!(decoder.metadata().title(), None);
+
+    decoder.try_seek(Duration::from_secs(1)).unwrap();
+    let start = sample_rate * channels;
+    let after_seek: Vec<f32> = decoder.by_ref().take(2000).collect();
+    assert_eq!(after_seek, samples[start..start + 2000]);
+    assert_eq!(
+        decoder.metadata().title(),
+        Some("Corelli Trio Sonata 11, m1")
+    );
+}
+
+/// Compares the samples after seeking with the samples of decoding the whole file.
+#[cfg(any(
+    all(feature = "minimp3", not(feature = "symphonia-mp3")),
+    all(feature = "vorbis", not(feature = "symphonia-vorbis"))
+))]
+fn assert_seek_is_sample_accurate(format: &str) {
+    let decoder = get_music(format);
+    let channels = decoder.channels() as usize;
+    let sample_rate = decoder.sample_rate() as usize;
+    let samples: Vec<f32> = decoder.collect();
+
+    let mut decoder = get_music(format);
+    for millis in [0, 10, 1500, 3000, 730, 9000] {
+        decoder.try_seek(Duration::from_millis(millis)).unwrap();
+        let start = millis as usize * sample_rate / 1000 * channels;
+        let after_seek: Vec<f32> = decoder.by_ref().take(2000).collect();
+        let expected = &samples[start..start + 2000];
+        let max_error = after_seek
+            .iter()
+            .zip(expected)
+            .map(|(a, b)| (a - b).abs())
+            .fold(0.0, f32::max);
+        assert!(max_error < 1e-4, "seek to {millis} ms, error {max_error}");
+    }
+}
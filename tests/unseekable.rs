@@ -0,0 +1,117 @@
+use std::io::{BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+use rodio::decoder::DecoderBuilder;
+use rodio::source::SeekError;
+use rodio::{Decoder, Source};
+
+/// Serves the file over a local TCP connection, which can not seek.
+fn stream(path: &'static str) -> TcpStream {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        let (mut connection, _) = listener.accept().unwrap();
+        let data = std::fs::read(path).unwrap();
+        // A closed connection just ends the test data.
+        let _ = connection.write_all(&data);
+    });
+    TcpStream::connect(address).unwrap()
+}
+
+fn decode_file(path: &str) -> Vec<f32> {
+    let file = std::fs::File::open(path).unwrap();
+    Decoder::new(BufReader::new(file)).unwrap().collect()
+}
+
+#[cfg(any(feature = "wav", feature = "symphonia-wav"))]
+#[test]
+fn decodes_wav_from_socket() {
+    let decoder = DecoderBuilder::new()
+        .with_hint("wav")
+        .build_unseekable(stream("assets/music.wav"))
+        .unwrap();
+    assert_eq!(decoder.channels(), 2);
+    let samples: Vec<f32> = decoder.collect();
+    assert_eq!(samples, decode_file("assets/music.wav"));
+}
+
+#[cfg(any(feature = "flac", feature = "symphonia-flac"))]
+#[test]
+fn decodes_flac_from_socket() {
+    let decoder = DecoderBuilder::new()
+        .with_hint("FLAC")
+        .build_unseekable(BufReader::new(stream("assets/music.flac")))
+        .unwrap();
+    let samples: Vec<f32> = decoder.collect();
+    assert_eq!(samples, decode_file("assets/music.flac"));
+}
+
+#[cfg(all(feature = "vorbis", not(feature = "symphonia-vorbis")))]
+#[test]
+fn decodes_vorbis_from_socket() {
+    let decoder = DecoderBuilder::new()
+        .with_hint("ogg")
+        .build_unseekable(stream("assets/music.ogg"))
+        .unwrap();
+    let samples: Vec<f32> = decoder.collect();
+    assert_eq!(samples, decode_file("assets/music.ogg"));
+}
+
+#[cfg(feature = "symphonia-mp3")]
+#[test]
+fn detects_format_without_hint() {
+    let decoder = DecoderBuilder::new()
+        .with_byte_len(std::fs::metadata("assets/music.mp3").unwrap().len())
+        .build_unseekable(stream("assets/music.mp3"))
+        .unwrap();
+    assert!(decoder.count() > 0);
+}
+
+#[cfg(all(feature = "minimp3", not(feature = "symphonia-mp3")))]
+#[test]
+fn decodes_mp3_from_socket() {
+    let decoder = DecoderBuilder::new()
+        .with_hint("mp3")
+        .build_unseekable(stream("assets/music.mp3"))
+        .unwrap();
+    assert_eq!(decoder.channels(), 2);
+    assert!(decoder.count() > 0);
+}
+
+#[cfg(any(feature = "wav", feature = "symphonia-wav"))]
+#[test]
+fn decodes_with_mime_type() {
+    let decoder = DecoderBuilder::new()
+        .with_mime_type("audio/x-wav; codecs=1")
+        .build_unseekable(stream("assets/music.wav"))
+        .unwrap();
+    assert_eq!(decoder.channels(), 2);
+    assert!(decoder.count() > 0);
+}
+
+#[cfg(any(feature = "wav", feature = "symphonia-wav"))]
+#[test]
+fn seeking_is_not_supported() {
+    let mut decoder = DecoderBuilder::new()
+        .with_hint("wav")
+        .build_unseekable(stream("assets/music.wav"))
+        .unwrap();
+    assert!(matches!(
+        decoder.try_seek(Duration::from_secs(1)),
+        Err(SeekError::NotSupported { .. })
+    ));
+    assert!(decoder.next().is_some());
+}
+
+#[cfg(all(feature = "wav", feature = "flac"))]
+#[test]
+fn wrong_hint_falls_back_to_detection() {
+    let file = std::fs::File::open("assets/music.wav").unwrap();
+    let decoder = DecoderBuilder::new()
+        .with_hint("flac")
+        .build(BufReader::new(file))
+        .unwrap();
+    assert_eq!(decoder.channels(), 2);
+}